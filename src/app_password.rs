@@ -0,0 +1,257 @@
+//! Per-client CardDAV app passwords.
+//!
+//! Each phone or desktop client gets its own username and generated
+//! password, so a lost device can be revoked without touching the others.
+//! Only an Argon2id hash is stored (in the encrypted database); the
+//! password itself is shown once, when it is created.
+//!
+//! A password can be scoped to one account and / or one address book id,
+//! and made read-only (PUT and DELETE are refused).
+//!
+//! Instead of typing the password, a device can also authenticate with a
+//! client certificate issued for its app password (`tls_client_auth`); it
+//! then gets the same scope.
+
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rusqlite::Connection;
+
+use crate::db;
+use crate::vault::SecureVault;
+
+/// Length of a generated app password.
+const PASSWORD_LEN: usize = 24;
+
+/// What a credential may access.  The default is everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scope {
+    /// Only this account, if set.
+    pub account: Option<String>,
+    /// Only address books with this id (`contacts` or a group id), if set.
+    pub book: Option<String>,
+    pub read_only: bool,
+}
+
+impl Scope {
+    pub fn of(password: &db::AppPassword) -> Self {
+        Self {
+            account: password.account.clone(),
+            book: password.book.clone(),
+            read_only: password.read_only,
+        }
+    }
+
+    /// Whether `account`'s principal and address book home are visible.
+    pub fn allows_account(&self, account: &str) -> bool {
+        self.account.as_deref().is_none_or(|a| a == account)
+    }
+
+    /// Whether the address book `book_id` of `account` is visible.
+    pub fn allows_book(&self, account: &str, book_id: &str) -> bool {
+        self.allows_account(account) && self.book.as_deref().is_none_or(|b| b == book_id)
+    }
+
+    /// Human-readable summary, e.g. `work/contacts, read-only`.
+    pub fn describe(&self) -> String {
+        let books = match (&self.account, &self.book) {
+            (None, None) => "all address books".to_string(),
+            (Some(account), None) => format!("{account}/*"),
+            (None, Some(book)) => format!("*/{book}"),
+            (Some(account), Some(book)) => format!("{account}/{book}"),
+        };
+        if self.read_only {
+            format!("{books}, read-only")
+        } else {
+            books
+        }
+    }
+}
+
+/// Returns `true` if `username` can name an app password: 1–64 ASCII
+/// letters, digits, `.`, `-`, `_` or `@` (no `:`, which Basic Auth uses as
+/// the separator).
+pub fn valid_username(username: &str) -> bool {
+    (1..=64).contains(&username.len())
+        && username
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_' | b'@'))
+}
+
+/// Create an app password for `username` and return the generated
+/// password.  It cannot be retrieved again.
+pub fn create(conn: &Connection, username: &str, label: &str, scope: &Scope) -> Result<String> {
+    anyhow::ensure!(valid_username(username), "invalid username {username:?}");
+    if let Some(account) = &scope.account {
+        anyhow::ensure!(db::valid_account_id(account), "invalid account name {account:?}");
+    }
+    if let Some(book) = &scope.book {
+        anyhow::ensure!(
+            !book.is_empty() && !book.contains('/'),
+            "invalid address book id {book:?}"
+        );
+    }
+
+    let password = crate::vault::generate_alphanumeric(PASSWORD_LEN);
+    let entry = db::AppPassword {
+        username: username.to_string(),
+        label: label.trim().to_string(),
+        account: scope.account.clone(),
+        book: scope.book.clone(),
+        read_only: scope.read_only,
+        created_at: String::new(),
+        last_used_at: None,
+    };
+    db::insert_app_password(conn, &entry, &hash(&password)?)?;
+    tracing::info!(username, scope = %scope.describe(), "app password created");
+    Ok(password)
+}
+
+/// Check a Basic Auth username / password pair.  Returns the matching app
+/// password (and records its use), or `None` if they don't match.
+pub fn verify(conn: &Connection, username: &str, password: &str) -> Result<Option<db::AppPassword>> {
+    let Some((entry, stored_hash)) = db::app_password(conn, username)? else {
+        // Spend the same time as for a wrong password, so response times
+        // don't reveal which usernames exist.
+        if let Ok(parsed) = PasswordHash::new(dummy_hash()) {
+            let _ = Argon2::default().verify_password(password.as_bytes(), &parsed);
+        }
+        return Ok(None);
+    };
+    let parsed = PasswordHash::new(&stored_hash)
+        .map_err(|e| anyhow!("corrupt hash for app password {username:?}: {e}"))?;
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return Ok(None);
+    }
+    db::touch_app_password(conn, username)?;
+    Ok(Some(entry))
+}
+
+/// A client certificate for a device, ready for import.
+pub struct DeviceCertificate {
+    /// PKCS#12 bundle of the certificate, its key and the local CA.
+    pub pkcs12: Vec<u8>,
+    /// Generated password protecting the bundle.
+    pub password: String,
+}
+
+/// Issue a client certificate for the device using the app password
+/// `username`; any certificate issued for it earlier stops working.  The
+/// local CA is created for the server's TLS `names` first if needed.
+pub fn issue_certificate(
+    conn: &Connection,
+    vault: &SecureVault,
+    username: &str,
+    names: &[String],
+) -> Result<DeviceCertificate> {
+    anyhow::ensure!(
+        db::app_password(conn, username)?.is_some(),
+        "no app password for {username:?} — create one first"
+    );
+    crate::tls::ensure_certs(vault, names)?;
+    let password = crate::vault::generate_alphanumeric(PASSWORD_LEN);
+    let cert = crate::tls::issue_client_certificate(vault, username, &password)?;
+    db::set_client_certificate(conn, username, &cert.sha256)?;
+    Ok(DeviceCertificate { pkcs12: cert.pkcs12, password })
+}
+
+/// The app password a client certificate (DER, already verified by TLS)
+/// was issued for, recording its use.  `None` if it was replaced or its
+/// app password revoked.
+pub fn verify_certificate(conn: &Connection, der: &[u8]) -> Result<Option<db::AppPassword>> {
+    let entry = db::app_password_by_certificate(conn, &crate::tls::fingerprint(der))?;
+    if let Some(entry) = &entry {
+        db::touch_app_password(conn, &entry.username)?;
+    }
+    Ok(entry)
+}
+
+/// A hash of a random password, checked against for unknown usernames.
+fn dummy_hash() -> &'static str {
+    static DUMMY: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    DUMMY.get_or_init(|| {
+        hash(&crate::vault::generate_alphanumeric(PASSWORD_LEN)).unwrap_or_default()
+    })
+}
+
+/// Hash a password with Argon2id (default parameters, random salt).
+fn hash(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("hashing app password: {e}"))?
+        .to_string())
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_verify() {
+        let conn = db::open_in_memory().unwrap();
+        let scope = Scope {
+            account: Some("work".into()),
+            book: None,
+            read_only: true,
+        };
+        let password = create(&conn, "phone", " Pixel 8 ", &scope).unwrap();
+        assert_eq!(password.len(), PASSWORD_LEN);
+
+        // Only the hash is stored.
+        let (entry, stored_hash) = db::app_password(&conn, "phone").unwrap().unwrap();
+        assert!(stored_hash.starts_with("$argon2id$"));
+        assert!(!stored_hash.contains(&password));
+        assert_eq!(entry.label, "Pixel 8");
+        assert_eq!(Scope::of(&entry), scope);
+
+        let verified = verify(&conn, "phone", &password).unwrap().unwrap();
+        assert_eq!(verified.username, "phone");
+        assert!(db::app_passwords(&conn).unwrap()[0].last_used_at.is_some());
+
+        // Username and password must both match.
+        assert!(verify(&conn, "phone", "wrong").unwrap().is_none());
+        assert!(verify(&conn, "laptop", &password).unwrap().is_none());
+
+        // Usernames are unique; revoked passwords stop working.
+        assert!(create(&conn, "phone", "", &Scope::default()).is_err());
+        db::revoke_app_password(&conn, "phone").unwrap();
+        assert!(verify(&conn, "phone", &password).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let conn = db::open_in_memory().unwrap();
+        assert!(create(&conn, "", "", &Scope::default()).is_err());
+        assert!(create(&conn, "a:b", "", &Scope::default()).is_err());
+        let bad_book = Scope { book: Some("a/b".into()), ..Scope::default() };
+        assert!(create(&conn, "phone", "", &bad_book).is_err());
+        let bad_account = Scope { account: Some("Work".into()), ..Scope::default() };
+        assert!(create(&conn, "phone", "", &bad_account).is_err());
+        assert!(valid_username("alice@example.com"));
+    }
+
+    #[test]
+    fn scope_checks() {
+        let full = Scope::default();
+        assert!(full.allows_book("work", "contacts"));
+        assert_eq!(full.describe(), "all address books");
+
+        let scope = Scope {
+            account: Some("work".into()),
+            book: Some("contacts".into()),
+            read_only: true,
+        };
+        assert!(scope.allows_account("work"));
+        assert!(!scope.allows_account("home"));
+        assert!(scope.allows_book("work", "contacts"));
+        assert!(!scope.allows_book("work", "family"));
+        assert!(!scope.allows_book("home", "contacts"));
+        assert_eq!(scope.describe(), "work/contacts, read-only");
+    }
+}
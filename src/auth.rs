@@ -4,6 +4,9 @@
 //! 1. [`login()`] — runs the OAuth2 installed-app flow (browser → localhost redirect → token).
 //! 2. [`ensure_authenticated()`] — checks that a valid token exists in SQLite before sync.
 //!
+//! Several Google accounts can be signed in at once; every function takes
+//! the account id (see [`crate::db::Account`]) the token belongs to.
+//!
 //! The loopback listener binds to an OS-assigned port on `127.0.0.1` to receive
 //! the Google redirect.
 
@@ -16,7 +19,23 @@ use crate::db;
 use crate::vault::SecureVault;
 
 /// Google People API read-only scope.
-const SCOPES: &[&str] = &["https://www.googleapis.com/auth/contacts.readonly"];
+const READONLY_SCOPES: &[&str] = &["https://www.googleapis.com/auth/contacts.readonly"];
+
+/// Google People API read/write scope (needed for CardDAV write-back).
+const READWRITE_SCOPES: &[&str] = &["https://www.googleapis.com/auth/contacts"];
+
+/// OAuth scopes to request — read/write when `write_access` is enabled
+/// (`Config::write_back`), read-only otherwise.
+///
+/// Switching between the two requires a fresh login, since the cached
+/// token is only valid for the scopes it was issued for.
+pub fn scopes(write_access: bool) -> &'static [&'static str] {
+    if write_access {
+        READWRITE_SCOPES
+    } else {
+        READONLY_SCOPES
+    }
+}
 
 // ── Browser launcher ──────────────────────────────────────────────────
 
@@ -85,15 +104,21 @@ impl InstalledFlowDelegate for BrowserDelegate {
 
 // ── Token file path ──────────────────────────────────────────────────
 
-/// Path to the yup-oauth2 token cache file (`<data_dir>/setu/oauth_token.json`).
+/// Path to an account's yup-oauth2 token cache file:
+/// `<data_dir>/setu/oauth_token.json` for the default account,
+/// `oauth_token-<account>.json` for the others.
 ///
 /// Shared between [`login()`] and [`crate::google_api::GoogleApi::build()`] so
 /// both use the same on-disk cache.
-pub fn token_file_path() -> Result<std::path::PathBuf> {
+pub fn token_file_path(account: &str) -> Result<std::path::PathBuf> {
     let base = dirs::data_dir().context("cannot resolve data directory")?;
     let dir = base.join("setu");
     std::fs::create_dir_all(&dir)?;
-    Ok(dir.join("oauth_token.json"))
+    if account == db::DEFAULT_ACCOUNT {
+        Ok(dir.join("oauth_token.json"))
+    } else {
+        Ok(dir.join(format!("oauth_token-{account}.json")))
+    }
 }
 
 // ── Login result ─────────────────────────────────────────────────────
@@ -117,15 +142,23 @@ type AuthHub = google_people1::PeopleService<
 /// 2. Open the Google auth URL in the Windows browser.
 /// 3. Capture the `code` from the redirect.
 /// 4. Exchange for an access + refresh token.
-/// 5. Persist the token to the OS keyring and email to encrypted SQLite.
+/// 5. Persist the token to the OS keyring and register `account` (with
+///    its email) in encrypted SQLite.
+///
+/// `write_access` selects the read/write contacts scope (see [`scopes`]).
+/// Signing in to an existing account replaces its token.
 ///
 /// Returns a [`LoginResult`] with the user's email on success.
 pub async fn login(
     client_id: &str,
     client_secret: &str,
+    write_access: bool,
     vault: &SecureVault,
     db_key: &str,
+    account: &str,
 ) -> Result<LoginResult> {
+    anyhow::ensure!(db::valid_account_id(account), "invalid account name {account:?}");
+
     let secret = yup_oauth2::ApplicationSecret {
         client_id: client_id.to_string(),
         client_secret: client_secret.to_string(),
@@ -135,7 +168,7 @@ pub async fn login(
         ..Default::default()
     };
 
-    let token_path = token_file_path()?;
+    let token_path = token_file_path(account)?;
 
     // Remove any stale token file so yup-oauth2 always starts a fresh
     // browser flow when the user explicitly clicks "Login".
@@ -155,7 +188,7 @@ pub async fn login(
 
     // Trigger the OAuth flow by requesting a token for the People API scope.
     let _token = auth
-        .token(SCOPES)
+        .token(scopes(write_access))
         .await
         .context("OAuth2 authorization failed")?;
 
@@ -166,13 +199,14 @@ pub async fn login(
         .context("reading token file after login")?;
 
     // Store token in OS keyring.
-    vault.store_oauth_token(&token_json)?;
+    vault.store_oauth_token(account, &token_json)?;
 
-    // Store a marker in SQLite (token lives in keyring, email comes later).
+    // Register the account in SQLite (token lives in keyring, email comes later).
     let db_key_owned = db_key.to_string();
+    let account_owned = account.to_string();
     tokio::task::spawn_blocking(move || {
         let conn = db::open(Some(&db_key_owned))?;
-        db::store_oauth_token(&conn, "<stored-in-keyring>", "")?;
+        db::add_account(&conn, &account_owned, "")?;
         anyhow::Ok(())
     })
     .await??;
@@ -180,7 +214,7 @@ pub async fn login(
     // Try to fetch the user's email (best-effort, with timeout).
     let email = match tokio::time::timeout(
         std::time::Duration::from_secs(10),
-        fetch_user_email_with_auth(auth),
+        fetch_user_email_with_auth(auth, write_access),
     )
     .await
     {
@@ -188,9 +222,10 @@ pub async fn login(
             // Update SQLite with the email.
             let addr_clone = addr.clone();
             let db_key_owned = db_key.to_string();
+            let account_owned = account.to_string();
             let _ = tokio::task::spawn_blocking(move || {
                 let conn = db::open(Some(&db_key_owned))?;
-                db::store_oauth_token(&conn, "<stored-in-keyring>", &addr_clone)?;
+                db::add_account(&conn, &account_owned, &addr_clone)?;
                 anyhow::Ok(())
             })
             .await;
@@ -214,6 +249,7 @@ async fn fetch_user_email_with_auth(
     auth: yup_oauth2::authenticator::Authenticator<
         hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
     >,
+    write_access: bool,
 ) -> Result<String> {
     let connector = hyper_rustls::HttpsConnectorBuilder::new()
         .with_webpki_roots()
@@ -230,6 +266,7 @@ async fn fetch_user_email_with_auth(
         .people()
         .get("people/me")
         .person_fields(fields)
+        .add_scopes(scopes(write_access))
         .doit()
         .await
         .context("People API people.get(me)")?;
@@ -244,15 +281,39 @@ async fn fetch_user_email_with_auth(
 
 // ── Authentication check ─────────────────────────────────────────────
 
-/// Check whether the user has a valid OAuth token in the OS keyring.
+/// Check whether an account has a valid OAuth token in the OS keyring.
 ///
 /// Call this before starting sync or search operations.
-pub fn ensure_authenticated(vault: &SecureVault) -> bool {
-    vault.has_oauth_token()
+pub fn ensure_authenticated(vault: &SecureVault, account: &str) -> bool {
+    vault.has_oauth_token(account)
 }
 
-/// Get the stored Google email (if the user has logged in).
-pub fn get_logged_in_email(db_key: &str) -> Result<Option<String>> {
+/// Ids of the registered accounts that have an OAuth token, in the order
+/// they were added.
+pub fn signed_in_accounts(vault: &SecureVault, db_key: &str) -> Result<Vec<String>> {
+    let conn = db::open(Some(db_key))?;
+    Ok(db::accounts(&conn)?
+        .into_iter()
+        .map(|a| a.id)
+        .filter(|id| ensure_authenticated(vault, id))
+        .collect())
+}
+
+/// Get an account's stored Google email (if it has logged in).
+pub fn get_logged_in_email(db_key: &str, account: &str) -> Result<Option<String>> {
+    let conn = db::open(Some(db_key))?;
+    db::get_google_email(&conn, account)
+}
+
+/// Sign an account out and forget it: drops its token (keyring and token
+/// file) and its cached contacts.
+pub fn remove_account(vault: &SecureVault, db_key: &str, account: &str) -> Result<()> {
+    vault.clear_oauth_token(account)?;
+    let token_path = token_file_path(account)?;
+    if token_path.exists() {
+        std::fs::remove_file(&token_path)
+            .with_context(|| format!("removing {}", token_path.display()))?;
+    }
     let conn = db::open(Some(db_key))?;
-    db::get_google_email(&conn)
+    db::remove_account(&conn, account)
 }
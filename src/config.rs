@@ -2,8 +2,10 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
 
+use crate::tls::CertificateFiles;
 use crate::vault::SecureVault;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,8 +18,107 @@ pub struct Config {
     pub sync_interval_secs: u64,
     #[serde(default = "default_server_port")]
     pub server_port: u16,
+    /// Addresses the CardDAV server listens on, one listener each: IPs or
+    /// host names, with or without a port (`server_port` if omitted), e.g.
+    /// `["127.0.0.1", "[::1]:5232", "homeserver.local"]`.
+    #[serde(default = "default_bind_addresses")]
+    pub bind_addresses: Vec<String>,
+    /// Allow non-loopback `bind_addresses`, e.g. `0.0.0.0` for phones on the
+    /// home network.  Requires `use_tls` unless `lan_allow_http` is set.
+    #[serde(default)]
+    pub lan_mode: bool,
+    /// Let LAN mode serve plain HTTP — passwords and contacts then cross
+    /// the network unencrypted.
+    #[serde(default)]
+    pub lan_allow_http: bool,
+    /// Extra names for the server certificate, e.g. the host name clients
+    /// use when the server listens on `0.0.0.0`.
+    #[serde(default)]
+    pub tls_hostnames: Vec<String>,
+    /// Use this PEM certificate (optionally followed by its chain) instead
+    /// of generating a local CA.  Needs `tls_key_path`.
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+    /// PEM private key of `tls_cert_path`.
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+    /// PEM intermediate certificates, if not already in `tls_cert_path`.
+    #[serde(default)]
+    pub tls_chain_path: Option<PathBuf>,
+    /// Require every client to present a device certificate issued by the
+    /// local CA (`--issue-client-cert`) instead of a password.
+    #[serde(default)]
+    pub tls_client_auth: bool,
+    /// Offer HTTP/2 (ALPN `h2`) next to HTTP/1.1 on HTTPS.
+    #[serde(default = "default_tls_http2")]
+    pub tls_http2: bool,
     #[serde(default)]
     pub use_tls: bool,
+    /// With `use_tls`, also listen on plain HTTP on this port (on the same
+    /// `bind_addresses`), e.g. while clients move to `https://`.
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// What the `http_port` listener does.
+    #[serde(default)]
+    pub http_mode: HttpMode,
+    /// Propagate CardDAV PUT / DELETE to Google (requests the read/write
+    /// contacts scope at login).
+    #[serde(default)]
+    pub write_back: bool,
+    /// Contact groups served as their own address books, matched by name
+    /// (case-insensitive), group id or resource name.  `None` publishes
+    /// every user group plus "Starred".
+    #[serde(default)]
+    pub published_groups: Option<Vec<String>>,
+    /// Username the shared CardDAV password works with (app passwords
+    /// have their own).  `None`, as in configs from before this setting,
+    /// accepts the shared password with any username.
+    #[serde(default)]
+    pub carddav_username: Option<String>,
+    /// Failed CardDAV logins, per client IP and per username, before
+    /// further attempts are locked out (see [`crate::lockout`]).  0 disables
+    /// the lockout.
+    #[serde(default = "default_auth_max_failures")]
+    pub auth_max_failures: u32,
+    /// Length of the first lockout; it doubles with every further failure.
+    #[serde(default = "default_auth_lockout_secs")]
+    pub auth_lockout_secs: u64,
+    /// Upper bound for the lockout.
+    #[serde(default = "default_auth_max_lockout_secs")]
+    pub auth_max_lockout_secs: u64,
+    /// Retries of a People API call that failed with `429`, `5xx` or a
+    /// network error (see [`crate::retry`]).  0 disables retrying.
+    #[serde(default = "default_google_max_retries")]
+    pub google_max_retries: u32,
+    /// Upper bound of the first retry delay; it doubles with every retry.
+    #[serde(default = "default_google_retry_base_ms")]
+    pub google_retry_base_ms: u64,
+    /// Longest delay between two attempts.
+    #[serde(default = "default_google_retry_max_ms")]
+    pub google_retry_max_ms: u64,
+    /// Time a People API call may take, retries included.
+    #[serde(default = "default_google_call_timeout_secs")]
+    pub google_call_timeout_secs: u64,
+    /// Failed calls in a row after which calls are paused for
+    /// `google_breaker_cooldown_secs`.  0 disables the circuit breaker.
+    #[serde(default = "default_google_breaker_threshold")]
+    pub google_breaker_threshold: u32,
+    #[serde(default = "default_google_breaker_cooldown_secs")]
+    pub google_breaker_cooldown_secs: u64,
+}
+
+/// Behaviour of the plain-HTTP listener next to HTTPS (`http_port`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpMode {
+    /// Serve CardDAV as on HTTPS.
+    #[default]
+    Serve,
+    /// Answer every request with `308 Permanent Redirect` to HTTPS.
+    Redirect,
+    /// Only redirect `/.well-known/carddav` to HTTPS; everything else is
+    /// `404 Not Found`.
+    WellKnown,
 }
 
 fn default_sync_interval() -> u64 {
@@ -26,6 +127,42 @@ fn default_sync_interval() -> u64 {
 fn default_server_port() -> u16 {
     5232
 }
+fn default_bind_addresses() -> Vec<String> {
+    vec!["127.0.0.1".into(), "::1".into()]
+}
+fn default_tls_http2() -> bool {
+    true
+}
+fn default_carddav_username() -> String {
+    "setu".into()
+}
+fn default_auth_max_failures() -> u32 {
+    5
+}
+fn default_auth_lockout_secs() -> u64 {
+    30
+}
+fn default_auth_max_lockout_secs() -> u64 {
+    3600
+}
+fn default_google_max_retries() -> u32 {
+    3
+}
+fn default_google_retry_base_ms() -> u64 {
+    500
+}
+fn default_google_retry_max_ms() -> u64 {
+    30_000
+}
+fn default_google_call_timeout_secs() -> u64 {
+    60
+}
+fn default_google_breaker_threshold() -> u32 {
+    5
+}
+fn default_google_breaker_cooldown_secs() -> u64 {
+    60
+}
 
 impl Default for Config {
     fn default() -> Self {
@@ -34,7 +171,30 @@ impl Default for Config {
             google_client_secret: String::new(),
             sync_interval_secs: default_sync_interval(),
             server_port: default_server_port(),
+            bind_addresses: default_bind_addresses(),
+            lan_mode: false,
+            lan_allow_http: false,
+            tls_hostnames: Vec::new(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_chain_path: None,
+            tls_client_auth: false,
+            tls_http2: default_tls_http2(),
             use_tls: false,
+            http_port: None,
+            http_mode: HttpMode::default(),
+            write_back: false,
+            published_groups: None,
+            carddav_username: Some(default_carddav_username()),
+            auth_max_failures: default_auth_max_failures(),
+            auth_lockout_secs: default_auth_lockout_secs(),
+            auth_max_lockout_secs: default_auth_max_lockout_secs(),
+            google_max_retries: default_google_max_retries(),
+            google_retry_base_ms: default_google_retry_base_ms(),
+            google_retry_max_ms: default_google_retry_max_ms(),
+            google_call_timeout_secs: default_google_call_timeout_secs(),
+            google_breaker_threshold: default_google_breaker_threshold(),
+            google_breaker_cooldown_secs: default_google_breaker_cooldown_secs(),
         }
     }
 }
@@ -86,6 +246,123 @@ impl Config {
         Ok(cfg)
     }
 
+    /// Resolve `bind_addresses` to the sockets to listen on.
+    ///
+    /// This is the LAN-mode guard: a non-loopback address is refused unless
+    /// `lan_mode` is set, and then also unless the server uses TLS (`tls`)
+    /// or `lan_allow_http` is set.
+    pub fn listen_addresses(&self, tls: bool) -> Result<Vec<SocketAddr>> {
+        let addrs = self.resolve_bind_addresses(None)?;
+        self.lan_guard(&addrs, tls)?;
+        Ok(addrs)
+    }
+
+    /// Sockets for the plain-HTTP listener next to HTTPS: `bind_addresses`
+    /// on `http_port`.  Empty without `http_port` or without TLS (`tls`),
+    /// when the main listener already is plain HTTP.
+    ///
+    /// Serving CardDAV there is plain HTTP for the LAN guard; redirecting
+    /// is not.  With `tls_client_auth` it may only redirect.
+    pub fn http_listen_addresses(&self, tls: bool) -> Result<Vec<SocketAddr>> {
+        let Some(port) = self.http_port else {
+            return Ok(Vec::new());
+        };
+        if !tls {
+            tracing::warn!(port, "http_port is ignored — the server is not using HTTPS");
+            return Ok(Vec::new());
+        }
+        anyhow::ensure!(port != self.server_port, "http_port must differ from server_port");
+        let serves = self.http_mode == HttpMode::Serve;
+        anyhow::ensure!(
+            !(serves && self.tls_client_auth),
+            "with tls_client_auth the HTTP listener can only redirect — set \"http_mode\": \"redirect\""
+        );
+        let addrs = self.resolve_bind_addresses(Some(port))?;
+        self.lan_guard(&addrs, !serves)?;
+        Ok(addrs)
+    }
+
+    /// Resolve `bind_addresses`, on `port` instead of the entries' own or
+    /// `server_port` if given.
+    fn resolve_bind_addresses(&self, port: Option<u16>) -> Result<Vec<SocketAddr>> {
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        for entry in &self.bind_addresses {
+            let (host, entry_port) = split_bind_address(entry, self.server_port)?;
+            let port = port.unwrap_or(entry_port);
+            let resolved: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+                Ok(ip) => vec![SocketAddr::new(ip, port)],
+                Err(_) => (host.as_str(), port)
+                    .to_socket_addrs()
+                    .with_context(|| format!("resolving bind address {entry:?}"))?
+                    .collect(),
+            };
+            for addr in resolved {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+        anyhow::ensure!(!addrs.is_empty(), "no bind_addresses configured");
+        Ok(addrs)
+    }
+
+    /// Refuse network-reachable `addrs` without `lan_mode`, and serving
+    /// them unencrypted (`!tls`) without `lan_allow_http`.
+    fn lan_guard(&self, addrs: &[SocketAddr], tls: bool) -> Result<()> {
+        if let Some(exposed) = addrs.iter().find(|a| !a.ip().is_loopback()) {
+            anyhow::ensure!(
+                self.lan_mode,
+                "bind address {exposed} is reachable from the network — set \"lan_mode\": true to allow it"
+            );
+            anyhow::ensure!(
+                tls || self.lan_allow_http,
+                "LAN mode requires HTTPS (\"use_tls\": true); set \"lan_allow_http\": true to serve plain HTTP anyway"
+            );
+            tracing::warn!(%exposed, tls, "LAN mode — CardDAV server reachable from the network");
+        }
+        Ok(())
+    }
+
+    /// Host names and IP addresses the server certificate must cover:
+    /// `localhost`, both loopback addresses, every bound host except the
+    /// wildcard addresses, and `tls_hostnames`.
+    pub fn tls_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec!["localhost".into(), "127.0.0.1".into(), "::1".into()];
+        let bound = self
+            .bind_addresses
+            .iter()
+            .filter_map(|entry| split_bind_address(entry, self.server_port).ok())
+            .map(|(host, _)| host)
+            .filter(|host| !host.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified()));
+        for name in bound.chain(self.tls_hostnames.iter().map(|n| n.trim().to_string())) {
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The user's own certificate, if `tls_cert_path` and `tls_key_path`
+    /// are set.  Setting only one of them is an error.
+    pub fn certificate_files(&self) -> Result<Option<CertificateFiles>> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => Ok(Some(CertificateFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+                chain: self.tls_chain_path.clone(),
+            })),
+            (None, None) => {
+                anyhow::ensure!(
+                    self.tls_chain_path.is_none(),
+                    "tls_chain_path is set without tls_cert_path and tls_key_path"
+                );
+                Ok(None)
+            }
+            (Some(_), None) => anyhow::bail!("tls_cert_path is set but tls_key_path is not"),
+            (None, Some(_)) => anyhow::bail!("tls_key_path is set but tls_cert_path is not"),
+        }
+    }
+
     /// Returns `true` if OAuth credentials are configured.
     ///
     /// Checks client ID in config + client secret in the OS keyring.
@@ -97,6 +374,149 @@ impl Config {
             .get_google_client_secret()
             .ok()
             .flatten()
-            .map_or(false, |s| !s.is_empty())
+            .is_some_and(|s| !s.is_empty())
+    }
+}
+
+/// Split a `bind_addresses` entry into host and port: `host`, `host:port`,
+/// `1.2.3.4`, `1.2.3.4:port`, `::1`, `[::1]` or `[::1]:port`.
+fn split_bind_address(entry: &str, default_port: u16) -> Result<(String, u16)> {
+    let entry = entry.trim();
+    if let Ok(addr) = entry.parse::<SocketAddr>() {
+        return Ok((addr.ip().to_string(), addr.port()));
+    }
+    let unbracketed = entry.strip_prefix('[').and_then(|e| e.strip_suffix(']')).unwrap_or(entry);
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return Ok((ip.to_string(), default_port));
+    }
+    anyhow::ensure!(!entry.is_empty(), "empty bind address");
+    match entry.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse()
+                .with_context(|| format!("invalid port in bind address {entry:?}"))?;
+            Ok((host.to_string(), port))
+        }
+        None => Ok((entry.to_string(), default_port)),
+    }
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(bind: &[&str]) -> Config {
+        Config {
+            bind_addresses: bind.iter().map(|b| b.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn split_bind_addresses() {
+        let split = |e| split_bind_address(e, 5232).unwrap();
+        assert_eq!(split("127.0.0.1"), ("127.0.0.1".into(), 5232));
+        assert_eq!(split("0.0.0.0:8443"), ("0.0.0.0".into(), 8443));
+        assert_eq!(split("::1"), ("::1".into(), 5232));
+        assert_eq!(split("[::1]"), ("::1".into(), 5232));
+        assert_eq!(split("[::]:8443"), ("::".into(), 8443));
+        assert_eq!(split(" homeserver.local "), ("homeserver.local".into(), 5232));
+        assert_eq!(split("homeserver.local:80"), ("homeserver.local".into(), 80));
+        assert!(split_bind_address("host:port", 5232).is_err());
+        assert!(split_bind_address("", 5232).is_err());
+    }
+
+    #[test]
+    fn loopback_by_default() {
+        let addrs = Config::default().listen_addresses(false).unwrap();
+        assert_eq!(
+            addrs,
+            ["127.0.0.1:5232".parse::<SocketAddr>().unwrap(), "[::1]:5232".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn lan_mode_guard() {
+        let mut cfg = config(&["127.0.0.1", "0.0.0.0"]);
+        let err = cfg.listen_addresses(true).unwrap_err().to_string();
+        assert!(err.contains("lan_mode"), "{err}");
+
+        cfg.lan_mode = true;
+        assert_eq!(cfg.listen_addresses(true).unwrap().len(), 2);
+        let err = cfg.listen_addresses(false).unwrap_err().to_string();
+        assert!(err.contains("use_tls"), "{err}");
+
+        cfg.lan_allow_http = true;
+        assert_eq!(cfg.listen_addresses(false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn http_listener_next_to_https() {
+        let mut cfg = config(&["127.0.0.1", "[::1]:8443"]);
+        assert!(cfg.http_listen_addresses(true).unwrap().is_empty());
+
+        cfg.http_port = Some(8080);
+        assert!(cfg.http_listen_addresses(false).unwrap().is_empty());
+        assert_eq!(
+            cfg.http_listen_addresses(true).unwrap(),
+            ["127.0.0.1:8080".parse::<SocketAddr>().unwrap(), "[::1]:8080".parse().unwrap()]
+        );
+
+        cfg.http_port = Some(5232);
+        assert!(cfg.http_listen_addresses(true).is_err());
+        cfg.http_port = Some(8080);
+        cfg.tls_client_auth = true;
+        let err = cfg.http_listen_addresses(true).unwrap_err().to_string();
+        assert!(err.contains("redirect"), "{err}");
+        cfg.http_mode = HttpMode::Redirect;
+        assert_eq!(cfg.http_listen_addresses(true).unwrap().len(), 2);
+
+        // On the LAN, redirecting is fine; serving needs lan_allow_http.
+        cfg = config(&["0.0.0.0"]);
+        cfg.lan_mode = true;
+        cfg.http_port = Some(8080);
+        cfg.http_mode = HttpMode::WellKnown;
+        assert_eq!(cfg.http_listen_addresses(true).unwrap().len(), 1);
+        cfg.http_mode = HttpMode::Serve;
+        let err = cfg.http_listen_addresses(true).unwrap_err().to_string();
+        assert!(err.contains("lan_allow_http"), "{err}");
+
+        let mode: HttpMode = serde_json::from_str("\"well_known\"").unwrap();
+        assert_eq!(mode, HttpMode::WellKnown);
+    }
+
+    #[test]
+    fn carddav_username_is_open_in_old_configs() {
+        assert_eq!(Config::default().carddav_username.as_deref(), Some("setu"));
+        let old: Config = serde_json::from_str(r#"{"google_client_id": "id"}"#).unwrap();
+        assert_eq!(old.carddav_username, None);
+    }
+
+    #[test]
+    fn tls_names_cover_bound_hosts() {
+        let mut cfg = config(&["0.0.0.0", "192.168.1.10:8443", "localhost", "[::]"]);
+        cfg.tls_hostnames = vec!["homeserver.local".into(), " ".into()];
+        assert_eq!(
+            cfg.tls_names(),
+            ["localhost", "127.0.0.1", "::1", "192.168.1.10", "homeserver.local"]
+        );
+    }
+
+    #[test]
+    fn certificate_files_need_cert_and_key() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.certificate_files().unwrap(), None);
+
+        cfg.tls_cert_path = Some("/etc/ssl/setu.pem".into());
+        let err = cfg.certificate_files().unwrap_err().to_string();
+        assert!(err.contains("tls_key_path"), "{err}");
+
+        cfg.tls_key_path = Some("/etc/ssl/setu.key".into());
+        cfg.tls_chain_path = Some("/etc/ssl/chain.pem".into());
+        let files = cfg.certificate_files().unwrap().unwrap();
+        assert_eq!(files.key, PathBuf::from("/etc/ssl/setu.key"));
+        assert_eq!(files.chain, Some(PathBuf::from("/etc/ssl/chain.pem")));
     }
 }
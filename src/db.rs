@@ -1,7 +1,8 @@
 //! SQLite database layer — contacts cache + sync metadata.
 
 use anyhow::{Context, Result};
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::collections::HashSet;
 use std::path::PathBuf;
 
 /// Resolve the database path: `%APPDATA%/setu/setu.db` on Windows.
@@ -81,50 +82,147 @@ pub fn migrate_to_encrypted(encryption_key: &str) -> Result<()> {
     Ok(())
 }
 
-fn migrate(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
-        "
+/// Current schema.  Every table but `accounts` and `app_passwords` is
+/// partitioned by the Google account its rows belong to.
+const SCHEMA: &str = "
+        -- Google accounts (one CardDAV principal each).  The OAuth token
+        -- itself lives in the OS keyring.
+        CREATE TABLE IF NOT EXISTS accounts (
+            -- URL-safe account id, e.g. 'personal' (see valid_account_id)
+            id             TEXT PRIMARY KEY NOT NULL,
+            -- Google email of the authenticated user
+            google_email   TEXT NOT NULL DEFAULT '',
+            added_at       TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
         CREATE TABLE IF NOT EXISTS contacts (
+            account        TEXT NOT NULL,
             -- Google People API resource name, e.g. 'people/c123456'
-            resource_name  TEXT PRIMARY KEY NOT NULL,
+            resource_name  TEXT NOT NULL,
             -- Google etag for change detection
             etag           TEXT NOT NULL,
             -- Display name (cached for quick listing)
             display_name   TEXT NOT NULL DEFAULT '',
             -- Pre-rendered vCard 3.0 blob
             vcard          TEXT NOT NULL,
+            -- Pre-rendered vCard 4.0 blob ('' until the contact is next synced)
+            vcard4         TEXT NOT NULL DEFAULT '',
             -- Phone numbers with non-digit chars stripped, space-separated
             searchable_phone TEXT NOT NULL DEFAULT '',
             -- ISO-8601 timestamp of last Google update
-            updated_at     TEXT NOT NULL DEFAULT (datetime('now'))
+            updated_at     TEXT NOT NULL DEFAULT (datetime('now')),
+            -- When a contact the sync had not seen was cached outside it
+            -- (on-demand search, write-back); NULL once a full sync lists it
+            on_demand_at   TEXT,
+            PRIMARY KEY (account, resource_name)
         );
 
         CREATE TABLE IF NOT EXISTS sync_metadata (
-            id          INTEGER PRIMARY KEY CHECK (id = 1),
+            account     TEXT PRIMARY KEY NOT NULL,
             -- Google People API syncToken for incremental sync
             sync_token  TEXT,
             -- ISO-8601 timestamp of last successful sync
-            last_sync   TEXT
+            last_sync   TEXT,
+            -- Bumped on every real contact change; served as the CTag
+            change_counter INTEGER NOT NULL DEFAULT 0
         );
 
-        -- Ensure the singleton row exists.
-        INSERT OR IGNORE INTO sync_metadata (id) VALUES (1);
+        -- A sync run stopped between two pages of connections.list, to be
+        -- resumed at its page token (see SyncCheckpoint).
+        CREATE TABLE IF NOT EXISTS sync_checkpoints (
+            account     TEXT PRIMARY KEY NOT NULL,
+            -- Sync token the run lists changes since; NULL for a full sync
+            since_token TEXT,
+            -- RFC 3339 timestamp of the run's first request
+            started_at  TEXT NOT NULL,
+            -- pageToken of the next page
+            page_token  TEXT NOT NULL
+        );
 
-        CREATE TABLE IF NOT EXISTS oauth_tokens (
-            id            INTEGER PRIMARY KEY CHECK (id = 1),
-            -- Serialized yup-oauth2 token as JSON
-            token_json    TEXT NOT NULL,
-            -- Google email of the authenticated user
-            google_email  TEXT NOT NULL DEFAULT ''
+        -- Contacts the committed pages of an unfinished full sync listed.
+        CREATE TABLE IF NOT EXISTS sync_listed (
+            account        TEXT NOT NULL,
+            resource_name  TEXT NOT NULL,
+            PRIMARY KEY (account, resource_name)
+        );
+
+        -- Hrefs clients created contacts at (PUT to a new URL), mapped to
+        -- the resource name Google assigned.
+        CREATE TABLE IF NOT EXISTS contact_aliases (
+            account        TEXT NOT NULL,
+            -- Resource name derived from the client's href
+            alias          TEXT NOT NULL,
+            resource_name  TEXT NOT NULL,
+            PRIMARY KEY (account, alias)
+        );
+
+        -- Change log for RFC 6578 sync-collection: one row per contact and
+        -- address book, holding the sequence number of its latest change.
+        CREATE TABLE IF NOT EXISTS contact_changes (
+            -- Monotonic change sequence (encoded in the CardDAV sync token)
+            seq            INTEGER PRIMARY KEY AUTOINCREMENT,
+            account        TEXT NOT NULL,
+            -- '' = all contacts, otherwise a contact group resource name
+            book           TEXT NOT NULL DEFAULT '',
+            resource_name  TEXT NOT NULL,
+            -- 1 = deleted / left the book (tombstone), 0 = created / updated
+            deleted        INTEGER NOT NULL DEFAULT 0,
+            changed_at     TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE (account, book, resource_name)
+        );
+
+        -- Google contact groups ('Family', 'Work', starred …).
+        CREATE TABLE IF NOT EXISTS contact_groups (
+            account        TEXT NOT NULL,
+            -- e.g. 'contactGroups/starred' or 'contactGroups/1a2b3c'
+            resource_name  TEXT NOT NULL,
+            -- Display name (Google's formattedName)
+            name           TEXT NOT NULL DEFAULT '',
+            -- USER_CONTACT_GROUP or SYSTEM_CONTACT_GROUP
+            group_type     TEXT NOT NULL DEFAULT '',
+            PRIMARY KEY (account, resource_name)
         );
-        ",
-    )?;
 
-    // Migration: add google_email to oauth_tokens for existing databases.
+        CREATE TABLE IF NOT EXISTS group_memberships (
+            account                TEXT NOT NULL,
+            group_resource_name    TEXT NOT NULL,
+            contact_resource_name  TEXT NOT NULL,
+            PRIMARY KEY (account, group_resource_name, contact_resource_name)
+        );
+
+        -- Per-client CardDAV credentials (see app_password.rs).
+        CREATE TABLE IF NOT EXISTS app_passwords (
+            id             INTEGER PRIMARY KEY AUTOINCREMENT,
+            -- Basic Auth username the password is only valid with
+            username       TEXT NOT NULL UNIQUE,
+            -- Free-form description, e.g. 'Pixel 8'
+            label          TEXT NOT NULL DEFAULT '',
+            -- Argon2id hash in PHC string format
+            password_hash  TEXT NOT NULL,
+            -- Scope: NULL = every account / every address book
+            account        TEXT,
+            book           TEXT,
+            -- 1 = PUT / DELETE are refused
+            read_only      INTEGER NOT NULL DEFAULT 0,
+            created_at     TEXT NOT NULL DEFAULT (datetime('now')),
+            last_used_at   TEXT,
+            -- SHA-256 (hex) of the device's client certificate, if one was issued
+            client_cert_sha256  TEXT UNIQUE
+        );
+        ";
+
+fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA)?;
+
+    // Migration: add google_email to oauth_tokens for existing databases
+    // (folded into `accounts` below).
+    let has_oauth_table: bool = conn
+        .prepare("SELECT id FROM oauth_tokens LIMIT 0")
+        .is_ok();
     let has_email_col: bool = conn
         .prepare("SELECT google_email FROM oauth_tokens LIMIT 0")
         .is_ok();
-    if !has_email_col {
+    if has_oauth_table && !has_email_col {
         conn.execute_batch(
             "ALTER TABLE oauth_tokens ADD COLUMN google_email TEXT NOT NULL DEFAULT '';"
         )?;
@@ -140,15 +238,159 @@ fn migrate(conn: &Connection) -> Result<()> {
         )?;
     }
 
-    // Index for fast phone-number substring searches.
+    // Migration: add vcard4 to contacts.  Dropping the sync token makes
+    // the next sync a full one, which renders it for every contact.
+    let has_vcard4_col: bool = conn
+        .prepare("SELECT vcard4 FROM contacts LIMIT 0")
+        .is_ok();
+    if !has_vcard4_col {
+        conn.execute_batch(
+            "ALTER TABLE contacts ADD COLUMN vcard4 TEXT NOT NULL DEFAULT '';
+             UPDATE sync_metadata SET sync_token = NULL;"
+        )?;
+    }
+
+    // Migration: add on_demand_at to contacts.  Existing rows count as
+    // synced; the next full sync purges them if Google no longer has them.
+    let has_on_demand_col: bool = conn
+        .prepare("SELECT on_demand_at FROM contacts LIMIT 0")
+        .is_ok();
+    if !has_on_demand_col {
+        conn.execute_batch("ALTER TABLE contacts ADD COLUMN on_demand_at TEXT;")?;
+    }
+
+    // Migration: add change_counter to sync_metadata for existing databases.
+    let has_counter_col: bool = conn
+        .prepare("SELECT change_counter FROM sync_metadata LIMIT 0")
+        .is_ok();
+    if !has_counter_col {
+        conn.execute_batch(
+            "ALTER TABLE sync_metadata ADD COLUMN change_counter INTEGER NOT NULL DEFAULT 0;"
+        )?;
+    }
+
+    // Migration: app passwords gained a client certificate fingerprint.
+    let has_cert_col: bool = conn
+        .prepare("SELECT client_cert_sha256 FROM app_passwords LIMIT 0")
+        .is_ok();
+    if !has_cert_col {
+        // UNIQUE can't be added by ALTER TABLE; an index does the same.
+        conn.execute_batch(
+            "ALTER TABLE app_passwords ADD COLUMN client_cert_sha256 TEXT;
+             CREATE UNIQUE INDEX app_passwords_client_cert ON app_passwords (client_cert_sha256);"
+        )?;
+    }
+
+    // Migration: the change log gained a `book` column (one entry per
+    // contact *and* address book).  SQLite can't alter the UNIQUE
+    // constraint in place, so rebuild the table, keeping sequence numbers.
+    let has_book_col: bool = conn
+        .prepare("SELECT book FROM contact_changes LIMIT 0")
+        .is_ok();
+    if !has_book_col {
+        conn.execute_batch(
+            "ALTER TABLE contact_changes RENAME TO contact_changes_old;
+             CREATE TABLE contact_changes (
+                 seq            INTEGER PRIMARY KEY AUTOINCREMENT,
+                 book           TEXT NOT NULL DEFAULT '',
+                 resource_name  TEXT NOT NULL,
+                 deleted        INTEGER NOT NULL DEFAULT 0,
+                 changed_at     TEXT NOT NULL DEFAULT (datetime('now')),
+                 UNIQUE (book, resource_name)
+             );
+             INSERT INTO contact_changes (seq, book, resource_name, deleted, changed_at)
+                 SELECT seq, '', resource_name, deleted, changed_at FROM contact_changes_old;
+             DROP TABLE contact_changes_old;"
+        )?;
+    }
+
+    // Migration: single-account databases.  Every table gained an
+    // `account` key, which changes primary keys, so move the old tables
+    // aside, create the current schema and copy the rows over as
+    // DEFAULT_ACCOUNT.  The singleton `oauth_tokens` row becomes that
+    // account.
+    //
+    // All in one transaction, so a crash can't leave the old tables
+    // renamed next to an empty new schema.  IMMEDIATE takes the write lock
+    // before the check, so a connection opened at the same time waits and
+    // then finds the migration done.
+    let has_account_col = |conn: &Connection| {
+        conn.prepare("SELECT account FROM contacts LIMIT 0").is_ok()
+    };
+    if !has_account_col(conn) {
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        if !has_account_col(&tx) {
+            migrate_to_accounts(&tx)?;
+        }
+        tx.commit()?;
+    }
+
+    // Indexes for fast phone-number substring searches and membership
+    // lookups.  (Created last: on a migrated database the old ones went
+    // away with the old tables.)
     conn.execute_batch(
         "CREATE INDEX IF NOT EXISTS idx_contacts_searchable_phone
-         ON contacts(searchable_phone);"
+         ON contacts(searchable_phone);
+         CREATE INDEX IF NOT EXISTS idx_group_memberships_contact
+         ON group_memberships(account, contact_resource_name);"
     )?;
 
     Ok(())
 }
 
+/// The single- to multi-account step of [`migrate`].
+fn migrate_to_accounts(conn: &Connection) -> Result<()> {
+    let has_oauth_table: bool = conn
+        .prepare("SELECT id FROM oauth_tokens LIMIT 0")
+        .is_ok();
+    conn.execute_batch(
+        "ALTER TABLE contacts RENAME TO contacts_v1;
+         ALTER TABLE sync_metadata RENAME TO sync_metadata_v1;
+         ALTER TABLE contact_aliases RENAME TO contact_aliases_v1;
+         ALTER TABLE contact_changes RENAME TO contact_changes_v1;
+         ALTER TABLE contact_groups RENAME TO contact_groups_v1;
+         ALTER TABLE group_memberships RENAME TO group_memberships_v1;",
+    )?;
+    conn.execute_batch(SCHEMA)?;
+    if !has_oauth_table {
+        conn.execute_batch(
+            "CREATE TABLE oauth_tokens (id INTEGER PRIMARY KEY, google_email TEXT NOT NULL DEFAULT '');",
+        )?;
+    }
+    conn.execute_batch(&format!(
+        "INSERT INTO accounts (id, google_email)
+             SELECT '{DEFAULT_ACCOUNT}', COALESCE((SELECT google_email FROM oauth_tokens), '')
+             WHERE EXISTS (SELECT 1 FROM oauth_tokens) OR EXISTS (SELECT 1 FROM contacts_v1);
+         INSERT INTO contacts (account, resource_name, etag, display_name, vcard, vcard4,
+                               searchable_phone, updated_at)
+             SELECT '{DEFAULT_ACCOUNT}', resource_name, etag, display_name, vcard, vcard4,
+                    searchable_phone, updated_at
+             FROM contacts_v1;
+         INSERT INTO sync_metadata (account, sync_token, last_sync, change_counter)
+             SELECT '{DEFAULT_ACCOUNT}', sync_token, last_sync, change_counter
+             FROM sync_metadata_v1;
+         INSERT INTO contact_aliases (account, alias, resource_name)
+             SELECT '{DEFAULT_ACCOUNT}', alias, resource_name FROM contact_aliases_v1;
+         INSERT INTO contact_changes (seq, account, book, resource_name, deleted, changed_at)
+             SELECT seq, '{DEFAULT_ACCOUNT}', book, resource_name, deleted, changed_at
+             FROM contact_changes_v1;
+         INSERT INTO contact_groups (account, resource_name, name, group_type)
+             SELECT '{DEFAULT_ACCOUNT}', resource_name, name, group_type FROM contact_groups_v1;
+         INSERT INTO group_memberships (account, group_resource_name, contact_resource_name)
+             SELECT '{DEFAULT_ACCOUNT}', group_resource_name, contact_resource_name
+             FROM group_memberships_v1;
+         DROP TABLE contacts_v1;
+         DROP TABLE sync_metadata_v1;
+         DROP TABLE contact_aliases_v1;
+         DROP TABLE contact_changes_v1;
+         DROP TABLE contact_groups_v1;
+         DROP TABLE group_memberships_v1;
+         DROP TABLE oauth_tokens;"
+    ))?;
+    tracing::info!("migrated database to per-account storage");
+    Ok(())
+}
+
 // ── Phone normalization ──────────────────────────────────────────────────
 
 /// Strip a phone number to digits only, preserving a leading `+`.
@@ -160,23 +402,225 @@ fn migrate(conn: &Connection) -> Result<()> {
 pub fn normalize_phone(raw: &str) -> String {
     let mut out = String::with_capacity(raw.len());
     for (i, ch) in raw.chars().enumerate() {
-        if ch.is_ascii_digit() {
-            out.push(ch);
-        } else if ch == '+' && i == 0 {
+        if ch.is_ascii_digit() || (ch == '+' && i == 0) {
             out.push(ch);
         }
     }
     out
 }
 
+// ── Accounts ─────────────────────────────────────────────────────────────
+
+/// Account id of a database migrated from a single-account install, and
+/// of the first account signed in.  Its OAuth token keeps the original
+/// keyring entry and token file.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// A Google account registered with setu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    /// URL-safe id, used in `/principals/<id>/` and `/addressbooks/<id>/`.
+    pub id: String,
+    /// Google email ('' until fetched after sign-in).
+    pub google_email: String,
+}
+
+/// Returns `true` if `id` can name an account: 1–32 lowercase ASCII
+/// letters, digits, `-` or `_`.  `contacts` is reserved for the
+/// all-contacts address book, which shares the legacy URL space.
+pub fn valid_account_id(id: &str) -> bool {
+    (1..=32).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+        && id != "contacts"
+}
+
+/// Register an account (no-op if it exists).  A non-empty `google_email`
+/// replaces the stored one.
+pub fn add_account(conn: &Connection, id: &str, google_email: &str) -> Result<()> {
+    anyhow::ensure!(valid_account_id(id), "invalid account name {id:?}");
+    conn.execute(
+        "INSERT INTO accounts (id, google_email) VALUES (?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET google_email = excluded.google_email
+         WHERE excluded.google_email <> ''",
+        params![id, google_email],
+    )?;
+    Ok(())
+}
+
+/// All registered accounts, in the order they were added.
+pub fn accounts(conn: &Connection) -> Result<Vec<Account>> {
+    let mut stmt = conn.prepare("SELECT id, google_email FROM accounts ORDER BY added_at, rowid")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(Account {
+                id: row.get(0)?,
+                google_email: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// The account served at the unqualified (pre-multi-account) URLs: the
+/// first one added, or [`DEFAULT_ACCOUNT`] if there are none yet.
+pub fn default_account(conn: &Connection) -> Result<String> {
+    Ok(accounts(conn)?
+        .into_iter()
+        .next()
+        .map(|a| a.id)
+        .unwrap_or_else(|| DEFAULT_ACCOUNT.to_string()))
+}
+
+/// Remove an account together with its contacts, groups and sync state.
+pub fn remove_account(conn: &Connection, account: &str) -> Result<()> {
+    for table in [
+        "contacts",
+        "sync_metadata",
+        "sync_checkpoints",
+        "sync_listed",
+        "contact_aliases",
+        "contact_changes",
+        "contact_groups",
+        "group_memberships",
+    ] {
+        conn.execute(&format!("DELETE FROM {table} WHERE account = ?1"), params![account])?;
+    }
+    conn.execute("DELETE FROM accounts WHERE id = ?1", params![account])?;
+    Ok(())
+}
+
+/// Get the stored Google email of an account.
+pub fn get_google_email(conn: &Connection, account: &str) -> Result<Option<String>> {
+    let result: Option<String> = conn
+        .query_row(
+            "SELECT google_email FROM accounts WHERE id = ?1",
+            params![account],
+            |row| row.get(0),
+        )
+        .optional()?;
+    // Treat empty string as None.
+    Ok(result.filter(|s| !s.is_empty()))
+}
+
+// ── App passwords ────────────────────────────────────────────────────────
+
+/// A stored app password (without its hash).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPassword {
+    pub username: String,
+    pub label: String,
+    /// Only this account's principal and address books, if set.
+    pub account: Option<String>,
+    /// Only address books with this id (`contacts` or a group id), if set.
+    pub book: Option<String>,
+    pub read_only: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+const APP_PASSWORD_COLUMNS: &str =
+    "username, label, account, book, read_only, created_at, last_used_at";
+
+fn app_password_from_row(row: &rusqlite::Row) -> rusqlite::Result<AppPassword> {
+    Ok(AppPassword {
+        username: row.get(0)?,
+        label: row.get(1)?,
+        account: row.get(2)?,
+        book: row.get(3)?,
+        read_only: row.get(4)?,
+        created_at: row.get(5)?,
+        last_used_at: row.get(6)?,
+    })
+}
+
+/// Store a new app password.  Fails if `username` is already taken.
+pub fn insert_app_password(conn: &Connection, password: &AppPassword, password_hash: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO app_passwords (username, label, password_hash, account, book, read_only)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            password.username,
+            password.label,
+            password_hash,
+            password.account,
+            password.book,
+            password.read_only,
+        ],
+    )
+    .with_context(|| format!("app password {:?} already exists", password.username))?;
+    Ok(())
+}
+
+/// All app passwords, oldest first.
+pub fn app_passwords(conn: &Connection) -> Result<Vec<AppPassword>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {APP_PASSWORD_COLUMNS} FROM app_passwords ORDER BY id"
+    ))?;
+    let rows = stmt
+        .query_map([], app_password_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// The app password of `username` together with its hash.
+pub fn app_password(conn: &Connection, username: &str) -> Result<Option<(AppPassword, String)>> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {APP_PASSWORD_COLUMNS}, password_hash FROM app_passwords WHERE username = ?1"),
+            params![username],
+            |row| Ok((app_password_from_row(row)?, row.get(7)?)),
+        )
+        .optional()?)
+}
+
+/// Record a successful login.  Written at most once a minute per
+/// password, since clients authenticate every request.
+pub fn touch_app_password(conn: &Connection, username: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE app_passwords SET last_used_at = datetime('now')
+         WHERE username = ?1
+           AND (last_used_at IS NULL OR last_used_at < datetime('now', '-1 minute'))",
+        params![username],
+    )?;
+    Ok(())
+}
+
+/// Register the client certificate issued for `username`'s device,
+/// replacing any earlier one.  Returns `false` if there is no such app
+/// password.
+pub fn set_client_certificate(conn: &Connection, username: &str, sha256: &str) -> Result<bool> {
+    Ok(conn.execute(
+        "UPDATE app_passwords SET client_cert_sha256 = ?2 WHERE username = ?1",
+        params![username, sha256],
+    )? > 0)
+}
+
+/// The app password a client certificate was issued for.
+pub fn app_password_by_certificate(conn: &Connection, sha256: &str) -> Result<Option<AppPassword>> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {APP_PASSWORD_COLUMNS} FROM app_passwords WHERE client_cert_sha256 = ?1"),
+            params![sha256],
+            app_password_from_row,
+        )
+        .optional()?)
+}
+
+/// Delete an app password.  Returns `false` if there was none.
+pub fn revoke_app_password(conn: &Connection, username: &str) -> Result<bool> {
+    Ok(conn.execute("DELETE FROM app_passwords WHERE username = ?1", params![username])? > 0)
+}
+
 // ── Query helpers ────────────────────────────────────────────────────────
 
-/// Get the current sync token (None on first run).
-pub fn get_sync_token(conn: &Connection) -> Result<Option<String>> {
+/// Get the current sync token of `account` (None on first run).
+pub fn get_sync_token(conn: &Connection, account: &str) -> Result<Option<String>> {
     let token = conn
         .query_row(
-            "SELECT sync_token FROM sync_metadata WHERE id = 1",
-            [],
+            "SELECT sync_token FROM sync_metadata WHERE account = ?1",
+            params![account],
             |row| row.get(0),
         )
         .optional()?
@@ -185,69 +629,581 @@ pub fn get_sync_token(conn: &Connection) -> Result<Option<String>> {
 }
 
 /// Persist a new sync token after a successful sync.
-pub fn set_sync_token(conn: &Connection, token: &str) -> Result<()> {
+pub fn set_sync_token(conn: &Connection, account: &str, token: &str) -> Result<()> {
     conn.execute(
-        "UPDATE sync_metadata SET sync_token = ?1, last_sync = datetime('now') WHERE id = 1",
-        params![token],
+        "INSERT INTO sync_metadata (account, sync_token, last_sync)
+         VALUES (?1, ?2, datetime('now'))
+         ON CONFLICT(account) DO UPDATE SET
+             sync_token = excluded.sync_token,
+             last_sync  = excluded.last_sync",
+        params![account, token],
+    )?;
+    Ok(())
+}
+
+/// Current value of an account's change counter (its address books' CTag).
+pub fn change_counter(conn: &Connection, account: &str) -> Result<i64> {
+    let counter = conn
+        .query_row(
+            "SELECT change_counter FROM sync_metadata WHERE account = ?1",
+            params![account],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(counter.unwrap_or(0))
+}
+
+fn bump_change_counter(conn: &Connection, account: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_metadata (account, change_counter) VALUES (?1, 1)
+         ON CONFLICT(account) DO UPDATE SET change_counter = change_counter + 1",
+        params![account],
     )?;
     Ok(())
 }
 
 /// Upsert a contact row.
+///
+/// Rewriting a contact with the same etag and vCard is a no-op: the row,
+/// the change log and the change counter are left untouched.  Returns
+/// `true` if anything was actually written.
 pub fn upsert_contact(
     conn: &Connection,
+    account: &str,
     resource_name: &str,
     etag: &str,
     display_name: &str,
     vcard: &str,
     searchable_phone: &str,
-) -> Result<()> {
-    conn.execute(
-        "INSERT INTO contacts (resource_name, etag, display_name, vcard, searchable_phone, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))
-         ON CONFLICT(resource_name) DO UPDATE SET
+) -> Result<bool> {
+    let written = conn.execute(
+        "INSERT INTO contacts (account, resource_name, etag, display_name, vcard, searchable_phone, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))
+         ON CONFLICT(account, resource_name) DO UPDATE SET
              etag             = excluded.etag,
              display_name     = excluded.display_name,
              vcard            = excluded.vcard,
              searchable_phone = excluded.searchable_phone,
-             updated_at       = excluded.updated_at",
-        params![resource_name, etag, display_name, vcard, searchable_phone],
+             updated_at       = excluded.updated_at
+         WHERE contacts.etag <> excluded.etag OR contacts.vcard <> excluded.vcard",
+        params![account, resource_name, etag, display_name, vcard, searchable_phone],
+    )?;
+    if written == 0 {
+        return Ok(false);
+    }
+    log_change(conn, account, "", resource_name, false)?;
+    for group in groups_of(conn, account, resource_name)? {
+        log_change(conn, account, &group, resource_name, false)?;
+    }
+    bump_change_counter(conn, account)?;
+    Ok(true)
+}
+
+/// Store the vCard 4.0 rendering of a contact written by [`upsert_contact`].
+pub fn set_vcard4(conn: &Connection, account: &str, resource_name: &str, vcard4: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contacts SET vcard4 = ?3 WHERE account = ?1 AND resource_name = ?2",
+        params![account, resource_name, vcard4],
     )?;
     Ok(())
 }
 
+/// A contact's vCard 4.0, or `None` if it has none stored yet.
+pub fn get_vcard4(conn: &Connection, account: &str, resource_name: &str) -> Result<Option<String>> {
+    let vcard4: Option<String> = conn
+        .query_row(
+            "SELECT vcard4 FROM contacts WHERE account = ?1 AND resource_name = ?2",
+            params![account, resource_name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(vcard4.filter(|v| !v.is_empty()))
+}
+
 /// Delete a contact by resource name (used for sync deletions).
-pub fn delete_contact(conn: &Connection, resource_name: &str) -> Result<()> {
+///
+/// Leaves a tombstone in the change log of every address book the
+/// contact was in, so sync-collection clients learn about the deletion.
+pub fn delete_contact(conn: &Connection, account: &str, resource_name: &str) -> Result<()> {
+    let removed = conn.execute(
+        "DELETE FROM contacts WHERE account = ?1 AND resource_name = ?2",
+        params![account, resource_name],
+    )?;
+    if removed > 0 {
+        log_change(conn, account, "", resource_name, true)?;
+        for group in groups_of(conn, account, resource_name)? {
+            log_change(conn, account, &group, resource_name, true)?;
+        }
+        bump_change_counter(conn, account)?;
+    }
+    conn.execute(
+        "DELETE FROM group_memberships WHERE account = ?1 AND contact_resource_name = ?2",
+        params![account, resource_name],
+    )?;
+    conn.execute(
+        "DELETE FROM contact_aliases WHERE account = ?1 AND resource_name = ?2",
+        params![account, resource_name],
+    )?;
+    Ok(())
+}
+
+/// Remember that a client created `resource_name` at the href of `alias`.
+///
+/// The alias is logged as deleted in every address book the contact is
+/// in, so sync-collection clients swap their copy for the real href.
+pub fn add_alias(conn: &Connection, account: &str, alias: &str, resource_name: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO contact_aliases (account, alias, resource_name) VALUES (?1, ?2, ?3)",
+        params![account, alias, resource_name],
+    )?;
+    log_change(conn, account, "", alias, true)?;
+    for group in groups_of(conn, account, resource_name)? {
+        log_change(conn, account, &group, alias, true)?;
+    }
+    bump_change_counter(conn, account)?;
+    Ok(())
+}
+
+/// The resource name `alias` stands for, or `alias` itself.
+pub fn resolve_alias(conn: &Connection, account: &str, alias: &str) -> Result<String> {
+    let found = conn
+        .query_row(
+            "SELECT resource_name FROM contact_aliases WHERE account = ?1 AND alias = ?2",
+            params![account, alias],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(found.unwrap_or_else(|| alias.to_string()))
+}
+
+/// Mark a contact just cached outside the sync (on-demand search,
+/// write-back) so a full sync running meanwhile doesn't purge it.
+pub fn mark_on_demand(conn: &Connection, account: &str, resource_name: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE contacts SET on_demand_at = datetime('now') WHERE account = ?1 AND resource_name = ?2",
+        params![account, resource_name],
+    )?;
+    Ok(())
+}
+
+// ── Full-sync reconciliation ────────────────────────────────────────────
+
+/// What [`reconcile_contacts`] did, for the sync log.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Synced contacts Google no longer lists, deleted.
+    pub purged: usize,
+    /// On-demand contacts cached before the listing started and missing
+    /// from it, deleted.
+    pub purged_on_demand: usize,
+    /// On-demand contacts cached while the listing ran, kept for the next
+    /// full sync to decide.
+    pub kept_on_demand: usize,
+    /// On-demand contacts the listing contained, now treated as synced.
+    pub adopted: usize,
+}
+
+/// Make an account's contacts match a complete `connections.list` result:
+/// `listed` are the resource names it returned, `started` when the first
+/// page was requested.
+///
+/// Synced contacts missing from `listed` were deleted in Google and go,
+/// with tombstones for sync-collection clients.  Contacts cached on demand
+/// (see [`mark_on_demand`]) are only purged if they were cached before
+/// `started` — anything newer may be missing merely because the listing
+/// had already passed it.  Listed on-demand contacts become synced ones.
+pub fn reconcile_contacts(
+    conn: &Connection,
+    account: &str,
+    listed: &HashSet<String>,
+    started: chrono::DateTime<chrono::Utc>,
+) -> Result<Reconciliation> {
+    let started = started.format("%Y-%m-%d %H:%M:%S").to_string();
+    let stored: Vec<(String, Option<String>)> = {
+        let mut stmt = conn.prepare("SELECT resource_name, on_demand_at FROM contacts WHERE account = ?1")?;
+        let rows = stmt
+            .query_map(params![account], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows
+    };
+
+    let mut result = Reconciliation::default();
+    for (resource_name, on_demand_at) in stored {
+        let listed = listed.contains(&resource_name);
+        match on_demand_at {
+            None if listed => {}
+            None => {
+                delete_contact(conn, account, &resource_name)?;
+                result.purged += 1;
+            }
+            Some(_) if listed => {
+                conn.execute(
+                    "UPDATE contacts SET on_demand_at = NULL WHERE account = ?1 AND resource_name = ?2",
+                    params![account, resource_name],
+                )?;
+                result.adopted += 1;
+            }
+            // Same-second timestamps count as cached during the listing.
+            Some(cached_at) if cached_at >= started => result.kept_on_demand += 1,
+            Some(_) => {
+                delete_contact(conn, account, &resource_name)?;
+                result.purged_on_demand += 1;
+            }
+        }
+    }
+    Ok(result)
+}
+
+// ── Sync checkpoints ────────────────────────────────────────────────────
+
+/// Where an interrupted sync run resumes.  Written with every page but
+/// the last, which clears it together with the switch to the new sync
+/// token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncCheckpoint {
+    /// Sync token the run lists changes since; `None` for a full sync.
+    pub since: Option<String>,
+    /// When the run's first page was requested.
+    pub started: chrono::DateTime<chrono::Utc>,
+    /// `pageToken` of the next page.
+    pub page_token: String,
+}
+
+/// The checkpoint of `account`'s unfinished sync run, if any.
+pub fn get_checkpoint(conn: &Connection, account: &str) -> Result<Option<SyncCheckpoint>> {
+    let row: Option<(Option<String>, String, String)> = conn
+        .query_row(
+            "SELECT since_token, started_at, page_token FROM sync_checkpoints WHERE account = ?1",
+            params![account],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((since, started, page_token)) = row else {
+        return Ok(None);
+    };
+    let started = chrono::DateTime::parse_from_rfc3339(&started)
+        .context("invalid sync checkpoint timestamp")?
+        .with_timezone(&chrono::Utc);
+    Ok(Some(SyncCheckpoint { since, started, page_token }))
+}
+
+/// Record how far `account`'s sync run got.
+pub fn set_checkpoint(conn: &Connection, account: &str, checkpoint: &SyncCheckpoint) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_checkpoints (account, since_token, started_at, page_token)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(account) DO UPDATE SET
+             since_token = excluded.since_token,
+             started_at  = excluded.started_at,
+             page_token  = excluded.page_token",
+        params![account, checkpoint.since, checkpoint.started.to_rfc3339(), checkpoint.page_token],
+    )?;
+    Ok(())
+}
+
+/// Forget `account`'s checkpoint and the contacts its full sync listed.
+pub fn clear_checkpoint(conn: &Connection, account: &str) -> Result<()> {
+    conn.execute("DELETE FROM sync_checkpoints WHERE account = ?1", params![account])?;
+    conn.execute("DELETE FROM sync_listed WHERE account = ?1", params![account])?;
+    Ok(())
+}
+
+/// Note that a page of the running full sync listed `resource_name`.
+pub fn add_listed(conn: &Connection, account: &str, resource_name: &str) -> Result<()> {
     conn.execute(
-        "DELETE FROM contacts WHERE resource_name = ?1",
-        params![resource_name],
+        "INSERT OR IGNORE INTO sync_listed (account, resource_name) VALUES (?1, ?2)",
+        params![account, resource_name],
+    )?;
+    Ok(())
+}
+
+/// Every contact the running full sync listed so far, for
+/// [`reconcile_contacts`].
+pub fn listed_contacts(conn: &Connection, account: &str) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT resource_name FROM sync_listed WHERE account = ?1")?;
+    let listed = stmt
+        .query_map(params![account], |row| row.get(0))?
+        .collect::<Result<HashSet<String>, _>>()?;
+    Ok(listed)
+}
+
+// ── Change log (sync-collection) ────────────────────────────────────────
+
+/// Record a change to `resource_name` in address book `book` (`""` for
+/// all contacts, else a group resource name), replacing any earlier entry
+/// so the log holds exactly one row (the latest sequence) per contact and
+/// book.
+fn log_change(
+    conn: &Connection,
+    account: &str,
+    book: &str,
+    resource_name: &str,
+    deleted: bool,
+) -> Result<()> {
+    conn.execute(
+        "DELETE FROM contact_changes WHERE account = ?1 AND book = ?2 AND resource_name = ?3",
+        params![account, book, resource_name],
+    )?;
+    conn.execute(
+        "INSERT INTO contact_changes (account, book, resource_name, deleted) VALUES (?1, ?2, ?3, ?4)",
+        params![account, book, resource_name, deleted],
+    )?;
+    Ok(())
+}
+
+/// Sequence number of the most recent change in any address book of any
+/// account (0 if nothing changed yet).
+pub fn current_change_seq(conn: &Connection) -> Result<i64> {
+    let seq = conn.query_row(
+        "SELECT COALESCE(MAX(seq), 0) FROM contact_changes",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(seq)
+}
+
+/// Sequence number of the most recent change in address book `book`.
+pub fn book_change_seq(conn: &Connection, account: &str, book: &str) -> Result<i64> {
+    let seq = conn.query_row(
+        "SELECT COALESCE(MAX(seq), 0) FROM contact_changes WHERE account = ?1 AND book = ?2",
+        params![account, book],
+        |row| row.get(0),
+    )?;
+    Ok(seq)
+}
+
+/// One change-log entry: `(resource_name, Some((etag, vcard)))` for a
+/// created / updated contact, `(resource_name, None)` for a deletion.
+pub type ContactChange = (String, Option<(String, String)>);
+
+/// Contacts changed in address book `book` after sequence `since`, oldest
+/// change first.
+pub fn changes_since(
+    conn: &Connection,
+    account: &str,
+    book: &str,
+    since: i64,
+) -> Result<Vec<ContactChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT ch.resource_name, ch.deleted, c.etag, c.vcard
+         FROM contact_changes ch
+         LEFT JOIN contacts c
+             ON c.account = ch.account AND c.resource_name = ch.resource_name
+         WHERE ch.account = ?1 AND ch.book = ?2 AND ch.seq > ?3
+         ORDER BY ch.seq",
     )?;
+    let rows = stmt
+        .query_map(params![account, book, since], |row| {
+            let rn: String = row.get(0)?;
+            let deleted: bool = row.get(1)?;
+            let etag: Option<String> = row.get(2)?;
+            let vcard: Option<String> = row.get(3)?;
+            Ok((rn, etag.zip(vcard).filter(|_| !deleted)))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// ── Contact groups ──────────────────────────────────────────────────────
+
+/// A Google contact group as cached locally.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredGroup {
+    pub resource_name: String,
+    pub name: String,
+    pub group_type: String,
+}
+
+/// Replace an account's cached contact groups with `groups`.  Groups that
+/// no longer exist lose their memberships and change log.
+pub fn replace_contact_groups(conn: &Connection, account: &str, groups: &[StoredGroup]) -> Result<()> {
+    let existing: Vec<String> = contact_groups(conn, account)?
+        .into_iter()
+        .map(|g| g.resource_name)
+        .collect();
+    for gone in existing
+        .iter()
+        .filter(|rn| !groups.iter().any(|g| &g.resource_name == *rn))
+    {
+        conn.execute(
+            "DELETE FROM contact_groups WHERE account = ?1 AND resource_name = ?2",
+            params![account, gone],
+        )?;
+        conn.execute(
+            "DELETE FROM group_memberships WHERE account = ?1 AND group_resource_name = ?2",
+            params![account, gone],
+        )?;
+        conn.execute(
+            "DELETE FROM contact_changes WHERE account = ?1 AND book = ?2",
+            params![account, gone],
+        )?;
+    }
+    for group in groups {
+        conn.execute(
+            "INSERT INTO contact_groups (account, resource_name, name, group_type) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(account, resource_name) DO UPDATE SET
+                 name       = excluded.name,
+                 group_type = excluded.group_type",
+            params![account, group.resource_name, group.name, group.group_type],
+        )?;
+    }
     Ok(())
 }
 
-/// Return all vCards as (resource_name, etag, vcard) tuples.
-pub fn all_contacts(conn: &Connection) -> Result<Vec<(String, String, String)>> {
-    let mut stmt =
-        conn.prepare("SELECT resource_name, etag, vcard FROM contacts ORDER BY display_name")?;
+/// All cached contact groups of an account, ordered by name.
+pub fn contact_groups(conn: &Connection, account: &str) -> Result<Vec<StoredGroup>> {
+    let mut stmt = conn.prepare(
+        "SELECT resource_name, name, group_type FROM contact_groups
+         WHERE account = ?1 ORDER BY name",
+    )?;
     let rows = stmt
-        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .query_map(params![account], |row| {
+            Ok(StoredGroup {
+                resource_name: row.get(0)?,
+                name: row.get(1)?,
+                group_type: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Group resource names `resource_name` is a member of.
+fn groups_of(conn: &Connection, account: &str, resource_name: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT group_resource_name FROM group_memberships
+         WHERE account = ?1 AND contact_resource_name = ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![account, resource_name], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(rows)
+}
+
+/// Set the groups a contact belongs to.  Joining a group is logged as a
+/// change in that group's address book, leaving it as a tombstone.
+pub fn set_memberships(
+    conn: &Connection,
+    account: &str,
+    resource_name: &str,
+    groups: &[String],
+) -> Result<()> {
+    let current = groups_of(conn, account, resource_name)?;
+    for left in current.iter().filter(|g| !groups.contains(g)) {
+        remove_membership(conn, account, left, resource_name)?;
+    }
+    for joined in groups.iter().filter(|g| !current.contains(g)) {
+        conn.execute(
+            "INSERT OR IGNORE INTO group_memberships (account, group_resource_name, contact_resource_name)
+             VALUES (?1, ?2, ?3)",
+            params![account, joined, resource_name],
+        )?;
+        log_change(conn, account, joined, resource_name, false)?;
+    }
+    Ok(())
+}
+
+/// Take a contact out of one group, leaving a tombstone in its book.
+pub fn remove_membership(
+    conn: &Connection,
+    account: &str,
+    group: &str,
+    resource_name: &str,
+) -> Result<()> {
+    let removed = conn.execute(
+        "DELETE FROM group_memberships
+         WHERE account = ?1 AND group_resource_name = ?2 AND contact_resource_name = ?3",
+        params![account, group, resource_name],
+    )?;
+    if removed > 0 {
+        log_change(conn, account, group, resource_name, true)?;
+    }
+    Ok(())
+}
+
+/// Returns `true` if `resource_name` is a member of `group`.
+pub fn is_member(conn: &Connection, account: &str, group: &str, resource_name: &str) -> Result<bool> {
+    let found = conn
+        .query_row(
+            "SELECT 1 FROM group_memberships
+             WHERE account = ?1 AND group_resource_name = ?2 AND contact_resource_name = ?3",
+            params![account, group, resource_name],
+            |_| Ok(()),
+        )
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// All contacts in `group` as `(resource_name, etag, vcard)`.
+pub fn group_contacts(
+    conn: &Connection,
+    account: &str,
+    group: &str,
+) -> Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT c.resource_name, c.etag, c.vcard
+         FROM contacts c
+         JOIN group_memberships m
+             ON m.account = c.account AND m.contact_resource_name = c.resource_name
+         WHERE c.account = ?1 AND m.group_resource_name = ?2
+         ORDER BY c.display_name",
+    )?;
+    let rows = stmt
+        .query_map(params![account, group], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Return all of an account's vCards as (resource_name, etag, vcard) tuples.
+pub fn all_contacts(conn: &Connection, account: &str) -> Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT resource_name, etag, vcard FROM contacts WHERE account = ?1 ORDER BY display_name",
+    )?;
+    let rows = stmt
+        .query_map(params![account], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
         .collect::<Result<Vec<_>, _>>()?;
     Ok(rows)
 }
 
 /// Look up a single contact's vCard by resource name.
-pub fn get_contact(conn: &Connection, resource_name: &str) -> Result<Option<(String, String)>> {
+pub fn get_contact(
+    conn: &Connection,
+    account: &str,
+    resource_name: &str,
+) -> Result<Option<(String, String)>> {
     let result = conn
         .query_row(
-            "SELECT etag, vcard FROM contacts WHERE resource_name = ?1",
-            params![resource_name],
+            "SELECT etag, vcard FROM contacts WHERE account = ?1 AND resource_name = ?2",
+            params![account, resource_name],
             |row| Ok((row.get(0)?, row.get(1)?)),
         )
         .optional()?;
     Ok(result)
 }
 
-/// Search for contacts by normalized phone number.
+/// When a contact's row last changed (its `updated_at`, which
+/// [`upsert_contact`] only moves on a real change), for `Last-Modified`.
+pub fn contact_modified(
+    conn: &Connection,
+    account: &str,
+    resource_name: &str,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let updated_at: Option<String> = conn
+        .query_row(
+            "SELECT updated_at FROM contacts WHERE account = ?1 AND resource_name = ?2",
+            params![account, resource_name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    // SQLite's datetime('now') is UTC without a zone suffix.
+    Ok(updated_at.and_then(|s| {
+        chrono::NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .map(|t| t.and_utc())
+    }))
+}
+
+/// Search an account's contacts by normalized phone number.
 ///
 /// Matches any contact that has a phone number ending with the same digits.
 /// For example, searching for `4156466123` will match `+14156466123` and
@@ -260,6 +1216,7 @@ pub fn get_contact(conn: &Connection, resource_name: &str) -> Result<Option<(Str
 /// Returns all matching `(resource_name, etag, vcard)` tuples.
 pub fn search_by_phone(
     conn: &Connection,
+    account: &str,
     normalized_number: &str,
 ) -> Result<Vec<(String, String, String)>> {
     if normalized_number.is_empty() {
@@ -275,11 +1232,11 @@ pub fn search_by_phone(
     let pattern = format!("%{query_digits}%");
     let mut stmt = conn.prepare(
         "SELECT resource_name, etag, vcard, searchable_phone FROM contacts
-         WHERE searchable_phone LIKE ?1
+         WHERE account = ?1 AND searchable_phone LIKE ?2
          ORDER BY display_name",
     )?;
     let candidates = stmt
-        .query_map(params![pattern], |row| {
+        .query_map(params![account, pattern], |row| {
             Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, String>(3)?))
         })?
         .collect::<Result<Vec<(String, String, String, String)>, _>>()?;
@@ -299,60 +1256,6 @@ pub fn search_by_phone(
     Ok(results)
 }
 
-// ── OAuth token persistence ─────────────────────────────────────────────
-
-/// Store the OAuth2 token JSON and (optionally) the user's Google email.
-pub fn store_oauth_token(conn: &Connection, token_json: &str, google_email: &str) -> Result<()> {
-    conn.execute(
-        "INSERT OR REPLACE INTO oauth_tokens (id, token_json, google_email)
-         VALUES (1, ?1, ?2)",
-        params![token_json, google_email],
-    )?;
-    Ok(())
-}
-
-/// Retrieve the stored OAuth2 token JSON (if any).
-pub fn get_oauth_token(conn: &Connection) -> Result<Option<String>> {
-    let result = conn
-        .query_row(
-            "SELECT token_json FROM oauth_tokens WHERE id = 1",
-            [],
-            |row| row.get(0),
-        )
-        .optional()?;
-    Ok(result)
-}
-
-/// Returns `true` if an OAuth2 token is stored.
-pub fn has_oauth_token(conn: &Connection) -> bool {
-    conn.query_row(
-        "SELECT COUNT(*) FROM oauth_tokens WHERE id = 1",
-        [],
-        |row| row.get::<_, i64>(0),
-    )
-    .unwrap_or(0)
-        > 0
-}
-
-/// Remove the stored OAuth2 token (logout).
-pub fn clear_oauth_token(conn: &Connection) -> Result<()> {
-    conn.execute("DELETE FROM oauth_tokens WHERE id = 1", [])?;
-    Ok(())
-}
-
-/// Get the stored Google email of the authenticated user.
-pub fn get_google_email(conn: &Connection) -> Result<Option<String>> {
-    let result: Option<String> = conn
-        .query_row(
-            "SELECT google_email FROM oauth_tokens WHERE id = 1",
-            [],
-            |row| row.get(0),
-        )
-        .optional()?;
-    // Treat empty string as None.
-    Ok(result.filter(|s| !s.is_empty()))
-}
-
 /// Open an in-memory database (for testing).
 #[cfg(test)]
 pub fn open_in_memory() -> Result<Connection> {
@@ -367,6 +1270,8 @@ pub fn open_in_memory() -> Result<Connection> {
 mod tests {
     use super::*;
 
+    const ACCOUNT: &str = DEFAULT_ACCOUNT;
+
     #[test]
     fn migrate_is_idempotent() {
         let conn = open_in_memory().unwrap();
@@ -380,26 +1285,55 @@ mod tests {
         let conn = open_in_memory().unwrap();
 
         // Initially None.
-        let token = get_sync_token(&conn).unwrap();
+        let token = get_sync_token(&conn, ACCOUNT).unwrap();
         assert!(token.is_none(), "expected None on first run");
 
         // Set a token.
-        set_sync_token(&conn, "token_v1").unwrap();
-        let token = get_sync_token(&conn).unwrap();
+        set_sync_token(&conn, ACCOUNT, "token_v1").unwrap();
+        let token = get_sync_token(&conn, ACCOUNT).unwrap();
         assert_eq!(token.as_deref(), Some("token_v1"));
 
         // Overwrite with a new token.
-        set_sync_token(&conn, "token_v2").unwrap();
-        let token = get_sync_token(&conn).unwrap();
+        set_sync_token(&conn, ACCOUNT, "token_v2").unwrap();
+        let token = get_sync_token(&conn, ACCOUNT).unwrap();
         assert_eq!(token.as_deref(), Some("token_v2"));
     }
 
+    #[test]
+    fn sync_checkpoint_lifecycle() {
+        let conn = open_in_memory().unwrap();
+        assert_eq!(get_checkpoint(&conn, ACCOUNT).unwrap(), None);
+
+        let started = chrono::DateTime::parse_from_rfc3339("2026-03-01T12:00:00.5Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let mut checkpoint = SyncCheckpoint { since: None, started, page_token: "p2".into() };
+        set_checkpoint(&conn, ACCOUNT, &checkpoint).unwrap();
+        add_listed(&conn, ACCOUNT, "people/c1").unwrap();
+        add_listed(&conn, ACCOUNT, "people/c1").unwrap();
+        add_listed(&conn, "work", "people/c9").unwrap();
+
+        checkpoint.page_token = "p3".into();
+        set_checkpoint(&conn, ACCOUNT, &checkpoint).unwrap();
+        add_listed(&conn, ACCOUNT, "people/c2").unwrap();
+        assert_eq!(get_checkpoint(&conn, ACCOUNT).unwrap(), Some(checkpoint));
+        assert_eq!(get_checkpoint(&conn, "work").unwrap(), None);
+        let listed = listed_contacts(&conn, ACCOUNT).unwrap();
+        assert_eq!(listed, HashSet::from(["people/c1".to_string(), "people/c2".to_string()]));
+
+        clear_checkpoint(&conn, ACCOUNT).unwrap();
+        assert_eq!(get_checkpoint(&conn, ACCOUNT).unwrap(), None);
+        assert!(listed_contacts(&conn, ACCOUNT).unwrap().is_empty());
+        assert_eq!(listed_contacts(&conn, "work").unwrap().len(), 1);
+    }
+
     #[test]
     fn upsert_and_get_contact() {
         let conn = open_in_memory().unwrap();
 
         upsert_contact(
             &conn,
+            ACCOUNT,
             "people/c111",
             "etag1",
             "Alice",
@@ -408,7 +1342,7 @@ mod tests {
         )
         .unwrap();
 
-        let result = get_contact(&conn, "people/c111").unwrap();
+        let result = get_contact(&conn, ACCOUNT, "people/c111").unwrap();
         assert!(result.is_some());
         let (etag, vcard) = result.unwrap();
         assert_eq!(etag, "etag1");
@@ -419,15 +1353,15 @@ mod tests {
     fn upsert_updates_existing() {
         let conn = open_in_memory().unwrap();
 
-        upsert_contact(&conn, "people/c111", "etag1", "Alice v1", "vcard_v1", "5550100").unwrap();
-        upsert_contact(&conn, "people/c111", "etag2", "Alice v2", "vcard_v2", "5550200").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c111", "etag1", "Alice v1", "vcard_v1", "5550100").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c111", "etag2", "Alice v2", "vcard_v2", "5550200").unwrap();
 
-        let (etag, vcard) = get_contact(&conn, "people/c111").unwrap().unwrap();
+        let (etag, vcard) = get_contact(&conn, ACCOUNT, "people/c111").unwrap().unwrap();
         assert_eq!(etag, "etag2");
         assert_eq!(vcard, "vcard_v2");
 
         // Should still be one row, not two.
-        let all = all_contacts(&conn).unwrap();
+        let all = all_contacts(&conn, ACCOUNT).unwrap();
         assert_eq!(all.len(), 1);
     }
 
@@ -435,33 +1369,50 @@ mod tests {
     fn delete_contact_removes_row() {
         let conn = open_in_memory().unwrap();
 
-        upsert_contact(&conn, "people/c111", "e1", "Alice", "vc1", "5550100").unwrap();
-        upsert_contact(&conn, "people/c222", "e2", "Bob", "vc2", "5550200").unwrap();
-        assert_eq!(all_contacts(&conn).unwrap().len(), 2);
+        upsert_contact(&conn, ACCOUNT, "people/c111", "e1", "Alice", "vc1", "5550100").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c222", "e2", "Bob", "vc2", "5550200").unwrap();
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 2);
 
-        delete_contact(&conn, "people/c111").unwrap();
-        assert_eq!(all_contacts(&conn).unwrap().len(), 1);
+        delete_contact(&conn, ACCOUNT, "people/c111").unwrap();
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 1);
 
-        let result = get_contact(&conn, "people/c111").unwrap();
+        let result = get_contact(&conn, ACCOUNT, "people/c111").unwrap();
         assert!(result.is_none());
     }
 
+    #[test]
+    fn alias_resolves_until_the_contact_is_deleted() {
+        let conn = open_in_memory().unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c111", "e1", "Alice", "vc1", "").unwrap();
+        let seq = current_change_seq(&conn).unwrap();
+
+        add_alias(&conn, ACCOUNT, "people/new-1", "people/c111").unwrap();
+        assert_eq!(resolve_alias(&conn, ACCOUNT, "people/new-1").unwrap(), "people/c111");
+        assert_eq!(resolve_alias(&conn, ACCOUNT, "people/c222").unwrap(), "people/c222");
+        assert_eq!(resolve_alias(&conn, "other", "people/new-1").unwrap(), "people/new-1");
+        let changes = changes_since(&conn, ACCOUNT, "", seq).unwrap();
+        assert_eq!(changes, vec![("people/new-1".to_string(), None)]);
+
+        delete_contact(&conn, ACCOUNT, "people/c111").unwrap();
+        assert_eq!(resolve_alias(&conn, ACCOUNT, "people/new-1").unwrap(), "people/new-1");
+    }
+
     #[test]
     fn delete_nonexistent_is_ok() {
         let conn = open_in_memory().unwrap();
         // Should not error.
-        delete_contact(&conn, "people/c_does_not_exist").unwrap();
+        delete_contact(&conn, ACCOUNT, "people/c_does_not_exist").unwrap();
     }
 
     #[test]
     fn all_contacts_ordered_by_display_name() {
         let conn = open_in_memory().unwrap();
 
-        upsert_contact(&conn, "people/c3", "e3", "Charlie", "vc3", "").unwrap();
-        upsert_contact(&conn, "people/c1", "e1", "Alice", "vc1", "").unwrap();
-        upsert_contact(&conn, "people/c2", "e2", "Bob", "vc2", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c3", "e3", "Charlie", "vc3", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2", "Bob", "vc2", "").unwrap();
 
-        let all = all_contacts(&conn).unwrap();
+        let all = all_contacts(&conn, ACCOUNT).unwrap();
         let names: Vec<&str> = all.iter().map(|(rn, _, _)| rn.as_str()).collect();
         assert_eq!(names, vec!["people/c1", "people/c2", "people/c3"]);
     }
@@ -470,12 +1421,12 @@ mod tests {
     fn sync_token_persists_with_last_sync_timestamp() {
         let conn = open_in_memory().unwrap();
 
-        set_sync_token(&conn, "tok123").unwrap();
+        set_sync_token(&conn, ACCOUNT, "tok123").unwrap();
 
         let last_sync: Option<String> = conn
             .query_row(
-                "SELECT last_sync FROM sync_metadata WHERE id = 1",
-                [],
+                "SELECT last_sync FROM sync_metadata WHERE account = ?1",
+                params![ACCOUNT],
                 |row| row.get(0),
             )
             .unwrap();
@@ -499,36 +1450,86 @@ mod tests {
             ("people/c3", "e3", "Charlie", "vc_charlie", "5550300"),
         ];
         for (rn, etag, name, vc, phone) in &contacts {
-            upsert_contact(&conn, rn, etag, name, vc, phone).unwrap();
+            upsert_contact(&conn, ACCOUNT, rn, etag, name, vc, phone).unwrap();
         }
-        set_sync_token(&conn, "sync_v1").unwrap();
+        set_sync_token(&conn, ACCOUNT, "sync_v1").unwrap();
 
-        assert_eq!(all_contacts(&conn).unwrap().len(), 3);
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 3);
         assert_eq!(
-            get_sync_token(&conn).unwrap().as_deref(),
+            get_sync_token(&conn, ACCOUNT).unwrap().as_deref(),
             Some("sync_v1")
         );
 
         // ── Incremental sync (delta) ─────────────────────────────
         // Bob was updated, Charlie was deleted, Dave was added.
-        upsert_contact(&conn, "people/c2", "e2_new", "Bob Updated", "vc_bob_v2", "5550201").unwrap();
-        delete_contact(&conn, "people/c3").unwrap();
-        upsert_contact(&conn, "people/c4", "e4", "Dave", "vc_dave", "5550400").unwrap();
-        set_sync_token(&conn, "sync_v2").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2_new", "Bob Updated", "vc_bob_v2", "5550201").unwrap();
+        delete_contact(&conn, ACCOUNT, "people/c3").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c4", "e4", "Dave", "vc_dave", "5550400").unwrap();
+        set_sync_token(&conn, ACCOUNT, "sync_v2").unwrap();
 
-        assert_eq!(all_contacts(&conn).unwrap().len(), 3); // Alice, Bob, Dave
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 3); // Alice, Bob, Dave
         assert_eq!(
-            get_sync_token(&conn).unwrap().as_deref(),
+            get_sync_token(&conn, ACCOUNT).unwrap().as_deref(),
             Some("sync_v2")
         );
 
         // Verify Bob's update.
-        let (etag, vcard) = get_contact(&conn, "people/c2").unwrap().unwrap();
+        let (etag, vcard) = get_contact(&conn, ACCOUNT, "people/c2").unwrap().unwrap();
         assert_eq!(etag, "e2_new");
         assert_eq!(vcard, "vc_bob_v2");
 
         // Verify Charlie is gone.
-        assert!(get_contact(&conn, "people/c3").unwrap().is_none());
+        assert!(get_contact(&conn, ACCOUNT, "people/c3").unwrap().is_none());
+    }
+
+    #[test]
+    fn full_sync_reconciliation() {
+        let conn = open_in_memory().unwrap();
+        let started = chrono::Utc::now();
+        let earlier = (started - chrono::Duration::hours(1)).format("%Y-%m-%d %H:%M:%S").to_string();
+
+        for rn in ["people/c1", "people/c2", "people/c3", "people/c4", "people/c5"] {
+            upsert_contact(&conn, ACCOUNT, rn, "e", rn, "vc", "").unwrap();
+        }
+        set_memberships(&conn, ACCOUNT, "people/c2", &["contactGroups/family".to_string()]).unwrap();
+        // c3 was found by an earlier search, c4 during the listing, and c5
+        // long ago but it is in the listing now.
+        for rn in ["people/c3", "people/c4", "people/c5"] {
+            mark_on_demand(&conn, ACCOUNT, rn).unwrap();
+        }
+        for rn in ["people/c3", "people/c5"] {
+            conn.execute(
+                "UPDATE contacts SET on_demand_at = ?2 WHERE resource_name = ?1",
+                params![rn, earlier],
+            )
+            .unwrap();
+        }
+        upsert_contact(&conn, "work", "people/c9", "e", "Other account", "vc", "").unwrap();
+        let seq = current_change_seq(&conn).unwrap();
+
+        let listed: HashSet<String> = ["people/c1", "people/c5"].into_iter().map(String::from).collect();
+        let result = reconcile_contacts(&conn, ACCOUNT, &listed, started).unwrap();
+        assert_eq!(
+            result,
+            Reconciliation { purged: 1, purged_on_demand: 1, kept_on_demand: 1, adopted: 1 }
+        );
+
+        let left: Vec<String> = all_contacts(&conn, ACCOUNT).unwrap().into_iter().map(|c| c.0).collect();
+        assert_eq!(left, ["people/c1", "people/c4", "people/c5"]);
+        assert!(get_contact(&conn, "work", "people/c9").unwrap().is_some());
+
+        // Purged contacts leave tombstones, also in their group books.
+        let changes = changes_since(&conn, ACCOUNT, "", seq).unwrap();
+        assert!(changes.iter().any(|c| c.0 == "people/c2" && c.1.is_none()));
+        assert!(changes.iter().any(|c| c.0 == "people/c3" && c.1.is_none()));
+        let changes = changes_since(&conn, ACCOUNT, "contactGroups/family", seq).unwrap();
+        assert!(changes.iter().any(|c| c.0 == "people/c2" && c.1.is_none()));
+
+        // c5 is synced now and goes once Google stops listing it; c4 is
+        // still on demand and survives a listing that started before it.
+        let listed = HashSet::from(["people/c1".to_string()]);
+        let result = reconcile_contacts(&conn, ACCOUNT, &listed, started).unwrap();
+        assert_eq!(result, Reconciliation { purged: 1, kept_on_demand: 1, ..Default::default() });
     }
 
     #[test]
@@ -558,11 +1559,11 @@ mod tests {
 
         // A LIKE query for the 10-digit core must match ALL three.
         let conn = open_in_memory().unwrap();
-        upsert_contact(&conn, "people/c10", "e1", "Parens", "vc1", &parenthetical).unwrap();
-        upsert_contact(&conn, "people/c20", "e2", "Intl", "vc2", &international).unwrap();
-        upsert_contact(&conn, "people/c30", "e3", "Dots", "vc3", &dotted).unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c10", "e1", "Parens", "vc1", &parenthetical).unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c20", "e2", "Intl", "vc2", &international).unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c30", "e3", "Dots", "vc3", &dotted).unwrap();
 
-        let hits = search_by_phone(&conn, "5551234567").unwrap();
+        let hits = search_by_phone(&conn, ACCOUNT, "5551234567").unwrap();
         assert_eq!(hits.len(), 3, "all three formats should match the 10-digit search");
     }
 
@@ -594,7 +1595,7 @@ mod tests {
                         .join(" ")
                 })
                 .unwrap_or_default();
-            upsert_contact(conn, rn, etag, &dn, &vc, &phones).unwrap();
+            upsert_contact(conn, ACCOUNT, rn, etag, &dn, &vc, &phones).unwrap();
         }
 
         // ── "Full sync" — seed three contacts + initial token ───────
@@ -640,11 +1641,11 @@ mod tests {
         for p in [&alice, &bob, &charlie] {
             store(&conn, p);
         }
-        set_sync_token(&conn, "syncTok_FULL_v1").unwrap();
+        set_sync_token(&conn, ACCOUNT, "syncTok_FULL_v1").unwrap();
 
-        assert_eq!(all_contacts(&conn).unwrap().len(), 3);
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 3);
         assert_eq!(
-            get_sync_token(&conn).unwrap().as_deref(),
+            get_sync_token(&conn, ACCOUNT).unwrap().as_deref(),
             Some("syncTok_FULL_v1")
         );
 
@@ -685,33 +1686,33 @@ mod tests {
         for p in [&alice_v2, &bob_v2] {
             store(&conn, p);
         }
-        set_sync_token(&conn, "syncTok_INC_v2").unwrap();
+        set_sync_token(&conn, ACCOUNT, "syncTok_INC_v2").unwrap();
 
         // ── Verify DB state ─────────────────────────────────────────
         // Still 3 contacts (upserts, not inserts).
-        assert_eq!(all_contacts(&conn).unwrap().len(), 3);
+        assert_eq!(all_contacts(&conn, ACCOUNT).unwrap().len(), 3);
 
         // Token updated.
         assert_eq!(
-            get_sync_token(&conn).unwrap().as_deref(),
+            get_sync_token(&conn, ACCOUNT).unwrap().as_deref(),
             Some("syncTok_INC_v2")
         );
 
         // Alice's etag + phone updated.
-        let (etag, vcard) = get_contact(&conn, "people/c100").unwrap().unwrap();
+        let (etag, vcard) = get_contact(&conn, ACCOUNT, "people/c100").unwrap().unwrap();
         assert_eq!(etag, "eA2");
         assert!(
             vcard.contains("+1-555-000-9999"),
             "Alice's vCard should contain the updated phone"
         );
-        let alice_hits = search_by_phone(&conn, "5550009999").unwrap();
+        let alice_hits = search_by_phone(&conn, ACCOUNT, "5550009999").unwrap();
         assert_eq!(alice_hits.len(), 1);
         // Old phone should no longer match.
-        let old_hits = search_by_phone(&conn, "5550001111").unwrap();
+        let old_hits = search_by_phone(&conn, ACCOUNT, "5550001111").unwrap();
         assert!(old_hits.is_empty(), "old phone should be replaced");
 
         // Bob's display name updated.
-        let (etag, vcard) = get_contact(&conn, "people/c200").unwrap().unwrap();
+        let (etag, vcard) = get_contact(&conn, ACCOUNT, "people/c200").unwrap().unwrap();
         assert_eq!(etag, "eB2");
         assert!(
             vcard.contains("Bob Updated"),
@@ -719,7 +1720,7 @@ mod tests {
         );
 
         // Charlie unchanged.
-        let (etag, _) = get_contact(&conn, "people/c300").unwrap().unwrap();
+        let (etag, _) = get_contact(&conn, ACCOUNT, "people/c300").unwrap().unwrap();
         assert_eq!(etag, "eC1");
     }
 
@@ -727,20 +1728,20 @@ mod tests {
     fn search_by_phone_finds_match() {
         let conn = open_in_memory().unwrap();
 
-        upsert_contact(&conn, "people/c1", "e1", "Alice", "vc1", "+15550100 5550200").unwrap();
-        upsert_contact(&conn, "people/c2", "e2", "Bob", "vc2", "5559999").unwrap();
-        upsert_contact(&conn, "people/c3", "e3", "Charlie", "vc3", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "+15550100 5550200").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2", "Bob", "vc2", "5559999").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c3", "e3", "Charlie", "vc3", "").unwrap();
 
-        let hits = search_by_phone(&conn, "5550100").unwrap();
+        let hits = search_by_phone(&conn, ACCOUNT, "5550100").unwrap();
         assert_eq!(hits.len(), 1);
         assert_eq!(hits[0].0, "people/c1");
 
         // No match
-        let hits = search_by_phone(&conn, "0000000").unwrap();
+        let hits = search_by_phone(&conn, ACCOUNT, "0000000").unwrap();
         assert!(hits.is_empty());
 
         // Empty query returns empty
-        let hits = search_by_phone(&conn, "").unwrap();
+        let hits = search_by_phone(&conn, ACCOUNT, "").unwrap();
         assert!(hits.is_empty());
     }
 
@@ -748,8 +1749,8 @@ mod tests {
     fn searchable_phone_is_stored_and_queryable() {
         let conn = open_in_memory().unwrap();
 
-        upsert_contact(&conn, "people/c1", "e1", "Alice", "vc1", "+15550100 5550200").unwrap();
-        upsert_contact(&conn, "people/c2", "e2", "Bob", "vc2", "5559999").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "+15550100 5550200").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2", "Bob", "vc2", "5559999").unwrap();
 
         // Search by substring match on the searchable_phone column.
         let mut stmt = conn
@@ -772,41 +1773,291 @@ mod tests {
     }
 
     #[test]
-    fn oauth_token_lifecycle() {
+    fn account_lifecycle() {
         let conn = open_in_memory().unwrap();
 
-        // Initially no token.
-        assert!(!has_oauth_token(&conn));
-        assert!(get_oauth_token(&conn).unwrap().is_none());
-        assert!(get_google_email(&conn).unwrap().is_none());
+        // No accounts yet: the unqualified URLs serve the default one.
+        assert!(accounts(&conn).unwrap().is_empty());
+        assert_eq!(default_account(&conn).unwrap(), DEFAULT_ACCOUNT);
+        assert!(get_google_email(&conn, "work").unwrap().is_none());
 
-        // Store a token.
-        store_oauth_token(&conn, r#"{"refresh_token":"abc123"}"#, "user@gmail.com").unwrap();
-        assert!(has_oauth_token(&conn));
-        assert_eq!(
-            get_oauth_token(&conn).unwrap().as_deref(),
-            Some(r#"{"refresh_token":"abc123"}"#)
-        );
-        assert_eq!(
-            get_google_email(&conn).unwrap().as_deref(),
-            Some("user@gmail.com")
-        );
+        add_account(&conn, "work", "").unwrap();
+        add_account(&conn, "personal", "me@gmail.com").unwrap();
+        assert_eq!(default_account(&conn).unwrap(), "work");
+        assert!(get_google_email(&conn, "work").unwrap().is_none());
 
-        // Overwrite with new token.
-        store_oauth_token(&conn, r#"{"refresh_token":"xyz789"}"#, "other@gmail.com").unwrap();
-        assert_eq!(
-            get_oauth_token(&conn).unwrap().as_deref(),
-            Some(r#"{"refresh_token":"xyz789"}"#)
-        );
+        // Re-adding fills in the email but never blanks it.
+        add_account(&conn, "work", "me@example.com").unwrap();
+        add_account(&conn, "work", "").unwrap();
         assert_eq!(
-            get_google_email(&conn).unwrap().as_deref(),
-            Some("other@gmail.com")
+            accounts(&conn).unwrap(),
+            vec![
+                Account { id: "work".into(), google_email: "me@example.com".into() },
+                Account { id: "personal".into(), google_email: "me@gmail.com".into() },
+            ]
         );
 
-        // Clear token.
-        clear_oauth_token(&conn).unwrap();
-        assert!(!has_oauth_token(&conn));
-        assert!(get_oauth_token(&conn).unwrap().is_none());
-        assert!(get_google_email(&conn).unwrap().is_none());
+        assert!(add_account(&conn, "Work Account", "").is_err());
+        assert!(add_account(&conn, "contacts", "").is_err());
+
+        upsert_contact(&conn, "work", "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        remove_account(&conn, "work").unwrap();
+        assert!(all_contacts(&conn, "work").unwrap().is_empty());
+        assert_eq!(default_account(&conn).unwrap(), "personal");
+    }
+
+    #[test]
+    fn app_password_lifecycle() {
+        let conn = open_in_memory().unwrap();
+        assert!(app_passwords(&conn).unwrap().is_empty());
+
+        let phone = AppPassword {
+            username: "phone".into(),
+            label: "Pixel 8".into(),
+            account: Some("work".into()),
+            book: None,
+            read_only: true,
+            created_at: String::new(),
+            last_used_at: None,
+        };
+        insert_app_password(&conn, &phone, "$argon2id$hash").unwrap();
+        assert!(insert_app_password(&conn, &phone, "$argon2id$other").is_err());
+
+        let (stored, hash) = app_password(&conn, "phone").unwrap().unwrap();
+        assert_eq!(hash, "$argon2id$hash");
+        assert_eq!(stored.label, "Pixel 8");
+        assert_eq!(stored.account.as_deref(), Some("work"));
+        assert!(stored.read_only);
+        assert!(!stored.created_at.is_empty());
+        assert!(stored.last_used_at.is_none());
+        assert!(app_password(&conn, "laptop").unwrap().is_none());
+
+        touch_app_password(&conn, "phone").unwrap();
+        let listed = app_passwords(&conn).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].last_used_at.is_some());
+
+        // A re-issued client certificate replaces the earlier one.
+        assert!(set_client_certificate(&conn, "phone", "aa11").unwrap());
+        assert_eq!(app_password_by_certificate(&conn, "aa11").unwrap().unwrap().username, "phone");
+        assert!(set_client_certificate(&conn, "phone", "bb22").unwrap());
+        assert!(app_password_by_certificate(&conn, "aa11").unwrap().is_none());
+        assert!(!set_client_certificate(&conn, "laptop", "cc33").unwrap());
+
+        assert!(revoke_app_password(&conn, "phone").unwrap());
+        assert!(app_password_by_certificate(&conn, "bb22").unwrap().is_none());
+        assert!(!revoke_app_password(&conn, "phone").unwrap());
+        assert!(app_password(&conn, "phone").unwrap().is_none());
+    }
+
+    #[test]
+    fn accounts_are_isolated() {
+        let conn = open_in_memory().unwrap();
+
+        // The same Google resource name may exist in both accounts.
+        upsert_contact(&conn, "work", "people/c1", "ew", "Alice (work)", "vc_w", "5550100").unwrap();
+        upsert_contact(&conn, "home", "people/c1", "eh", "Alice (home)", "vc_h", "5550200").unwrap();
+        set_sync_token(&conn, "work", "tok_work").unwrap();
+
+        assert_eq!(get_contact(&conn, "work", "people/c1").unwrap().unwrap().0, "ew");
+        assert_eq!(get_contact(&conn, "home", "people/c1").unwrap().unwrap().0, "eh");
+        assert_eq!(get_sync_token(&conn, "work").unwrap().as_deref(), Some("tok_work"));
+        assert!(get_sync_token(&conn, "home").unwrap().is_none());
+        assert_eq!(search_by_phone(&conn, "work", "5550200").unwrap().len(), 0);
+        assert_eq!(search_by_phone(&conn, "home", "5550200").unwrap().len(), 1);
+
+        // Each account has its own CTag and change log.
+        let work_seq = book_change_seq(&conn, "work", "").unwrap();
+        delete_contact(&conn, "home", "people/c1").unwrap();
+        assert_eq!(change_counter(&conn, "home").unwrap(), 2);
+        assert_eq!(change_counter(&conn, "work").unwrap(), 1);
+        assert!(changes_since(&conn, "work", "", work_seq).unwrap().is_empty());
+        assert!(get_contact(&conn, "work", "people/c1").unwrap().is_some());
+    }
+
+    #[test]
+    fn migrates_single_account_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE contacts (
+                 resource_name TEXT PRIMARY KEY, etag TEXT NOT NULL,
+                 display_name TEXT NOT NULL DEFAULT '', vcard TEXT NOT NULL,
+                 updated_at TEXT NOT NULL DEFAULT (datetime('now')));
+             CREATE TABLE sync_metadata (
+                 id INTEGER PRIMARY KEY CHECK (id = 1), sync_token TEXT, last_sync TEXT);
+             INSERT INTO sync_metadata (id, sync_token) VALUES (1, 'tok');
+             CREATE TABLE contact_changes (
+                 seq INTEGER PRIMARY KEY AUTOINCREMENT, resource_name TEXT NOT NULL UNIQUE,
+                 deleted INTEGER NOT NULL DEFAULT 0,
+                 changed_at TEXT NOT NULL DEFAULT (datetime('now')));
+             CREATE TABLE oauth_tokens (id INTEGER PRIMARY KEY CHECK (id = 1), token_json TEXT NOT NULL);
+             INSERT INTO oauth_tokens (id, token_json) VALUES (1, '<stored-in-keyring>');
+             CREATE TABLE contact_aliases (alias TEXT PRIMARY KEY NOT NULL, resource_name TEXT NOT NULL);
+             INSERT INTO contacts (resource_name, etag, vcard) VALUES ('people/c1', 'e1', 'vc1');
+             INSERT INTO contact_aliases (alias, resource_name) VALUES ('people/new-1', 'people/c1');
+             INSERT INTO contact_changes (resource_name) VALUES ('people/c1');",
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+        migrate(&conn).unwrap();
+
+        assert_eq!(accounts(&conn).unwrap().len(), 1);
+        assert_eq!(default_account(&conn).unwrap(), DEFAULT_ACCOUNT);
+        assert_eq!(get_contact(&conn, DEFAULT_ACCOUNT, "people/c1").unwrap().unwrap().0, "e1");
+        assert_eq!(changes_since(&conn, DEFAULT_ACCOUNT, "", 0).unwrap().len(), 1);
+        assert_eq!(resolve_alias(&conn, DEFAULT_ACCOUNT, "people/new-1").unwrap(), "people/c1");
+        // The vcard4 migration dropped the token to force a full sync.
+        assert!(get_sync_token(&conn, DEFAULT_ACCOUNT).unwrap().is_none());
+    }
+
+    #[test]
+    fn failed_account_migration_leaves_the_old_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        // A leftover table makes the rename of contact_groups fail after
+        // contacts was already renamed.
+        conn.execute_batch(
+            "CREATE TABLE contacts (
+                 resource_name TEXT PRIMARY KEY, etag TEXT NOT NULL,
+                 display_name TEXT NOT NULL DEFAULT '', vcard TEXT NOT NULL,
+                 updated_at TEXT NOT NULL DEFAULT (datetime('now')));
+             INSERT INTO contacts (resource_name, etag, vcard) VALUES ('people/c1', 'e1', 'vc1');
+             CREATE TABLE contact_groups_v1 (x);",
+        )
+        .unwrap();
+
+        assert!(migrate(&conn).is_err());
+        let etag: String = conn
+            .query_row("SELECT etag FROM contacts WHERE resource_name = 'people/c1'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(etag, "e1");
+        assert!(conn.prepare("SELECT 1 FROM contacts_v1").is_err());
+
+        // With the obstacle gone, the next open migrates.
+        conn.execute_batch("DROP TABLE contact_groups_v1;").unwrap();
+        migrate(&conn).unwrap();
+        assert_eq!(get_contact(&conn, DEFAULT_ACCOUNT, "people/c1").unwrap().unwrap().0, "e1");
+    }
+
+    #[test]
+    fn change_log_tracks_upserts_and_deletes() {
+        let conn = open_in_memory().unwrap();
+        assert_eq!(current_change_seq(&conn).unwrap(), 0);
+
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2", "Bob", "vc2", "").unwrap();
+        let token = current_change_seq(&conn).unwrap();
+        assert!(token > 0);
+
+        // Nothing changed since the token.
+        assert!(changes_since(&conn, ACCOUNT, "", token).unwrap().is_empty());
+
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1b", "Alice", "vc1b", "").unwrap();
+        delete_contact(&conn, ACCOUNT, "people/c2").unwrap();
+
+        let changes = changes_since(&conn, ACCOUNT, "", token).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].0, "people/c1");
+        assert_eq!(changes[0].1, Some(("e1b".to_string(), "vc1b".to_string())));
+        assert_eq!(changes[1], ("people/c2".to_string(), None));
+
+        // A full listing from 0 reports each contact once, at its latest state.
+        let all = changes_since(&conn, ACCOUNT, "", 0).unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn deleting_unknown_contact_leaves_no_tombstone() {
+        let conn = open_in_memory().unwrap();
+        delete_contact(&conn, ACCOUNT, "people/c_missing").unwrap();
+        assert_eq!(current_change_seq(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn change_counter_only_moves_on_real_changes() {
+        let conn = open_in_memory().unwrap();
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 0);
+
+        assert!(upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap());
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 1);
+        let seq = current_change_seq(&conn).unwrap();
+
+        // Same etag + vCard: nothing is written.
+        assert!(!upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap());
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 1);
+        assert_eq!(current_change_seq(&conn).unwrap(), seq);
+
+        // New etag: counter moves.
+        assert!(upsert_contact(&conn, ACCOUNT, "people/c1", "e2", "Alice", "vc1", "").unwrap());
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 2);
+
+        delete_contact(&conn, ACCOUNT, "people/c1").unwrap();
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 3);
+
+        // Deleting an absent row is not a change.
+        delete_contact(&conn, ACCOUNT, "people/c1").unwrap();
+        assert_eq!(change_counter(&conn, ACCOUNT).unwrap(), 3);
+    }
+
+    #[test]
+    fn contact_modified_reads_updated_at() {
+        let conn = open_in_memory().unwrap();
+        assert_eq!(contact_modified(&conn, ACCOUNT, "people/c1").unwrap(), None);
+
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        conn.execute(
+            "UPDATE contacts SET updated_at = '2024-05-02 08:30:00' WHERE resource_name = 'people/c1'",
+            [],
+        )
+        .unwrap();
+        let modified = contact_modified(&conn, ACCOUNT, "people/c1").unwrap().unwrap();
+        assert_eq!(modified.to_rfc3339(), "2024-05-02T08:30:00+00:00");
+
+        // A no-op upsert leaves Last-Modified alone.
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        assert_eq!(contact_modified(&conn, ACCOUNT, "people/c1").unwrap(), Some(modified));
+    }
+
+    #[test]
+    fn group_books_log_joins_and_leaves() {
+        let conn = open_in_memory().unwrap();
+        let family = "contactGroups/family1".to_string();
+        replace_contact_groups(
+            &conn,
+            ACCOUNT,
+            &[StoredGroup {
+                resource_name: family.clone(),
+                name: "Family".into(),
+                group_type: "USER_CONTACT_GROUP".into(),
+            }],
+        )
+        .unwrap();
+
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1", "Alice", "vc1", "").unwrap();
+        upsert_contact(&conn, ACCOUNT, "people/c2", "e2", "Bob", "vc2", "").unwrap();
+        set_memberships(&conn, ACCOUNT, "people/c1", std::slice::from_ref(&family)).unwrap();
+        assert!(is_member(&conn, ACCOUNT, &family, "people/c1").unwrap());
+        assert_eq!(group_contacts(&conn, ACCOUNT, &family).unwrap().len(), 1);
+
+        let token = book_change_seq(&conn, ACCOUNT, &family).unwrap();
+        assert!(token > 0);
+
+        // An update to a member is logged in the group book too.
+        upsert_contact(&conn, ACCOUNT, "people/c1", "e1b", "Alice", "vc1b", "").unwrap();
+        let changes = changes_since(&conn, ACCOUNT, &family, token).unwrap();
+        assert_eq!(changes, vec![("people/c1".to_string(), Some(("e1b".into(), "vc1b".into())))]);
+
+        // Leaving the group is a tombstone in the group book only.
+        let token = book_change_seq(&conn, ACCOUNT, &family).unwrap();
+        set_memberships(&conn, ACCOUNT, "people/c1", &[]).unwrap();
+        assert_eq!(changes_since(&conn, ACCOUNT, &family, token).unwrap(), vec![("people/c1".to_string(), None)]);
+        assert!(get_contact(&conn, ACCOUNT, "people/c1").unwrap().is_some());
+
+        // Removing the group drops its memberships and log.
+        set_memberships(&conn, ACCOUNT, "people/c2", std::slice::from_ref(&family)).unwrap();
+        replace_contact_groups(&conn, ACCOUNT, &[]).unwrap();
+        assert!(contact_groups(&conn, ACCOUNT).unwrap().is_empty());
+        assert!(!is_member(&conn, ACCOUNT, &family, "people/c2").unwrap());
+        assert_eq!(book_change_seq(&conn, ACCOUNT, &family).unwrap(), 0);
     }
 }
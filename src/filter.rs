@@ -0,0 +1,461 @@
+//! `addressbook-query` filter evaluation (RFC 6352 §10.5).
+//!
+//! A [`Filter`] is parsed from the `<C:filter>` element of a REPORT body
+//! and evaluated against the cached vCard text of each contact:
+//!
+//! - `prop-filter` on any property, combined with the filter's
+//!   `test="anyof|allof"` (default `anyof`)
+//! - `is-not-defined`, `param-filter` and `text-match` inside a
+//!   `prop-filter`, combined with the prop-filter's own `test`
+//! - every `match-type` (`equals`, `contains`, `starts-with`,
+//!   `ends-with`) and `negate-condition`
+//! - the `i;unicode-casemap` (default), `i;ascii-casemap` and `i;octet`
+//!   collations
+//!
+//! `TEL` text-matches compare digits only, so `+1 (555) 987-6543` matches a
+//! contact stored as `555-987-6543` (same rules as [`db::search_by_phone`]).
+
+use anyhow::{bail, Context, Result};
+
+use crate::db;
+use crate::vcard::{self, Property};
+use crate::xml::{Element, CARDDAV};
+
+// ── Model ───────────────────────────────────────────────────────────────
+
+/// How a list of conditions is combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Test {
+    AnyOf,
+    AllOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchType {
+    Equals,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    /// `i;unicode-casemap` (RFC 5051) — approximated by Unicode lowercasing.
+    UnicodeCasemap,
+    /// `i;ascii-casemap` — ASCII letters compared case-insensitively.
+    AsciiCasemap,
+    /// `i;octet` — exact comparison.
+    Octet,
+}
+
+/// Error for a `collation` the server doesn't implement; the REPORT
+/// handler maps it to the `CARDDAV:supported-collation` precondition.
+#[derive(Debug)]
+pub struct UnsupportedCollation(pub String);
+
+impl std::fmt::Display for UnsupportedCollation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsupported collation {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedCollation {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextMatch {
+    pub text: String,
+    pub collation: Collation,
+    pub match_type: MatchType,
+    pub negate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamFilter {
+    /// Upper-cased parameter name, e.g. `TYPE`.
+    pub name: String,
+    pub is_not_defined: bool,
+    pub text_match: Option<TextMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropFilter {
+    /// Upper-cased property name, e.g. `EMAIL`.
+    pub name: String,
+    pub test: Test,
+    pub is_not_defined: bool,
+    pub text_matches: Vec<TextMatch>,
+    pub param_filters: Vec<ParamFilter>,
+}
+
+/// A parsed `<C:filter>`.  The default (no prop-filters) matches every
+/// contact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub test: Test,
+    pub prop_filters: Vec<PropFilter>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            test: Test::AnyOf,
+            prop_filters: Vec::new(),
+        }
+    }
+}
+
+// ── Parsing ─────────────────────────────────────────────────────────────
+
+impl Filter {
+    /// Parse a `<C:filter>` element.
+    pub fn from_element(filter: &Element) -> Result<Self> {
+        let prop_filters = filter
+            .children_named(CARDDAV, "prop-filter")
+            .map(PropFilter::from_element)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            test: parse_test(filter)?,
+            prop_filters,
+        })
+    }
+}
+
+impl PropFilter {
+    fn from_element(el: &Element) -> Result<Self> {
+        let name = el
+            .attr("name")
+            .context("prop-filter without a name")?
+            .to_ascii_uppercase();
+        let text_matches = el
+            .children_named(CARDDAV, "text-match")
+            .map(TextMatch::from_element)
+            .collect::<Result<_>>()?;
+        let param_filters = el
+            .children_named(CARDDAV, "param-filter")
+            .map(ParamFilter::from_element)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            name,
+            test: parse_test(el)?,
+            is_not_defined: el.child(CARDDAV, "is-not-defined").is_some(),
+            text_matches,
+            param_filters,
+        })
+    }
+}
+
+impl ParamFilter {
+    fn from_element(el: &Element) -> Result<Self> {
+        let name = el
+            .attr("name")
+            .context("param-filter without a name")?
+            .to_ascii_uppercase();
+        let text_match = el
+            .child(CARDDAV, "text-match")
+            .map(TextMatch::from_element)
+            .transpose()?;
+        Ok(Self {
+            name,
+            is_not_defined: el.child(CARDDAV, "is-not-defined").is_some(),
+            text_match,
+        })
+    }
+}
+
+impl TextMatch {
+    fn from_element(el: &Element) -> Result<Self> {
+        let collation = match el.attr("collation").unwrap_or("i;unicode-casemap") {
+            "i;unicode-casemap" => Collation::UnicodeCasemap,
+            "i;ascii-casemap" => Collation::AsciiCasemap,
+            "i;octet" => Collation::Octet,
+            other => return Err(UnsupportedCollation(other.to_string()).into()),
+        };
+        let match_type = match el.attr("match-type").unwrap_or("contains") {
+            "equals" => MatchType::Equals,
+            "contains" => MatchType::Contains,
+            "starts-with" => MatchType::StartsWith,
+            "ends-with" => MatchType::EndsWith,
+            other => bail!("unknown match-type {other:?}"),
+        };
+        let negate = match el.attr("negate-condition").unwrap_or("no") {
+            "yes" => true,
+            "no" => false,
+            other => bail!("invalid negate-condition {other:?}"),
+        };
+        Ok(Self {
+            text: el.text().to_string(),
+            collation,
+            match_type,
+            negate,
+        })
+    }
+}
+
+fn parse_test(el: &Element) -> Result<Test> {
+    match el.attr("test").unwrap_or("anyof") {
+        "anyof" => Ok(Test::AnyOf),
+        "allof" => Ok(Test::AllOf),
+        other => bail!("invalid test attribute {other:?}"),
+    }
+}
+
+// ── Evaluation ──────────────────────────────────────────────────────────
+
+impl Filter {
+    /// Evaluate the filter against a vCard.
+    pub fn matches(&self, vcard_text: &str) -> bool {
+        if self.prop_filters.is_empty() {
+            return true;
+        }
+        let props = vcard::parse_properties(vcard_text);
+        combine(self.test, self.prop_filters.iter(), |pf| pf.matches(&props))
+    }
+
+    /// The phone number searched for by a plain `TEL` text-match, if any —
+    /// used for the on-demand Google lookup when nothing matches locally.
+    pub fn phone_query(&self) -> Option<&str> {
+        self.prop_filters
+            .iter()
+            .filter(|pf| pf.name == "TEL" && !pf.is_not_defined)
+            .flat_map(|pf| &pf.text_matches)
+            .find(|tm| !tm.negate && !tm.text.is_empty())
+            .map(|tm| tm.text.as_str())
+    }
+}
+
+impl PropFilter {
+    fn matches(&self, props: &[Property]) -> bool {
+        let mut instances = props.iter().filter(|p| p.name == self.name).peekable();
+        if self.is_not_defined {
+            return instances.peek().is_none();
+        }
+        if self.text_matches.is_empty() && self.param_filters.is_empty() {
+            return instances.peek().is_some();
+        }
+
+        // The conditions are evaluated per property instance; the filter
+        // matches if any instance satisfies them.
+        instances.any(|prop| {
+            let value = prop.text();
+            let text_results = self
+                .text_matches
+                .iter()
+                .map(|tm| tm.matches_property(&self.name, &value));
+            let param_results = self.param_filters.iter().map(|pf| pf.matches(prop));
+            combine(self.test, text_results.chain(param_results), |ok| ok)
+        })
+    }
+}
+
+impl ParamFilter {
+    fn matches(&self, prop: &Property) -> bool {
+        let mut values = prop
+            .params
+            .iter()
+            .filter(|(k, _)| *k == self.name)
+            .map(|(_, v)| v)
+            .peekable();
+        if self.is_not_defined {
+            return values.peek().is_none();
+        }
+        match &self.text_match {
+            None => values.peek().is_some(),
+            Some(tm) => {
+                let hit = values.any(|v| tm.matches_text(v));
+                // A negated match on an absent parameter is vacuously true.
+                hit != tm.negate
+            }
+        }
+    }
+}
+
+impl TextMatch {
+    fn matches_property(&self, prop_name: &str, value: &str) -> bool {
+        if prop_name == "TEL" {
+            let needle = phone_digits(&self.text);
+            if !needle.is_empty() {
+                return phone_matches(&phone_digits(value), &needle, self.match_type) != self.negate;
+            }
+        }
+        self.matches_text(value) != self.negate
+    }
+
+    /// Match ignoring `negate` (the caller applies it).
+    fn matches_text(&self, value: &str) -> bool {
+        let (value, needle) = match self.collation {
+            Collation::UnicodeCasemap => (value.to_lowercase(), self.text.to_lowercase()),
+            Collation::AsciiCasemap => (value.to_ascii_lowercase(), self.text.to_ascii_lowercase()),
+            Collation::Octet => (value.to_string(), self.text.clone()),
+        };
+        match self.match_type {
+            MatchType::Equals => value == needle,
+            MatchType::Contains => value.contains(&needle),
+            MatchType::StartsWith => value.starts_with(&needle),
+            MatchType::EndsWith => value.ends_with(&needle),
+        }
+    }
+}
+
+/// Digits of a phone number, without the leading `+`.
+fn phone_digits(raw: &str) -> String {
+    db::normalize_phone(raw).trim_start_matches('+').to_string()
+}
+
+/// Compare phone digits.  `equals` / `contains` tolerate a missing country
+/// code on either side (one number is a suffix of the other).
+fn phone_matches(value: &str, needle: &str, match_type: MatchType) -> bool {
+    if value.is_empty() {
+        return false;
+    }
+    let suffix_either_way = value.ends_with(needle) || needle.ends_with(value);
+    match match_type {
+        MatchType::Equals => suffix_either_way,
+        MatchType::Contains => suffix_either_way || value.contains(needle),
+        MatchType::StartsWith => value.starts_with(needle),
+        MatchType::EndsWith => value.ends_with(needle),
+    }
+}
+
+fn combine<T>(test: Test, mut items: impl Iterator<Item = T>, pred: impl FnMut(T) -> bool) -> bool {
+    match test {
+        Test::AnyOf => items.any(pred),
+        Test::AllOf => items.all(pred),
+    }
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml;
+
+    const JANE: &str = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane;;;\r\nFN:Jane Doe\r\n\
+        NICKNAME:JD\r\nEMAIL;TYPE=INTERNET,WORK:jane@work.example\r\n\
+        EMAIL;TYPE=INTERNET,HOME:jane@home.example\r\nTEL;TYPE=CELL:+1 (555) 987-6543\r\n\
+        ORG:Acme\\, Inc.\r\nEND:VCARD\r\n";
+
+    const BOB: &str = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Smith;Bob;;;\r\nFN:Bob Smith\r\n\
+        EMAIL;TYPE=INTERNET:bob@example.org\r\nEND:VCARD\r\n";
+
+    fn filter(inner: &str) -> Filter {
+        let body = format!(
+            r#"<C:filter xmlns:C="urn:ietf:params:xml:ns:carddav" {inner}</C:filter>"#
+        );
+        Filter::from_element(&xml::parse(&body).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let f = filter(">");
+        assert!(f.matches(JANE));
+        assert!(f.matches(BOB));
+    }
+
+    #[test]
+    fn match_types_and_default_collation() {
+        let f = filter(r#"><C:prop-filter name="EMAIL"><C:text-match match-type="starts-with">JANE@</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+        assert!(!f.matches(BOB));
+
+        let f = filter(r#"><C:prop-filter name="fn"><C:text-match match-type="equals">bob smith</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(BOB));
+        assert!(!f.matches(JANE));
+
+        let f = filter(r#"><C:prop-filter name="EMAIL"><C:text-match match-type="ends-with">.org</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(BOB));
+
+        // Default match-type is contains; values are compared unescaped.
+        let f = filter(r#"><C:prop-filter name="ORG"><C:text-match>acme, inc</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+    }
+
+    #[test]
+    fn octet_collation_is_case_sensitive() {
+        let f = filter(r#"><C:prop-filter name="NICKNAME"><C:text-match collation="i;octet">jd</C:text-match></C:prop-filter>"#);
+        assert!(!f.matches(JANE));
+        let f = filter(r#"><C:prop-filter name="NICKNAME"><C:text-match collation="i;ascii-casemap">jd</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+    }
+
+    #[test]
+    fn unsupported_collation_is_a_typed_error() {
+        let body = r#"<C:filter xmlns:C="urn:ietf:params:xml:ns:carddav"><C:prop-filter name="FN"><C:text-match collation="x;klingon">a</C:text-match></C:prop-filter></C:filter>"#;
+        let err = Filter::from_element(&xml::parse(body).unwrap()).unwrap_err();
+        assert!(err.downcast_ref::<UnsupportedCollation>().is_some());
+    }
+
+    #[test]
+    fn negate_and_is_not_defined() {
+        let f = filter(r#"><C:prop-filter name="FN"><C:text-match negate-condition="yes">jane</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(BOB));
+        assert!(!f.matches(JANE));
+
+        let f = filter(r#"><C:prop-filter name="NICKNAME"><C:is-not-defined/></C:prop-filter>"#);
+        assert!(f.matches(BOB));
+        assert!(!f.matches(JANE));
+
+        // Bare prop-filter: property must exist.
+        let f = filter(r#"><C:prop-filter name="TEL"/>"#);
+        assert!(f.matches(JANE));
+        assert!(!f.matches(BOB));
+    }
+
+    #[test]
+    fn param_filters() {
+        let f = filter(r#"><C:prop-filter name="EMAIL" test="allof"><C:text-match>jane</C:text-match><C:param-filter name="type"><C:text-match match-type="equals">home</C:text-match></C:param-filter></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+        assert!(!f.matches(BOB));
+
+        let f = filter(r#"><C:prop-filter name="EMAIL"><C:param-filter name="TYPE"><C:text-match match-type="equals">work</C:text-match></C:param-filter></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+        assert!(!f.matches(BOB));
+
+        let f = filter(r#"><C:prop-filter name="TEL"><C:param-filter name="PREF"><C:is-not-defined/></C:param-filter></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+    }
+
+    #[test]
+    fn prop_filter_test_applies_per_instance() {
+        // Both conditions must hold on the *same* EMAIL.
+        let f = filter(r#"><C:prop-filter name="EMAIL" test="allof"><C:text-match>work</C:text-match><C:text-match>home</C:text-match></C:prop-filter>"#);
+        assert!(!f.matches(JANE));
+        let f = filter(r#"><C:prop-filter name="EMAIL" test="anyof"><C:text-match>work</C:text-match><C:text-match>home</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+    }
+
+    #[test]
+    fn filter_level_anyof_allof() {
+        let anyof = filter(r#" test="anyof"><C:prop-filter name="FN"><C:text-match>bob</C:text-match></C:prop-filter><C:prop-filter name="NICKNAME"/>"#);
+        assert!(anyof.matches(JANE));
+        assert!(anyof.matches(BOB));
+
+        let allof = filter(r#" test="allof"><C:prop-filter name="FN"><C:text-match>bob</C:text-match></C:prop-filter><C:prop-filter name="NICKNAME"/>"#);
+        assert!(!allof.matches(JANE));
+        assert!(!allof.matches(BOB));
+    }
+
+    #[test]
+    fn tel_compares_digits_with_country_code_tolerance() {
+        let f = filter(r#"><C:prop-filter name="TEL"><C:text-match>5559876543</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+        let f = filter(r#"><C:prop-filter name="TEL"><C:text-match match-type="equals">+44 555 987 6543</C:text-match></C:prop-filter>"#);
+        assert!(!f.matches(JANE));
+        let f = filter(r#"><C:prop-filter name="TEL"><C:text-match match-type="starts-with">+1 555</C:text-match></C:prop-filter>"#);
+        assert!(f.matches(JANE));
+        assert!(!f.matches(BOB));
+        assert_eq!(f.phone_query(), Some("+1 555"));
+    }
+
+    #[test]
+    fn rejects_invalid_attributes() {
+        for inner in [
+            r#" test="some">"#,
+            r#"><C:prop-filter name="FN"><C:text-match match-type="fuzzy">a</C:text-match></C:prop-filter>"#,
+            r#"><C:prop-filter><C:is-not-defined/></C:prop-filter>"#,
+        ] {
+            let body = format!(r#"<C:filter xmlns:C="urn:ietf:params:xml:ns:carddav"{inner}</C:filter>"#);
+            assert!(Filter::from_element(&xml::parse(&body).unwrap()).is_err(), "{inner}");
+        }
+    }
+}
@@ -6,17 +6,26 @@
 //! (empty query) before real searches will return results.  [`GoogleApi::warmup_search`]
 //! should be called once at startup; subsequent [`GoogleApi::search_by_phone`]
 //! calls will re-warm automatically if the cache has gone stale (>5 min).
+//!
+//! # Errors
+//! Failed API calls are a [`GoogleError`], classified by what the caller
+//! should do about them: start over without the sync token, ask the user
+//! to sign in again, back off, retry later, or give up.  Every call goes
+//! through the account's retry policy and circuit breaker first (see
+//! [`crate::retry`]), so what reaches the caller already failed for good.
 
 use anyhow::{Context, Result};
-use google_people1::api::Person;
+use google_people1::api::{ContactGroup, ListConnectionsResponse, ModifyContactGroupMembersRequest, Person};
 use google_people1::common::FieldMask;
 use google_people1::PeopleService;
+use std::fmt;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
 use crate::auth;
 use crate::config::Config;
+use crate::retry::{self, Backoff, CircuitBreaker};
 
 // ── Hub type alias ──────────────────────────────────────────────────────
 
@@ -33,10 +42,174 @@ pub const PERSON_FIELDS: &[&str] = &[
     "addresses",
     "organizations",
     "birthdays",
+    "genders",
+    "events",
     "photos",
+    "memberships",
     "metadata",
 ];
 
+/// Subset of [`PERSON_FIELDS`] that CardDAV write-back maps from a vCard.
+/// An update only passes those it can write without losing data as
+/// `updatePersonFields` (see [`crate::vcard::changes`]).
+pub const WRITABLE_PERSON_FIELDS: &[&str] = &[
+    "names",
+    "emailAddresses",
+    "phoneNumbers",
+    "addresses",
+    "organizations",
+    "birthdays",
+];
+
+// ── Errors ──────────────────────────────────────────────────────────────
+
+/// A failed People API call, by how the caller should react.
+#[derive(Debug)]
+pub enum GoogleError {
+    /// The sync token is too old (`EXPIRED_SYNC_TOKEN`, or `410 Gone`):
+    /// list all contacts again without it.
+    SyncTokenExpired,
+    /// The refresh token was revoked or the client is no longer allowed
+    /// (`invalid_grant` and friends, `401`): the user has to sign in again.
+    AuthRevoked(String),
+    /// Rate limit or daily quota hit (`429`, `RESOURCE_EXHAUSTED`):
+    /// back off, for `retry_after` if Google said how long.
+    QuotaExceeded { retry_after: Option<Duration>, message: String },
+    /// Network failure or `5xx`: the same call may work later.
+    Transient(String),
+    /// Anything else; retrying won't help.
+    Permanent(String),
+}
+
+/// Result of a People API call.
+pub type GoogleResult<T> = std::result::Result<T, GoogleError>;
+
+impl GoogleError {
+    /// `true` for errors worth retrying unchanged ([`Self::Transient`],
+    /// [`Self::QuotaExceeded`]).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Transient(_) | Self::QuotaExceeded { .. })
+    }
+
+    /// Classify a JSON error body (`{"error": {"code", "status", "details", ...}}`).
+    fn from_body(body: &serde_json::Value) -> Self {
+        let error = &body["error"];
+        let code = error["code"].as_u64().unwrap_or(0);
+        let status = error["status"].as_str().unwrap_or_default();
+        let message = error["message"].as_str().map_or_else(|| body.to_string(), String::from);
+        // `details` (ErrorInfo) carries the reason on current APIs, the
+        // legacy `errors` list on older ones.
+        let reasons: Vec<&str> = ["details", "errors"]
+            .iter()
+            .filter_map(|key| error[*key].as_array())
+            .flatten()
+            .filter_map(|entry| entry["reason"].as_str())
+            .collect();
+        let retry_after = retry::retry_info(body);
+
+        if code == 410 || reasons.contains(&"EXPIRED_SYNC_TOKEN") {
+            Self::SyncTokenExpired
+        } else if code == 401 || status == "UNAUTHENTICATED" {
+            Self::AuthRevoked(message)
+        } else if code == 429
+            || status == "RESOURCE_EXHAUSTED"
+            || reasons.iter().any(|r| QUOTA_REASONS.contains(r))
+        {
+            Self::QuotaExceeded { retry_after, message }
+        } else if code >= 500 {
+            Self::Transient(message)
+        } else {
+            Self::Permanent(message)
+        }
+    }
+
+    /// Classify a failure whose body was not JSON, by its status code.
+    fn from_response(response: &google_people1::common::Response) -> Self {
+        let status = response.status();
+        let message = status.to_string();
+        match status.as_u16() {
+            410 => Self::SyncTokenExpired,
+            401 => Self::AuthRevoked(message),
+            429 => Self::QuotaExceeded {
+                retry_after: response
+                    .headers()
+                    .get(http::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse().ok())
+                    .map(Duration::from_secs),
+                message,
+            },
+            500.. => Self::Transient(message),
+            _ => Self::Permanent(message),
+        }
+    }
+
+    /// Classify a failure to get an access token from the authenticator.
+    fn from_token_error(err: &(dyn std::error::Error + Send + Sync + 'static)) -> Self {
+        use yup_oauth2::error::AuthErrorCode;
+        let message = err.to_string();
+        match err.downcast_ref::<yup_oauth2::Error>() {
+            Some(yup_oauth2::Error::AuthError(auth)) => match auth.error {
+                AuthErrorCode::InvalidGrant
+                | AuthErrorCode::InvalidClient
+                | AuthErrorCode::UnauthorizedClient
+                | AuthErrorCode::AccessDenied
+                | AuthErrorCode::InvalidScope
+                | AuthErrorCode::ExpiredToken => Self::AuthRevoked(message),
+                _ => Self::Permanent(message),
+            },
+            Some(
+                yup_oauth2::Error::HttpError(_)
+                | yup_oauth2::Error::HttpClientError(_)
+                | yup_oauth2::Error::LowLevelError(_),
+            ) => Self::Transient(message),
+            _ => Self::Permanent(message),
+        }
+    }
+}
+
+/// Legacy `errors[].reason` and `ErrorInfo.reason` values for rate limits.
+const QUOTA_REASONS: &[&str] = &[
+    "RATE_LIMIT_EXCEEDED",
+    "rateLimitExceeded",
+    "userRateLimitExceeded",
+    "quotaExceeded",
+    "dailyLimitExceeded",
+];
+
+impl From<google_people1::Error> for GoogleError {
+    fn from(err: google_people1::Error) -> Self {
+        use google_people1::Error;
+        match err {
+            Error::BadRequest(body) => Self::from_body(&body),
+            Error::Failure(response) => Self::from_response(&response),
+            Error::MissingToken(e) => Self::from_token_error(e.as_ref()),
+            Error::HttpError(e) => Self::Transient(e.to_string()),
+            Error::Io(e) => Self::Transient(e.to_string()),
+            other => Self::Permanent(other.to_string().trim_end().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for GoogleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SyncTokenExpired => write!(f, "sync token expired"),
+            Self::AuthRevoked(m) => write!(f, "Google sign-in revoked or invalid: {m}"),
+            Self::QuotaExceeded { retry_after: Some(d), message } => {
+                write!(f, "Google API quota exceeded (retry in {}s): {message}", d.as_secs())
+            }
+            Self::QuotaExceeded { retry_after: None, message } => {
+                write!(f, "Google API quota exceeded: {message}")
+            }
+            Self::Transient(m) => write!(f, "Google API temporarily unavailable: {m}"),
+            Self::Permanent(m) => write!(f, "Google API error: {m}"),
+        }
+    }
+}
+
+impl std::error::Error for GoogleError {}
+
 // ── GoogleApi ───────────────────────────────────────────────────────────
 
 /// Thread-safe wrapper around a `PeopleService` hub.
@@ -47,6 +220,15 @@ pub struct GoogleApi {
     hub: Arc<Hub>,
     /// Timestamp of the last successful warmup call.
     warmup_at: Arc<Mutex<Option<Instant>>>,
+    /// No on-demand searches before this, after a quota error.
+    search_hold: Arc<Mutex<Option<Instant>>>,
+    /// OAuth scopes requested on every call (read-only unless write-back
+    /// is enabled in the config).
+    scopes: &'static [&'static str],
+    /// Id of the setu account whose token this client uses.
+    account: String,
+    retry_policy: retry::Policy,
+    breaker: Arc<CircuitBreaker>,
 }
 
 /// How long a warmup remains valid before we re-warm automatically.
@@ -55,16 +237,21 @@ const WARMUP_TTL_SECS: u64 = 300;
 /// Pause after a fresh warmup before issuing the real search.
 const POST_WARMUP_DELAY_SECS: u64 = 2;
 
+/// How long to pause on-demand searches after a quota error that didn't
+/// say when to retry.
+const QUOTA_BACKOFF: Duration = Duration::from_secs(60);
+
 impl GoogleApi {
-    /// Build a fully-authenticated `GoogleApi` from the application config.
+    /// Build a fully-authenticated `GoogleApi` for `account` from the
+    /// application config.
     ///
     /// The `client_secret` is passed explicitly (loaded from the OS keyring)
     /// rather than read from the config struct.
     ///
     /// This creates the OAuth2 authenticator (with on-disk token cache) and
     /// the HTTPS + HTTP/2 client.  The returned handle is `Clone + Send + Sync`.
-    pub async fn build(config: &Config, client_secret: &str) -> Result<Self> {
-        let token_path = auth::token_file_path()?;
+    pub async fn build(config: &Config, client_secret: &str, account: &str) -> Result<Self> {
+        let token_path = auth::token_file_path(account)?;
 
         let secret = yup_oauth2::ApplicationSecret {
             client_id: config.google_client_id.clone(),
@@ -96,17 +283,106 @@ impl GoogleApi {
                 .build(connector);
 
         let hub = PeopleService::new(client, auth);
+        Ok(Self::with_hub(hub, config, account))
+    }
 
-        Ok(Self {
+    fn with_hub(hub: Hub, config: &Config, account: &str) -> Self {
+        let retry_policy = retry::Policy::from_config(config);
+        Self {
             hub: Arc::new(hub),
             warmup_at: Arc::new(Mutex::new(None)),
-        })
+            search_hold: Arc::new(Mutex::new(None)),
+            scopes: auth::scopes(config.write_back),
+            account: account.to_string(),
+            retry_policy,
+            breaker: Arc::new(CircuitBreaker::new(retry_policy)),
+        }
     }
 
-    /// Direct access to the underlying `PeopleService` hub (used by the sync
-    /// engine for `connections_list` calls).
-    pub fn hub(&self) -> &Hub {
-        &self.hub
+    /// Id of the account this client acts for.
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// OAuth scopes attached to every People API call, so reads and
+    /// writes share a single cached token.
+    pub fn scopes(&self) -> &'static [&'static str] {
+        self.scopes
+    }
+
+    /// Returns `true` if the client was built with the read/write contacts
+    /// scope (CardDAV PUT / DELETE are propagated to Google).
+    pub fn can_write(&self) -> bool {
+        self.scopes == auth::scopes(true)
+    }
+
+    // ── Retries ─────────────────────────────────────────────────────
+
+    /// Make one People API call under the retry policy: `call` installs
+    /// the [`Backoff`] it is given as the call's delegate, which retries
+    /// it (cautiously unless `replayable`); the whole call is bounded by
+    /// the policy's deadline and refused outright while the circuit
+    /// breaker is open.
+    async fn call<T>(
+        &self,
+        replayable: bool,
+        call: impl AsyncFnOnce(&mut Backoff) -> google_people1::Result<T>,
+    ) -> GoogleResult<T> {
+        if let Err(wait) = self.breaker.admit() {
+            return Err(GoogleError::Transient(format!(
+                "calls paused for {}s after repeated failures",
+                wait.as_secs().max(1)
+            )));
+        }
+
+        let mut backoff = Backoff::new(self.retry_policy, replayable);
+        let deadline = self.retry_policy.deadline;
+        let result = match tokio::time::timeout(deadline, call(&mut backoff)).await {
+            Ok(result) => result.map_err(GoogleError::from),
+            Err(_) => Err(GoogleError::Transient(format!("no answer within {}s", deadline.as_secs()))),
+        };
+        let result = match result {
+            // A `Retry-After` header only reaches us through the delegate.
+            Err(GoogleError::QuotaExceeded { retry_after: None, message }) => Err(GoogleError::QuotaExceeded {
+                retry_after: backoff.retry_after(),
+                message,
+            }),
+            other => other,
+        };
+        self.breaker.record(result.as_ref().is_err_and(GoogleError::is_retryable));
+        result
+    }
+
+    // ── Connections ─────────────────────────────────────────────────
+
+    /// One page of the signed-in user's contacts with all
+    /// [`PERSON_FIELDS`]: changes since `sync_token` if given, else all of
+    /// them.  The last page carries the next sync token.
+    pub async fn connections_page(
+        &self,
+        sync_token: Option<&str>,
+        page_token: Option<&str>,
+    ) -> GoogleResult<ListConnectionsResponse> {
+        let fields = FieldMask::new::<&str>(PERSON_FIELDS);
+        self.call(true, async |retry| {
+            let mut req = self
+                .hub
+                .people()
+                .connections_list("people/me")
+                .person_fields(fields)
+                .page_size(1000)
+                .request_sync_token(true)
+                .add_scopes(self.scopes)
+                .delegate(retry);
+            if let Some(token) = sync_token {
+                req = req.sync_token(token);
+            }
+            if let Some(token) = page_token {
+                req = req.page_token(token);
+            }
+            Ok(req.doit().await?.1)
+        })
+        .await
     }
 
     // ── Search warmup ───────────────────────────────────────────────
@@ -114,18 +390,21 @@ impl GoogleApi {
     /// Send an empty `searchContacts` request to prime Google's server-side
     /// cache.  Must be called at least once before real searches return
     /// results.
-    pub async fn warmup_search(&self) -> Result<()> {
+    pub async fn warmup_search(&self) -> GoogleResult<()> {
         let fields = FieldMask::new::<&str>(PERSON_FIELDS);
-        let _ = self
-            .hub
-            .people()
-            .search_contacts()
-            .query("")
-            .read_mask(fields)
-            .page_size(1)
-            .doit()
-            .await
-            .context("warmup searchContacts")?;
+        self.call(true, async |retry| {
+            self.hub
+                .people()
+                .search_contacts()
+                .query("")
+                .read_mask(fields)
+                .page_size(1)
+                .add_scopes(self.scopes)
+                .delegate(retry)
+                .doit()
+                .await
+        })
+        .await?;
 
         let mut state = self.warmup_at.lock().await;
         *state = Some(Instant::now());
@@ -136,14 +415,14 @@ impl GoogleApi {
     /// Returns `true` if a warmup has been performed within [`WARMUP_TTL_SECS`].
     async fn is_warm(&self) -> bool {
         let state = self.warmup_at.lock().await;
-        state.map_or(false, |t| {
+        state.is_some_and(|t| {
             t.elapsed() < std::time::Duration::from_secs(WARMUP_TTL_SECS)
         })
     }
 
     /// Ensure the search cache is warm, performing a fresh warmup + delay
     /// if necessary.
-    async fn ensure_warm(&self) -> Result<()> {
+    async fn ensure_warm(&self) -> GoogleResult<()> {
         if !self.is_warm().await {
             self.warmup_search().await?;
             tokio::time::sleep(std::time::Duration::from_secs(POST_WARMUP_DELAY_SECS)).await;
@@ -156,21 +435,43 @@ impl GoogleApi {
     /// Search Google Contacts by phone number.
     ///
     /// Automatically warms up the search cache if it has expired.
-    /// Returns `Ok(None)` when no match is found.
-    pub async fn search_by_phone(&self, number: &str) -> Result<Option<Person>> {
+    /// Returns `Ok(None)` when no match is found.  After a quota error,
+    /// searches fail with [`GoogleError::QuotaExceeded`] without calling
+    /// Google until the quota is expected back.
+    pub async fn search_by_phone(&self, number: &str) -> GoogleResult<Option<Person>> {
+        if let Some(until) = *self.search_hold.lock().await {
+            if let Some(retry_after) = until.checked_duration_since(Instant::now()) {
+                return Err(GoogleError::QuotaExceeded {
+                    retry_after: Some(retry_after),
+                    message: "searches paused after an earlier quota error".into(),
+                });
+            }
+        }
+        let result = self.search_by_phone_now(number).await;
+        if let Err(GoogleError::QuotaExceeded { retry_after, .. }) = &result {
+            *self.search_hold.lock().await = Some(Instant::now() + retry_after.unwrap_or(QUOTA_BACKOFF));
+        }
+        result
+    }
+
+    async fn search_by_phone_now(&self, number: &str) -> GoogleResult<Option<Person>> {
         self.ensure_warm().await?;
 
         let fields = FieldMask::new::<&str>(PERSON_FIELDS);
         let (_resp, result) = self
-            .hub
-            .people()
-            .search_contacts()
-            .query(number)
-            .read_mask(fields)
-            .page_size(5)
-            .doit()
-            .await
-            .context("searchContacts by phone")?;
+            .call(true, async |retry| {
+                self.hub
+                    .people()
+                    .search_contacts()
+                    .query(number)
+                    .read_mask(fields)
+                    .page_size(5)
+                    .add_scopes(self.scopes)
+                    .delegate(retry)
+                    .doit()
+                    .await
+            })
+            .await?;
 
         let person = result
             .results
@@ -178,4 +479,451 @@ impl GoogleApi {
 
         Ok(person)
     }
+
+    // ── Write-back ──────────────────────────────────────────────────
+
+    /// Create a new contact in Google and return it with all
+    /// [`PERSON_FIELDS`] populated (including the assigned resource name).
+    pub async fn create_contact(&self, person: Person) -> GoogleResult<Person> {
+        let fields = FieldMask::new::<&str>(PERSON_FIELDS);
+        let (_resp, created) = self
+            .call(false, async |retry| {
+                self.hub
+                    .people()
+                    .create_contact(person)
+                    .person_fields(fields)
+                    .add_scopes(self.scopes)
+                    .delegate(retry)
+                    .doit()
+                    .await
+            })
+            .await?;
+        Ok(created)
+    }
+
+    /// Fetch one contact with [`PERSON_FIELDS`].
+    pub async fn get_contact(&self, resource_name: &str) -> GoogleResult<Person> {
+        let fields = FieldMask::new::<&str>(PERSON_FIELDS);
+        let (_resp, person) = self
+            .call(true, async |retry| {
+                self.hub
+                    .people()
+                    .get(resource_name)
+                    .person_fields(fields)
+                    .add_scopes(self.scopes)
+                    .delegate(retry)
+                    .doit()
+                    .await
+            })
+            .await?;
+        Ok(person)
+    }
+
+    /// Replace `update_fields` (some of [`WRITABLE_PERSON_FIELDS`]) of an
+    /// existing contact.
+    ///
+    /// `person.etag` must carry the etag last seen from Google; the API
+    /// rejects the update if the contact changed in the meantime.
+    pub async fn update_contact(
+        &self,
+        resource_name: &str,
+        person: Person,
+        update_fields: &[&str],
+    ) -> GoogleResult<Person> {
+        let update_fields = FieldMask::new(update_fields);
+        let fields = FieldMask::new::<&str>(PERSON_FIELDS);
+        let (_resp, updated) = self
+            .call(false, async |retry| {
+                self.hub
+                    .people()
+                    .update_contact(person, resource_name)
+                    .update_person_fields(update_fields)
+                    .person_fields(fields)
+                    .add_scopes(self.scopes)
+                    .delegate(retry)
+                    .doit()
+                    .await
+            })
+            .await?;
+        Ok(updated)
+    }
+
+    /// Delete a contact in Google.
+    pub async fn delete_contact(&self, resource_name: &str) -> GoogleResult<()> {
+        self.call(true, async |retry| {
+            self.hub
+                .people()
+                .delete_contact(resource_name)
+                .add_scopes(self.scopes)
+                .delegate(retry)
+                .doit()
+                .await
+        })
+        .await?;
+        Ok(())
+    }
+
+    // ── Contact groups ──────────────────────────────────────────────
+
+    /// List all contact groups (user labels and system groups such as
+    /// "starred"), following pagination.
+    pub async fn list_contact_groups(&self) -> GoogleResult<Vec<ContactGroup>> {
+        let mut groups = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let (_resp, body) = self
+                .call(true, async |retry| {
+                    let mut req = self
+                        .hub
+                        .contact_groups()
+                        .list()
+                        .group_fields(FieldMask::new(&["name", "groupType", "metadata"]))
+                        .page_size(1000)
+                        .add_scopes(self.scopes)
+                        .delegate(retry);
+                    if let Some(ref pt) = page_token {
+                        req = req.page_token(pt);
+                    }
+                    req.doit().await
+                })
+                .await?;
+            groups.extend(body.contact_groups.unwrap_or_default());
+
+            match body.next_page_token {
+                Some(pt) => page_token = Some(pt),
+                None => break,
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Remove a contact from a contact group (the contact itself is kept).
+    pub async fn remove_from_contact_group(&self, group: &str, resource_name: &str) -> GoogleResult<()> {
+        let req = ModifyContactGroupMembersRequest {
+            resource_names_to_remove: Some(vec![resource_name.to_string()]),
+            ..Default::default()
+        };
+        self.call(true, async |retry| {
+            self.hub
+                .contact_groups()
+                .members_modify(req, group)
+                .add_scopes(self.scopes)
+                .delegate(retry)
+                .doit()
+                .await
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Resource names of the contact groups a person belongs to, or `None` if
+/// the response did not include memberships at all.
+pub fn group_memberships(person: &Person) -> Option<Vec<String>> {
+    let memberships = person.memberships.as_ref()?;
+    Some(
+        memberships
+            .iter()
+            .filter_map(|m| m.contact_group_membership.as_ref())
+            .filter_map(|g| g.contact_group_resource_name.clone())
+            .collect(),
+    )
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn classify(body: serde_json::Value) -> GoogleError {
+        google_people1::Error::BadRequest(body).into()
+    }
+
+    #[test]
+    fn classifies_error_bodies() {
+        let expired = json!({"error": {
+            "code": 400,
+            "message": "Sync token is expired. Clear local cache and retry call without the sync token.",
+            "status": "FAILED_PRECONDITION",
+            "details": [{"@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "EXPIRED_SYNC_TOKEN"}]
+        }});
+        assert!(matches!(classify(expired), GoogleError::SyncTokenExpired));
+        assert!(matches!(classify(json!({"error": {"code": 410}})), GoogleError::SyncTokenExpired));
+
+        // Other failed preconditions are not an expired token.
+        let precondition = json!({"error": {"code": 400, "message": "expired etag", "status": "FAILED_PRECONDITION"}});
+        assert!(matches!(classify(precondition), GoogleError::Permanent(m) if m == "expired etag"));
+
+        let unauthenticated = json!({"error": {"code": 401, "message": "Invalid Credentials", "status": "UNAUTHENTICATED"}});
+        assert!(matches!(classify(unauthenticated), GoogleError::AuthRevoked(_)));
+
+        let quota = json!({"error": {
+            "code": 429,
+            "message": "Quota exceeded",
+            "status": "RESOURCE_EXHAUSTED",
+            "details": [{"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "12.5s"}]
+        }});
+        match classify(quota) {
+            GoogleError::QuotaExceeded { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_millis(12_500)))
+            }
+            other => panic!("{other:?}"),
+        }
+        let legacy_quota = json!({"error": {"code": 403, "errors": [{"reason": "userRateLimitExceeded"}]}});
+        assert!(matches!(classify(legacy_quota), GoogleError::QuotaExceeded { retry_after: None, .. }));
+
+        let unavailable = json!({"error": {"code": 503, "message": "The service is currently unavailable.", "status": "UNAVAILABLE"}});
+        let err = classify(unavailable);
+        assert!(err.is_retryable());
+        assert_eq!(err.to_string(), "Google API temporarily unavailable: The service is currently unavailable.");
+
+        let denied = json!({"error": {"code": 403, "message": "The caller does not have permission", "status": "PERMISSION_DENIED"}});
+        assert!(!classify(denied).is_retryable());
+    }
+
+    #[test]
+    fn classifies_non_json_failures() {
+        use http_body_util::BodyExt;
+        let response = |status: u16, retry_after: Option<&str>| {
+            let mut builder = hyper::Response::builder().status(status);
+            if let Some(secs) = retry_after {
+                builder = builder.header(http::header::RETRY_AFTER, secs);
+            }
+            let body = http_body_util::Empty::<hyper::body::Bytes>::new().map_err(|never| match never {}).boxed();
+            google_people1::Error::Failure(builder.body(body).unwrap()).into()
+        };
+
+        assert!(matches!(response(410, None), GoogleError::SyncTokenExpired));
+        assert!(matches!(response(401, None), GoogleError::AuthRevoked(_)));
+        assert!(matches!(
+            response(429, Some("30")),
+            GoogleError::QuotaExceeded { retry_after: Some(d), .. } if d == Duration::from_secs(30)
+        ));
+        assert!(matches!(response(502, None), GoogleError::Transient(_)));
+        assert!(matches!(response(404, None), GoogleError::Permanent(_)));
+    }
+
+    #[test]
+    fn classifies_token_failures() {
+        use yup_oauth2::error::{AuthError, AuthErrorCode};
+        let token_error = |err: yup_oauth2::Error| -> GoogleError {
+            google_people1::Error::MissingToken(Box::new(err)).into()
+        };
+        let auth_error = |error| {
+            yup_oauth2::Error::AuthError(AuthError {
+                error,
+                error_description: Some("Token has been expired or revoked.".into()),
+                error_uri: None,
+            })
+        };
+
+        assert!(matches!(token_error(auth_error(AuthErrorCode::InvalidGrant)), GoogleError::AuthRevoked(_)));
+        assert!(matches!(token_error(auth_error(AuthErrorCode::InvalidRequest)), GoogleError::Permanent(_)));
+        let offline = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "offline");
+        assert!(matches!(token_error(yup_oauth2::Error::LowLevelError(offline)), GoogleError::Transient(_)));
+        assert!(matches!(
+            google_people1::Error::MissingToken("no token".into()).into(),
+            GoogleError::Permanent(_)
+        ));
+    }
+
+    // ── Retries against a fake People API ───────────────────────────
+
+    /// What the fake server answers to its `n`-th request (0-based).
+    struct Reply {
+        status: u16,
+        retry_after: Option<&'static str>,
+        delay: Duration,
+    }
+
+    fn reply(status: u16) -> Reply {
+        Reply { status, retry_after: None, delay: Duration::ZERO }
+    }
+
+    /// Serve `script` on a local port; returns a client pointed at it
+    /// and the number of requests served so far.
+    async fn fake_google(
+        script: fn(usize) -> Reply,
+        config: Config,
+    ) -> (GoogleApi, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+        let app = axum::Router::new().fallback(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let Reply { status, retry_after, delay } = script(n);
+                tokio::time::sleep(delay).await;
+                let body = match status {
+                    200 => json!({"connections": [], "nextSyncToken": "t1"}),
+                    _ => json!({"error": {"code": status, "message": format!("status {status}")}}),
+                };
+                let mut response = axum::response::IntoResponse::into_response((
+                    axum::http::StatusCode::from_u16(status).unwrap(),
+                    axum::Json(body),
+                ));
+                if let Some(secs) = retry_after {
+                    response.headers_mut().insert("retry-after", secs.parse().unwrap());
+                }
+                response
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http2()
+            .build();
+        let client =
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .build(connector);
+        let mut hub = PeopleService::new(client, "token".to_string());
+        hub.base_url(format!("http://{addr}/"));
+        hub.root_url(format!("http://{addr}/"));
+        (GoogleApi::with_hub(hub, &config, "default"), served)
+    }
+
+    fn retry_config() -> Config {
+        Config {
+            google_max_retries: 2,
+            google_retry_base_ms: 10,
+            google_retry_max_ms: 50,
+            google_call_timeout_secs: 5,
+            google_breaker_threshold: 0,
+            ..Config::default()
+        }
+    }
+
+    fn served(counter: &std::sync::atomic::AtomicUsize) -> usize {
+        counter.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let (api, count) = fake_google(|n| reply(if n < 2 { 503 } else { 200 }), retry_config()).await;
+        let page = api.connections_page(None, None).await.unwrap();
+        assert_eq!(page.next_sync_token.as_deref(), Some("t1"));
+        assert_eq!(served(&count), 3);
+
+        // Out of retries: the error is surfaced as transient.
+        let (api, count) = fake_google(|_| reply(503), retry_config()).await;
+        let err = api.connections_page(None, None).await.unwrap_err();
+        assert!(matches!(err, GoogleError::Transient(_)), "{err:?}");
+        assert_eq!(served(&count), 3);
+
+        // Client errors are not retried.
+        let (api, count) = fake_google(|_| reply(404), retry_config()).await;
+        let err = api.connections_page(None, None).await.unwrap_err();
+        assert!(matches!(err, GoogleError::Permanent(_)), "{err:?}");
+        assert_eq!(served(&count), 1);
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let script = |n| match n {
+            0 => Reply { retry_after: Some("1"), ..reply(429) },
+            _ => reply(200),
+        };
+        let (api, count) = fake_google(script, retry_config()).await;
+        let start = Instant::now();
+        api.connections_page(None, None).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(served(&count), 2);
+
+        // A delay beyond the deadline is not waited out but reported.
+        let script = |_| Reply { retry_after: Some("120"), ..reply(429) };
+        let (api, count) = fake_google(script, retry_config()).await;
+        match api.connections_page(None, None).await.unwrap_err() {
+            GoogleError::QuotaExceeded { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(120)))
+            }
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(served(&count), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_at_the_deadline() {
+        let script = |_| Reply { delay: Duration::from_secs(30), ..reply(200) };
+        let config = Config { google_call_timeout_secs: 1, ..retry_config() };
+        let (api, _) = fake_google(script, config).await;
+        let start = Instant::now();
+        let err = api.connections_page(None, None).await.unwrap_err();
+        assert!(matches!(err, GoogleError::Transient(_)), "{err:?}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn writes_are_retried_only_when_safe() {
+        let (api, count) = fake_google(|_| reply(500), retry_config()).await;
+        assert!(api.create_contact(Person::default()).await.is_err());
+        assert_eq!(served(&count), 1);
+
+        let (api, count) = fake_google(|n| reply(if n == 0 { 503 } else { 200 }), retry_config()).await;
+        api.create_contact(Person::default()).await.unwrap();
+        assert_eq!(served(&count), 2);
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_pauses_calls() {
+        let config = Config {
+            google_max_retries: 0,
+            google_breaker_threshold: 2,
+            google_breaker_cooldown_secs: 1,
+            ..retry_config()
+        };
+        let (api, count) = fake_google(|n| reply(if n < 2 { 503 } else { 200 }), config).await;
+        for _ in 0..2 {
+            assert!(api.connections_page(None, None).await.is_err());
+        }
+        assert_eq!(served(&count), 2);
+
+        // Open: refused without a request.
+        let err = api.delete_contact("people/c1").await.unwrap_err();
+        assert!(matches!(err, GoogleError::Transient(ref m) if m.contains("paused")), "{err:?}");
+        assert_eq!(served(&count), 2);
+
+        // After the cooldown a trial call goes out and closes it.
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        api.connections_page(None, None).await.unwrap();
+        api.connections_page(None, None).await.unwrap();
+        assert_eq!(served(&count), 4);
+    }
+
+    #[tokio::test]
+    async fn dropped_trial_call_does_not_wedge_the_breaker() {
+        let config = Config {
+            google_max_retries: 0,
+            google_call_timeout_secs: 1,
+            google_breaker_threshold: 1,
+            google_breaker_cooldown_secs: 1,
+            ..retry_config()
+        };
+        let script = |n| match n {
+            0 => reply(503),
+            1 => Reply { delay: Duration::from_secs(30), ..reply(200) },
+            _ => reply(200),
+        };
+        let (api, count) = fake_google(script, config).await;
+        assert!(api.connections_page(None, None).await.is_err());
+
+        // The trial's caller goes away (a client disconnecting mid-search).
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let trial = tokio::time::timeout(Duration::from_millis(200), api.connections_page(None, None)).await;
+        assert!(trial.is_err());
+        assert!(api.connections_page(None, None).await.is_err());
+        assert_eq!(served(&count), 2);
+
+        // After the trial's deadline the next call is let through.
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        api.connections_page(None, None).await.unwrap();
+        assert_eq!(served(&count), 3);
+    }
 }
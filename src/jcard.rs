@@ -0,0 +1,306 @@
+//! jCard (RFC 7095) and xCard (RFC 6351) — the JSON and XML forms of a
+//! vCard 4.0.
+//!
+//! Both are converted from the vCard text rendered by
+//! [`crate::vcard::person_to_vcard4`], so every representation carries the
+//! same properties as the vCard itself.  A 3.0 vCard (contacts cached
+//! before 4.0 rendering existed) converts as well; only its `VERSION` is
+//! replaced.
+
+use serde_json::{json, Map, Value};
+
+use crate::vcard::{parse_properties, Property};
+use crate::xml::escape;
+
+/// xCard namespace.
+pub const XCARD_NS: &str = "urn:ietf:params:xml:ns:vcard-4.0";
+
+// ── Value types ─────────────────────────────────────────────────────────
+
+/// Component names of the structured properties, in value order
+/// (RFC 6351 §3.4 uses them as element names).
+fn components_of(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "N" => Some(&["surname", "given", "additional", "prefix", "suffix"]),
+        "ADR" => Some(&[
+            "pobox", "ext", "street", "locality", "region", "code", "country",
+        ]),
+        "GENDER" => Some(&["sex", "identity"]),
+        _ => None,
+    }
+}
+
+/// Value type of a property (RFC 6350 §4): its `VALUE` parameter, else
+/// the property's default type.
+fn value_type(prop: &Property) -> String {
+    if let Some((_, value)) = prop.params.iter().find(|(k, _)| k == "VALUE") {
+        return value.to_ascii_lowercase();
+    }
+    match prop.name.as_str() {
+        "BDAY" | "ANNIVERSARY" => "date-and-or-time",
+        "REV" => "timestamp",
+        "PHOTO" | "LOGO" | "SOUND" | "URL" | "KEY" | "SOURCE" | "IMPP" | "MEMBER" | "FBURL"
+        | "CALADRURI" | "CALURI" | "GEO" => "uri",
+        _ => "text",
+    }
+    .to_string()
+}
+
+/// RFC 6350 basic-format dates and timestamps (`19850412`, `--0412`,
+/// `20240502T083000Z`) in the extended format jCard requires
+/// (`1985-04-12`, `--04-12`, `2024-05-02T08:30:00Z`).  Values that are
+/// already extended pass through unchanged.
+fn extended_date_time(value: &str) -> String {
+    let (date, time) = match value.split_once('T') {
+        Some((d, t)) => (d, Some(t)),
+        None => (value, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    let date = match date.strip_prefix("--") {
+        Some(md) if md.len() == 4 && all_digits(md) => format!("--{}-{}", &md[..2], &md[2..]),
+        None if date.len() == 8 && all_digits(date) => {
+            format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+        }
+        _ => date.to_string(),
+    };
+    let Some(time) = time else {
+        return date;
+    };
+    let split = time.find(['Z', '+', '-']).unwrap_or(time.len());
+    let (clock, zone) = time.split_at(split);
+    let clock = if clock.len() == 6 && all_digits(clock) {
+        format!("{}:{}:{}", &clock[..2], &clock[2..4], &clock[4..])
+    } else {
+        clock.to_string()
+    };
+    format!("{date}T{clock}{zone}")
+}
+
+/// The properties worth converting: everything but the BEGIN/END framing.
+fn content_properties(vcard: &str) -> impl Iterator<Item = Property> {
+    parse_properties(vcard)
+        .into_iter()
+        .filter(|p| p.name != "BEGIN" && p.name != "END")
+}
+
+/// Parameters to carry over, with lower-case names.  `VALUE` is not a
+/// parameter in jCard / xCard — it becomes the value type.
+fn params(prop: &Property) -> Vec<(String, Vec<String>)> {
+    let mut out: Vec<(String, Vec<String>)> = Vec::new();
+    for (key, value) in prop.params.iter().filter(|(k, _)| k != "VALUE") {
+        let key = key.to_ascii_lowercase();
+        match out.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(value.clone()),
+            None => out.push((key, vec![value.clone()])),
+        }
+    }
+    out
+}
+
+// ── jCard ───────────────────────────────────────────────────────────────
+
+/// Convert a vCard to its jCard form, e.g.
+/// `["vcard",[["version",{},"text","4.0"],["fn",{},"text","Jane Doe"]]]`.
+pub fn to_jcard(vcard: &str) -> String {
+    let properties: Vec<Value> = content_properties(vcard)
+        .map(|prop| {
+            if prop.name == "VERSION" {
+                return json!(["version", {}, "text", "4.0"]);
+            }
+            let mut parameters = Map::new();
+            if let Some(group) = &prop.group {
+                parameters.insert("group".into(), json!(group.to_ascii_lowercase()));
+            }
+            for (key, mut values) in params(&prop) {
+                let value = match values.len() {
+                    1 => Value::String(values.remove(0)),
+                    _ => json!(values),
+                };
+                parameters.insert(key, value);
+            }
+
+            let kind = value_type(&prop);
+            // N and ADR are always arrays; GENDER and ORG only when they
+            // have more than one component (RFC 7095 §3.3.1.3).
+            let value = if matches!(prop.name.as_str(), "N" | "ADR") {
+                json!(prop.components())
+            } else if matches!(prop.name.as_str(), "GENDER" | "ORG") {
+                match prop.components() {
+                    parts if parts.len() == 1 => json!(parts[0]),
+                    parts => json!(parts),
+                }
+            } else {
+                match kind.as_str() {
+                    "text" => json!(prop.text()),
+                    "uri" => json!(prop.value),
+                    _ => json!(extended_date_time(&prop.value)),
+                }
+            };
+            json!([prop.name.to_ascii_lowercase(), parameters, kind, value])
+        })
+        .collect();
+
+    json!(["vcard", properties]).to_string()
+}
+
+// ── xCard ───────────────────────────────────────────────────────────────
+
+/// Convert a vCard to a complete xCard document (`<vcards>` with one
+/// `<vcard>`).  `VERSION` is implied by the namespace and left out.
+pub fn to_xcard(vcard: &str) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<vcards xmlns=\"{XCARD_NS}\">\n  <vcard>\n"
+    );
+    for prop in content_properties(vcard).filter(|p| p.name != "VERSION") {
+        let name = prop.name.to_ascii_lowercase();
+        let mut element = format!("<{name}>");
+
+        let parameters = params(&prop);
+        if !parameters.is_empty() {
+            element.push_str("<parameters>");
+            for (key, values) in parameters {
+                let kind = if key == "pref" { "integer" } else { "text" };
+                element.push_str(&format!("<{key}>"));
+                for value in values {
+                    element.push_str(&format!("<{kind}>{}</{kind}>", escape(&value)));
+                }
+                element.push_str(&format!("</{key}>"));
+            }
+            element.push_str("</parameters>");
+        }
+
+        if let Some(names) = components_of(&prop.name) {
+            let values = prop.components();
+            for (i, component) in names.iter().enumerate() {
+                match values.get(i).filter(|v| !v.is_empty()) {
+                    Some(v) => {
+                        element.push_str(&format!("<{component}>{}</{component}>", escape(v)))
+                    }
+                    None => element.push_str(&format!("<{component}/>")),
+                }
+            }
+        } else if prop.name == "ORG" {
+            for part in prop.components() {
+                element.push_str(&format!("<text>{}</text>", escape(&part)));
+            }
+        } else {
+            let kind = value_type(&prop);
+            let value = if kind == "text" {
+                prop.text()
+            } else {
+                prop.value.clone()
+            };
+            element.push_str(&format!("<{kind}>{}</{kind}>", escape(&value)));
+        }
+        element.push_str(&format!("</{name}>"));
+
+        match &prop.group {
+            Some(group) => xml.push_str(&format!(
+                "    <group name=\"{}\">{element}</group>\n",
+                escape(&group.to_ascii_lowercase())
+            )),
+            None => xml.push_str(&format!("    {element}\n")),
+        }
+    }
+    xml.push_str("  </vcard>\n</vcards>\n");
+    xml
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VCARD4: &str = "BEGIN:VCARD\r\nVERSION:4.0\r\nKIND:individual\r\nUID:people-c1\r\n\
+        N:Doe;Jane;;;\r\nFN:Jane\\, Doe\r\nEMAIL;TYPE=home:jane@example.com\r\n\
+        ADR;TYPE=home:;;123 Main St;Springfield;IL;62701;US\r\nORG:Acme Corp\r\n\
+        BDAY:19900315\r\nANNIVERSARY:--0601\r\nGENDER:F\r\n\
+        PHOTO;MEDIATYPE=image/jpeg:https://lh3.google.com/p\r\nREV:20240502T083000Z\r\nEND:VCARD\r\n";
+
+    #[test]
+    fn extended_date_time_formats() {
+        assert_eq!(extended_date_time("19900315"), "1990-03-15");
+        assert_eq!(extended_date_time("--0601"), "--06-01");
+        assert_eq!(
+            extended_date_time("20240502T083000Z"),
+            "2024-05-02T08:30:00Z"
+        );
+        assert_eq!(extended_date_time("1990-03-15"), "1990-03-15");
+        assert_eq!(
+            extended_date_time("2024-05-02T08:30:00Z"),
+            "2024-05-02T08:30:00Z"
+        );
+    }
+
+    #[test]
+    fn jcard_maps_types_and_structures() {
+        let jcard: Value = serde_json::from_str(&to_jcard(VCARD4)).unwrap();
+        assert_eq!(jcard[0], "vcard");
+        let props = jcard[1].as_array().unwrap();
+        let find = |name: &str| props.iter().find(|p| p[0] == name).unwrap().clone();
+
+        assert_eq!(find("version"), json!(["version", {}, "text", "4.0"]));
+        assert_eq!(find("fn"), json!(["fn", {}, "text", "Jane, Doe"]));
+        assert_eq!(
+            find("n"),
+            json!(["n", {}, "text", ["Doe", "Jane", "", "", ""]])
+        );
+        assert_eq!(
+            find("email"),
+            json!(["email", {"type": "home"}, "text", "jane@example.com"])
+        );
+        assert_eq!(find("org"), json!(["org", {}, "text", "Acme Corp"]));
+        assert_eq!(
+            find("bday"),
+            json!(["bday", {}, "date-and-or-time", "1990-03-15"])
+        );
+        assert_eq!(find("anniversary")[3], "--06-01");
+        assert_eq!(find("gender"), json!(["gender", {}, "text", "F"]));
+        assert_eq!(
+            find("photo"),
+            json!(["photo", {"mediatype": "image/jpeg"}, "uri", "https://lh3.google.com/p"])
+        );
+        assert_eq!(
+            find("rev"),
+            json!(["rev", {}, "timestamp", "2024-05-02T08:30:00Z"])
+        );
+        assert!(!props.iter().any(|p| p[0] == "begin" || p[0] == "end"));
+    }
+
+    #[test]
+    fn jcard_from_vcard3_reports_version_4() {
+        let jcard: Value = serde_json::from_str(&to_jcard(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:A\r\nEND:VCARD\r\n",
+        ))
+        .unwrap();
+        assert_eq!(jcard[1][0], json!(["version", {}, "text", "4.0"]));
+    }
+
+    #[test]
+    fn xcard_document_structure() {
+        let xcard = to_xcard(VCARD4);
+        assert!(xcard.contains("<vcards xmlns=\"urn:ietf:params:xml:ns:vcard-4.0\">"));
+        assert!(!xcard.contains("<version>"));
+        assert!(xcard.contains("<fn><text>Jane, Doe</text></fn>"));
+        assert!(xcard.contains(
+            "<n><surname>Doe</surname><given>Jane</given><additional/><prefix/><suffix/></n>"
+        ));
+        assert!(xcard.contains(
+            "<email><parameters><type><text>home</text></type></parameters><text>jane@example.com</text></email>"
+        ));
+        assert!(xcard.contains("<adr><parameters><type><text>home</text></type></parameters><pobox/><ext/><street>123 Main St</street>"));
+        assert!(xcard.contains("<bday><date-and-or-time>19900315</date-and-or-time></bday>"));
+        assert!(xcard.contains("<gender><sex>F</sex><identity/></gender>"));
+
+        // Well-formed, and in the xCard namespace.
+        let root = crate::xml::parse(&xcard).unwrap();
+        assert!(root.is(XCARD_NS, "vcards"));
+        assert!(root
+            .child(XCARD_NS, "vcard")
+            .unwrap()
+            .child(XCARD_NS, "fn")
+            .is_some());
+    }
+}
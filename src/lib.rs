@@ -4,11 +4,18 @@
 //! so unit tests can run on the host (Linux) without linking the full
 //! Windows GUI / tray dependencies.
 
+pub mod app_password;
 pub mod auth;
 pub mod config;
 pub mod db;
+pub mod filter;
 pub mod google_api;
+pub mod jcard;
+pub mod lockout;
+pub mod retry;
 pub mod server;
+pub mod sync;
 pub mod tls;
 pub mod vault;
 pub mod vcard;
+pub mod xml;
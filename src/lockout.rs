@@ -0,0 +1,465 @@
+//! Brute-force protection for CardDAV Basic Auth.
+//!
+//! Failed logins are counted per client IP and per username.  Once either
+//! counter reaches `max_failures`, every attempt from that IP or for that
+//! username is refused — even with the right password — until the lockout
+//! runs out.  The first lockout lasts `lockout`; each further failure
+//! doubles it, up to `max_lockout`.
+//!
+//! While its password is verified, an attempt holds a reservation that
+//! counts against `max_failures` like a failure (see
+//! [`Lockout::begin_attempt`]).  Once failures and reservations reach the
+//! limit, further attempts wait for one to finish, so parallel guesses
+//! cannot all get past the check during the slow verification.  Only a
+//! confirmed wrong password starts a lockout: a burst of correct logins
+//! just queues briefly, and a reservation is released when its attempt
+//! succeeds, errors or is dropped.  A successful login resets both
+//! counters.  Counters without a failure for `max_lockout` are forgotten,
+//! so an occasional typo never adds up.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::config::Config;
+
+/// Lockout thresholds (see [`Config::auth_max_failures`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Failures before the first lockout; 0 disables lockouts.
+    pub max_failures: u32,
+    pub lockout: Duration,
+    pub max_lockout: Duration,
+}
+
+impl Policy {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            max_failures: cfg.auth_max_failures,
+            lockout: Duration::from_secs(cfg.auth_lockout_secs),
+            max_lockout: Duration::from_secs(cfg.auth_max_lockout_secs.max(cfg.auth_lockout_secs)),
+        }
+    }
+
+    /// Returns `true` if `failures` failures and attempts in flight leave
+    /// no room for another attempt.
+    fn exhausted(&self, failures: u32) -> bool {
+        self.max_failures != 0 && failures >= self.max_failures
+    }
+
+    /// Lockout after `failures` consecutive failures, if any.
+    fn lockout_after(&self, failures: u32) -> Option<Duration> {
+        if self.max_failures == 0 || failures < self.max_failures {
+            return None;
+        }
+        let doublings = (failures - self.max_failures).min(31);
+        Some(self.lockout.saturating_mul(1 << doublings).min(self.max_lockout))
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+/// What a failed attempt was counted towards.
+#[derive(Hash, PartialEq, Eq, Debug, Clone)]
+enum Key {
+    Ip(IpAddr),
+    User(String),
+}
+
+#[derive(Debug)]
+struct Counter {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+    /// Attempts being verified.
+    in_flight: u32,
+}
+
+/// Result of [`Attempt::failed`] or [`Lockout::record_failure`], for the
+/// security log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    /// Highest consecutive failure count of the IP and the username.
+    pub failures: u32,
+    /// Lockout started by this failure, if any.
+    pub lockout: Option<Duration>,
+}
+
+/// Failed-login counters shared by all connections.
+#[derive(Debug)]
+pub struct Lockout {
+    policy: Policy,
+    counters: Mutex<HashMap<Key, Counter>>,
+    /// Signalled whenever an attempt releases its reservation.
+    released: Notify,
+}
+
+/// A login attempt let through by [`Lockout::begin_attempt`].  Its
+/// reservation is released when it is resolved or dropped.
+#[derive(Debug)]
+pub struct Attempt<'a> {
+    lockout: &'a Lockout,
+    ip: Option<IpAddr>,
+    username: String,
+}
+
+impl Attempt<'_> {
+    /// The password was right: reset the counters.
+    pub fn succeeded(self) {
+        self.lockout.record_success(self.ip, &self.username);
+    }
+
+    /// The password was wrong: count it.
+    pub fn failed(self) -> Failure {
+        self.lockout.record_failure(self.ip, &self.username)
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        self.lockout.release(self.ip, &self.username);
+    }
+}
+
+/// Outcome of [`Lockout::try_begin_attempt_at`].
+#[derive(Debug)]
+enum Admission<'a> {
+    Admitted(Attempt<'a>),
+    /// Failures and attempts in flight are at the limit.
+    Busy,
+    Locked(Duration),
+}
+
+impl Lockout {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            counters: Mutex::new(HashMap::new()),
+            released: Notify::new(),
+        }
+    }
+
+    /// Time left before `username` may try again from `ip`, if locked out.
+    pub fn locked(&self, ip: Option<IpAddr>, username: &str) -> Option<Duration> {
+        self.locked_at(ip, username, Instant::now())
+    }
+
+    /// Start a login attempt from `ip` as `username`: refused with the
+    /// time left while locked out, otherwise let through with a
+    /// reservation once the failures and attempts in flight leave room.
+    pub async fn begin_attempt(
+        &self,
+        ip: Option<IpAddr>,
+        username: &str,
+    ) -> Result<Attempt<'_>, Duration> {
+        loop {
+            // Registered before the check so no release in between is missed.
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            match self.try_begin_attempt_at(ip, username, Instant::now()) {
+                Admission::Admitted(attempt) => return Ok(attempt),
+                Admission::Locked(remaining) => return Err(remaining),
+                Admission::Busy => released.await,
+            }
+        }
+    }
+
+    /// Count a failed login from `ip` as `username`.
+    pub fn record_failure(&self, ip: Option<IpAddr>, username: &str) -> Failure {
+        self.record_failure_at(ip, username, Instant::now())
+    }
+
+    /// Reset the counters after a successful login.  Reservations of
+    /// other attempts in flight are kept.
+    pub fn record_success(&self, ip: Option<IpAddr>, username: &str) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        for key in keys(ip, username) {
+            if let Some(counter) = counters.get_mut(&key) {
+                counter.failures = 0;
+                counter.locked_until = None;
+                if counter.in_flight == 0 {
+                    counters.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn try_begin_attempt_at(
+        &self,
+        ip: Option<IpAddr>,
+        username: &str,
+        now: Instant,
+    ) -> Admission<'_> {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(remaining) = locked_in(&counters, ip, username, now) {
+            return Admission::Locked(remaining);
+        }
+        let keys = keys(ip, username);
+        let busy = keys.iter().any(|key| {
+            counters
+                .get(key)
+                .is_some_and(|c| self.policy.exhausted(c.failures.saturating_add(c.in_flight)))
+        });
+        if busy {
+            return Admission::Busy;
+        }
+        for key in keys {
+            let counter = counters.entry(key).or_insert(Counter {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+                in_flight: 0,
+            });
+            counter.in_flight += 1;
+        }
+        Admission::Admitted(Attempt {
+            lockout: self,
+            ip,
+            username: username.to_string(),
+        })
+    }
+
+    /// Drop the reservation of an attempt and wake attempts waiting for one.
+    fn release(&self, ip: Option<IpAddr>, username: &str) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        for key in keys(ip, username) {
+            if let Some(counter) = counters.get_mut(&key) {
+                counter.in_flight = counter.in_flight.saturating_sub(1);
+                let idle = counter.failures == 0 && counter.locked_until.is_none();
+                if idle && counter.in_flight == 0 {
+                    counters.remove(&key);
+                }
+            }
+        }
+        drop(counters);
+        self.released.notify_waiters();
+    }
+
+    fn locked_at(&self, ip: Option<IpAddr>, username: &str, now: Instant) -> Option<Duration> {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        locked_in(&counters, ip, username, now)
+    }
+
+    fn record_failure_at(&self, ip: Option<IpAddr>, username: &str, now: Instant) -> Failure {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        self.count_failure(&mut counters, ip, username, now)
+    }
+
+    fn count_failure(
+        &self,
+        counters: &mut HashMap<Key, Counter>,
+        ip: Option<IpAddr>,
+        username: &str,
+        now: Instant,
+    ) -> Failure {
+        let forget_after = self.policy.max_lockout.max(self.policy.lockout);
+        counters.retain(|_, c| {
+            now.duration_since(c.last_failure) < forget_after
+                || c.locked_until.is_some_and(|until| until > now)
+                || c.in_flight > 0
+        });
+
+        let mut result = Failure { failures: 0, lockout: None };
+        for key in keys(ip, username) {
+            let counter = counters.entry(key).or_insert(Counter {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+                in_flight: 0,
+            });
+            counter.failures = counter.failures.saturating_add(1);
+            counter.last_failure = now;
+            if let Some(lockout) = self.policy.lockout_after(counter.failures) {
+                counter.locked_until = Some(now + lockout);
+                result.lockout = result.lockout.max(Some(lockout));
+            }
+            result.failures = result.failures.max(counter.failures);
+        }
+        result
+    }
+}
+
+/// Time left of the longer lockout of `ip` and `username`, if any.
+fn locked_in(
+    counters: &HashMap<Key, Counter>,
+    ip: Option<IpAddr>,
+    username: &str,
+    now: Instant,
+) -> Option<Duration> {
+    keys(ip, username)
+        .iter()
+        .filter_map(|key| counters.get(key)?.locked_until)
+        .filter(|until| *until > now)
+        .max()
+        .map(|until| until - now)
+}
+
+fn keys(ip: Option<IpAddr>, username: &str) -> Vec<Key> {
+    ip.map(Key::Ip)
+        .into_iter()
+        .chain(std::iter::once(Key::User(username.to_string())))
+        .collect()
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        Policy {
+            max_failures: 3,
+            lockout: Duration::from_secs(10),
+            max_lockout: Duration::from_secs(60),
+        }
+    }
+
+    const IP: Option<IpAddr> = Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+
+    #[test]
+    fn lockout_doubles_up_to_the_cap() {
+        let p = policy();
+        assert_eq!(p.lockout_after(2), None);
+        assert_eq!(p.lockout_after(3), Some(Duration::from_secs(10)));
+        assert_eq!(p.lockout_after(4), Some(Duration::from_secs(20)));
+        assert_eq!(p.lockout_after(5), Some(Duration::from_secs(40)));
+        assert_eq!(p.lockout_after(6), Some(Duration::from_secs(60)));
+        assert_eq!(p.lockout_after(u32::MAX), Some(Duration::from_secs(60)));
+
+        let disabled = Policy { max_failures: 0, ..p };
+        assert_eq!(disabled.lockout_after(100), None);
+    }
+
+    #[test]
+    fn locks_out_ip_and_username() {
+        let lockout = Lockout::new(policy());
+        let start = Instant::now();
+
+        for n in 1..=2 {
+            let failure = lockout.record_failure_at(IP, "phone", start);
+            assert_eq!(failure, Failure { failures: n, lockout: None });
+        }
+        assert_eq!(lockout.locked_at(IP, "phone", start), None);
+
+        let failure = lockout.record_failure_at(IP, "phone", start);
+        assert_eq!(failure.lockout, Some(Duration::from_secs(10)));
+        assert_eq!(lockout.locked_at(IP, "phone", start), Some(Duration::from_secs(10)));
+
+        // The IP is locked for other usernames, the username from other IPs.
+        assert!(lockout.locked_at(IP, "laptop", start).is_some());
+        let other_ip = Some(IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 9)));
+        assert!(lockout.locked_at(other_ip, "phone", start).is_some());
+        assert!(lockout.locked_at(other_ip, "laptop", start).is_none());
+
+        // It runs out, and the next failure locks out for twice as long.
+        let later = start + Duration::from_secs(11);
+        assert_eq!(lockout.locked_at(IP, "phone", later), None);
+        let failure = lockout.record_failure_at(IP, "phone", later);
+        assert_eq!(failure, Failure { failures: 4, lockout: Some(Duration::from_secs(20)) });
+    }
+
+    #[test]
+    fn success_and_time_reset_counters() {
+        let lockout = Lockout::new(policy());
+        let start = Instant::now();
+
+        lockout.record_failure_at(IP, "phone", start);
+        lockout.record_failure_at(IP, "phone", start);
+        lockout.record_success(IP, "phone");
+        assert_eq!(lockout.record_failure_at(IP, "phone", start).failures, 1);
+
+        // Idle counters are forgotten after max_lockout.
+        let much_later = start + Duration::from_secs(61);
+        assert_eq!(lockout.record_failure_at(IP, "phone", much_later).failures, 1);
+    }
+
+    /// Admit an attempt, panicking if it has to wait or is locked out.
+    fn admit(lockout: &Lockout, now: Instant) -> Attempt<'_> {
+        match lockout.try_begin_attempt_at(IP, "phone", now) {
+            Admission::Admitted(attempt) => attempt,
+            other => panic!("attempt not admitted: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn attempts_in_flight_hold_back_further_guesses() {
+        let lockout = Lockout::new(policy());
+        let now = Instant::now();
+
+        // Three guesses in flight at once: the fourth waits before any of
+        // them has been checked, but nothing is locked out yet.
+        let guesses: Vec<_> = (0..3).map(|_| admit(&lockout, now)).collect();
+        assert!(matches!(lockout.try_begin_attempt_at(IP, "phone", now), Admission::Busy));
+        assert_eq!(lockout.locked_at(IP, "phone", now), None);
+
+        // Once they turn out wrong, the lockout starts.
+        let failures: Vec<_> = guesses.into_iter().map(Attempt::failed).collect();
+        assert_eq!(failures[2], Failure { failures: 3, lockout: Some(Duration::from_secs(10)) });
+        let next = lockout.try_begin_attempt_at(IP, "phone", Instant::now());
+        assert!(matches!(next, Admission::Locked(_)));
+    }
+
+    #[test]
+    fn unresolved_attempts_release_their_reservation() {
+        let lockout = Lockout::new(policy());
+        let now = Instant::now();
+
+        // A credential check that errors out is neither a success nor a
+        // failure, however often it happens.
+        for _ in 0..10 {
+            drop(admit(&lockout, now));
+        }
+        assert!(lockout.counters.lock().unwrap().is_empty());
+
+        // A success keeps the reservations of the others in flight.
+        let first = admit(&lockout, now);
+        let _second = admit(&lockout, now);
+        first.succeeded();
+        let counters = lockout.counters.lock().unwrap();
+        assert_eq!(counters[&Key::User("phone".into())].in_flight, 1);
+    }
+
+    #[tokio::test]
+    async fn parallel_correct_logins_are_not_refused() {
+        let lockout = std::sync::Arc::new(Lockout::new(policy()));
+
+        // Twice as many logins as max_failures, all at once, each taking
+        // a while to verify.
+        let logins: Vec<_> = (0..6)
+            .map(|_| {
+                let lockout = lockout.clone();
+                tokio::spawn(async move {
+                    let attempt = lockout.begin_attempt(IP, "phone").await?;
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    attempt.succeeded();
+                    Ok::<_, Duration>(())
+                })
+            })
+            .collect();
+        for login in logins {
+            assert_eq!(login.await.unwrap(), Ok(()));
+        }
+        assert_eq!(lockout.locked(IP, "phone"), None);
+    }
+
+    #[test]
+    fn requests_without_ip_count_per_username() {
+        let lockout = Lockout::new(policy());
+        let now = Instant::now();
+        for _ in 0..3 {
+            lockout.record_failure_at(None, "phone", now);
+        }
+        assert!(lockout.locked_at(None, "phone", now).is_some());
+        assert!(lockout.locked_at(IP, "phone", now).is_some());
+        assert!(lockout.locked_at(IP, "laptop", now).is_none());
+    }
+}
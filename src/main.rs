@@ -6,12 +6,18 @@
 //!   setu --headless   → run without tray (CardDAV server + sync only)
 //!   setu --install    → install systemd user service (Linux only)
 //!   setu --uninstall  → remove systemd user service (Linux only)
+//!   setu --add-app-password <user> [--label <text>] [--account <id>]
+//!        [--book <id>] [--read-only]
+//!                     → create a per-client CardDAV password and print it
+//!   setu --list-app-passwords / --revoke-app-password <user>
+//!   setu --issue-client-cert <user> [--out <file.p12>]
+//!                     → issue a device certificate for that app password
 
 // Hide the console window on Windows release builds.
 #![cfg_attr(all(not(debug_assertions), target_os = "windows"), windows_subsystem = "windows")]
 
 // Modules shared with the lib crate (for testability).
-use setu_lib::{auth, config, db, google_api, server, vault};
+use setu_lib::{app_password, auth, config, db, google_api, server, sync, vault};
 
 // GUI modules (only compiled with the "gui" feature).
 #[cfg(feature = "gui")]
@@ -19,11 +25,6 @@ mod settings;
 #[cfg(feature = "gui")]
 mod tray;
 
-// Always-available modules.
-mod sync;
-mod vcard;
-
-#[cfg(target_os = "linux")]
 use anyhow::Context;
 use std::sync::Mutex;
 
@@ -83,13 +84,22 @@ fn main() -> anyhow::Result<()> {
 
     // --show-carddav-password prints the CardDAV Basic Auth password and exits.
     if args.iter().any(|a| a == "--show-carddav-password") {
+        let cfg = config::Config::load()?;
         let pw = vault.get_or_init_carddav_password()?;
         eprintln!("CardDAV Basic Auth credentials:");
-        eprintln!("  Username: setu  (any username works)");
+        match &cfg.carddav_username {
+            Some(username) => eprintln!("  Username: {username}  (other usernames need an app password)"),
+            None => eprintln!("  Username: any  (set carddav_username to require one)"),
+        }
         eprintln!("  Password: {pw}");
         return Ok(());
     }
 
+    // App password management prints its result and exits.
+    if let Some(result) = app_password_command(&args, &vault) {
+        return result;
+    }
+
     // --settings opens the GUI and exits (requires "gui" feature).
     if args.iter().any(|a| a == "--settings") {
         #[cfg(feature = "gui")]
@@ -132,14 +142,17 @@ fn main() -> anyhow::Result<()> {
     let _carddav_password = vault.get_or_init_carddav_password()?;
 
     // ── First-run: open settings if setup is incomplete ──────────
-    let is_setup_complete = cfg.has_credentials(&vault) && auth::ensure_authenticated(&vault);
+    let has_account = |db_key: &str| {
+        auth::signed_in_accounts(&vault, db_key).is_ok_and(|accounts| !accounts.is_empty())
+    };
+    let is_setup_complete = cfg.has_credentials(&vault) && has_account(&db_key);
     if !is_setup_complete {
         #[cfg(feature = "gui")]
         if !headless {
             tracing::info!("setup incomplete — opening settings for first-run setup");
             settings::show(vault, db_key.clone())?;
             cfg = config::Config::load_and_migrate(&vault)?;
-            if !cfg.has_credentials(&vault) || !auth::ensure_authenticated(&vault) {
+            if !cfg.has_credentials(&vault) || !has_account(&db_key) {
                 tracing::warn!("setup still incomplete after settings — exiting");
                 return Ok(());
             }
@@ -197,28 +210,29 @@ fn main() -> anyhow::Result<()> {
     #[allow(unused_variables)]
     let (sync_tx, sync_rx) = tokio::sync::mpsc::channel::<()>(4);
 
-    // ── Build shared GoogleApi (if credentials are configured) ───
-    let google_api: Option<google_api::GoogleApi> = if cfg.has_credentials(&vault) {
+    // ── Build one GoogleApi per signed-in account ───────────────
+    let mut google_apis: Vec<google_api::GoogleApi> = Vec::new();
+    if cfg.has_credentials(&vault) {
         let client_secret = vault
             .get_google_client_secret()?
             .unwrap_or_default();
-        match rt.block_on(google_api::GoogleApi::build(&cfg, &client_secret)) {
-            Ok(api) => {
-                tracing::info!("Google API client initialised");
-                Some(api)
-            }
-            Err(e) => {
-                tracing::error!("failed to build Google API client: {e:#}");
-                None
+        for account in auth::signed_in_accounts(&vault, &db_key)? {
+            match rt.block_on(google_api::GoogleApi::build(&cfg, &client_secret, &account)) {
+                Ok(api) => {
+                    tracing::info!(account = %account, "Google API client initialised");
+                    google_apis.push(api);
+                }
+                Err(e) => {
+                    tracing::error!(account = %account, "failed to build Google API client: {e:#}");
+                }
             }
         }
     } else {
         tracing::warn!("Google credentials not configured — sync & on-demand search disabled");
-        None
-    };
+    }
 
-    // Fire the search warmup in the background.
-    if let Some(ref api) = google_api {
+    // Fire the search warmups in the background.
+    for api in &google_apis {
         let warmup_api = api.clone();
         rt.spawn(async move {
             if let Err(e) = warmup_api.warmup_search().await {
@@ -228,11 +242,25 @@ fn main() -> anyhow::Result<()> {
     }
 
     // Load TLS config if HTTPS is enabled.
+    let own_certificate = cfg.certificate_files()?;
     let tls_config = if cfg.use_tls {
-        match setu_lib::tls::load_server_tls_config() {
-            Ok(tls) => {
-                tracing::info!("TLS enabled — CardDAV server will use HTTPS");
-                Some(tls)
+        // Re-issues the local server certificate if the bound names changed;
+        // with the user's own certificate there is no local CA.
+        if own_certificate.is_none() {
+            if let Err(e) = setu_lib::tls::ensure_certs(&vault, &cfg.tls_names()) {
+                tracing::error!("failed to update TLS certificates: {e:#}");
+            }
+        }
+        match setu_lib::tls::ServerCert::load(own_certificate.as_ref(), &cfg.tls_names(), vault) {
+            Ok(cert) => {
+                tracing::info!(own_certificate = own_certificate.is_some(), "TLS enabled — CardDAV server will use HTTPS");
+                // Picks up renewed or replaced certificate files while running.
+                rt.spawn(cert.clone().watch());
+                Some(cert.server_config(cfg.tls_client_auth, cfg.tls_http2)?)
+            }
+            // A configured certificate must work; don't quietly drop to HTTP.
+            Err(e) if own_certificate.is_some() => {
+                return Err(e.context("cannot use tls_cert_path / tls_key_path"));
             }
             Err(e) => {
                 tracing::error!("failed to load TLS config, falling back to HTTP: {e:#}");
@@ -243,17 +271,38 @@ fn main() -> anyhow::Result<()> {
         None
     };
 
-    // Spawn the CardDAV server (with optional GoogleApi for on-demand search).
-    let server_port = cfg.server_port;
-    let server_api = google_api.clone();
+    // Client certificates are the only credential then; never drop them.
+    anyhow::ensure!(
+        !cfg.tls_client_auth || tls_config.is_some(),
+        "tls_client_auth requires HTTPS (\"use_tls\": true) and a working certificate"
+    );
+
+    // Refuses non-loopback addresses without lan_mode, and LAN mode
+    // without TLS (also when loading it failed above).
+    let listen_addrs = cfg.listen_addresses(tls_config.is_some())?;
+    let http_addrs = cfg.http_listen_addresses(tls_config.is_some())?;
+
+    // Spawn the CardDAV server (with the GoogleApis for on-demand search).
+    let listeners = server::Listeners {
+        addrs: listen_addrs.clone(),
+        tls_config,
+        http_addrs: http_addrs.clone(),
+        http_mode: cfg.http_mode,
+    };
+    let server_apis = google_apis.clone();
     let server_db_key = db_key.clone();
+    let published_groups = cfg.published_groups.clone();
+    let carddav_username = cfg.carddav_username.clone();
+    let auth_policy = setu_lib::lockout::Policy::from_config(&cfg);
     rt.spawn(async move {
         if let Err(e) = server::start_carddav_server(
-            server_port,
-            server_api,
+            listeners,
+            server_apis,
             server_db_key,
             vault,
-            tls_config,
+            published_groups,
+            carddav_username,
+            auth_policy,
         )
         .await
         {
@@ -261,13 +310,13 @@ fn main() -> anyhow::Result<()> {
         }
     });
 
-    // Spawn the sync loop (only if we have a GoogleApi).
-    if let Some(api) = google_api.clone() {
+    // Spawn the sync loop (only if an account is signed in).
+    if !google_apis.is_empty() {
         let interval = cfg.sync_interval_secs;
         let sync_db_key = db_key.clone();
         rt.spawn(async move {
             if let Err(e) =
-                sync::run_sync_loop(api, interval, sync_rx, vault, sync_db_key).await
+                sync::run_sync_loop(google_apis, interval, sync_rx, vault, sync_db_key).await
             {
                 tracing::error!("sync loop error: {e:#}");
             }
@@ -323,10 +372,15 @@ fn main() -> anyhow::Result<()> {
     // Headless mode: block on Ctrl+C.
     tracing::info!("running headless (Ctrl+C to stop)");
     eprintln!(
-        "Setu v{} (build {}) — CardDAV server on port {}",
+        "Setu v{} (build {}) — CardDAV server on {}",
         env!("CARGO_PKG_VERSION"),
         env!("SETU_BUILD_ID"),
-        cfg.server_port,
+        listen_addrs
+            .iter()
+            .map(|a| a.to_string())
+            .chain(http_addrs.iter().map(|a| format!("{a} (HTTP)")))
+            .collect::<Vec<_>>()
+            .join(", "),
     );
     eprintln!("Press Ctrl+C to stop.");
 
@@ -338,6 +392,81 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// ── App password commands ───────────────────────────────────────────
+
+/// Handle `--add-app-password`, `--list-app-passwords` and
+/// `--revoke-app-password`.  Returns `None` if none of them was given.
+fn app_password_command(args: &[String], vault: &vault::SecureVault) -> Option<anyhow::Result<()>> {
+    let add = flag_value(args, "--add-app-password");
+    let revoke = flag_value(args, "--revoke-app-password");
+    let issue = flag_value(args, "--issue-client-cert");
+    let list = args.iter().any(|a| a == "--list-app-passwords");
+    if add.is_none() && revoke.is_none() && issue.is_none() && !list {
+        return None;
+    }
+
+    Some((|| {
+        let db_key = vault.get_or_init_db_key()?;
+        db::migrate_to_encrypted(&db_key)?;
+        let conn = db::open(Some(&db_key))?;
+
+        if let Some(username) = add {
+            let scope = app_password::Scope {
+                account: flag_value(args, "--account").map(str::to_string),
+                book: flag_value(args, "--book").map(str::to_string),
+                read_only: args.iter().any(|a| a == "--read-only"),
+            };
+            let label = flag_value(args, "--label").unwrap_or_default();
+            let password = app_password::create(&conn, username, label, &scope)?;
+            eprintln!("App password created ({}). It will not be shown again:", scope.describe());
+            eprintln!("  Username: {username}");
+            eprintln!("  Password: {password}");
+        } else if let Some(username) = revoke {
+            if !db::revoke_app_password(&conn, username)? {
+                anyhow::bail!("no app password for {username:?}");
+            }
+            tracing::info!(username, "app password revoked");
+            eprintln!("App password for {username:?} revoked.");
+        } else if let Some(username) = issue {
+            let cfg = config::Config::load()?;
+            let cert = app_password::issue_certificate(&conn, vault, username, &cfg.tls_names())?;
+            let out = flag_value(args, "--out")
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| format!("{username}.p12").into());
+            std::fs::write(&out, &cert.pkcs12)
+                .with_context(|| format!("writing {}", out.display()))?;
+            eprintln!("Client certificate for {username:?} written to {}.", out.display());
+            eprintln!("Import it on the device with this password (not shown again):");
+            eprintln!("  {}", cert.password);
+            if !cfg.tls_client_auth {
+                eprintln!("Set \"tls_client_auth\": true in the config to require client certificates.");
+            }
+        } else {
+            let passwords = db::app_passwords(&conn)?;
+            if passwords.is_empty() {
+                eprintln!("No app passwords.");
+            }
+            for p in passwords {
+                eprintln!(
+                    "{:<20} {:<24} {:<32} created {}  last used {}",
+                    p.username,
+                    p.label,
+                    app_password::Scope::of(&p).describe(),
+                    p.created_at,
+                    p.last_used_at.as_deref().unwrap_or("never"),
+                );
+            }
+        }
+        Ok(())
+    })())
+}
+
+/// The argument following `flag`, e.g. `--label "Pixel 8"`.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let pos = args.iter().position(|a| a == flag)?;
+    args.get(pos + 1).map(String::as_str)
+}
+
 // ── One-time data directory migration from "wincard" → "setu" ───────
 
 /// Copy data files from the old `wincard` directory to `setu`.
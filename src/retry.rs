@@ -0,0 +1,338 @@
+//! Retries, deadlines and a circuit breaker for People API calls.
+//!
+//! Every call made through [`crate::google_api::GoogleApi`] carries a
+//! [`Backoff`] as its `Delegate`: a `429` or `5xx` answer, or a failed
+//! connection, is retried after an exponentially growing delay with full
+//! jitter — or after the `Retry-After` / `RetryInfo` delay Google asked
+//! for.  A call gives up after `max_retries`, or when the next attempt
+//! would not start before its deadline.  Calls that must not run twice
+//! (creating or updating a contact) are only retried when Google cannot
+//! have acted on them: refused connections, `429` and `503`.
+//!
+//! The [`CircuitBreaker`] counts calls that still failed that way.  After
+//! `breaker_threshold` in a row it opens: calls fail at once, without
+//! touching the network, for `breaker_cooldown`.  Then a single trial
+//! call is let through; it closes the breaker again or re-opens it.  A
+//! trial that never reports back (its caller went away) is given up
+//! after the call deadline, and the next call becomes the trial.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use google_people1::common::{Delegate, Response, Retry};
+use rand::Rng;
+
+use crate::config::Config;
+
+/// Retry settings (see [`Config::google_max_retries`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    /// Upper bound of the first delay; it doubles with every retry.
+    pub base_delay: Duration,
+    /// Longest delay between two attempts.
+    pub max_delay: Duration,
+    /// Time a call may take, all attempts and delays included.
+    pub deadline: Duration,
+    /// Failed calls in a row that open the circuit breaker; 0 disables it.
+    pub breaker_threshold: u32,
+    /// How long an open breaker refuses calls.
+    pub breaker_cooldown: Duration,
+}
+
+impl Policy {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            max_retries: cfg.google_max_retries,
+            base_delay: Duration::from_millis(cfg.google_retry_base_ms),
+            max_delay: Duration::from_millis(cfg.google_retry_max_ms.max(cfg.google_retry_base_ms)),
+            deadline: Duration::from_secs(cfg.google_call_timeout_secs),
+            breaker_threshold: cfg.google_breaker_threshold,
+            breaker_cooldown: Duration::from_secs(cfg.google_breaker_cooldown_secs),
+        }
+    }
+
+    /// Delay before retry number `retry` (0-based): uniformly random up to
+    /// `base_delay * 2^retry`, capped at `max_delay`.
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(1 << retry.min(31)).min(self.max_delay);
+        rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+// ── Per-call retries ─────────────────────────────────────────────────────
+
+/// `Delegate` for one People API call, deciding whether and when to retry.
+pub struct Backoff {
+    policy: Policy,
+    /// Whether running the call twice does no harm.
+    replayable: bool,
+    retries: u32,
+    deadline: Instant,
+    /// Delay Google asked for in its last answer, if any.
+    retry_after: Option<Duration>,
+}
+
+impl Backoff {
+    pub fn new(policy: Policy, replayable: bool) -> Self {
+        Self {
+            policy,
+            replayable,
+            retries: 0,
+            deadline: Instant::now() + policy.deadline,
+            retry_after: None,
+        }
+    }
+
+    /// Delay the last failed answer asked for (`Retry-After` or
+    /// `RetryInfo`), for a call that gave up without waiting it out.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    fn retry(&mut self, asked: Option<Duration>) -> Retry {
+        self.retry_after = asked;
+        if self.retries >= self.policy.max_retries {
+            return Retry::Abort;
+        }
+        let delay = asked.unwrap_or_else(|| self.policy.backoff(self.retries));
+        if Instant::now() + delay >= self.deadline {
+            return Retry::Abort;
+        }
+        self.retries += 1;
+        tracing::debug!(retry = self.retries, delay_ms = delay.as_millis() as u64, "retrying Google API call");
+        Retry::After(delay)
+    }
+}
+
+impl Delegate for Backoff {
+    fn http_error(&mut self, err: &hyper_util::client::legacy::Error) -> Retry {
+        tracing::debug!("Google API connection failed: {err}");
+        if !self.replayable && !err.is_connect() {
+            return Retry::Abort;
+        }
+        self.retry(None)
+    }
+
+    fn http_failure(&mut self, response: &Response, body: Option<&serde_json::Value>) -> Retry {
+        let status = response.status().as_u16();
+        let retryable = status == 429 || if self.replayable { status >= 500 } else { status == 503 };
+        if !retryable {
+            return Retry::Abort;
+        }
+        let header = response
+            .headers()
+            .get(http::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs);
+        self.retry(header.or_else(|| body.and_then(retry_info)))
+    }
+}
+
+/// `retryDelay` of a `google.rpc.RetryInfo` in a JSON error body.
+pub fn retry_info(body: &serde_json::Value) -> Option<Duration> {
+    body["error"]["details"]
+        .as_array()?
+        .iter()
+        .filter_map(|entry| entry["retryDelay"].as_str())
+        .find_map(|delay| {
+            let secs: f64 = delay.strip_suffix('s')?.parse().ok()?;
+            Duration::try_from_secs_f64(secs).ok()
+        })
+}
+
+// ── Circuit breaker ──────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Calls go through; `failures` failed in a row.
+    Closed { failures: u32 },
+    /// Calls are refused until `until`.
+    Open { until: Instant },
+    /// One trial call is in flight since `since`; others are refused
+    /// until it reports back or its deadline has passed.
+    HalfOpen { since: Instant },
+}
+
+/// Circuit breaker shared by all calls of one Google account.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    policy: Policy,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go out now; `Err` holds the time left while the
+    /// breaker is open.
+    pub fn admit(&self) -> Result<(), Duration> {
+        self.admit_at(Instant::now())
+    }
+
+    /// Record the outcome of an admitted call: `failed` if Google was
+    /// unreachable, overloaded or over quota.
+    pub fn record(&self, failed: bool) {
+        self.record_at(failed, Instant::now())
+    }
+
+    fn admit_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            State::Open { until } => Err(until - now),
+            State::HalfOpen { since } if now >= since + self.policy.deadline => {
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            State::HalfOpen { since } => Err(since + self.policy.deadline - now),
+        }
+    }
+
+    fn record_at(&self, failed: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let failures = match (*state, failed) {
+            (_, false) => 0,
+            (State::Closed { failures }, true) => failures + 1,
+            // A failed trial re-opens at once.
+            (_, true) => self.policy.breaker_threshold,
+        };
+        *state = if self.policy.breaker_threshold > 0 && failures >= self.policy.breaker_threshold {
+            if !matches!(*state, State::Open { .. }) {
+                tracing::warn!(
+                    cooldown_secs = self.policy.breaker_cooldown.as_secs(),
+                    "Google API keeps failing — pausing calls"
+                );
+            }
+            State::Open { until: now + self.policy.breaker_cooldown }
+        } else {
+            State::Closed { failures }
+        };
+    }
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        Policy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(250),
+            deadline: Duration::from_secs(10),
+            breaker_threshold: 2,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_the_cap() {
+        let p = policy();
+        for _ in 0..100 {
+            assert!(p.backoff(0) <= Duration::from_millis(100));
+            assert!(p.backoff(1) <= Duration::from_millis(200));
+            assert!(p.backoff(5) <= Duration::from_millis(250));
+            assert!(p.backoff(u32::MAX) <= Duration::from_millis(250));
+        }
+        // Jitter: not every delay is the ceiling.
+        assert!((0..100).any(|_| p.backoff(2) < Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn backoff_stops_at_max_retries_and_deadline() {
+        let mut backoff = Backoff::new(policy(), true);
+        for _ in 0..3 {
+            assert!(matches!(backoff.retry(None), Retry::After(_)));
+        }
+        assert!(matches!(backoff.retry(None), Retry::Abort));
+
+        // Google's delay is honoured, unless it would overrun the deadline.
+        let mut backoff = Backoff::new(policy(), true);
+        assert!(matches!(backoff.retry(Some(Duration::from_secs(2))), Retry::After(d) if d == Duration::from_secs(2)));
+        assert!(matches!(backoff.retry(Some(Duration::from_secs(60))), Retry::Abort));
+        assert_eq!(backoff.retry_after(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn reads_retry_info() {
+        let body = serde_json::json!({"error": {"details": [
+            {"@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "RATE_LIMIT_EXCEEDED"},
+            {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "1.5s"}
+        ]}});
+        assert_eq!(retry_info(&body), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_info(&serde_json::json!({"error": {"code": 503}})), None);
+    }
+
+    #[test]
+    fn breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(policy());
+        let start = Instant::now();
+
+        breaker.record_at(true, start);
+        assert_eq!(breaker.admit_at(start), Ok(()));
+        breaker.record_at(true, start);
+        assert_eq!(breaker.admit_at(start), Err(Duration::from_secs(30)));
+
+        // After the cooldown one trial goes out; a failure re-opens.
+        let later = start + Duration::from_secs(30);
+        assert_eq!(breaker.admit_at(later), Ok(()));
+        assert!(breaker.admit_at(later).is_err());
+        breaker.record_at(true, later);
+        assert_eq!(breaker.admit_at(later), Err(Duration::from_secs(30)));
+
+        // A successful trial closes it.
+        let much_later = later + Duration::from_secs(30);
+        assert_eq!(breaker.admit_at(much_later), Ok(()));
+        breaker.record_at(false, much_later);
+        breaker.record_at(true, much_later);
+        assert_eq!(breaker.admit_at(much_later), Ok(()));
+    }
+
+    #[test]
+    fn breaker_gives_up_on_an_abandoned_trial() {
+        let breaker = CircuitBreaker::new(policy());
+        let start = Instant::now();
+        breaker.record_at(true, start);
+        breaker.record_at(true, start);
+
+        // The trial never records; others wait until its deadline.
+        let trial = start + Duration::from_secs(30);
+        assert_eq!(breaker.admit_at(trial), Ok(()));
+        assert_eq!(breaker.admit_at(trial + Duration::from_secs(4)), Err(Duration::from_secs(6)));
+        let next = trial + Duration::from_secs(10);
+        assert_eq!(breaker.admit_at(next), Ok(()));
+        assert!(breaker.admit_at(next).is_err());
+        breaker.record_at(false, next);
+        assert_eq!(breaker.admit_at(next), Ok(()));
+    }
+
+    #[test]
+    fn breaker_can_be_disabled() {
+        let breaker = CircuitBreaker::new(Policy { breaker_threshold: 0, ..policy() });
+        let now = Instant::now();
+        for _ in 0..10 {
+            breaker.record_at(true, now);
+        }
+        assert_eq!(breaker.admit_at(now), Ok(()));
+    }
+}
@@ -1,72 +1,200 @@
-//! Read-only CardDAV server — serves Google Contacts from SQLite as vCards.
+//! CardDAV server — serves Google Contacts from SQLite as vCards.
 //!
 //! Discovery chain (RFC 6764 / RFC 6352):
 //!   GET  /.well-known/carddav          → 301 /
-//!   PROPFIND /                          → current-user-principal → /principals/
-//!   PROPFIND /principals/               → addressbook-home-set  → /addressbook/
-//!   PROPFIND /addressbook/   (Depth:0)  → address book properties
-//!   PROPFIND /addressbook/   (Depth:1)  → properties + per-contact entries
-//!   REPORT  /addressbook/               → addressbook-multiget or addressbook-query
-//!   GET     /addressbook/<id>.vcf       → individual vCard 3.0
+//!   PROPFIND /                          → current-user-principal → /principals/<account>/
+//!   PROPFIND /principals/<account>/     → addressbook-home-set  → /addressbooks/<account>/
+//!                                          (Depth:1 also lists every address book)
+//!   PROPFIND /addressbooks/<account>/   → one address book per entry below
+//!            (Depth:1)
+//!   PROPFIND <book>/         (Depth:0)  → address book properties
+//!   PROPFIND <book>/         (Depth:1)  → properties + per-contact entries
+//!   REPORT   <book>/                    → addressbook-multiget, addressbook-query
+//!                                          or sync-collection (RFC 6578)
+//!   GET      <book>/<id>.vcf            → individual vCard 3.0 (4.0, jCard or
+//!                                          xCard if the `Accept` header asks)
+//!   PUT      <book>/<id>.vcf            → create / update in Google (write-back)
+//!   DELETE   <book>/<id>.vcf            → delete in Google (write-back)
+//!
+//! Address books (per account):
+//!   /addressbooks/<account>/contacts/     all contacts
+//!   /addressbooks/<account>/<group-id>/   one per published Google contact
+//!                                         group ("Family", "Work", starred …)
+//!
+//!   Each book has its own CTag and sync tokens.  In a group book, PUT of a
+//!   new contact adds it to the group and DELETE only removes it from the
+//!   group; the contact itself stays in Google.  PUT to a contact that
+//!   exists but is not in the group is `409 Conflict`.
+//!
+//! Accounts:
+//!   Every signed-in Google account is its own principal with its own
+//!   address books, cached contacts and sync state.  `/` points at the
+//!   account named by the Basic Auth username, if any.  Otherwise it points
+//!   at the default account (the first one added), which is also served at
+//!   the single-account URLs clients may already be configured with:
+//!   `/principals/`, `/addressbooks/`, `/addressbooks/<group-id>/` and
+//!   `/addressbook/` (all contacts).  An account id takes precedence over
+//!   a group id in `/addressbooks/<id>/`.
+//!
+//! Authentication (HTTP Basic):
+//!   The shared CardDAV password works with the configured username only
+//!   (`carddav_username`; any username where that is unset, as in configs
+//!   from older versions) and grants full access.  An app password (see
+//!   [`crate::app_password`]) only works with its own username and is held
+//!   to its scope: other accounts and books are `403 Forbidden`, and so
+//!   are PUT / DELETE if it is read-only.  `/` points a password scoped to
+//!   one account at that account's principal.
+//!   Passwords are compared in constant time; repeated failures lock out
+//!   the client IP and the username (`429 Too Many Requests`, see
+//!   [`crate::lockout`]) and are logged under the `setu::security` target.
 //!
 //! On-demand search (for OpenBubbles / phone-number lookup):
 //!   When an addressbook-query REPORT includes a TEL `prop-filter` and no
-//!   local match is found, the server queries Google People API in real-time,
-//!   caches the result in SQLite, and returns it immediately.
-
-use anyhow::Result;
+//!   local match is found, the server queries the book's Google account in
+//!   real-time, caches the result in SQLite, and returns it immediately.
+//!
+//! vCard versions and formats:
+//!   Contacts are cached as both vCard 3.0 and 4.0; jCard (RFC 7095,
+//!   `application/vcard+json`) and xCard (RFC 6351, `application/vcard+xml`)
+//!   are converted from the 4.0 text.  REPORT / PROPFIND pick one via the
+//!   `content-type` / `version` attributes of `C:address-data` (listed in
+//!   `C:supported-address-data`); GET via `Accept`.
+//!
+//! Conditional requests (RFC 7232):
+//!   GET / HEAD / PROPFIND on a contact honour `If-Match`, `If-None-Match`,
+//!   `If-Modified-Since` and `If-Unmodified-Since`; an unchanged card is a
+//!   `304 Not Modified`.  `Last-Modified` is the row's `updated_at`, and an
+//!   address book's ETag follows its CTag.  Each format of a contact has
+//!   its own strong ETag (see [`crate::vcard::Format::etag`]); writes
+//!   accept any of them in `If-Match`.
+//!
+//! Incremental sync (RFC 6578):
+//!   Every cache upsert / delete and group membership change is recorded in
+//!   the `contact_changes` log, per address book.  Sync tokens encode a
+//!   position in that log, so a sync-collection REPORT returns only what
+//!   changed since the client's token, with deletions as `404 Not Found`
+//!   responses.
+//!
+//! Write-back (when `Config::write_back` is enabled):
+//!   PUT and DELETE are forwarded to the People API, honouring `If-Match` /
+//!   `If-None-Match` against the Google etag stored for the contact.  The
+//!   Google response is cached so the next GET / REPORT sees the new state.
+//!   Google names new contacts itself: a PUT that creates one answers with
+//!   its real href in `Location`, and the href the client PUT to stays an
+//!   alias of it until the client picks up the real one on its next sync.
+
+use anyhow::{bail, Context, Result};
 use axum::{
     body::Body,
-    extract::{Path, Request, State},
-    http::{header, Method, StatusCode},
+    extract::{ConnectInfo, Path, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
     middleware::{self, Next},
     response::Response,
     routing::any,
     Router,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::app_password::{self, Scope};
+use crate::config::HttpMode;
 use crate::db;
-use crate::google_api::GoogleApi;
+use crate::filter::{Filter, UnsupportedCollation};
+use crate::google_api::{GoogleApi, GoogleError};
+use crate::lockout::{self, Lockout};
 use crate::vault::SecureVault;
+use crate::xml::{self, Element, QName, CALENDARSERVER, CARDDAV, DAV};
 
 // ── Shared application state ────────────────────────────────────────────
 
 /// State shared across all axum handlers via `State<AppState>`.
 #[derive(Clone)]
 pub struct AppState {
-    /// `None` when Google credentials are not configured.
-    pub google_api: Option<GoogleApi>,
+    /// Google clients by account id; empty when Google credentials are not
+    /// configured.
+    pub google_apis: HashMap<String, GoogleApi>,
     /// Hex-encoded SQLCipher encryption key.
     pub db_key: String,
     /// Vault handle — reads CardDAV password from keyring on each request.
     pub vault: SecureVault,
+    /// Contact groups served as address books
+    /// (see [`crate::config::Config::published_groups`]).
+    pub published_groups: Option<Vec<String>>,
+    /// Username of the shared CardDAV password
+    /// (see [`crate::config::Config::carddav_username`]); `None` for any.
+    pub carddav_username: Option<String>,
+    /// Failed-login counters for brute-force protection.
+    pub lockout: Arc<Lockout>,
+}
+
+impl AppState {
+    /// The Google client of `account`, if it is signed in.
+    fn google_api(&self, account: &str) -> Option<GoogleApi> {
+        self.google_apis.get(account).cloned()
+    }
 }
 
 // ── Public entry point ───────────────────────────────────────────────────
 
-/// Start the CardDAV server on `127.0.0.1:{port}`.
+/// Where the CardDAV server listens.
+pub struct Listeners {
+    /// Main listeners (see [`crate::config::Config::listen_addresses`]).
+    pub addrs: Vec<std::net::SocketAddr>,
+    /// When `Some`, the main listeners accept HTTPS connections using the
+    /// provided `rustls::ServerConfig`.  When `None`, they listen on plain
+    /// HTTP (the default, backward-compatible behaviour).
+    pub tls_config: Option<Arc<rustls::ServerConfig>>,
+    /// Additional plain-HTTP listeners next to HTTPS (see
+    /// [`crate::config::Config::http_listen_addresses`]).
+    pub http_addrs: Vec<std::net::SocketAddr>,
+    /// What the `http_addrs` listeners do.
+    pub http_mode: HttpMode,
+}
+
+/// Start the CardDAV server with one listener per address in `listeners`.
+/// An address that cannot be bound is logged and skipped; it is an error
+/// if none of the main addresses can.
 ///
-/// When `tls_config` is `Some`, the server accepts HTTPS connections using the
-/// provided `rustls::ServerConfig`.  When `None`, it listens on plain HTTP
-/// (the default, backward-compatible behaviour).
+/// All listeners share one `Router` and its state.  The plain-HTTP
+/// listeners next to HTTPS wrap it in [`http_redirect`] unless they serve
+/// CardDAV themselves.
 pub async fn start_carddav_server(
-    port: u16,
-    google_api: Option<GoogleApi>,
+    listeners: Listeners,
+    google_apis: Vec<GoogleApi>,
     db_key: String,
     vault: SecureVault,
-    tls_config: Option<Arc<rustls::ServerConfig>>,
+    published_groups: Option<Vec<String>>,
+    carddav_username: Option<String>,
+    auth_policy: lockout::Policy,
 ) -> Result<()> {
+    if carddav_username.is_none() {
+        tracing::warn!(
+            "carddav_username is not set, so the shared CardDAV password works with any \
+             username; set one (e.g. \"setu\") in Settings or config.json to require it"
+        );
+    }
     let state = AppState {
-        google_api,
+        google_apis: google_apis
+            .into_iter()
+            .map(|api| (api.account().to_string(), api))
+            .collect(),
         db_key,
         vault,
+        published_groups,
+        carddav_username,
+        lockout: Arc::new(Lockout::new(auth_policy)),
     };
 
     let app = Router::new()
         .route("/.well-known/carddav", any(well_known))
         .route("/", any(root_handler))
         .route("/principals/", any(principals_handler))
+        .route("/principals/{account}/", any(account_principal_handler))
+        .route("/addressbooks/", any(home_handler))
+        .route("/addressbooks/{scope}/", any(scope_handler))
+        .route("/addressbooks/{scope}/{id}", any(book_contact_handler))
+        .route("/addressbooks/{scope}/{book}/", any(account_book_handler))
+        .route("/addressbooks/{scope}/{book}/{id}", any(account_contact_handler))
         .route("/addressbook/", any(addressbook_handler))
         .route("/addressbook/{id}", any(contact_handler))
         .layer(middleware::from_fn_with_state(
@@ -75,9 +203,53 @@ pub async fn start_carddav_server(
         ))
         .with_state(state);
 
-    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let Listeners { addrs, tls_config, http_addrs, http_mode } = listeners;
+    let main = bind_all(addrs).await;
+    anyhow::ensure!(!main.is_empty(), "CardDAV server could not bind any address");
+
+    let mut servers = tokio::task::JoinSet::new();
+    if !http_addrs.is_empty() {
+        let https_port = main.iter().find_map(|l| l.local_addr().ok()).map_or(443, |a| a.port());
+        let http_app = match http_mode {
+            HttpMode::Serve => app.clone(),
+            mode => app.clone().layer(middleware::from_fn_with_state(
+                HttpRedirect { mode, https_port },
+                http_redirect,
+            )),
+        };
+        for listener in bind_all(http_addrs).await {
+            servers.spawn(serve(listener, http_app.clone(), None));
+        }
+    }
+    for listener in main {
+        servers.spawn(serve(listener, app.clone(), tls_config.clone()));
+    }
+    // The listeners run forever; the first one to stop takes the server down.
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+    Ok(())
+}
+
+/// Bind every address in `addrs`, logging and skipping those that fail.
+async fn bind_all(addrs: Vec<std::net::SocketAddr>) -> Vec<tokio::net::TcpListener> {
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => tracing::error!(%addr, "cannot listen on address: {e}"),
+        }
+    }
+    listeners
+}
 
+/// Serve `app` on one listener, over HTTPS when `tls_config` is `Some`.
+async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls_config: Option<Arc<rustls::ServerConfig>>,
+) -> Result<()> {
+    let addr = listener.local_addr()?;
     match tls_config {
         Some(tls_cfg) => {
             tracing::info!(%addr, "CardDAV server listening (HTTPS)");
@@ -85,7 +257,11 @@ pub async fn start_carddav_server(
         }
         None => {
             tracing::info!(%addr, "CardDAV server listening (HTTP)");
-            axum::serve(listener, app).await?;
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await?;
             Ok(())
         }
     }
@@ -120,20 +296,36 @@ async fn serve_tls(
                 }
             };
 
+            // Only present with `tls_client_auth`; rustls verified it.
+            let client_cert = tls_stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| ClientCertificate(Arc::new(cert.clone().into_owned())));
+
+            // Speak whatever ALPN settled on, so HTTP/2 is only used when
+            // offered (`tls_http2`); no ALPN means HTTP/1.1.
+            let http2 = tls_stream.get_ref().1.alpn_protocol() == Some(b"h2");
+
             let io = TokioIo::new(tls_stream);
 
-            let service = hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+            let service = hyper::service::service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
                 let svc = app.clone();
+                // What `into_make_service_with_connect_info` does for plain HTTP.
+                req.extensions_mut().insert(ConnectInfo(remote_addr));
+                if let Some(cert) = &client_cert {
+                    req.extensions_mut().insert(cert.clone());
+                }
                 async move {
                     let mut svc = svc;
                     Service::call(&mut svc, req).await
                 }
             });
 
-            if let Err(e) = auto::Builder::new(TokioExecutor::new())
-                .serve_connection(io, service)
-                .await
-            {
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let builder = if http2 { builder.http2_only() } else { builder.http1_only() };
+            if let Err(e) = builder.serve_connection(io, service).await {
                 tracing::debug!(%remote_addr, "connection error: {e}");
             }
         });
@@ -142,9 +334,26 @@ async fn serve_tls(
 
 // ── Basic Auth middleware ────────────────────────────────────────────────
 
+/// The client certificate a TLS connection presented (`tls_client_auth`),
+/// already verified against the local CA.
+#[derive(Clone)]
+struct ClientCertificate(Arc<rustls::pki_types::CertificateDer<'static>>);
+
+/// Accepts the shared CardDAV password together with `carddav_username`
+/// (any username if unset; full access) or an app password together with
+/// its username.  The credential's [`Scope`] is added to the request
+/// extensions for the handlers.
+///
+/// A connection with a client certificate is authenticated by it alone:
+/// it gets the scope of the app password the certificate was issued for,
+/// or `403 Forbidden` if that was revoked or the certificate replaced.
+///
+/// Failed logins count towards a lockout of the client IP and the username
+/// (see [`crate::lockout`]); while locked out, requests get `429 Too Many
+/// Requests` without their credentials being checked.
 async fn basic_auth_middleware(
     State(state): State<AppState>,
-    req: Request,
+    mut req: Request,
     next: Next,
 ) -> Response {
     // OPTIONS requests pass through without auth (DAV discovery).
@@ -152,35 +361,143 @@ async fn basic_auth_middleware(
         return next.run(req).await;
     }
 
-    // Read the current password from the OS keyring on every request,
-    // so changes in Settings take effect without restarting.
-    let expected_pw = match state.vault.get_or_init_carddav_password() {
-        Ok(pw) => pw,
-        Err(e) => {
-            tracing::error!("failed to read CardDAV password from keyring: {e:#}");
-            return internal_error();
+    if let Some(cert) = req.extensions().get::<ClientCertificate>().cloned() {
+        return match certificate_scope(&state, cert).await {
+            Ok(Some(scope)) => {
+                req.extensions_mut().insert(scope);
+                next.run(req).await
+            }
+            Ok(None) => {
+                tracing::warn!(
+                    target: "setu::security",
+                    ip = ?req.extensions().get::<ConnectInfo<std::net::SocketAddr>>().map(|ConnectInfo(a)| a.ip()),
+                    "CardDAV client certificate refused: replaced or its app password revoked"
+                );
+                forbidden()
+            }
+            Err(e) => {
+                tracing::error!("failed to check client certificate: {e:#}");
+                internal_error()
+            }
+        };
+    }
+
+    // Clients send a first request without credentials to get the
+    // challenge, so a missing header is not a failed attempt.
+    let Some((user, password)) = basic_auth_credentials(req.headers()) else {
+        return unauthorized();
+    };
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<std::net::SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    // Reserved against the lockout until it is verified, so parallel
+    // guesses can't all slip past it during the slow password check.
+    let attempt = match state.lockout.begin_attempt(ip, &user).await {
+        Ok(attempt) => attempt,
+        Err(remaining) => {
+            tracing::warn!(
+                target: "setu::security",
+                ip = ?ip,
+                username = %user,
+                retry_after_secs = remaining.as_secs() + 1,
+                "CardDAV login refused: locked out"
+            );
+            return too_many_requests(remaining);
         }
     };
 
-    if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if let Some(encoded) = auth_str.strip_prefix("Basic ") {
-                if let Ok(decoded) = base64_decode(encoded) {
-                    if let Some((_user, password)) = decoded.split_once(':') {
-                        if password == expected_pw {
-                            return next.run(req).await;
-                        }
-                    }
-                }
-            }
+    match authenticate(&state, &user, &password).await {
+        Ok(Some(scope)) => {
+            attempt.succeeded();
+            req.extensions_mut().insert(scope);
+            next.run(req).await
+        }
+        Ok(None) => {
+            let failure = attempt.failed();
+            tracing::warn!(
+                target: "setu::security",
+                ip = ?ip,
+                username = %user,
+                failures = failure.failures,
+                lockout_secs = failure.lockout.map(|d| d.as_secs()),
+                "CardDAV login failed"
+            );
+            unauthorized()
+        }
+        Err(e) => {
+            tracing::error!("failed to check CardDAV credentials: {e:#}");
+            internal_error()
         }
     }
+}
 
-    Response::builder()
-        .status(StatusCode::UNAUTHORIZED)
-        .header("WWW-Authenticate", "Basic realm=\"Setu CardDAV\"")
-        .body(Body::from("Unauthorized"))
-        .unwrap()
+/// The scope of a Basic Auth username / password pair, or `None` if they
+/// match neither the shared password nor an app password.
+async fn authenticate(state: &AppState, user: &str, password: &str) -> Result<Option<Scope>> {
+    // Read the current password from the OS keyring on every request,
+    // so changes in Settings take effect without restarting.
+    if state.carddav_username.as_deref().is_none_or(|name| name == user) {
+        let expected_pw = state
+            .vault
+            .get_or_init_carddav_password()
+            .context("reading CardDAV password from keyring")?;
+        if constant_time_eq(password, &expected_pw) {
+            return Ok(Some(Scope::default()));
+        }
+    }
+
+    // Argon2 verification is deliberately slow — keep it off the runtime.
+    let db_key = state.db_key.clone();
+    let (user, password) = (user.to_string(), password.to_string());
+    let entry = tokio::task::spawn_blocking(move || {
+        let conn = db::open(Some(&db_key))?;
+        app_password::verify(&conn, &user, &password)
+    })
+    .await??;
+    Ok(entry.as_ref().map(Scope::of))
+}
+
+/// The scope of the app password a client certificate was issued for.
+async fn certificate_scope(state: &AppState, ClientCertificate(cert): ClientCertificate) -> Result<Option<Scope>> {
+    let db_key = state.db_key.clone();
+    let entry = tokio::task::spawn_blocking(move || {
+        let conn = db::open(Some(&db_key))?;
+        app_password::verify_certificate(&conn, &cert)
+    })
+    .await??;
+    Ok(entry.as_ref().map(Scope::of))
+}
+
+/// Compare secrets in time independent of where they differ (only their
+/// length can leak).
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    use subtle::ConstantTimeEq;
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// The scope the middleware granted this request.
+fn request_scope(req: &Request) -> Scope {
+    req.extensions().get::<Scope>().cloned().unwrap_or_default()
+}
+
+/// The username and password of a request's Basic `Authorization` header.
+fn basic_auth_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = base64_decode(encoded).ok()?;
+    decoded
+        .split_once(':')
+        .map(|(user, password)| (user.to_string(), password.to_string()))
+}
+
+/// The username of a request's Basic `Authorization` header, if any.
+fn basic_auth_user(headers: &HeaderMap) -> Option<String> {
+    basic_auth_credentials(headers).map(|(user, _)| user)
 }
 
 fn base64_decode(input: &str) -> std::result::Result<String, ()> {
@@ -201,104 +518,421 @@ async fn well_known() -> Response {
         .unwrap()
 }
 
+// ── Plain HTTP next to HTTPS ─────────────────────────────────────────────
+
+/// State of [`http_redirect`].
+#[derive(Clone, Copy)]
+struct HttpRedirect {
+    mode: HttpMode,
+    /// Port of the HTTPS listener the redirects point to.
+    https_port: u16,
+}
+
+/// Answer on the plain-HTTP listener with `308 Permanent Redirect` to the
+/// same URL on HTTPS — every request, or only `/.well-known/carddav` with
+/// everything else `404 Not Found` ([`HttpMode::WellKnown`]).  A 308 keeps
+/// the method and body, so a PROPFIND is repeated as a PROPFIND.
+async fn http_redirect(State(redirect): State<HttpRedirect>, req: Request, next: Next) -> Response {
+    let redirects = match redirect.mode {
+        HttpMode::Serve => false,
+        HttpMode::Redirect => true,
+        HttpMode::WellKnown => {
+            if req.uri().path() != "/.well-known/carddav" {
+                return not_found();
+            }
+            true
+        }
+    };
+    if !redirects {
+        return next.run(req).await;
+    }
+
+    let host = request_host(&req).unwrap_or_else(|| "localhost".to_string());
+    let port = match redirect.https_port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(header::LOCATION, format!("https://{host}{port}{path}"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Host the client asked for, without its port (IPv6 stays bracketed).
+fn request_host(req: &Request) -> Option<String> {
+    let authority = match req.uri().authority() {
+        Some(authority) => authority.clone(),
+        None => req.headers().get(header::HOST)?.to_str().ok()?.parse().ok()?,
+    };
+    Some(authority.host().to_string()).filter(|host| !host.is_empty())
+}
+
 // ── Root (/) — current-user-principal discovery ──────────────────────────
 
-async fn root_handler(req: Request) -> Response {
-    tracing::info!(method = %req.method(), "/ request");
-    match *req.method() {
-        Method::OPTIONS => options_response(),
-        _ if req.method().as_str() == "PROPFIND" => root_propfind(),
+async fn root_handler(State(state): State<AppState>, req: Request) -> Response {
+    let method = req.method().clone();
+    tracing::info!(method = %method, "/ request");
+    match method.as_str() {
+        "OPTIONS" => options_response(),
+        "PROPFIND" => {
+            let principal = match user_principal(&state, req.headers(), &request_scope(&req)) {
+                Ok(p) => p,
+                Err(e) => {
+                    tracing::error!("DB error resolving principal: {e:#}");
+                    return internal_error();
+                }
+            };
+            match read_propfind(req).await {
+                Ok(request) => root_propfind(&principal, &request),
+                Err(e) => body_error(e),
+            }
+        }
+        _ => method_not_allowed(),
+    }
+}
+
+/// The principal of the account an app password is scoped to, else of the
+/// account named by the Basic Auth username, or else the single-account
+/// `/principals/` (the default account).
+fn user_principal(state: &AppState, headers: &HeaderMap, scope: &Scope) -> Result<String> {
+    match scope.account.clone().or_else(|| basic_auth_user(headers)) {
+        Some(user) => Ok(account_home(state, &user)?
+            .unwrap_or_else(|| Home::legacy(db::DEFAULT_ACCOUNT))
+            .principal),
+        None => Ok(Home::legacy(db::DEFAULT_ACCOUNT).principal),
+    }
+}
+
+fn root_propfind(principal: &str, request: &PropRequest) -> Response {
+    let mut xml = multistatus_start();
+    append_response(&mut xml, "/", &root_props(principal), request);
+    xml.push_str("</D:multistatus>");
+    multistatus_response(&xml)
+}
+
+fn root_props(principal: &str) -> Vec<LiveProp> {
+    vec![
+        LiveProp::new(DAV, "resourcetype", "<D:collection/>"),
+        LiveProp::new(
+            DAV,
+            "current-user-principal",
+            format!("<D:href>{}</D:href>", xml::escape(principal)),
+        ),
+    ]
+}
+
+// ── Accounts ─────────────────────────────────────────────────────────────
+
+/// Where an account's principal and address books live.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Home {
+    account: String,
+    /// Principal URL, e.g. `/principals/work/`.
+    principal: String,
+    /// `addressbook-home-set`, e.g. `/addressbooks/work/`.
+    path: String,
+}
+
+impl Home {
+    /// The single-account URLs (`/principals/`, `/addressbooks/`), kept for
+    /// clients configured before accounts had a principal each.
+    fn legacy(account: &str) -> Self {
+        Self {
+            account: account.to_string(),
+            principal: "/principals/".into(),
+            path: "/addressbooks/".into(),
+        }
+    }
+
+    fn for_account(account: &str) -> Self {
+        Self {
+            account: account.to_string(),
+            principal: format!("/principals/{account}/"),
+            path: format!("/addressbooks/{account}/"),
+        }
+    }
+}
+
+/// The default account at the single-account URLs.
+fn legacy_home(state: &AppState) -> Result<Home> {
+    let conn = db::open(Some(&state.db_key))?;
+    Ok(Home::legacy(&db::default_account(&conn)?))
+}
+
+/// The home of `account`, if it names a registered account.
+fn account_home(state: &AppState, account: &str) -> Result<Option<Home>> {
+    if !db::valid_account_id(account) {
+        return Ok(None);
+    }
+    let conn = db::open(Some(&state.db_key))?;
+    Ok(db::accounts(&conn)?
+        .iter()
+        .any(|a| a.id == account)
+        .then(|| Home::for_account(account)))
+}
+
+// ── Principals (/principals/<account>/) — addressbook-home-set ───────────
+
+/// Single-account `/principals/` — the default account.
+async fn principals_handler(State(state): State<AppState>, req: Request) -> Response {
+    match legacy_home(&state) {
+        Ok(home) => principal_request(state, home, req).await,
+        Err(e) => {
+            tracing::error!("DB error resolving principal: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn account_principal_handler(
+    State(state): State<AppState>,
+    Path(account): Path<String>,
+    req: Request,
+) -> Response {
+    match account_home(&state, &account) {
+        Ok(Some(home)) => principal_request(state, home, req).await,
+        Ok(None) => not_found(),
+        Err(e) => {
+            tracing::error!("DB error resolving principal: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn principal_request(state: AppState, home: Home, req: Request) -> Response {
+    let method = req.method().clone();
+    let depth = depth_header(&req);
+    tracing::info!(method = %method, depth = %depth, principal = %home.principal, "principal request");
+    let scope = request_scope(&req);
+    if !scope.allows_account(&home.account) {
+        return forbidden();
+    }
+    match method.as_str() {
+        "OPTIONS" => options_response(),
+        "PROPFIND" => match read_propfind(req).await {
+            Ok(request) => principals_propfind(&depth, &request, &state, &home, &scope),
+            Err(e) => body_error(e),
+        },
         _ => method_not_allowed(),
     }
 }
 
-fn root_propfind() -> Response {
-    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-  <D:response>
-    <D:href>/</D:href>
-    <D:propstat>
-      <D:prop>
-        <D:resourcetype>
-          <D:collection/>
-        </D:resourcetype>
-        <D:current-user-principal>
-          <D:href>/principals/</D:href>
-        </D:current-user-principal>
-      </D:prop>
-      <D:status>HTTP/1.1 200 OK</D:status>
-    </D:propstat>
-  </D:response>
-</D:multistatus>"#;
-
-    multistatus_response(xml)
-}
-
-// ── Principals (/principals/) — addressbook-home-set ─────────────────────
-
-async fn principals_handler(req: Request) -> Response {
-    tracing::info!(method = %req.method(), "/principals/ request");
-    match *req.method() {
-        Method::OPTIONS => options_response(),
-        _ if req.method().as_str() == "PROPFIND" => principals_propfind(),
+/// PROPFIND on the principal.  With `Depth: 1` the address books are
+/// listed as well, for clients that look for them right here instead of
+/// following `addressbook-home-set`.
+fn principals_propfind(
+    depth: &str,
+    request: &PropRequest,
+    state: &AppState,
+    home: &Home,
+    scope: &Scope,
+) -> Response {
+    let mut xml = multistatus_start();
+    append_response(&mut xml, &home.principal, &principal_props(home), request);
+    if depth == "1" || depth == "infinity" {
+        if let Err(e) = append_books(&mut xml, request, state, home, scope) {
+            tracing::error!("DB error in PROPFIND: {e:#}");
+            return internal_error();
+        }
+    }
+    xml.push_str("</D:multistatus>");
+    multistatus_response(&xml)
+}
+
+fn principal_props(home: &Home) -> Vec<LiveProp> {
+    let principal = format!("<D:href>{}</D:href>", xml::escape(&home.principal));
+    vec![
+        LiveProp::new(DAV, "resourcetype", "<D:collection/><D:principal/>"),
+        LiveProp::new(DAV, "current-user-principal", principal.clone()),
+        LiveProp::new(DAV, "principal-URL", principal),
+        LiveProp::new(
+            CARDDAV,
+            "addressbook-home-set",
+            format!("<D:href>{}</D:href>", xml::escape(&home.path)),
+        ),
+    ]
+}
+
+// ── Address book home (/addressbooks/<account>/) ─────────────────────────
+
+/// Single-account `/addressbooks/` — the default account.
+async fn home_handler(State(state): State<AppState>, req: Request) -> Response {
+    match legacy_home(&state) {
+        Ok(home) => home_request(state, home, req).await,
+        Err(e) => {
+            tracing::error!("DB error resolving address book home: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn home_request(state: AppState, home: Home, req: Request) -> Response {
+    let method = req.method().clone();
+    let depth = depth_header(&req);
+    tracing::info!(method = %method, depth = %depth, home = %home.path, "address book home request");
+    let scope = request_scope(&req);
+    if !scope.allows_account(&home.account) {
+        return forbidden();
+    }
+    match method.as_str() {
+        "OPTIONS" => options_response(),
+        "PROPFIND" => match read_propfind(req).await {
+            Ok(request) => home_propfind(&depth, &request, &state, &home, &scope),
+            Err(e) => body_error(e),
+        },
         _ => method_not_allowed(),
     }
 }
 
-fn principals_propfind() -> Response {
-    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-  <D:response>
-    <D:href>/principals/</D:href>
-    <D:propstat>
-      <D:prop>
-        <D:resourcetype>
-          <D:collection/>
-        </D:resourcetype>
-        <C:addressbook-home-set>
-          <D:href>/addressbook/</D:href>
-        </C:addressbook-home-set>
-      </D:prop>
-      <D:status>HTTP/1.1 200 OK</D:status>
-    </D:propstat>
-  </D:response>
-</D:multistatus>"#;
+/// PROPFIND on the home collection; `Depth: 1` lists every address book.
+fn home_propfind(
+    depth: &str,
+    request: &PropRequest,
+    state: &AppState,
+    home: &Home,
+    scope: &Scope,
+) -> Response {
+    let mut xml = multistatus_start();
+    append_response(
+        &mut xml,
+        &home.path,
+        &[LiveProp::new(DAV, "resourcetype", "<D:collection/>")],
+        request,
+    );
+    if depth == "1" || depth == "infinity" {
+        if let Err(e) = append_books(&mut xml, request, state, home, scope) {
+            tracing::error!("DB error in PROPFIND: {e:#}");
+            return internal_error();
+        }
+    }
+    xml.push_str("</D:multistatus>");
+    multistatus_response(&xml)
+}
 
-    multistatus_response(xml)
+/// Append one `<D:response>` per published address book of `home` that
+/// `scope` allows.
+fn append_books(
+    xml: &mut String,
+    request: &PropRequest,
+    state: &AppState,
+    home: &Home,
+    scope: &Scope,
+) -> Result<()> {
+    let conn = db::open(Some(&state.db_key))?;
+    for book in published_books(&conn, home, state.published_groups.as_deref())?
+        .into_iter()
+        .filter(|b| scope.allows_book(&b.account, b.id()))
+    {
+        let ctag = book.ctag(&conn)?;
+        let sync_seq = db::book_change_seq(&conn, &book.account, book.change_key())?;
+        append_response(xml, &book.path, &addressbook_props(&book, ctag, sync_seq), request);
+    }
+    Ok(())
 }
 
-// ── Address book (/addressbook/) ─────────────────────────────────────────
+// ── Address books (/addressbooks/<account>/<book>/, /addressbook/) ───────
 
+/// Legacy `/addressbook/` — all contacts of the default account.
 async fn addressbook_handler(State(state): State<AppState>, req: Request) -> Response {
+    match legacy_home(&state) {
+        Ok(home) => book_request(state, Book::legacy(&home.account), req).await,
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+/// `/addressbooks/<scope>/`: an account's home, or else a book of the
+/// default account at its single-account path.
+async fn scope_handler(
+    State(state): State<AppState>,
+    Path(scope): Path<String>,
+    req: Request,
+) -> Response {
+    let home = match account_home(&state, &scope) {
+        Ok(home) => home,
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            return internal_error();
+        }
+    };
+    if let Some(home) = home {
+        return home_request(state, home, req).await;
+    }
+    match book_at(&state, None, &scope) {
+        Ok(Some(book)) => book_request(state, book, req).await,
+        Ok(None) => not_found(),
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn account_book_handler(
+    State(state): State<AppState>,
+    Path((account, book_id)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    match book_at(&state, Some(&account), &book_id) {
+        Ok(Some(book)) => book_request(state, book, req).await,
+        Ok(None) => not_found(),
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn book_request(state: AppState, book: Book, req: Request) -> Response {
     let method = req.method().clone();
-    let depth = req
-        .headers()
-        .get("Depth")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("0")
-        .to_string();
+    let depth = depth_header(&req);
 
     tracing::info!(
         method = %method,
         depth = %depth,
+        book = %book.path,
         user_agent = req.headers().get("User-Agent").and_then(|v| v.to_str().ok()).unwrap_or("-"),
-        "CardDAV /addressbook/ request"
+        "CardDAV address book request"
     );
+    if !request_scope(&req).allows_book(&book.account, book.id()) {
+        return forbidden();
+    }
 
     match method.as_str() {
         "OPTIONS" => options_response(),
-        "PROPFIND" => addressbook_propfind(&depth, &state.db_key),
-        "REPORT" => addressbook_report(req, state.google_api, &state.db_key).await,
+        "PROPFIND" => match read_propfind(req).await {
+            Ok(request) => addressbook_propfind(&depth, &request, &book, &state.db_key),
+            Err(e) => body_error(e),
+        },
+        "REPORT" => {
+            let google_api = state.google_api(&book.account);
+            addressbook_report(req, &book, google_api, &state.db_key).await
+        }
         _ => method_not_allowed(),
     }
 }
 
-/// PROPFIND on the address book collection.
+/// PROPFIND on an address book collection.
 ///
 /// - **Depth: 0** — return only the collection's own properties.
 /// - **Depth: 1** — return the collection *plus* one entry per contact.
-fn addressbook_propfind(depth: &str, db_key: &str) -> Response {
-    let contacts = match db::open(Some(db_key)).and_then(|conn| db::all_contacts(&conn)) {
+fn addressbook_propfind(depth: &str, request: &PropRequest, book: &Book, db_key: &str) -> Response {
+    let listing = db::open(Some(db_key)).and_then(|conn| {
+        let ctag = book.ctag(&conn)?;
+        let sync_seq = db::book_change_seq(&conn, &book.account, book.change_key())?;
+        let contacts = contacts_in(
+            db_key,
+            &book.account,
+            book.contacts(&conn)?,
+            request.format().source_version(),
+        )?;
+        Ok((contacts, ctag, sync_seq))
+    });
+    let (contacts, ctag, sync_seq) = match listing {
         Ok(c) => c,
         Err(e) => {
             tracing::error!("DB error in PROPFIND: {e:#}");
@@ -306,77 +940,110 @@ fn addressbook_propfind(depth: &str, db_key: &str) -> Response {
         }
     };
 
-    let ctag = chrono::Utc::now().timestamp().to_string();
-
-    let mut xml = String::with_capacity(4096);
-    xml.push_str(
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav" xmlns:CS="http://calendarserver.org/ns/">
-  <D:response>
-    <D:href>/addressbook/</D:href>
-    <D:propstat>
-      <D:prop>
-        <D:resourcetype>
-          <D:collection/>
-          <C:addressbook/>
-        </D:resourcetype>
-        <D:displayname>Google Contacts</D:displayname>
-        <CS:getctag>"#,
-    );
-    xml.push_str(&xml_escape(&ctag));
-    xml.push_str(
-        r#"</CS:getctag>
-        <D:supported-report-set>
-          <D:supported-report>
-            <D:report><C:addressbook-multiget/></D:report>
-          </D:supported-report>
-          <D:supported-report>
-            <D:report><C:addressbook-query/></D:report>
-          </D:supported-report>
-        </D:supported-report-set>
-      </D:prop>
-      <D:status>HTTP/1.1 200 OK</D:status>
-    </D:propstat>
-  </D:response>
-"#,
-    );
+    let mut xml = multistatus_start();
+    append_response(&mut xml, &book.path, &addressbook_props(book, ctag, sync_seq), request);
 
     // Depth: 1 — include each contact as a child resource.
     if depth == "1" || depth == "infinity" {
-        for (resource_name, etag, _vcard) in &contacts {
-            let href = contact_href(resource_name);
-            xml.push_str("  <D:response>\n    <D:href>");
-            xml.push_str(&xml_escape(&href));
-            xml.push_str("</D:href>\n    <D:propstat>\n      <D:prop>\n");
-            xml.push_str("        <D:getetag>\"");
-            xml.push_str(&xml_escape(etag));
-            xml.push_str("\"</D:getetag>\n");
-            xml.push_str(
-                "        <D:getcontenttype>text/vcard;charset=utf-8</D:getcontenttype>\n",
+        for (resource_name, etag, vcard) in &contacts {
+            append_response(
+                &mut xml,
+                &book.contact_href(resource_name),
+                &contact_props(etag, vcard, request),
+                request,
             );
-            xml.push_str("        <D:resourcetype/>\n");
-            xml.push_str("      </D:prop>\n      <D:status>HTTP/1.1 200 OK</D:status>\n");
-            xml.push_str("    </D:propstat>\n  </D:response>\n");
         }
     }
 
-    tracing::info!(depth = depth, contact_count = contacts.len(), "PROPFIND /addressbook/ response");
+    tracing::info!(depth = depth, book = %book.path, contact_count = contacts.len(), "PROPFIND address book response");
 
     xml.push_str("</D:multistatus>");
-    multistatus_response(&xml)
+    let mut response = multistatus_response(&xml);
+    if let Ok(etag) = collection_etag(ctag).parse() {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response
+}
+
+/// Entity tag of an address book collection: it changes exactly when the
+/// CTag does, i.e. whenever a member is added, changed or removed.
+fn collection_etag(ctag: i64) -> String {
+    format!("\"ctag-{ctag}\"")
+}
+
+fn addressbook_props(book: &Book, ctag: i64, sync_seq: i64) -> Vec<LiveProp> {
+    vec![
+        LiveProp::new(DAV, "resourcetype", "<D:collection/><C:addressbook/>"),
+        LiveProp::new(DAV, "displayname", xml::escape(&book.display_name)),
+        LiveProp::new(CALENDARSERVER, "getctag", ctag.to_string()),
+        LiveProp::new(DAV, "getetag", collection_etag(ctag)),
+        LiveProp::new(DAV, "sync-token", xml::escape(&sync_token(sync_seq))),
+        LiveProp::new(
+            DAV,
+            "supported-report-set",
+            "<D:supported-report><D:report><C:addressbook-multiget/></D:report></D:supported-report>\
+             <D:supported-report><D:report><C:addressbook-query/></D:report></D:supported-report>\
+             <D:supported-report><D:report><D:sync-collection/></D:report></D:supported-report>",
+        ),
+        LiveProp::new(
+            CARDDAV,
+            "supported-address-data",
+            "<C:address-data-type content-type=\"text/vcard\" version=\"3.0\"/>\
+             <C:address-data-type content-type=\"text/vcard\" version=\"4.0\"/>\
+             <C:address-data-type content-type=\"application/vcard+json\" version=\"4.0\"/>\
+             <C:address-data-type content-type=\"application/vcard+xml\" version=\"4.0\"/>",
+        ),
+    ]
+}
+
+/// Properties of a single contact resource.  `address-data` is only
+/// returned when asked for by name, never for `allprop`, is trimmed to the
+/// requested vCard properties (if any) and rendered in the requested
+/// format; `getcontenttype` and `getcontentlength` describe that payload.
+fn contact_props(etag: &str, vcard: &str, request: &PropRequest) -> Vec<LiveProp> {
+    let address_data = match request {
+        PropRequest::Prop {
+            address_data: Some(keep),
+            ..
+        } => crate::vcard::trim_vcard(vcard, keep),
+        _ => vcard.to_string(),
+    };
+    let format = served_format(request, vcard);
+    let address_data = format.render(&address_data);
+    vec![
+        LiveProp::new(DAV, "getetag", format!("\"{}\"", xml::escape(&format.etag(etag)))),
+        LiveProp::new(DAV, "getcontenttype", format.content_type()),
+        LiveProp::new(DAV, "getcontentlength", address_data.len().to_string()),
+        LiveProp::new(DAV, "resourcetype", ""),
+        LiveProp::new(CARDDAV, "address-data", xml::escape(&address_data)).not_in_allprop(),
+    ]
+}
+
+/// The format `vcard` is served in for `request`.
+fn served_format(request: &PropRequest, vcard: &str) -> crate::vcard::Format {
+    match request.format() {
+        // A 4.0 request may have been answered from the 3.0 fallback.
+        crate::vcard::Format::VCard(_) => crate::vcard::Format::VCard(crate::vcard::version_of(vcard)),
+        other => other,
+    }
 }
 
-/// REPORT on the address book — handles `addressbook-multiget`, generic
-/// `addressbook-query`, and **on-demand TEL search** with Google fallback.
+/// REPORT on the address book — handles `addressbook-multiget`,
+/// `sync-collection`, `addressbook-query` (full RFC 6352 filters, see
+/// [`crate::filter`]), and **on-demand TEL search** with Google fallback.
 ///
-/// On-demand flow (when a TEL `prop-filter` is present):
-///   1. Normalise the phone number from the filter.
-///   2. Search the local SQLite `searchable_phone` column.
-///   3. If no local hit **and** a `GoogleApi` is available, call
-///      `search_by_phone` in real-time.
-///   4. Upsert the Google result into SQLite with a fresh ETag.
-///   5. Return the standard multistatus XML containing the vCard.
-async fn addressbook_report(req: Request, google_api: Option<GoogleApi>, db_key: &str) -> Response {
+/// On-demand flow (when the query has a TEL `text-match`):
+///   1. Evaluate the filter against the local cache (TEL compares digits).
+///   2. If no local hit **and** the book's account has a `GoogleApi`, call
+///      `search_by_phone` in real-time (all-contacts book only).
+///   3. Upsert the Google result into SQLite.
+///   4. Return the standard multistatus XML containing the vCard.
+async fn addressbook_report(
+    req: Request,
+    book: &Book,
+    google_api: Option<GoogleApi>,
+    db_key: &str,
+) -> Response {
     let body_bytes = match axum::body::to_bytes(req.into_body(), 1024 * 64).await {
         Ok(b) => b,
         Err(_) => return bad_request("request body too large"),
@@ -385,108 +1052,268 @@ async fn addressbook_report(req: Request, google_api: Option<GoogleApi>, db_key:
 
     tracing::info!(body = %body_str, "REPORT request body");
 
-    let is_multiget = body_str.contains("addressbook-multiget");
-
-    // ── addressbook-multiget: filter by href list ───────────────────
-    if is_multiget {
-        let contacts = match db::open(Some(db_key)).and_then(|conn| db::all_contacts(&conn)) {
-            Ok(c) => c,
-            Err(e) => {
-                tracing::error!("DB error in REPORT: {e:#}");
-                return internal_error();
-            }
-        };
+    let report = match parse_report(&body_str) {
+        Ok(Some(r)) => r,
+        Ok(None) => return unsupported_report(),
+        Err(e) => return body_error(e),
+    };
+    let props = &report.props;
 
-        let requested_hrefs = extract_hrefs(&body_str);
+    let (filter, limit) = match report.kind {
+        // ── sync-collection (RFC 6578): changes since the client's token ──
+        ReportKind::SyncCollection { token } => {
+            return sync_collection_report(book, token.as_deref(), props, db_key);
+        }
 
-        let filtered: Vec<&(String, String, String)> = if requested_hrefs.is_empty() {
-            contacts.iter().collect()
-        } else {
-            contacts
-                .iter()
-                .filter(|(rn, _, _)| {
-                    let href = contact_href(rn);
-                    requested_hrefs.iter().any(|rh| rh == &href)
-                })
-                .collect()
-        };
+        // ── addressbook-multiget: filter by href list ───────────────
+        ReportKind::Multiget { hrefs } => {
+            let contacts = match db::open(Some(db_key))
+                .and_then(|conn| book.contacts(&conn))
+                .and_then(|c| contacts_in(db_key, &book.account, c, props.format().source_version()))
+            {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::error!("DB error in REPORT: {e:#}");
+                    return internal_error();
+                }
+            };
 
-        return build_report_xml(&filtered);
-    }
+            if hrefs.is_empty() {
+                let all_refs: Vec<&(String, String, String)> = contacts.iter().collect();
+                return build_report_xml(book, &all_refs, &[], false, props);
+            }
 
-    // ── addressbook-query: check for TEL prop-filter ────────────────
-    let tel_filter = extract_tel_filter(&body_str);
+            let mut found = Vec::new();
+            let mut missing = Vec::new();
+            for href in &hrefs {
+                let path = href_path(href);
+                match contacts.iter().find(|(rn, _, _)| book.contact_href(rn) == path) {
+                    Some(contact) => found.push(contact),
+                    None => missing.push(href.clone()),
+                }
+            }
+            return build_report_xml(book, &found, &missing, false, props);
+        }
+
+        // ── addressbook-query: evaluate the filter ──────────────────
+        ReportKind::Query { filter, limit } => (filter, limit),
+    };
+
+    let contacts = match db::open(Some(db_key)).and_then(|conn| book.contacts(&conn)) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("DB error in REPORT: {e:#}");
+            return internal_error();
+        }
+    };
 
-    if let Some(ref raw_phone) = tel_filter {
+    let mut hits: Vec<&(String, String, String)> = contacts
+        .iter()
+        .filter(|(_, _, vcard)| filter.matches(vcard))
+        .collect();
+
+    // ── On-demand Google lookup for a TEL search with no local hit ──
+    // A group book only holds the group's members, so a contact found in
+    // Google (which is in no particular group) is only offered to the
+    // all-contacts book.
+    let google_api = google_api.filter(|_| book.group.is_none());
+    if let (true, Some(raw_phone), Some(api)) = (hits.is_empty(), filter.phone_query(), &google_api) {
         let normalized = db::normalize_phone(raw_phone);
         tracing::debug!(raw = raw_phone, normalized = %normalized, "TEL prop-filter in REPORT");
 
         if !normalized.is_empty() {
-            // 1. Local DB search
-            let local_hits = match db::open(Some(db_key)).and_then(|conn| db::search_by_phone(&conn, &normalized)) {
-                Ok(h) => h,
+            tracing::info!(phone = raw_phone, "no local match — querying Google");
+            match api.search_by_phone(raw_phone).await {
+                Ok(Some(person)) => {
+                    let contact = match cache_person(&person, &book.account, db_key).and_then(|c| {
+                        contacts_in(db_key, &book.account, vec![c], props.format().source_version())
+                    })
+                    {
+                        Ok(c) => c,
+                        Err(e) => {
+                            tracing::error!("failed to cache Google result: {e:#}");
+                            return internal_error();
+                        }
+                    };
+                    return build_report_xml_owned(book, &contact, props);
+                }
+                Ok(None) => {
+                    tracing::debug!(phone = raw_phone, "Google search returned no results");
+                }
+                // In every case the client gets the (empty) local result.
+                Err(e @ GoogleError::AuthRevoked(_)) => {
+                    tracing::error!(account = %book.account, "Google search failed — sign in again in the settings: {e}");
+                }
+                Err(e @ GoogleError::QuotaExceeded { .. }) => {
+                    tracing::warn!(account = %book.account, "Google search skipped: {e}");
+                }
+                Err(e @ GoogleError::Transient(_)) => {
+                    tracing::warn!(account = %book.account, "Google search failed, not retrying: {e}");
+                }
                 Err(e) => {
-                    tracing::error!("DB error in phone search: {e:#}");
-                    return internal_error();
+                    tracing::error!("Google search failed: {e}");
                 }
-            };
-
-            if !local_hits.is_empty() {
-                return build_report_xml_owned(&local_hits);
             }
+        }
+    }
 
-            // 2. Google fallback (on-demand)
-            if let Some(ref api) = google_api {
-                tracing::info!(phone = raw_phone, "no local match — querying Google");
-                match api.search_by_phone(raw_phone).await {
-                    Ok(Some(person)) => {
-                        let contact = match cache_person(&person, db_key) {
-                            Ok(c) => c,
-                            Err(e) => {
-                                tracing::error!("failed to cache Google result: {e:#}");
-                                return internal_error();
-                            }
-                        };
-                        return build_report_xml_owned(&[contact]);
-                    }
-                    Ok(None) => {
-                        tracing::debug!(phone = raw_phone, "Google search returned no results");
-                    }
-                    Err(e) => {
-                        tracing::error!("Google search failed: {e:#}");
-                    }
-                }
+    // ── C:limit / C:nresults ────────────────────────────────────────
+    let truncated = limit.is_some_and(|n| hits.len() > n);
+    if let Some(n) = limit {
+        hits.truncate(n);
+    }
+
+    // Filters were evaluated on the stored 3.0; answer in the asked version.
+    let hits = match contacts_in(
+        db_key,
+        &book.account,
+        hits.into_iter().cloned().collect(),
+        props.format().source_version(),
+    ) {
+        Ok(h) => h,
+        Err(e) => {
+            tracing::error!("DB error in REPORT: {e:#}");
+            return internal_error();
+        }
+    };
+    let hits: Vec<&(String, String, String)> = hits.iter().collect();
+
+    build_report_xml(book, &hits, &[], truncated, props)
+}
+
+/// REPORT `sync-collection`: report every contact created, updated or
+/// deleted since the client's sync token, plus a fresh token.
+///
+/// An empty token requests an initial sync (all current contacts, no
+/// tombstones).  Deleted contacts are reported as bare `404 Not Found`
+/// responses.  Tokens this server did not issue yield `403` with the
+/// `DAV:valid-sync-token` precondition.
+fn sync_collection_report(
+    book: &Book,
+    token: Option<&str>,
+    props: &PropRequest,
+    db_key: &str,
+) -> Response {
+    let since = match token {
+        None => None,
+        Some(token) => match parse_sync_token(token) {
+            Some(seq) => Some(seq),
+            None => {
+                tracing::info!(token = %token, "sync-collection with unknown sync token");
+                return invalid_sync_token();
             }
+        },
+    };
 
-            // TEL filter was present but no match found — return empty result.
-            return build_report_xml_owned(&[]);
+    let conn = match db::open(Some(db_key)) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("DB error in sync-collection: {e:#}");
+            return internal_error();
         }
+    };
+
+    // Read the current position *before* the changes so that anything
+    // committed in between is reported again next time rather than lost.
+    let book_key = book.change_key();
+    let listing = db::book_change_seq(&conn, &book.account, book_key).and_then(|current| {
+        Ok((current, db::changes_since(&conn, &book.account, book_key, since.unwrap_or(0))?))
+    });
+    let (current, mut changes) = match listing {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!("DB error in sync-collection: {e:#}");
+            return internal_error();
+        }
+    };
+
+    match since {
+        Some(seq) if seq > current => return invalid_sync_token(),
+        Some(_) => {}
+        // Initial sync: only current members, no tombstones.
+        None => changes.retain(|(_, state)| state.is_some()),
     }
 
-    // ── Generic addressbook-query (no TEL filter, or fallback) ──────
-    let contacts = match db::open(Some(db_key)).and_then(|conn| db::all_contacts(&conn)) {
+    let version = props.format().source_version();
+    let changes: Result<Vec<db::ContactChange>> = changes
+        .into_iter()
+        .map(|(rn, state)| {
+            let state = match state {
+                Some((etag, vcard)) => {
+                    Some((etag, vcard_in(&conn, &book.account, &rn, vcard, version)?.1))
+                }
+                None => None,
+            };
+            Ok((rn, state))
+        })
+        .collect();
+    let changes = match changes {
         Ok(c) => c,
         Err(e) => {
-            tracing::error!("DB error in REPORT: {e:#}");
+            tracing::error!("DB error in sync-collection: {e:#}");
             return internal_error();
         }
     };
 
-    let all_refs: Vec<&(String, String, String)> = contacts.iter().collect();
-    build_report_xml(&all_refs)
+    build_sync_collection_xml(book, &changes, &sync_token(current), props)
+}
+
+/// A contact's vCard in `version`, and the version actually returned:
+/// the cache row holds 3.0 and the 4.0 rendering is stored beside it, but
+/// contacts not re-synced since the 4.0 column was added only have 3.0.
+fn vcard_in(
+    conn: &rusqlite::Connection,
+    account: &str,
+    resource_name: &str,
+    vcard: String,
+    version: crate::vcard::Version,
+) -> Result<(crate::vcard::Version, String)> {
+    if version == crate::vcard::Version::V4 {
+        if let Some(vcard4) = db::get_vcard4(conn, account, resource_name)? {
+            return Ok((version, vcard4));
+        }
+    }
+    Ok((crate::vcard::Version::V3, vcard))
+}
+
+/// [`vcard_in`] over a list of `(resource_name, etag, vcard)` tuples.
+fn contacts_in(
+    db_key: &str,
+    account: &str,
+    contacts: Vec<(String, String, String)>,
+    version: crate::vcard::Version,
+) -> Result<Vec<(String, String, String)>> {
+    if version == crate::vcard::Version::V3 {
+        return Ok(contacts);
+    }
+    let conn = db::open(Some(db_key))?;
+    contacts
+        .into_iter()
+        .map(|(rn, etag, vcard)| {
+            let (_, vcard) = vcard_in(&conn, account, &rn, vcard, version)?;
+            Ok((rn, etag, vcard))
+        })
+        .collect()
 }
 
-/// Upsert a Google `Person` into the local DB and return `(resource_name, etag, vcard)`.
-fn cache_person(person: &google_people1::api::Person, db_key: &str) -> Result<(String, String, String)> {
+/// Upsert a Google `Person` of `account` into the local DB and return
+/// `(resource_name, etag, vcard)`.
+fn cache_person(
+    person: &google_people1::api::Person,
+    account: &str,
+    db_key: &str,
+) -> Result<(String, String, String)> {
     let conn = db::open(Some(db_key))?;
-    cache_person_to_conn(&conn, person)
+    cache_person_to_conn(&conn, account, person)
 }
 
 /// Testable core of [`cache_person`]: converts a Google `Person` to a vCard,
 /// normalises phone numbers, upserts the row, and returns the tuple needed
-/// for the multistatus XML response.
+/// for the multistatus XML response.  A contact the sync hasn't stored yet
+/// is marked as cached on demand (see [`db::reconcile_contacts`]).
 fn cache_person_to_conn(
     conn: &rusqlite::Connection,
+    account: &str,
     person: &google_people1::api::Person,
 ) -> Result<(String, String, String)> {
     let resource_name = person
@@ -495,14 +1322,14 @@ fn cache_person_to_conn(
         .unwrap_or("unknown")
         .to_string();
 
+    let display_name = crate::vcard::display_name(person);
+    let vcard_text = crate::vcard::person_to_vcard(person);
+
     let etag = person
         .etag
         .as_deref()
         .map(String::from)
-        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-
-    let display_name = crate::vcard::display_name(person);
-    let vcard_text = crate::vcard::person_to_vcard(person);
+        .unwrap_or_else(|| crate::vcard::content_etag(&vcard_text));
 
     let searchable_phone = person
         .phone_numbers
@@ -518,16 +1345,26 @@ fn cache_person_to_conn(
         })
         .unwrap_or_default();
 
+    let known = db::get_contact(conn, account, &resource_name)?.is_some();
     db::upsert_contact(
         conn,
+        account,
         &resource_name,
         &etag,
         &display_name,
         &vcard_text,
         &searchable_phone,
     )?;
+    if !known {
+        db::mark_on_demand(conn, account, &resource_name)?;
+    }
+    db::set_vcard4(conn, account, &resource_name, &crate::vcard::person_to_vcard4(person))?;
+    if let Some(groups) = crate::google_api::group_memberships(person) {
+        db::set_memberships(conn, account, &resource_name, &groups)?;
+    }
 
     tracing::info!(
+        account = %account,
         resource_name = %resource_name,
         display_name = %display_name,
         "cached on-demand Google contact"
@@ -536,58 +1373,155 @@ fn cache_person_to_conn(
     Ok((resource_name, etag, vcard_text))
 }
 
-// ── Individual contact (/addressbook/<id>.vcf) ──────────────────────────
+// ── Individual contact (<book>/<id>.vcf) ─────────────────────────────────
 
+/// Legacy `/addressbook/<id>.vcf`.
 async fn contact_handler(
     State(state): State<AppState>,
     Path(id): Path<String>,
     req: Request,
 ) -> Response {
+    match legacy_home(&state) {
+        Ok(home) => contact_request(state, Book::legacy(&home.account), &id, req).await,
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+/// `/addressbooks/<book>/<id>.vcf` — a contact of the default account at
+/// its single-account path.
+async fn book_contact_handler(
+    State(state): State<AppState>,
+    Path((book_id, id)): Path<(String, String)>,
+    req: Request,
+) -> Response {
+    match book_at(&state, None, &book_id) {
+        Ok(Some(book)) => contact_request(state, book, &id, req).await,
+        Ok(None) => not_found(),
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn account_contact_handler(
+    State(state): State<AppState>,
+    Path((account, book_id, id)): Path<(String, String, String)>,
+    req: Request,
+) -> Response {
+    match book_at(&state, Some(&account), &book_id) {
+        Ok(Some(book)) => contact_request(state, book, &id, req).await,
+        Ok(None) => not_found(),
+        Err(e) => {
+            tracing::error!("DB error resolving address book: {e:#}");
+            internal_error()
+        }
+    }
+}
+
+async fn contact_request(state: AppState, book: Book, id: &str, req: Request) -> Response {
     let method = req.method().clone();
-    let depth = req
-        .headers()
-        .get("Depth")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("0")
-        .to_string();
+    let scope = request_scope(&req);
+    if !scope.allows_book(&book.account, book.id()) {
+        return forbidden();
+    }
+    if scope.read_only && matches!(method.as_str(), "PUT" | "DELETE") {
+        tracing::info!(method = %method, book = %book.path, "read-only app password → 403");
+        return forbidden();
+    }
 
     match method.as_str() {
-        "GET" | "HEAD" => contact_get(&id, &state.db_key),
-        "PROPFIND" => contact_propfind(&id, &depth, &state.db_key),
+        "GET" | "HEAD" => contact_get(&book, id, req.headers(), &state.db_key),
+        "PROPFIND" => {
+            let headers = req.headers().clone();
+            match read_propfind(req).await {
+                Ok(request) => contact_propfind(&book, id, &headers, &request, &state.db_key),
+                Err(e) => body_error(e),
+            }
+        }
+        "PUT" => {
+            let google_api = state.google_api(&book.account);
+            contact_put(&book, id, req, google_api, &state.db_key).await
+        }
+        "DELETE" => {
+            let google_api = state.google_api(&book.account);
+            contact_delete(&book, id, req.headers(), google_api, &state.db_key).await
+        }
         "OPTIONS" => options_response(),
         _ => method_not_allowed(),
     }
 }
 
-fn contact_get(id: &str, db_key: &str) -> Response {
-    let resource_name = id_to_resource_name(id);
-    tracing::info!(resource_name = %resource_name, "GET /addressbook/{id}");
-
-    let conn = match db::open(Some(db_key)) {
+fn contact_get(book: &Book, id: &str, headers: &HeaderMap, db_key: &str) -> Response {
+    let format = accepted_format(headers);
+    let (conn, resource_name) = match db::open(Some(db_key))
+        .and_then(|conn| book.resource_name(&conn, id).map(|rn| (conn, rn)))
+    {
         Ok(c) => c,
         Err(e) => {
             tracing::error!("DB error: {e:#}");
             return internal_error();
         }
     };
+    tracing::info!(resource_name = %resource_name, book = %book.path, "GET contact");
+
+    let found = book.get_contact(&conn, &resource_name).and_then(|contact| {
+        contact
+            .map(|(etag, vcard)| {
+                Ok((etag, vcard, db::contact_modified(&conn, &book.account, &resource_name)?))
+            })
+            .transpose()
+    });
+    match found {
+        Ok(Some((etag, vcard, modified))) => {
+            let (version, vcard) =
+                match vcard_in(&conn, &book.account, &resource_name, vcard, format.source_version()) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tracing::error!("DB error: {e:#}");
+                        return internal_error();
+                    }
+                };
+            // A 4.0 request may have been answered from the 3.0 fallback.
+            let served = match format {
+                crate::vcard::Format::VCard(_) => crate::vcard::Format::VCard(version),
+                other => other,
+            };
+            let etag = served.etag(&etag);
 
-    match db::get_contact(&conn, &resource_name) {
-        Ok(Some((etag, vcard))) => {
-            tracing::info!(resource_name = %resource_name, etag = %etag, len = vcard.len(), "GET response → 200");
-            tracing::debug!(vcard = %vcard, "GET vCard body");
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "text/vcard;charset=utf-8")
+            let mut response = Response::builder()
                 .header(header::ETAG, format!("\"{etag}\""))
-                .body(Body::from(vcard))
+                .header(header::VARY, "Accept");
+            if let Some(modified) = modified {
+                response = response.header(header::LAST_MODIFIED, http_date(modified));
+            }
+            match read_condition(headers, true, &etag, modified) {
+                Condition::Proceed => {}
+                Condition::NotModified => {
+                    tracing::info!(resource_name = %resource_name, etag = %etag, "GET response → 304");
+                    return response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap();
+                }
+                Condition::Failed => {
+                    tracing::info!(resource_name = %resource_name, "GET precondition failed → 412");
+                    return precondition_failed();
+                }
+            }
+
+            let body = served.render(&vcard);
+            tracing::info!(resource_name = %resource_name, etag = %etag, format = ?served, len = body.len(), "GET response → 200");
+            tracing::debug!(body = %body, "GET vCard body");
+            response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, served.content_type())
+                .body(Body::from(body))
                 .unwrap()
         }
         Ok(None) => {
             tracing::info!(resource_name = %resource_name, "GET response → 404");
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("Not Found"))
-                .unwrap()
+            not_found()
         }
         Err(e) => {
             tracing::error!("DB error: {e:#}");
@@ -596,9 +1530,18 @@ fn contact_get(id: &str, db_key: &str) -> Response {
     }
 }
 
-fn contact_propfind(id: &str, _depth: &str, db_key: &str) -> Response {
-    let resource_name = id_to_resource_name(id);
-    let conn = match db::open(Some(db_key)) {
+/// PROPFIND on a single contact.  Conditional headers are honoured as for
+/// GET, except that a matching `If-None-Match` is a 412 (RFC 7232 §3.2).
+fn contact_propfind(
+    book: &Book,
+    id: &str,
+    headers: &HeaderMap,
+    request: &PropRequest,
+    db_key: &str,
+) -> Response {
+    let (conn, resource_name) = match db::open(Some(db_key))
+        .and_then(|conn| book.resource_name(&conn, id).map(|rn| (conn, rn)))
+    {
         Ok(c) => c,
         Err(e) => {
             tracing::error!("DB error: {e:#}");
@@ -606,35 +1549,39 @@ fn contact_propfind(id: &str, _depth: &str, db_key: &str) -> Response {
         }
     };
 
-    match db::get_contact(&conn, &resource_name) {
-        Ok(Some((etag, vcard))) => {
-            let href = format!("/addressbook/{id}");
-            let xml = format!(
-                r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-  <D:response>
-    <D:href>{href}</D:href>
-    <D:propstat>
-      <D:prop>
-        <D:getetag>"{etag}"</D:getetag>
-        <D:getcontenttype>text/vcard;charset=utf-8</D:getcontenttype>
-        <D:getcontentlength>{len}</D:getcontentlength>
-        <D:resourcetype/>
-      </D:prop>
-      <D:status>HTTP/1.1 200 OK</D:status>
-    </D:propstat>
-  </D:response>
-</D:multistatus>"#,
-                href = xml_escape(&href),
-                etag = xml_escape(&etag),
-                len = vcard.len(),
+    let found = book.get_contact(&conn, &resource_name).and_then(|contact| {
+        contact
+            .map(|(etag, vcard)| {
+                Ok((etag, vcard, db::contact_modified(&conn, &book.account, &resource_name)?))
+            })
+            .transpose()
+    });
+    let (etag, vcard, modified) = match found {
+        Ok(Some(contact)) => contact,
+        Ok(None) => return not_found(),
+        Err(e) => {
+            tracing::error!("DB error: {e:#}");
+            return internal_error();
+        }
+    };
+
+    match vcard_in(&conn, &book.account, &resource_name, vcard, request.format().source_version()) {
+        Ok((_, vcard)) => {
+            let served = served_format(request, &vcard).etag(&etag);
+            if read_condition(headers, false, &served, modified) != Condition::Proceed {
+                tracing::info!(resource_name = %resource_name, "PROPFIND precondition failed → 412");
+                return precondition_failed();
+            }
+            let mut xml = multistatus_start();
+            append_response(
+                &mut xml,
+                &format!("{}{id}", book.path),
+                &contact_props(&etag, &vcard, request),
+                request,
             );
+            xml.push_str("</D:multistatus>");
             multistatus_response(&xml)
         }
-        Ok(None) => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Not Found"))
-            .unwrap(),
         Err(e) => {
             tracing::error!("DB error: {e:#}");
             internal_error()
@@ -642,66 +1589,737 @@ fn contact_propfind(id: &str, _depth: &str, db_key: &str) -> Response {
     }
 }
 
+// ── Write-back (PUT / DELETE) ────────────────────────────────────────────
+
+/// Response for a PUT / DELETE when write-back is not possible: 403 when it
+/// is disabled in settings, 503 when the book's Google account is not
+/// signed in.
+fn write_back_unavailable(has_google_api: bool) -> Response {
+    if has_google_api {
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("Write-back is disabled in Setu settings"))
+            .unwrap()
+    } else {
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("Google account not configured"))
+            .unwrap()
+    }
+}
+
+/// PUT a vCard: update the existing Google contact, or create a new one.
+///
+/// An update fetches the contact from Google first and only writes the
+/// fields the client changed and the vCard carries without loss (see
+/// [`crate::vcard::changes`]).
+///
+/// Google assigns the resource name of a new contact, so a create answers
+/// `201 Created` with a `Location` header pointing at the contact's actual
+/// href (and no ETag, since the client's URL is not where it was stored).
+/// The client's href stays an alias of the new contact for GET, PROPFIND,
+/// PUT and DELETE, and is reported deleted to sync-collection.
+/// A contact created in a group book is created as a member of the group.
+async fn contact_put(
+    book: &Book,
+    id: &str,
+    req: Request,
+    google_api: Option<GoogleApi>,
+    db_key: &str,
+) -> Response {
+    let Some(api) = google_api.as_ref().filter(|api| api.can_write()) else {
+        return write_back_unavailable(google_api.is_some());
+    };
+
+    let headers = req.headers().clone();
+    let body_bytes = match axum::body::to_bytes(req.into_body(), 1024 * 1024).await {
+        Ok(b) => b,
+        Err(_) => return bad_request("request body too large"),
+    };
+    let body_str = String::from_utf8_lossy(&body_bytes);
+
+    let mut person = match crate::vcard::vcard_to_person(&body_str) {
+        Ok(p) => p,
+        Err(e) => return bad_request(&format!("invalid vCard: {e}")),
+    };
+
+    let target = db::open(Some(db_key)).and_then(|conn| {
+        let resource_name = book.resource_name(&conn, id)?;
+        let target = put_target(&conn, book, &resource_name)?;
+        Ok((resource_name, target))
+    });
+    let (resource_name, target) = match target {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("DB error in PUT: {e:#}");
+            return internal_error();
+        }
+    };
+    let existing = match target {
+        PutTarget::Update(etag) => Some(etag),
+        PutTarget::Create => None,
+        PutTarget::OutsideBook => {
+            tracing::info!(resource_name = %resource_name, book = %book.path, "PUT of a contact outside the book → 409");
+            return conflict("The contact exists in Google but not in this address book");
+        }
+    };
+
+    if !write_preconditions_hold(&headers, existing.as_deref()) {
+        tracing::info!(resource_name = %resource_name, "PUT precondition failed → 412");
+        return precondition_failed();
+    }
+
+    let is_update = existing.is_some();
+    let result = match existing {
+        Some(etag) => {
+            async {
+                let current = api.get_contact(&resource_name).await?;
+                let changes = crate::vcard::changes(&current, &person);
+                if !changes.skipped.is_empty() {
+                    tracing::warn!(
+                        resource_name = %resource_name,
+                        fields = ?changes.skipped,
+                        "PUT changes fields a vCard can't hold losslessly; keeping Google's"
+                    );
+                }
+                if changes.update.is_empty() {
+                    return Ok(current);
+                }
+                person.resource_name = Some(resource_name.clone());
+                person.etag = Some(etag);
+                api.update_contact(&resource_name, person, &changes.update).await
+            }
+            .await
+        }
+        None => {
+            if let Some(group) = &book.group {
+                person.memberships = Some(vec![group_membership(group)]);
+            }
+            api.create_contact(person).await
+        }
+    };
+
+    let saved = match result {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Google write-back failed: {e:#}");
+            return bad_gateway();
+        }
+    };
+
+    let (saved_rn, etag, _vcard) = match cache_person(&saved, &book.account, db_key) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("failed to cache written contact: {e:#}");
+            return internal_error();
+        }
+    };
+    if !is_update && saved_rn != resource_name {
+        let aliased = db::open(Some(db_key))
+            .and_then(|conn| db::add_alias(&conn, &book.account, &resource_name, &saved_rn));
+        if let Err(e) = aliased {
+            tracing::error!("failed to record the href of a created contact: {e:#}");
+            return internal_error();
+        }
+    }
+
+    if is_update {
+        tracing::info!(resource_name = %saved_rn, etag = %etag, "PUT updated contact → 204");
+        Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ETAG, format!("\"{etag}\""))
+            .body(Body::empty())
+            .unwrap()
+    } else {
+        tracing::info!(resource_name = %saved_rn, "PUT created contact → 201");
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .header(header::LOCATION, book.contact_href(&saved_rn))
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+/// What a PUT to a contact href does.
+#[derive(Debug, PartialEq)]
+enum PutTarget {
+    /// Update the contact, whose current etag this is.
+    Update(String),
+    /// Create a new contact.
+    Create,
+    /// The contact exists, but is not in this group book.  Creating it
+    /// would duplicate it in Google.
+    OutsideBook,
+}
+
+fn put_target(conn: &rusqlite::Connection, book: &Book, resource_name: &str) -> Result<PutTarget> {
+    if let Some((etag, _)) = book.get_contact(conn, resource_name)? {
+        return Ok(PutTarget::Update(etag));
+    }
+    if book.group.is_some() && db::get_contact(conn, &book.account, resource_name)?.is_some() {
+        return Ok(PutTarget::OutsideBook);
+    }
+    Ok(PutTarget::Create)
+}
+
+/// DELETE a contact in Google and drop it from the local cache.  In a
+/// group book only the membership is removed; the contact is kept.
+async fn contact_delete(
+    book: &Book,
+    id: &str,
+    headers: &HeaderMap,
+    google_api: Option<GoogleApi>,
+    db_key: &str,
+) -> Response {
+    let Some(api) = google_api.as_ref().filter(|api| api.can_write()) else {
+        return write_back_unavailable(google_api.is_some());
+    };
+
+    let existing = db::open(Some(db_key)).and_then(|conn| {
+        let resource_name = book.resource_name(&conn, id)?;
+        Ok((book.get_contact(&conn, &resource_name)?, resource_name))
+    });
+    let (existing, resource_name) = match existing {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("DB error in DELETE: {e:#}");
+            return internal_error();
+        }
+    };
+
+    let Some((etag, _)) = existing else {
+        return not_found();
+    };
+
+    if !write_preconditions_hold(headers, Some(&etag)) {
+        tracing::info!(resource_name = %resource_name, "DELETE precondition failed → 412");
+        return precondition_failed();
+    }
+
+    let removed = match &book.group {
+        Some(group) => api.remove_from_contact_group(group, &resource_name).await,
+        None => api.delete_contact(&resource_name).await,
+    };
+    if let Err(e) = removed {
+        tracing::error!("Google delete failed: {e:#}");
+        return bad_gateway();
+    }
+
+    let forgotten = db::open(Some(db_key)).and_then(|conn| match &book.group {
+        Some(group) => db::remove_membership(&conn, &book.account, group, &resource_name),
+        None => db::delete_contact(&conn, &book.account, &resource_name),
+    });
+    if let Err(e) = forgotten {
+        tracing::error!("DB error in DELETE: {e:#}");
+        return internal_error();
+    }
+
+    tracing::info!(resource_name = %resource_name, "DELETE → 204");
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Evaluate `If-Match` / `If-None-Match` for a state-changing request
+/// (RFC 7232 §3.1–3.2) against the contact's current etag, with strong
+/// and weak comparison respectively.  The etag of any representation
+/// (see [`crate::vcard::Format::etag`]) counts: the write replaces them all.
+///
+/// Returns `false` when the request must be rejected with 412.
+fn write_preconditions_hold(headers: &HeaderMap, current_etag: Option<&str>) -> bool {
+    let matches = |list: &str, current: &str, weak: bool| {
+        crate::vcard::Format::ALL
+            .iter()
+            .any(|format| etag_list_matches(list, &format.etag(current), weak))
+    };
+    if let Some(if_match) = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok()) {
+        match current_etag {
+            Some(cur) if matches(if_match, cur, false) => {}
+            _ => return false,
+        }
+    }
+    if let Some(if_none) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        if let Some(cur) = current_etag {
+            if matches(if_none, cur, true) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Returns `true` if a comma-separated entity-tag list contains `*` or
+/// `current`.  Weak comparison (`If-None-Match`) ignores `W/` prefixes;
+/// strong comparison (`If-Match`) never matches a weak tag (RFC 7232 §2.3.2).
+fn etag_list_matches(list: &str, current: &str, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|tag| {
+        let tag = match tag.strip_prefix("W/") {
+            Some(_) if !weak => return false,
+            Some(opaque) => opaque,
+            None => tag,
+        };
+        tag == "*" || tag.trim_matches('"') == current
+    })
+}
+
+// ── Conditional reads (RFC 7232) ─────────────────────────────────────────
+
+/// Outcome of a read request's conditional headers.
+#[derive(Debug, PartialEq)]
+enum Condition {
+    Proceed,
+    /// `304 Not Modified` — only ever for GET / HEAD.
+    NotModified,
+    /// `412 Precondition Failed`.
+    Failed,
+}
+
+/// Evaluate `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` against a resource's etag and modification time, in
+/// the order of RFC 7232 §6.
+///
+/// `get` is true for GET / HEAD.  For other methods a matching
+/// `If-None-Match` fails with 412 and `If-Modified-Since` is ignored.
+fn read_condition(
+    headers: &HeaderMap,
+    get: bool,
+    etag: &str,
+    modified: Option<chrono::DateTime<chrono::Utc>>,
+) -> Condition {
+    let text = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(if_match) = text(header::IF_MATCH) {
+        if !etag_list_matches(if_match, etag, false) {
+            return Condition::Failed;
+        }
+    } else if let (Some(since), Some(modified)) =
+        (header_date(headers, header::IF_UNMODIFIED_SINCE), modified)
+    {
+        if modified > since {
+            return Condition::Failed;
+        }
+    }
+
+    if let Some(if_none) = text(header::IF_NONE_MATCH) {
+        if etag_list_matches(if_none, etag, true) {
+            return if get { Condition::NotModified } else { Condition::Failed };
+        }
+    } else if let (true, Some(since), Some(modified)) =
+        (get, header_date(headers, header::IF_MODIFIED_SINCE), modified)
+    {
+        // A date in the future is invalid and ignored (§3.3).
+        if modified <= since && since <= chrono::Utc::now() {
+            return Condition::NotModified;
+        }
+    }
+    Condition::Proceed
+}
+
+/// Parse an HTTP-date header (RFC 7231 §7.1.1.1: IMF-fixdate, or the
+/// obsolete RFC 850 and asctime forms).  Invalid dates are ignored.
+fn header_date(
+    headers: &HeaderMap,
+    name: header::HeaderName,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+        return Some(date.to_utc());
+    }
+    ["%A, %d-%b-%y %H:%M:%S GMT", "%a %b %e %H:%M:%S %Y"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(value, fmt).ok())
+        .map(|t| t.and_utc())
+}
+
+/// Format a timestamp as an IMF-fixdate, for `Last-Modified`.
+fn http_date(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+// ── Request bodies ───────────────────────────────────────────────────────
+
+/// The properties a PROPFIND or REPORT asked for.
+#[derive(Debug, PartialEq)]
+enum PropRequest {
+    /// `<D:allprop/>` (or an empty PROPFIND body).
+    AllProp,
+    /// `<D:propname/>`: names only, no values.
+    PropName,
+    /// An explicit `<D:prop>` list.  `address_data` holds the
+    /// `<C:address-data><C:prop name="…"/></C:address-data>` subset, if the
+    /// client asked for only some vCard properties, and `format` the
+    /// representation from its `content-type` / `version` attributes.
+    Prop {
+        names: Vec<QName>,
+        address_data: Option<Vec<crate::vcard::PropSelector>>,
+        format: crate::vcard::Format,
+    },
+}
+
+impl PropRequest {
+    /// Read the `allprop` / `propname` / `prop` child of a PROPFIND or
+    /// REPORT root element.  Fails with [`UnsupportedAddressData`] when
+    /// `address-data` asks for a format we don't produce.
+    fn from_element(root: &Element) -> Result<Option<Self>> {
+        if root.child(DAV, "allprop").is_some() {
+            Ok(Some(Self::AllProp))
+        } else if root.child(DAV, "propname").is_some() {
+            Ok(Some(Self::PropName))
+        } else {
+            let Some(prop) = root.child(DAV, "prop") else {
+                return Ok(None);
+            };
+            let requested = prop.child(CARDDAV, "address-data");
+            let format = match requested {
+                Some(el) => address_data_format(el)?,
+                None => crate::vcard::Format::default(),
+            };
+            Ok(Some(Self::Prop {
+                names: prop.children.iter().map(|c| c.name.clone()).collect(),
+                address_data: requested.and_then(address_data_subset),
+                format,
+            }))
+        }
+    }
+
+    /// An explicit property list without an `address-data` subset.
+    fn names(names: Vec<QName>) -> Self {
+        Self::Prop {
+            names,
+            address_data: None,
+            format: crate::vcard::Format::default(),
+        }
+    }
+
+    /// The representation to return in `address-data`.
+    fn format(&self) -> crate::vcard::Format {
+        match self {
+            Self::Prop { format, .. } => *format,
+            _ => crate::vcard::Format::default(),
+        }
+    }
+
+    /// Default for REPORTs that don't list any properties: etag + vCard.
+    fn etag_and_data() -> Self {
+        Self::names(vec![
+            QName::new(DAV, "getetag"),
+            QName::new(CARDDAV, "address-data"),
+        ])
+    }
+}
+
+/// The `<C:prop>` children of a `<C:address-data>` request, or `None` when
+/// the client wants the whole vCard (no children, or `<C:allprop/>`).
+fn address_data_subset(address_data: &Element) -> Option<Vec<crate::vcard::PropSelector>> {
+    if address_data.child(CARDDAV, "allprop").is_some() {
+        return None;
+    }
+    let selectors: Vec<_> = address_data
+        .children_named(CARDDAV, "prop")
+        .filter_map(|p| {
+            Some(crate::vcard::PropSelector {
+                name: p.attr("name")?.to_ascii_uppercase(),
+                novalue: p.attr("novalue") == Some("yes"),
+            })
+        })
+        .collect();
+    (!selectors.is_empty()).then_some(selectors)
+}
+
+/// A `C:address-data` request for a media type / version we can't render
+/// (answered with the `CARDDAV:supported-address-data` precondition).
+#[derive(Debug)]
+struct UnsupportedAddressData(String);
+
+impl std::fmt::Display for UnsupportedAddressData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsupported address data type {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedAddressData {}
+
+/// The representation named by a `<C:address-data>` element's attributes
+/// (RFC 6352 §10.4: `text/vcard` and `3.0` when absent).
+fn address_data_format(address_data: &Element) -> Result<crate::vcard::Format> {
+    let content_type = address_data.attr("content-type").unwrap_or("text/vcard");
+    let version = address_data.attr("version");
+    crate::vcard::Format::from_media_type(content_type, version).ok_or_else(|| {
+        UnsupportedAddressData(format!("{content_type} {}", version.unwrap_or(""))).into()
+    })
+}
+
+/// Parse a PROPFIND body.  An empty body means `allprop` (RFC 4918 §9.1).
+fn parse_propfind(body: &str) -> Result<PropRequest> {
+    if body.trim().is_empty() {
+        return Ok(PropRequest::AllProp);
+    }
+    let root = xml::parse(body)?;
+    if !root.is(DAV, "propfind") {
+        bail!("expected DAV:propfind, found {}", root.name.local);
+    }
+    Ok(PropRequest::from_element(&root)?.unwrap_or(PropRequest::AllProp))
+}
+
+async fn read_propfind(req: Request) -> Result<PropRequest> {
+    let body = axum::body::to_bytes(req.into_body(), 1024 * 64)
+        .await
+        .context("reading PROPFIND body")?;
+    parse_propfind(&String::from_utf8_lossy(&body))
+}
+
+/// A parsed REPORT body.
+#[derive(Debug)]
+struct Report {
+    kind: ReportKind,
+    props: PropRequest,
+}
+
+#[derive(Debug, PartialEq)]
+enum ReportKind {
+    /// `C:addressbook-multiget` with its `D:href` list.
+    Multiget { hrefs: Vec<String> },
+    /// `C:addressbook-query` with its filter and optional `C:nresults`.
+    Query { filter: Filter, limit: Option<usize> },
+    /// `D:sync-collection`; `None` token means an initial sync.
+    SyncCollection { token: Option<String> },
+}
+
+/// Parse a REPORT body.  Returns `Ok(None)` for well-formed reports we
+/// don't support.
+fn parse_report(body: &str) -> Result<Option<Report>> {
+    let root = xml::parse(body)?;
+    let props = PropRequest::from_element(&root)?.unwrap_or_else(PropRequest::etag_and_data);
+
+    let kind = if root.is(CARDDAV, "addressbook-multiget") {
+        let hrefs = root
+            .children_named(DAV, "href")
+            .map(|h| h.text().to_string())
+            .filter(|h| !h.is_empty())
+            .collect();
+        ReportKind::Multiget { hrefs }
+    } else if root.is(CARDDAV, "addressbook-query") {
+        let filter = match root.child(CARDDAV, "filter") {
+            Some(el) => Filter::from_element(el)?,
+            None => Filter::default(),
+        };
+        let limit = root
+            .child(CARDDAV, "limit")
+            .and_then(|l| l.child(CARDDAV, "nresults"))
+            .map(|n| n.text().parse::<usize>().context("invalid C:nresults"))
+            .transpose()?;
+        ReportKind::Query { filter, limit }
+    } else if root.is(DAV, "sync-collection") {
+        let token = root
+            .child(DAV, "sync-token")
+            .map(|t| t.text().to_string())
+            .filter(|t| !t.is_empty());
+        ReportKind::SyncCollection { token }
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(Report { kind, props }))
+}
+
 // ── Response builders ────────────────────────────────────────────────────
 
-/// Build a standard REPORT multistatus response from a slice of borrowed tuples.
-fn build_report_xml(contacts: &[&(String, String, String)]) -> Response {
-    let names: Vec<&str> = contacts.iter().map(|(rn, _, _)| rn.as_str()).collect();
-    tracing::info!(count = contacts.len(), contacts = ?names, "REPORT response");
+/// A live property of a resource, with its value pre-rendered as XML.
+struct LiveProp {
+    name: QName,
+    value: String,
+    /// Whether the property is included in `allprop` responses.
+    in_allprop: bool,
+}
 
-    let mut xml = String::with_capacity(contacts.len() * 2048);
-    xml.push_str(
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-"#,
-    );
+impl LiveProp {
+    fn new(ns: &str, local: &str, value: impl Into<String>) -> Self {
+        Self {
+            name: QName::new(ns, local),
+            value: value.into(),
+            in_allprop: true,
+        }
+    }
 
-    for (resource_name, etag, vcard) in contacts {
-        append_contact_response(&mut xml, resource_name, etag, vcard);
+    fn not_in_allprop(self) -> Self {
+        Self {
+            in_allprop: false,
+            ..self
+        }
     }
 
-    xml.push_str("</D:multistatus>");
+    fn render(&self) -> String {
+        if self.value.is_empty() {
+            xml::empty_tag(&self.name)
+        } else {
+            format!(
+                "{}{}{}",
+                xml::start_tag(&self.name),
+                self.value,
+                xml::end_tag(&self.name)
+            )
+        }
+    }
+}
 
-    tracing::debug!(body = %xml, "REPORT response body");
-    multistatus_response(&xml)
+/// XML declaration and opening `<D:multistatus>` tag.
+fn multistatus_start() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<D:multistatus {}>\n",
+        xml::NAMESPACE_DECLS
+    )
 }
 
-/// Build a standard REPORT multistatus response from a slice of owned tuples.
-fn build_report_xml_owned(contacts: &[(String, String, String)]) -> Response {
-    let names: Vec<&str> = contacts.iter().map(|(rn, _, _)| rn.as_str()).collect();
-    tracing::info!(count = contacts.len(), contacts = ?names, "REPORT response");
+/// Append a `<D:response>` for `href` answering `request` from `props`.
+///
+/// Requested properties the resource doesn't have are listed in a separate
+/// `404 Not Found` propstat, as RFC 4918 §9.1 requires.
+fn append_response(xml: &mut String, href: &str, props: &[LiveProp], request: &PropRequest) {
+    let mut found = Vec::new();
+    let mut missing = Vec::new();
+    match request {
+        PropRequest::AllProp => {
+            found.extend(props.iter().filter(|p| p.in_allprop).map(LiveProp::render));
+        }
+        PropRequest::PropName => {
+            found.extend(props.iter().map(|p| xml::empty_tag(&p.name)));
+        }
+        PropRequest::Prop { names, .. } => {
+            for name in names {
+                match props.iter().find(|p| name.is(&p.name.ns, &p.name.local)) {
+                    Some(prop) => found.push(prop.render()),
+                    None => missing.push(xml::empty_tag(name)),
+                }
+            }
+        }
+    }
 
-    let mut xml = String::with_capacity(contacts.len() * 2048);
-    xml.push_str(
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-"#,
-    );
+    xml.push_str("  <D:response>\n    <D:href>");
+    xml.push_str(&xml::escape(href));
+    xml.push_str("</D:href>\n");
+    if !found.is_empty() || missing.is_empty() {
+        append_propstat(xml, &found, "200 OK");
+    }
+    if !missing.is_empty() {
+        append_propstat(xml, &missing, "404 Not Found");
+    }
+    xml.push_str("  </D:response>\n");
+}
 
-    for (resource_name, etag, vcard) in contacts {
-        append_contact_response(&mut xml, resource_name, etag, vcard);
+fn append_propstat(xml: &mut String, props: &[String], status: &str) {
+    xml.push_str("    <D:propstat>\n      <D:prop>\n");
+    for prop in props {
+        xml.push_str("        ");
+        xml.push_str(prop);
+        xml.push('\n');
     }
+    xml.push_str("      </D:prop>\n      <D:status>HTTP/1.1 ");
+    xml.push_str(status);
+    xml.push_str("</D:status>\n    </D:propstat>\n");
+}
 
+/// Build a standard REPORT multistatus response from a slice of borrowed
+/// tuples, plus a `404 Not Found` response for each href in `missing`.
+///
+/// `truncated` adds the `507 Insufficient Storage` response for the
+/// collection that RFC 6352 §8.6.2 requires when a `C:limit` cut the
+/// result short.
+fn build_report_xml(
+    book: &Book,
+    contacts: &[&(String, String, String)],
+    missing: &[String],
+    truncated: bool,
+    props: &PropRequest,
+) -> Response {
+    let names: Vec<&str> = contacts.iter().map(|(rn, _, _)| rn.as_str()).collect();
+    tracing::info!(count = contacts.len(), contacts = ?names, missing = ?missing, "REPORT response");
+
+    let mut xml = multistatus_start();
+    for (resource_name, etag, vcard) in contacts {
+        append_contact_response(&mut xml, book, resource_name, etag, vcard, props);
+    }
+    for href in missing {
+        append_not_found(&mut xml, href);
+    }
+    if truncated {
+        xml.push_str("  <D:response>\n    <D:href>");
+        xml.push_str(&xml::escape(&book.path));
+        xml.push_str("</D:href>\n");
+        xml.push_str("    <D:status>HTTP/1.1 507 Insufficient Storage</D:status>\n");
+        xml.push_str("    <D:error><D:number-of-matches-within-limits/></D:error>\n");
+        xml.push_str("  </D:response>\n");
+    }
     xml.push_str("</D:multistatus>");
 
     tracing::debug!(body = %xml, "REPORT response body");
     multistatus_response(&xml)
 }
 
+/// Build a standard REPORT multistatus response from a slice of owned tuples.
+fn build_report_xml_owned(
+    book: &Book,
+    contacts: &[(String, String, String)],
+    props: &PropRequest,
+) -> Response {
+    let refs: Vec<&(String, String, String)> = contacts.iter().collect();
+    build_report_xml(book, &refs, &[], false, props)
+}
+
 /// Append a single `<D:response>` element for a contact to the XML buffer.
-fn append_contact_response(xml: &mut String, resource_name: &str, etag: &str, vcard: &str) {
-    let href = contact_href(resource_name);
+fn append_contact_response(
+    xml: &mut String,
+    book: &Book,
+    resource_name: &str,
+    etag: &str,
+    vcard: &str,
+    props: &PropRequest,
+) {
+    append_response(
+        xml,
+        &book.contact_href(resource_name),
+        &contact_props(etag, vcard, props),
+        props,
+    );
+}
+
+/// Append a bare `404 Not Found` response (unknown multiget href, or a
+/// contact deleted since the client's sync token).
+fn append_not_found(xml: &mut String, href: &str) {
     xml.push_str("  <D:response>\n    <D:href>");
-    xml.push_str(&xml_escape(&href));
-    xml.push_str("</D:href>\n    <D:propstat>\n      <D:prop>\n");
-    xml.push_str("        <D:getetag>\"");
-    xml.push_str(&xml_escape(etag));
-    xml.push_str("\"</D:getetag>\n");
-    xml.push_str("        <C:address-data>");
-    xml.push_str(&xml_escape(vcard));
-    xml.push_str("</C:address-data>\n");
-    xml.push_str("      </D:prop>\n      <D:status>HTTP/1.1 200 OK</D:status>\n");
-    xml.push_str("    </D:propstat>\n  </D:response>\n");
+    xml.push_str(&xml::escape(href));
+    xml.push_str("</D:href>\n    <D:status>HTTP/1.1 404 Not Found</D:status>\n");
+    xml.push_str("  </D:response>\n");
+}
+
+/// Build the multistatus body of a `sync-collection` REPORT.
+///
+/// `changes` comes from [`db::changes_since`]: `None` marks a contact that
+/// was deleted or left the book.
+fn build_sync_collection_xml(
+    book: &Book,
+    changes: &[db::ContactChange],
+    token: &str,
+    props: &PropRequest,
+) -> Response {
+    tracing::info!(count = changes.len(), token = token, "sync-collection response");
+
+    let mut xml = multistatus_start();
+    for (resource_name, state) in changes {
+        match state {
+            Some((etag, vcard)) => {
+                append_contact_response(&mut xml, book, resource_name, etag, vcard, props)
+            }
+            None => append_not_found(&mut xml, &book.contact_href(resource_name)),
+        }
+    }
+
+    xml.push_str("  <D:sync-token>");
+    xml.push_str(&xml::escape(token));
+    xml.push_str("</D:sync-token>\n</D:multistatus>");
+
+    tracing::debug!(body = %xml, "sync-collection response body");
+    multistatus_response(&xml)
 }
 
 fn multistatus_response(xml: &str) -> Response {
@@ -716,12 +2334,44 @@ fn multistatus_response(xml: &str) -> Response {
 fn options_response() -> Response {
     Response::builder()
         .status(StatusCode::OK)
-        .header("Allow", "OPTIONS, GET, HEAD, PROPFIND, REPORT")
+        .header("Allow", "OPTIONS, GET, HEAD, PROPFIND, REPORT, PUT, DELETE")
         .header("DAV", "1, 3, addressbook")
         .body(Body::empty())
         .unwrap()
 }
 
+fn not_found() -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("Not Found"))
+        .unwrap()
+}
+
+fn unauthorized() -> Response {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header("WWW-Authenticate", "Basic realm=\"Setu CardDAV\"")
+        .body(Body::from("Unauthorized"))
+        .unwrap()
+}
+
+/// The client is locked out after too many failed logins.
+fn too_many_requests(retry_after: std::time::Duration) -> Response {
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(header::RETRY_AFTER, (retry_after.as_secs() + 1).to_string())
+        .body(Body::from("Too many failed logins"))
+        .unwrap()
+}
+
+/// The app password's scope does not cover this resource or method.
+fn forbidden() -> Response {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(Body::from("Forbidden"))
+        .unwrap()
+}
+
 fn method_not_allowed() -> Response {
     Response::builder()
         .status(StatusCode::METHOD_NOT_ALLOWED)
@@ -736,117 +2386,358 @@ fn internal_error() -> Response {
         .unwrap()
 }
 
-fn bad_request(msg: &str) -> Response {
+fn precondition_failed() -> Response {
     Response::builder()
-        .status(StatusCode::BAD_REQUEST)
+        .status(StatusCode::PRECONDITION_FAILED)
+        .body(Body::from("Precondition Failed"))
+        .unwrap()
+}
+
+fn conflict(msg: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::CONFLICT)
         .body(Body::from(msg.to_string()))
         .unwrap()
 }
 
-// ── URL / resource-name helpers ──────────────────────────────────────────
+fn bad_gateway() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .body(Body::from("Google API request failed"))
+        .unwrap()
+}
 
-/// Convert a Google resource name (`people/c123`) to a CardDAV href.
-fn contact_href(resource_name: &str) -> String {
-    let safe = resource_name.replace('/', "_");
-    format!("/addressbook/{safe}.vcf")
+/// `403 Forbidden` with the RFC 6578 `DAV:valid-sync-token` precondition,
+/// telling the client to discard its token and resync from scratch.
+fn invalid_sync_token() -> Response {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml;charset=utf-8")
+        .body(Body::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<D:error xmlns:D="DAV:"><D:valid-sync-token/></D:error>"#,
+        ))
+        .unwrap()
 }
 
-/// Reverse of `contact_href`: `people_c123.vcf` → `people/c123`.
-fn id_to_resource_name(id: &str) -> String {
-    id.trim_end_matches(".vcf").replace('_', "/")
+/// `403 Forbidden` with the RFC 3253 `DAV:supported-report` precondition.
+fn unsupported_report() -> Response {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml;charset=utf-8")
+        .body(Body::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<D:error xmlns:D="DAV:"><D:supported-report/></D:error>"#,
+        ))
+        .unwrap()
 }
 
-/// Minimal XML escaping for attribute/text values.
-pub(crate) fn xml_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-}
-
-/// Extract `<D:href>` values from a REPORT request body.
-fn extract_hrefs(xml: &str) -> Vec<String> {
-    let mut hrefs = Vec::new();
-    // Match both <D:href> and <href> (some clients omit the namespace prefix).
-    for tag_open in &["<D:href>", "<href>"] {
-        let tag_close = tag_open.replace('<', "</");
-        let mut pos = 0;
-        while let Some(start) = xml[pos..].find(tag_open) {
-            let abs_start = pos + start + tag_open.len();
-            if let Some(end) = xml[abs_start..].find(&tag_close) {
-                let href = xml[abs_start..abs_start + end].trim().to_string();
-                if !href.is_empty() {
-                    hrefs.push(href);
-                }
-                pos = abs_start + end + tag_close.len();
-            } else {
-                break;
+/// `403 Forbidden` with the RFC 6352 `CARDDAV:supported-address-data`
+/// precondition.
+fn unsupported_address_data() -> Response {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml;charset=utf-8")
+        .body(Body::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<D:error xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav"><C:supported-address-data/></D:error>"#,
+        ))
+        .unwrap()
+}
+
+/// `403 Forbidden` with the RFC 6352 `CARDDAV:supported-collation`
+/// precondition.
+fn unsupported_collation() -> Response {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml;charset=utf-8")
+        .body(Body::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<D:error xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav"><C:supported-collation/></D:error>"#,
+        ))
+        .unwrap()
+}
+
+/// Response for a request body we can't act on: a 403 precondition for
+/// well-formed requests asking for something unsupported, else 400.
+fn body_error(err: anyhow::Error) -> Response {
+    if err.downcast_ref::<UnsupportedCollation>().is_some() {
+        tracing::info!("addressbook-query: {err:#}");
+        return unsupported_collation();
+    }
+    if err.downcast_ref::<UnsupportedAddressData>().is_some() {
+        tracing::info!("address-data: {err:#}");
+        return unsupported_address_data();
+    }
+    malformed_body(err)
+}
+
+fn malformed_body(err: anyhow::Error) -> Response {
+    tracing::debug!("malformed request body: {err:#}");
+    bad_request("malformed XML request body")
+}
+
+fn bad_request(msg: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(msg.to_string()))
+        .unwrap()
+}
+
+// ── Address books ────────────────────────────────────────────────────────
+
+/// Path segment of the all-contacts book under an address book home.
+const ALL_CONTACTS_ID: &str = "contacts";
+
+/// An address book collection: every contact of an account, or one of its
+/// Google contact groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Book {
+    /// Account whose contacts the book holds.
+    account: String,
+    /// Collection path with trailing slash, e.g. `/addressbooks/work/contacts/`.
+    path: String,
+    /// Contact group resource name; `None` for all contacts.
+    group: Option<String>,
+    display_name: String,
+}
+
+impl Book {
+    /// All contacts at the pre-groups `/addressbook/` path, kept for
+    /// clients configured before address books were split per group.
+    fn legacy(account: &str) -> Self {
+        Self {
+            account: account.to_string(),
+            path: "/addressbook/".into(),
+            group: None,
+            display_name: "Google Contacts".into(),
+        }
+    }
+
+    fn all_contacts(home: &Home) -> Self {
+        Self {
+            path: format!("{}{ALL_CONTACTS_ID}/", home.path),
+            ..Self::legacy(&home.account)
+        }
+    }
+
+    /// `contactGroups/1a2b3c` is served at `<home>/1a2b3c/`.
+    fn for_group(home: &Home, group: &db::StoredGroup) -> Self {
+        let id = group
+            .resource_name
+            .strip_prefix("contactGroups/")
+            .unwrap_or(&group.resource_name);
+        Self {
+            account: home.account.clone(),
+            path: format!("{}{id}/", home.path),
+            group: Some(group.resource_name.clone()),
+            display_name: group.name.clone(),
+        }
+    }
+
+    /// The book's id under its home: `contacts` or the group id.
+    fn id(&self) -> &str {
+        match &self.group {
+            Some(group) => group.strip_prefix("contactGroups/").unwrap_or(group),
+            None => ALL_CONTACTS_ID,
+        }
+    }
+
+    /// This book's key in the change log (`""` for all contacts).
+    fn change_key(&self) -> &str {
+        self.group.as_deref().unwrap_or("")
+    }
+
+    /// Convert a Google resource name (`people/c123`) to a CardDAV href.
+    fn contact_href(&self, resource_name: &str) -> String {
+        let safe = resource_name.replace('/', "_");
+        format!("{}{safe}.vcf", self.path)
+    }
+
+    /// Resource name of the contact at href id `id` (`people_c123.vcf`),
+    /// following the alias of a contact a client created at its own href.
+    fn resource_name(&self, conn: &rusqlite::Connection, id: &str) -> Result<String> {
+        db::resolve_alias(conn, &self.account, &id_to_resource_name(id))
+    }
+
+    /// All contacts in this book as `(resource_name, etag, vcard)`.
+    fn contacts(&self, conn: &rusqlite::Connection) -> Result<Vec<(String, String, String)>> {
+        match &self.group {
+            Some(group) => db::group_contacts(conn, &self.account, group),
+            None => db::all_contacts(conn, &self.account),
+        }
+    }
+
+    /// Look up a contact's `(etag, vcard)`, hiding contacts not in this book.
+    fn get_contact(
+        &self,
+        conn: &rusqlite::Connection,
+        resource_name: &str,
+    ) -> Result<Option<(String, String)>> {
+        if let Some(group) = &self.group {
+            if !db::is_member(conn, &self.account, group, resource_name)? {
+                return Ok(None);
             }
         }
+        db::get_contact(conn, &self.account, resource_name)
+    }
+
+    /// CTag.  All contacts keep the account's change counter; a group book
+    /// uses the latest change in its own log.
+    fn ctag(&self, conn: &rusqlite::Connection) -> Result<i64> {
+        match &self.group {
+            Some(group) => db::book_change_seq(conn, &self.account, group),
+            None => db::change_counter(conn, &self.account),
+        }
     }
-    hrefs
 }
 
-/// Extract the phone number from a `<C:prop-filter name="TEL">` element
-/// inside an `addressbook-query` REPORT body.
-///
-/// Handles both namespaced (`<C:prop-filter>`, `<C:text-match>`) and
-/// non-namespaced variants.
-///
-/// Example XML:
-/// ```xml
-/// <C:addressbook-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
-///   <D:prop><D:getetag/><C:address-data/></D:prop>
-///   <C:filter>
-///     <C:prop-filter name="TEL">
-///       <C:text-match collation="i;unicode-casemap" match-type="contains">
-///         5551234567
-///       </C:text-match>
-///     </C:prop-filter>
-///   </C:filter>
-/// </C:addressbook-query>
-/// ```
-fn extract_tel_filter(xml: &str) -> Option<String> {
-    // Look for prop-filter with name="TEL" (with or without C: prefix).
-    let tel_marker_patterns = [
-        "prop-filter name=\"TEL\"",
-        "prop-filter name='TEL'",
-    ];
-
-    let tel_pos = tel_marker_patterns
-        .iter()
-        .filter_map(|pat| xml.find(pat))
-        .min()?;
+/// Whether `group` is served as an address book.  Without a configured
+/// selection every user group plus "Starred" is published; otherwise
+/// entries match the group name (case-insensitive), id or resource name.
+fn group_published(group: &db::StoredGroup, selection: Option<&[String]>) -> bool {
+    let id = group
+        .resource_name
+        .strip_prefix("contactGroups/")
+        .unwrap_or(&group.resource_name);
+    match selection {
+        None => {
+            group.group_type == "USER_CONTACT_GROUP" || group.resource_name == "contactGroups/starred"
+        }
+        Some(selection) => selection.iter().map(|s| s.trim()).any(|s| {
+            s.eq_ignore_ascii_case(&group.name) || s == id || s == group.resource_name
+        }),
+    }
+}
 
-    // Now find the text-match content after this position.
-    let after_tel = &xml[tel_pos..];
+/// The all-contacts book of `home` followed by one book per published group.
+fn published_books(
+    conn: &rusqlite::Connection,
+    home: &Home,
+    selection: Option<&[String]>,
+) -> Result<Vec<Book>> {
+    let groups = db::contact_groups(conn, &home.account)?;
+    let mut books = vec![Book::all_contacts(home)];
+    books.extend(
+        groups
+            .iter()
+            .filter(|g| group_published(g, selection))
+            .map(|g| Book::for_group(home, g)),
+    );
+    Ok(books)
+}
 
-    // Try both namespaced and non-namespaced text-match tags.
-    for tag_open in &["<C:text-match", "<text-match"] {
-        let tag_close = if tag_open.starts_with("<C:") {
-            "</C:text-match>"
-        } else {
-            "</text-match>"
-        };
+/// The published book served at `<home>/<book_id>/`.
+fn resolve_book(state: &AppState, home: &Home, book_id: &str) -> Result<Option<Book>> {
+    if book_id == ALL_CONTACTS_ID {
+        return Ok(Some(Book::all_contacts(home)));
+    }
+    let conn = db::open(Some(&state.db_key))?;
+    let path = format!("{}{book_id}/", home.path);
+    Ok(published_books(&conn, home, state.published_groups.as_deref())?
+        .into_iter()
+        .find(|b| b.path == path))
+}
 
-        if let Some(open_start) = after_tel.find(tag_open) {
-            let after_open = &after_tel[open_start..];
-            // Skip past the opening tag (find the closing >)
-            if let Some(gt_pos) = after_open.find('>') {
-                let content_start = gt_pos + 1;
-                if let Some(close_pos) = after_open[content_start..].find(tag_close) {
-                    let value = after_open[content_start..content_start + close_pos]
-                        .trim()
-                        .to_string();
-                    if !value.is_empty() {
-                        return Some(value);
-                    }
-                }
+/// The book at `/addressbooks/<account>/<book_id>/`, or with no account at
+/// the default account's single-account `/addressbooks/<book_id>/`.
+fn book_at(state: &AppState, account: Option<&str>, book_id: &str) -> Result<Option<Book>> {
+    let home = match account {
+        Some(account) => match account_home(state, account)? {
+            Some(home) => home,
+            None => return Ok(None),
+        },
+        None => legacy_home(state)?,
+    };
+    resolve_book(state, &home, book_id)
+}
+
+/// A `Person.memberships` entry for `group`.
+fn group_membership(group: &str) -> google_people1::api::Membership {
+    google_people1::api::Membership {
+        contact_group_membership: Some(google_people1::api::ContactGroupMembership {
+            contact_group_resource_name: Some(group.to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+// ── URL / resource-name helpers ──────────────────────────────────────────
+
+/// The representation preferred by a GET's `Accept` header (RFC 9110
+/// §12.5.1): the supported media type with the highest quality.  Wildcards
+/// and a missing header mean vCard 3.0.
+fn accepted_format(headers: &HeaderMap) -> crate::vcard::Format {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return crate::vcard::Format::default();
+    };
+    let mut best: Option<(f32, crate::vcard::Format)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media = parts.next().unwrap_or("");
+        let mut version = None;
+        let mut quality = 1.0_f32;
+        for param in parts {
+            match param.split_once('=').map(|(k, v)| (k.trim(), v.trim().trim_matches('"'))) {
+                Some((k, v)) if k.eq_ignore_ascii_case("version") => version = Some(v),
+                Some((k, v)) if k.eq_ignore_ascii_case("q") => quality = v.parse().unwrap_or(0.0),
+                _ => {}
             }
         }
+        let Some(v) = crate::vcard::Format::from_media_type(media, version) else {
+            continue;
+        };
+        if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+            best = Some((quality, v));
+        }
     }
+    best.map(|(_, v)| v).unwrap_or_default()
+}
 
-    None
+/// Value of the `Depth` header (`"0"` when absent).
+fn depth_header(req: &Request) -> String {
+    req.headers()
+        .get("Depth")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("0")
+        .to_string()
+}
+
+/// Path part of a request href, percent-decoded.  Clients may send
+/// absolute URLs (`https://host/addressbook/x.vcf`) or escaped paths.
+fn href_path(href: &str) -> String {
+    let path = match href.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => href,
+    };
+    percent_encoding::percent_decode_str(path)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Reverse of [`Book::contact_href`]: `people_c123.vcf` → `people/c123`.
+fn id_to_resource_name(id: &str) -> String {
+    id.trim_end_matches(".vcf").replace('_', "/")
+}
+
+// ── Sync tokens ──────────────────────────────────────────────────────────
+
+/// Prefix of the opaque sync-token URIs handed to clients; the suffix is
+/// the change-log sequence number.
+const SYNC_TOKEN_PREFIX: &str = "urn:setu:sync:";
+
+fn sync_token(seq: i64) -> String {
+    format!("{SYNC_TOKEN_PREFIX}{seq}")
+}
+
+/// Reverse of [`sync_token`].  Returns `None` for tokens we did not issue.
+fn parse_sync_token(token: &str) -> Option<i64> {
+    token
+        .strip_prefix(SYNC_TOKEN_PREFIX)?
+        .parse()
+        .ok()
+        .filter(|seq: &i64| *seq >= 0)
 }
 
 // ── Tests ────────────────────────────────────────────────────────────────
@@ -856,51 +2747,97 @@ mod tests {
     use super::*;
     use google_people1::api::{Name, Person, PhoneNumber};
 
+    const ACCOUNT: &str = db::DEFAULT_ACCOUNT;
+
+    fn home() -> Home {
+        Home::legacy(ACCOUNT)
+    }
+
     #[test]
     fn test_contact_href_roundtrip() {
         let rn = "people/c1234567890";
-        let href = contact_href(rn);
+        let href = Book::legacy(ACCOUNT).contact_href(rn);
         assert_eq!(href, "/addressbook/people_c1234567890.vcf");
 
         let recovered = id_to_resource_name("people_c1234567890.vcf");
         assert_eq!(recovered, rn);
     }
 
-    #[test]
-    fn test_xml_escape() {
-        assert_eq!(xml_escape("a<b>c&d\"e"), "a&lt;b&gt;c&amp;d&quot;e");
-        assert_eq!(xml_escape("plain text"), "plain text");
+    /// Parse a REPORT body that is expected to be supported.
+    fn report(xml: &str) -> Report {
+        parse_report(xml).unwrap().expect("supported REPORT")
+    }
+
+    /// The TEL text of an `addressbook-query` body.
+    fn phone_query(xml: &str) -> Option<String> {
+        let ReportKind::Query { filter, .. } = report(xml).kind else {
+            panic!("expected addressbook-query");
+        };
+        filter.phone_query().map(String::from)
     }
 
     #[test]
-    fn test_extract_hrefs_namespaced() {
+    fn test_multiget_hrefs_namespaced() {
         let xml = r#"<?xml version="1.0"?>
 <C:addressbook-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
   <D:prop><D:getetag/><C:address-data/></D:prop>
   <D:href>/addressbook/people_c111.vcf</D:href>
   <D:href>/addressbook/people_c222.vcf</D:href>
 </C:addressbook-multiget>"#;
-        let hrefs = extract_hrefs(xml);
+        let parsed = report(xml);
         assert_eq!(
-            hrefs,
-            vec![
-                "/addressbook/people_c111.vcf",
-                "/addressbook/people_c222.vcf"
-            ]
+            parsed.kind,
+            ReportKind::Multiget {
+                hrefs: vec![
+                    "/addressbook/people_c111.vcf".into(),
+                    "/addressbook/people_c222.vcf".into(),
+                ]
+            }
         );
+        assert_eq!(parsed.props, PropRequest::etag_and_data());
     }
 
     #[test]
-    fn test_extract_hrefs_no_namespace() {
+    fn test_multiget_hrefs_no_namespace() {
         let xml = r#"<addressbook-multiget>
   <href>/addressbook/people_c999.vcf</href>
 </addressbook-multiget>"#;
-        let hrefs = extract_hrefs(xml);
-        assert_eq!(hrefs, vec!["/addressbook/people_c999.vcf"]);
+        assert_eq!(
+            report(xml).kind,
+            ReportKind::Multiget {
+                hrefs: vec!["/addressbook/people_c999.vcf".into()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_multiget_hrefs_other_prefix_and_entities() {
+        let xml = r#"<card:addressbook-multiget xmlns:card="urn:ietf:params:xml:ns:carddav" xmlns="DAV:">
+  <href><![CDATA[/addressbook/people_c1.vcf]]></href>
+  <href>https://localhost:5232/addressbook/people_c2.vcf?a=1&amp;b=2</href>
+</card:addressbook-multiget>"#;
+        let ReportKind::Multiget { hrefs } = report(xml).kind else {
+            panic!("expected multiget");
+        };
+        assert_eq!(hrefs[0], "/addressbook/people_c1.vcf");
+        assert_eq!(
+            hrefs[1],
+            "https://localhost:5232/addressbook/people_c2.vcf?a=1&b=2"
+        );
     }
 
     #[test]
-    fn test_extract_tel_filter_namespaced() {
+    fn test_href_path() {
+        assert_eq!(href_path("/addressbook/people_c1.vcf"), "/addressbook/people_c1.vcf");
+        assert_eq!(
+            href_path("https://localhost:5232/addressbook/people_c1.vcf"),
+            "/addressbook/people_c1.vcf"
+        );
+        assert_eq!(href_path("/addressbook/people%5Fc1.vcf"), "/addressbook/people_c1.vcf");
+    }
+
+    #[test]
+    fn test_tel_filter_namespaced() {
         let xml = r#"<?xml version="1.0"?>
 <C:addressbook-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
   <D:prop><D:getetag/><C:address-data/></D:prop>
@@ -910,11 +2847,11 @@ mod tests {
     </C:prop-filter>
   </C:filter>
 </C:addressbook-query>"#;
-        assert_eq!(extract_tel_filter(xml), Some("5551234567".into()));
+        assert_eq!(phone_query(xml).as_deref(), Some("5551234567"));
     }
 
     #[test]
-    fn test_extract_tel_filter_no_namespace() {
+    fn test_tel_filter_no_namespace() {
         let xml = r#"<addressbook-query>
   <filter>
     <prop-filter name="TEL">
@@ -922,11 +2859,23 @@ mod tests {
     </prop-filter>
   </filter>
 </addressbook-query>"#;
-        assert_eq!(extract_tel_filter(xml), Some("+1-555-999-0000".into()));
+        assert_eq!(phone_query(xml).as_deref(), Some("+1-555-999-0000"));
+    }
+
+    #[test]
+    fn test_tel_filter_reordered_attributes_and_prefix() {
+        let xml = r#"<q:addressbook-query xmlns:q="urn:ietf:params:xml:ns:carddav">
+  <q:filter test="anyof">
+    <q:prop-filter test="allof" name='tel'>
+      <q:text-match match-type="equals" collation="i;unicode-casemap">+1 &#40;555&#41; 000</q:text-match>
+    </q:prop-filter>
+  </q:filter>
+</q:addressbook-query>"#;
+        assert_eq!(phone_query(xml).as_deref(), Some("+1 (555) 000"));
     }
 
     #[test]
-    fn test_extract_tel_filter_missing() {
+    fn test_tel_filter_missing() {
         let xml = r#"<C:addressbook-query xmlns:C="urn:ietf:params:xml:ns:carddav">
   <C:filter>
     <C:prop-filter name="FN">
@@ -934,13 +2883,163 @@ mod tests {
     </C:prop-filter>
   </C:filter>
 </C:addressbook-query>"#;
-        assert_eq!(extract_tel_filter(xml), None);
+        assert_eq!(phone_query(xml), None);
+    }
+
+    #[test]
+    fn test_query_limit_and_collation() {
+        let xml = r#"<C:addressbook-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <C:filter test="allof">
+    <C:prop-filter name="EMAIL"><C:text-match match-type="starts-with">jo</C:text-match></C:prop-filter>
+  </C:filter>
+  <C:limit><C:nresults>5</C:nresults></C:limit>
+</C:addressbook-query>"#;
+        let ReportKind::Query { filter, limit } = report(xml).kind else {
+            panic!("expected addressbook-query");
+        };
+        assert_eq!(limit, Some(5));
+        assert_eq!(filter.prop_filters[0].name, "EMAIL");
+
+        let bad = r#"<C:addressbook-query xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <C:filter><C:prop-filter name="FN"><C:text-match collation="i;nope">x</C:text-match></C:prop-filter></C:filter>
+</C:addressbook-query>"#;
+        let err = parse_report(bad).unwrap_err();
+        assert!(err.downcast_ref::<UnsupportedCollation>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_multiget_partial_address_data() {
+        let xml = r#"<C:addressbook-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <D:prop>
+    <D:getetag/>
+    <C:address-data><C:prop name="FN"/><C:prop name="tel"/></C:address-data>
+  </D:prop>
+  <D:href>/addressbook/people_c1.vcf</D:href>
+</C:addressbook-multiget>"#;
+        let parsed = report(xml);
+        let PropRequest::Prop { address_data, .. } = &parsed.props else {
+            panic!("expected a prop list");
+        };
+        let names: Vec<&str> = address_data.iter().flatten().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["FN", "TEL"]);
+
+        let contact = (
+            "people/c1".to_string(),
+            "e1".to_string(),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nUID:people-c1\r\nFN:Jane\r\nEMAIL:j@x.org\r\nTEL:555\r\nEND:VCARD\r\n"
+                .to_string(),
+        );
+        let resp = build_report_xml(&Book::legacy(ACCOUNT), &[&contact], &[], false, &parsed.props);
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("FN:Jane\r\nTEL:555\r\n"));
+        assert!(body.contains("UID:people-c1"));
+        assert!(!body.contains("EMAIL"));
+
+        // <C:allprop/> (or no children) means the whole vCard.
+        let all = r#"<C:addressbook-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <D:prop><C:address-data><C:allprop/></C:address-data></D:prop>
+</C:addressbook-multiget>"#;
+        assert!(matches!(
+            report(all).props,
+            PropRequest::Prop {
+                address_data: None,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_report_xml_truncated_by_limit() {
+        let contact = (
+            "people/c1".to_string(),
+            "e1".to_string(),
+            "BEGIN:VCARD\r\nEND:VCARD\r\n".to_string(),
+        );
+        let resp = build_report_xml(&Book::legacy(ACCOUNT), &[&contact], &[], true, &PropRequest::etag_and_data());
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let xml = std::str::from_utf8(&body).unwrap();
+        assert_eq!(xml.matches("<D:response>").count(), 2);
+        assert!(xml.contains("HTTP/1.1 507 Insufficient Storage"));
+        assert!(xml.contains("<D:number-of-matches-within-limits/>"));
+    }
+
+    #[test]
+    fn test_report_empty_or_unsupported_body() {
+        assert!(parse_report("").is_err());
+        assert!(parse_report("<empty/>").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_propfind_variants() {
+        assert_eq!(parse_propfind("").unwrap(), PropRequest::AllProp);
+        assert_eq!(
+            parse_propfind(r#"<propfind xmlns="DAV:"><allprop/></propfind>"#).unwrap(),
+            PropRequest::AllProp
+        );
+        assert_eq!(
+            parse_propfind(r#"<D:propfind xmlns:D="DAV:"><D:propname/></D:propfind>"#).unwrap(),
+            PropRequest::PropName
+        );
+        assert_eq!(
+            parse_propfind(
+                r#"<d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+                     <d:prop><d:displayname/><cs:getctag/></d:prop>
+                   </d:propfind>"#
+            )
+            .unwrap(),
+            PropRequest::names(vec![
+                QName::new(DAV, "displayname"),
+                QName::new(CALENDARSERVER, "getctag"),
+            ])
+        );
+        assert!(parse_propfind("<D:propfind xmlns:D=\"DAV:\">").is_err());
+        assert!(parse_propfind(r#"<D:report xmlns:D="DAV:"/>"#).is_err());
     }
 
+    /// Requested properties the resource lacks go into a 404 propstat.
     #[test]
-    fn test_extract_tel_filter_empty_body() {
-        assert_eq!(extract_tel_filter(""), None);
-        assert_eq!(extract_tel_filter("<empty/>"), None);
+    fn test_append_response_splits_unknown_props() {
+        let request = PropRequest::names(vec![
+            QName::new(DAV, "displayname"),
+            QName::new("http://apple.com/ns/ical/", "calendar-color"),
+            QName::new(CALENDARSERVER, "getctag"),
+        ]);
+        let mut xml = String::new();
+        append_response(&mut xml, "/addressbook/", &addressbook_props(&Book::legacy(ACCOUNT), 7, 3), &request);
+
+        let (ok, not_found) = xml.split_once("HTTP/1.1 200 OK").unwrap();
+        assert!(ok.contains("<D:displayname>Google Contacts</D:displayname>"));
+        assert!(ok.contains("<CS:getctag>7</CS:getctag>"));
+        assert!(!ok.contains("resourcetype"));
+        assert!(!ok.contains("sync-token"));
+        assert!(not_found.contains(
+            r#"<x:calendar-color xmlns:x="http://apple.com/ns/ical/"/>"#
+        ));
+        assert!(not_found.contains("HTTP/1.1 404 Not Found"));
+        assert_eq!(xml.matches("<D:propstat>").count(), 2);
+    }
+
+    #[test]
+    fn test_append_response_allprop_and_propname() {
+        let props = contact_props("e1", "BEGIN:VCARD\r\nEND:VCARD\r\n", &PropRequest::AllProp);
+
+        let mut xml = String::new();
+        append_response(&mut xml, "/addressbook/x.vcf", &props, &PropRequest::AllProp);
+        assert!(xml.contains("<D:getetag>\"e1\"</D:getetag>"));
+        assert!(xml.contains("<D:resourcetype/>"));
+        assert!(!xml.contains("address-data"), "address-data is not part of allprop");
+        assert!(!xml.contains("404"));
+
+        let mut xml = String::new();
+        append_response(&mut xml, "/addressbook/x.vcf", &props, &PropRequest::PropName);
+        assert!(xml.contains("<D:getetag/>"));
+        assert!(xml.contains("<C:address-data/>"));
+        assert!(!xml.contains("e1"));
     }
 
     // ── Test 3: XML Generation ──────────────────────────────────────
@@ -949,7 +3048,7 @@ mod tests {
     /// (`DAV: 1, 3, addressbook`) and 207 Multi-Status code.
     #[test]
     fn test_propfind_response_headers() {
-        let resp = root_propfind();
+        let resp = root_propfind("/principals/", &PropRequest::AllProp);
         assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
         assert_eq!(
             resp.headers().get("DAV").unwrap().to_str().unwrap(),
@@ -968,7 +3067,7 @@ mod tests {
     /// that clients rely on: `current-user-principal` → `/principals/`.
     #[tokio::test]
     async fn test_propfind_root_xml_structure() {
-        let resp = root_propfind();
+        let resp = root_propfind("/principals/", &PropRequest::AllProp);
         let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
             .await
             .unwrap();
@@ -987,14 +3086,22 @@ mod tests {
     /// Verify the principals PROPFIND points to the addressbook-home-set.
     #[tokio::test]
     async fn test_propfind_principals_xml_structure() {
-        let resp = principals_propfind();
+        let state = AppState {
+            google_apis: HashMap::new(),
+            db_key: String::new(),
+            vault: SecureVault,
+            published_groups: None,
+            carddav_username: Some("setu".into()),
+            lockout: Arc::new(Lockout::new(lockout::Policy::default())),
+        };
+        let resp = principals_propfind("0", &PropRequest::AllProp, &state, &home(), &Scope::default());
         let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
             .await
             .unwrap();
         let xml = std::str::from_utf8(&body).unwrap();
 
         assert!(xml.contains("<C:addressbook-home-set>"));
-        assert!(xml.contains("<D:href>/addressbook/</D:href>"));
+        assert!(xml.contains("<D:href>/addressbooks/</D:href>"));
     }
 
     /// Verify that `build_report_xml_owned` produces valid multistatus XML
@@ -1014,7 +3121,7 @@ mod tests {
             ),
         ];
 
-        let resp = build_report_xml_owned(&contacts);
+        let resp = build_report_xml_owned(&Book::legacy(ACCOUNT), &contacts, &PropRequest::etag_and_data());
 
         // Mandatory headers.
         assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
@@ -1079,8 +3186,7 @@ mod tests {
   </C:filter>
 </C:addressbook-query>"#;
 
-        let raw_phone = extract_tel_filter(report_body)
-            .expect("TEL filter should be parsed");
+        let raw_phone = phone_query(report_body).expect("TEL filter should be parsed");
         assert_eq!(raw_phone, "+1 (555) 987-6543");
 
         // ── 2. Normalise ────────────────────────────────────────────
@@ -1089,7 +3195,7 @@ mod tests {
 
         // ── 3. Local DB search → miss ───────────────────────────────
         let conn = db::open_in_memory().unwrap();
-        let hits = db::search_by_phone(&conn, "5559876543").unwrap();
+        let hits = db::search_by_phone(&conn, ACCOUNT, "5559876543").unwrap();
         assert!(hits.is_empty(), "DB should be empty before caching");
 
         // ── 4. Simulate Google returning a Person, cache it ─────────
@@ -1110,7 +3216,7 @@ mod tests {
             ..Default::default()
         };
 
-        let (rn, etag, vcard) = cache_person_to_conn(&conn, &google_person)
+        let (rn, etag, vcard) = cache_person_to_conn(&conn, ACCOUNT, &google_person)
             .expect("cache_person_to_conn should succeed");
 
         assert_eq!(rn, "people/c98765");
@@ -1119,21 +3225,26 @@ mod tests {
         assert!(vcard.contains("TEL;"));
 
         // ── 5. Local DB search → hit ────────────────────────────────
-        let hits = db::search_by_phone(&conn, "5559876543").unwrap();
+        let hits = db::search_by_phone(&conn, ACCOUNT, "5559876543").unwrap();
         assert_eq!(hits.len(), 1, "contact should now be cached");
         assert_eq!(hits[0].0, "people/c98765");
         assert!(hits[0].2.contains("Eve Searcher"));
 
         // Also verify via get_contact.
-        let (db_etag, db_vcard) = db::get_contact(&conn, "people/c98765")
+        let (db_etag, db_vcard) = db::get_contact(&conn, ACCOUNT, "people/c98765")
             .unwrap()
             .expect("contact should exist in DB");
         assert_eq!(db_etag, "google_etag_xyz");
         assert!(db_vcard.contains("BEGIN:VCARD"));
         assert!(db_vcard.contains("END:VCARD"));
 
+        // A full sync whose listing started before the search keeps it.
+        let started = chrono::Utc::now() - chrono::Duration::minutes(1);
+        let reconciled = db::reconcile_contacts(&conn, ACCOUNT, &Default::default(), started).unwrap();
+        assert_eq!(reconciled.kept_on_demand, 1);
+
         // ── 6. Build the multistatus XML and verify ─────────────────
-        let resp = build_report_xml_owned(&hits);
+        let resp = build_report_xml_owned(&Book::legacy(ACCOUNT), &hits, &PropRequest::etag_and_data());
 
         assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
         assert_eq!(
@@ -1170,4 +3281,726 @@ mod tests {
     fn test_base64_decode_invalid() {
         assert!(base64_decode("!!!not-valid!!!").is_err());
     }
+
+    // ── Write-back precondition tests ───────────────────────────────
+
+    fn headers_with(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(name, value.parse().unwrap());
+        h
+    }
+
+    #[test]
+    fn test_put_never_duplicates_a_contact_outside_the_group() {
+        let conn = db::open_in_memory().unwrap();
+        for rn in ["people/c1", "people/c2"] {
+            db::upsert_contact(&conn, ACCOUNT, rn, "e1", rn, "vc", "").unwrap();
+        }
+        db::set_memberships(&conn, ACCOUNT, "people/c2", &["contactGroups/family".to_string()]).unwrap();
+        let family = Book {
+            group: Some("contactGroups/family".into()),
+            ..Book::legacy(ACCOUNT)
+        };
+
+        assert_eq!(put_target(&conn, &family, "people/c2").unwrap(), PutTarget::Update("e1".into()));
+        assert_eq!(put_target(&conn, &family, "people/c1").unwrap(), PutTarget::OutsideBook);
+        assert_eq!(put_target(&conn, &family, "people/c9").unwrap(), PutTarget::Create);
+        let all = Book::legacy(ACCOUNT);
+        assert_eq!(put_target(&conn, &all, "people/c1").unwrap(), PutTarget::Update("e1".into()));
+    }
+
+    #[test]
+    fn test_write_preconditions_if_match() {
+        let h = headers_with(header::IF_MATCH, "\"etag1\"");
+        assert!(write_preconditions_hold(&h, Some("etag1")));
+        assert!(!write_preconditions_hold(&h, Some("etag2")), "stale etag must 412");
+        assert!(!write_preconditions_hold(&h, None), "If-Match on missing resource must 412");
+
+        let star = headers_with(header::IF_MATCH, "*");
+        assert!(write_preconditions_hold(&star, Some("anything")));
+        assert!(!write_preconditions_hold(&star, None));
+
+        // If-Match compares strongly: a weak tag never matches.
+        let weak = headers_with(header::IF_MATCH, "W/\"etag1\"");
+        assert!(!write_preconditions_hold(&weak, Some("etag1")));
+
+        // The etag of the vCard 4.0 / jCard a client fetched is current too.
+        let v4 = headers_with(header::IF_MATCH, "\"etag1-v4\"");
+        assert!(write_preconditions_hold(&v4, Some("etag1")));
+        assert!(!write_preconditions_hold(&v4, Some("etag2")));
+    }
+
+    #[test]
+    fn test_write_preconditions_if_none_match() {
+        // Create-only semantics: `If-None-Match: *` fails if the contact exists.
+        let star = headers_with(header::IF_NONE_MATCH, "*");
+        assert!(write_preconditions_hold(&star, None));
+        assert!(!write_preconditions_hold(&star, Some("etag1")));
+
+        let list = headers_with(header::IF_NONE_MATCH, "\"a\", W/\"etag1\"");
+        assert!(!write_preconditions_hold(&list, Some("etag1")));
+        assert!(write_preconditions_hold(&list, Some("etag2")));
+    }
+
+    #[test]
+    fn test_write_preconditions_absent_headers() {
+        let h = HeaderMap::new();
+        assert!(write_preconditions_hold(&h, None));
+        assert!(write_preconditions_hold(&h, Some("etag1")));
+    }
+
+    // ── Conditional read tests ──────────────────────────────────────
+
+    #[test]
+    fn test_read_condition_etags() {
+        let none = HeaderMap::new();
+        assert_eq!(read_condition(&none, true, "e1", None), Condition::Proceed);
+
+        let if_none = headers_with(header::IF_NONE_MATCH, "\"e0\", \"e1\"");
+        assert_eq!(read_condition(&if_none, true, "e1", None), Condition::NotModified);
+        assert_eq!(read_condition(&if_none, true, "e2", None), Condition::Proceed);
+        assert_eq!(read_condition(&if_none, false, "e1", None), Condition::Failed, "PROPFIND never 304s");
+
+        let if_match = headers_with(header::IF_MATCH, "\"e1\"");
+        assert_eq!(read_condition(&if_match, true, "e1", None), Condition::Proceed);
+        assert_eq!(read_condition(&if_match, true, "e2", None), Condition::Failed);
+        let weak_match = headers_with(header::IF_MATCH, "W/\"e1\"");
+        assert_eq!(read_condition(&weak_match, true, "e1", None), Condition::Failed);
+        let weak_none = headers_with(header::IF_NONE_MATCH, "W/\"e1\"");
+        assert_eq!(read_condition(&weak_none, true, "e1", None), Condition::NotModified);
+    }
+
+    #[test]
+    fn test_read_condition_dates() {
+        let modified = chrono::DateTime::parse_from_rfc3339("2024-05-02T08:30:00Z").unwrap().to_utc();
+        assert_eq!(http_date(modified), "Thu, 02 May 2024 08:30:00 GMT");
+
+        let since = |value: &str| headers_with(header::IF_MODIFIED_SINCE, value);
+        let same = since("Thu, 02 May 2024 08:30:00 GMT");
+        assert_eq!(read_condition(&same, true, "e1", Some(modified)), Condition::NotModified);
+        assert_eq!(read_condition(&same, false, "e1", Some(modified)), Condition::Proceed);
+        let earlier = since("Thursday, 02-May-24 08:29:59 GMT");
+        assert_eq!(read_condition(&earlier, true, "e1", Some(modified)), Condition::Proceed);
+        let asctime = since("Thu May  2 09:00:00 2024");
+        assert_eq!(read_condition(&asctime, true, "e1", Some(modified)), Condition::NotModified);
+        assert_eq!(read_condition(&since("yesterday"), true, "e1", Some(modified)), Condition::Proceed);
+        assert_eq!(read_condition(&same, true, "e1", None), Condition::Proceed, "no Last-Modified");
+
+        // If-None-Match takes precedence over If-Modified-Since.
+        let mut both = same.clone();
+        both.insert(header::IF_NONE_MATCH, "\"e0\"".parse().unwrap());
+        assert_eq!(read_condition(&both, true, "e1", Some(modified)), Condition::Proceed);
+
+        let unmodified = headers_with(header::IF_UNMODIFIED_SINCE, "Thu, 02 May 2024 08:00:00 GMT");
+        assert_eq!(read_condition(&unmodified, true, "e1", Some(modified)), Condition::Failed);
+    }
+
+    #[test]
+    fn test_collection_etag_follows_ctag() {
+        let book = Book::all_contacts(&home());
+        let props = addressbook_props(&book, 7, 7);
+        let mut xml = String::new();
+        append_response(&mut xml, &book.path, &props, &PropRequest::names(vec![QName::new(DAV, "getetag")]));
+        assert!(xml.contains("<D:getetag>\"ctag-7\"</D:getetag>"), "{xml}");
+        assert_ne!(collection_etag(7), collection_etag(8));
+    }
+
+    // ── sync-collection ─────────────────────────────────────────────
+
+    #[test]
+    fn test_sync_token_roundtrip() {
+        assert_eq!(parse_sync_token(&sync_token(0)), Some(0));
+        assert_eq!(parse_sync_token(&sync_token(42)), Some(42));
+        assert_eq!(parse_sync_token("urn:setu:sync:-1"), None);
+        assert_eq!(parse_sync_token("http://example.com/sync/42"), None);
+        assert_eq!(parse_sync_token("urn:setu:sync:abc"), None);
+    }
+
+    #[test]
+    fn test_parse_sync_collection() {
+        let body = r#"<?xml version="1.0" encoding="utf-8" ?>
+<D:sync-collection xmlns:D="DAV:">
+  <D:sync-token>urn:setu:sync:7</D:sync-token>
+  <D:sync-level>1</D:sync-level>
+  <D:prop><D:getetag/></D:prop>
+</D:sync-collection>"#;
+        let parsed = report(body);
+        assert_eq!(
+            parsed.kind,
+            ReportKind::SyncCollection {
+                token: Some("urn:setu:sync:7".into())
+            }
+        );
+        assert_eq!(parsed.props, PropRequest::names(vec![QName::new(DAV, "getetag")]));
+
+        let initial = r#"<sync-collection xmlns="DAV:"><sync-token/><sync-level>1</sync-level></sync-collection>"#;
+        assert_eq!(report(initial).kind, ReportKind::SyncCollection { token: None });
+
+        let empty = r#"<D:sync-collection xmlns:D="DAV:"><D:sync-token></D:sync-token></D:sync-collection>"#;
+        assert_eq!(report(empty).kind, ReportKind::SyncCollection { token: None });
+    }
+
+    /// Changes recorded by `cache_person_to_conn` / `delete_contact` come
+    /// back from the sync report — updates with an etag, deletions as 404.
+    #[tokio::test]
+    async fn test_sync_collection_reports_changes_and_tombstones() {
+        let conn = db::open_in_memory().unwrap();
+        let alice = Person {
+            resource_name: Some("people/c111".into()),
+            etag: Some("etag_a".into()),
+            names: Some(vec![Name {
+                display_name: Some("Alice".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let bob = Person {
+            resource_name: Some("people/c222".into()),
+            etag: Some("etag_b".into()),
+            ..Default::default()
+        };
+        cache_person_to_conn(&conn, ACCOUNT, &alice).unwrap();
+        cache_person_to_conn(&conn, ACCOUNT, &bob).unwrap();
+        let token = db::book_change_seq(&conn, ACCOUNT, "").unwrap();
+
+        db::delete_contact(&conn, ACCOUNT, "people/c222").unwrap();
+        let changes = db::changes_since(&conn, ACCOUNT, "", token).unwrap();
+        let new_token = sync_token(db::book_change_seq(&conn, ACCOUNT, "").unwrap());
+
+        let resp = build_sync_collection_xml(
+            &Book::legacy(ACCOUNT),
+            &changes,
+            &new_token,
+            &PropRequest::names(vec![QName::new(DAV, "getetag")]),
+        );
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let xml = std::str::from_utf8(&body).unwrap();
+
+        assert_eq!(xml.matches("<D:response>").count(), 1);
+        assert!(!xml.contains("people_c111.vcf"));
+        assert!(xml.contains("<D:href>/addressbook/people_c222.vcf</D:href>"));
+        assert!(xml.contains("HTTP/1.1 404 Not Found"));
+        assert!(xml.contains(&format!("<D:sync-token>{new_token}</D:sync-token>")));
+
+        // Initial sync over the whole log, with address-data requested.
+        let all = db::changes_since(&conn, ACCOUNT, "", 0).unwrap();
+        let resp = build_sync_collection_xml(
+            &Book::legacy(ACCOUNT),
+            &all[..1],
+            &new_token,
+            &PropRequest::etag_and_data(),
+        );
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let xml = std::str::from_utf8(&body).unwrap();
+        assert!(xml.contains("\"etag_a\""));
+        assert!(xml.contains("<C:address-data>"));
+        assert!(xml.contains("FN:Alice"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_sync_token_response() {
+        let resp = invalid_sync_token();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        assert!(std::str::from_utf8(&body).unwrap().contains("<D:valid-sync-token/>"));
+    }
+
+    #[test]
+    fn test_group_books_are_published_by_selection() {
+        let group = |rn: &str, name: &str, kind: &str| db::StoredGroup {
+            resource_name: rn.into(),
+            name: name.into(),
+            group_type: kind.into(),
+        };
+        let family = group("contactGroups/1a2b", "Family", "USER_CONTACT_GROUP");
+        let starred = group("contactGroups/starred", "Starred", "SYSTEM_CONTACT_GROUP");
+        let my_contacts = group("contactGroups/myContacts", "My Contacts", "SYSTEM_CONTACT_GROUP");
+
+        // Default: user groups + starred.
+        assert!(group_published(&family, None));
+        assert!(group_published(&starred, None));
+        assert!(!group_published(&my_contacts, None));
+
+        // Explicit selection by name, id or resource name.
+        let selection = vec!["family".to_string(), "myContacts".to_string()];
+        assert!(group_published(&family, Some(&selection)));
+        assert!(group_published(&my_contacts, Some(&selection)));
+        assert!(!group_published(&starred, Some(&selection)));
+        assert!(group_published(&starred, Some(&["contactGroups/starred".to_string()])));
+
+        let conn = db::open_in_memory().unwrap();
+        db::replace_contact_groups(&conn, ACCOUNT, &[family, starred, my_contacts]).unwrap();
+        let paths: Vec<String> = published_books(&conn, &home(), None)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.path)
+            .collect();
+        assert_eq!(
+            paths,
+            ["/addressbooks/contacts/", "/addressbooks/1a2b/", "/addressbooks/starred/"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_book_only_serves_members() {
+        let conn = db::open_in_memory().unwrap();
+        let family = db::StoredGroup {
+            resource_name: "contactGroups/1a2b".into(),
+            name: "Family".into(),
+            group_type: "USER_CONTACT_GROUP".into(),
+        };
+        db::replace_contact_groups(&conn, ACCOUNT, std::slice::from_ref(&family)).unwrap();
+
+        let member = |rn: &str, groups: &[&str]| Person {
+            resource_name: Some(rn.into()),
+            etag: Some(format!("etag_{rn}")),
+            memberships: Some(groups.iter().map(|g| group_membership(g)).collect()),
+            ..Default::default()
+        };
+        cache_person_to_conn(&conn, ACCOUNT, &member("people/c1", &["contactGroups/1a2b"])).unwrap();
+        cache_person_to_conn(&conn, ACCOUNT, &member("people/c2", &[])).unwrap();
+
+        let book = Book::for_group(&home(), &family);
+        assert_eq!(book.path, "/addressbooks/1a2b/");
+        assert_eq!(book.contacts(&conn).unwrap().len(), 1);
+        assert!(book.get_contact(&conn, "people/c1").unwrap().is_some());
+        assert!(book.get_contact(&conn, "people/c2").unwrap().is_none());
+        assert!(Book::all_contacts(&home()).get_contact(&conn, "people/c2").unwrap().is_some());
+
+        // Each book keeps its own CTag.
+        let group_ctag = book.ctag(&conn).unwrap();
+        cache_person_to_conn(&conn, ACCOUNT, &member("people/c3", &[])).unwrap();
+        assert_eq!(book.ctag(&conn).unwrap(), group_ctag);
+
+        let changes = db::changes_since(&conn, ACCOUNT, book.change_key(), 0).unwrap();
+        let resp = build_sync_collection_xml(&book, &changes, "t", &PropRequest::etag_and_data());
+        let body = axum::body::to_bytes(resp.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let xml = std::str::from_utf8(&body).unwrap();
+        assert!(xml.contains("<D:href>/addressbooks/1a2b/people_c1.vcf</D:href>"));
+        assert!(!xml.contains("people_c2"));
+    }
+
+    #[tokio::test]
+    async fn test_account_homes_are_separate() {
+        let work = Home::for_account("work");
+        let mut xml = String::new();
+        append_response(&mut xml, &work.principal, &principal_props(&work), &PropRequest::AllProp);
+        assert!(xml.contains("<D:href>/principals/work/</D:href>"), "{xml}");
+        assert!(xml.contains("<D:href>/addressbooks/work/</D:href>"), "{xml}");
+
+        let conn = db::open_in_memory().unwrap();
+        let family = db::StoredGroup {
+            resource_name: "contactGroups/1a2b".into(),
+            name: "Family".into(),
+            group_type: "USER_CONTACT_GROUP".into(),
+        };
+        db::replace_contact_groups(&conn, "work", std::slice::from_ref(&family)).unwrap();
+        let paths: Vec<String> = published_books(&conn, &work, None)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.path)
+            .collect();
+        assert_eq!(paths, ["/addressbooks/work/contacts/", "/addressbooks/work/1a2b/"]);
+        // The default account has no groups of its own.
+        assert_eq!(published_books(&conn, &home(), None).unwrap().len(), 1);
+
+        let person = Person {
+            resource_name: Some("people/c1".into()),
+            etag: Some("e1".into()),
+            ..Default::default()
+        };
+        cache_person_to_conn(&conn, "work", &person).unwrap();
+        let work_book = Book::all_contacts(&work);
+        assert_eq!(work_book.contact_href("people/c1"), "/addressbooks/work/contacts/people_c1.vcf");
+        assert!(work_book.get_contact(&conn, "people/c1").unwrap().is_some());
+        assert!(Book::all_contacts(&home()).get_contact(&conn, "people/c1").unwrap().is_none());
+    }
+
+    /// App password scopes are enforced before any DB or Google access.
+    #[tokio::test]
+    async fn test_app_password_scope_enforced() {
+        let state = AppState {
+            google_apis: HashMap::new(),
+            db_key: String::new(),
+            vault: SecureVault,
+            published_groups: None,
+            carddav_username: Some("setu".into()),
+            lockout: Arc::new(Lockout::new(lockout::Policy::default())),
+        };
+        let book = Book::all_contacts(&Home::for_account("work"));
+        let request = |method: &str, scope: &Scope| {
+            let mut req = Request::builder()
+                .method(method)
+                .uri("/addressbooks/work/contacts/people_c1.vcf")
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut().insert(scope.clone());
+            req
+        };
+
+        let read_only = Scope { read_only: true, ..Scope::default() };
+        for method in ["PUT", "DELETE"] {
+            let resp = contact_request(state.clone(), book.clone(), "people_c1.vcf", request(method, &read_only)).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{method}");
+        }
+
+        let other_account = Scope { account: Some("home".into()), ..Scope::default() };
+        let resp = contact_request(state.clone(), book.clone(), "people_c1.vcf", request("GET", &other_account)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = home_request(state.clone(), Home::for_account("work"), request("PROPFIND", &other_account)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let family_only = Scope { book: Some("1a2b".into()), ..Scope::default() };
+        let resp = book_request(state.clone(), book.clone(), request("PROPFIND", &family_only)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(family_only.allows_account("work"));
+    }
+
+    /// A locked-out client is refused before its password is checked.
+    #[tokio::test]
+    async fn test_locked_out_client_gets_429() {
+        use base64::Engine;
+        use tower::ServiceExt;
+
+        let state = AppState {
+            google_apis: HashMap::new(),
+            db_key: String::new(),
+            vault: SecureVault,
+            published_groups: None,
+            carddav_username: Some("setu".into()),
+            lockout: Arc::new(Lockout::new(lockout::Policy {
+                max_failures: 1,
+                lockout: std::time::Duration::from_secs(60),
+                max_lockout: std::time::Duration::from_secs(60),
+            })),
+        };
+        state.lockout.record_failure(None, "phone");
+        let app = Router::new()
+            .route("/", any(root_handler))
+            .layer(middleware::from_fn_with_state(state.clone(), basic_auth_middleware))
+            .with_state(state);
+        let request = |auth: Option<&str>| {
+            let mut req = Request::builder().method("PROPFIND").uri("/");
+            if let Some(auth) = auth {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(auth);
+                req = req.header(header::AUTHORIZATION, format!("Basic {encoded}"));
+            }
+            req.body(Body::empty()).unwrap()
+        };
+
+        let resp = app.clone().oneshot(request(Some("phone:secret"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after), "{retry_after}");
+
+        // The challenge request without credentials is not affected.
+        let resp = app.oneshot(request(None)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// The plain-HTTP listener redirects to HTTPS before authentication.
+    #[tokio::test]
+    async fn test_http_listener_redirects() {
+        use tower::ServiceExt;
+
+        let state = AppState {
+            google_apis: HashMap::new(),
+            db_key: String::new(),
+            vault: SecureVault,
+            published_groups: None,
+            carddav_username: Some("setu".into()),
+            lockout: Arc::new(Lockout::new(lockout::Policy::default())),
+        };
+        let app = Router::new()
+            .route("/.well-known/carddav", any(well_known))
+            .route("/", any(root_handler))
+            .layer(middleware::from_fn_with_state(state.clone(), basic_auth_middleware))
+            .with_state(state);
+        let http_app = |mode, https_port| {
+            app.clone()
+                .layer(middleware::from_fn_with_state(HttpRedirect { mode, https_port }, http_redirect))
+        };
+        let request = |uri: &str, host: Option<&str>| {
+            let mut req = Request::builder().method("PROPFIND").uri(uri);
+            if let Some(host) = host {
+                req = req.header(header::HOST, host);
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        let location = |resp: &Response| resp.headers()[header::LOCATION].to_str().unwrap().to_string();
+
+        let redirect = http_app(HttpMode::Redirect, 8443);
+        let resp = redirect.clone().oneshot(request("/addressbooks/?x=1", Some("nas.lan:8080"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location(&resp), "https://nas.lan:8443/addressbooks/?x=1");
+        let resp = redirect.clone().oneshot(request("/", Some("[::1]:8080"))).await.unwrap();
+        assert_eq!(location(&resp), "https://[::1]:8443/");
+        let resp = redirect.oneshot(request("/", None)).await.unwrap();
+        assert_eq!(location(&resp), "https://localhost:8443/");
+
+        let well_known_only = http_app(HttpMode::WellKnown, 443);
+        let resp = well_known_only
+            .clone()
+            .oneshot(request("/.well-known/carddav", Some("192.168.1.5:8080")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location(&resp), "https://192.168.1.5/.well-known/carddav");
+        let resp = well_known_only.oneshot(request("/", Some("192.168.1.5"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Serving asks for credentials as on HTTPS.
+        let resp = app.oneshot(request("/", Some("nas.lan:8080"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// One request through `serve_tls`, with the server offering HTTP/2
+    /// (`server_h2`) and the client offering `client_alpn`.  Returns the
+    /// negotiated protocol and the response.
+    async fn tls_request(server_h2: bool, client_alpn: &[&[u8]]) -> (Option<Vec<u8>>, Response<hyper::body::Incoming>) {
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let key = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into());
+        let mut server_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        server_config.alpn_protocols = crate::tls::alpn_protocols(server_h2);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/.well-known/carddav", any(well_known));
+        let server = tokio::spawn(serve_tls(listener, app, Arc::new(server_config)));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let mut client_config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_config.alpn_protocols = client_alpn.iter().map(|p| p.to_vec()).collect();
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(client_config))
+            .connect("localhost".try_into().unwrap(), tcp)
+            .await
+            .unwrap();
+        let alpn = tls.get_ref().1.alpn_protocol().map(<[u8]>::to_vec);
+
+        let io = TokioIo::new(tls);
+        let uri = format!("https://localhost:{}/.well-known/carddav", addr.port());
+        let req = || hyper::Request::get(&uri).body(http_body_util::Empty::<hyper::body::Bytes>::new()).unwrap();
+        let resp = if alpn.as_deref() == Some(b"h2") {
+            let (mut sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), io).await.unwrap();
+            tokio::spawn(conn);
+            sender.send_request(req()).await.unwrap()
+        } else {
+            let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await.unwrap();
+            tokio::spawn(conn);
+            sender.send_request(req()).await.unwrap()
+        };
+        server.abort();
+        (alpn, resp)
+    }
+
+    #[tokio::test]
+    async fn test_tls_negotiates_http2() {
+        let (alpn, resp) = tls_request(true, &[b"h2", b"http/1.1"]).await;
+        assert_eq!(alpn.as_deref(), Some(&b"h2"[..]));
+        assert_eq!(resp.version(), http::Version::HTTP_2);
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[header::LOCATION], "/");
+    }
+
+    #[tokio::test]
+    async fn test_tls_serves_http1() {
+        // A client that only speaks HTTP/1.1.
+        let (alpn, resp) = tls_request(true, &[b"http/1.1"]).await;
+        assert_eq!(alpn.as_deref(), Some(&b"http/1.1"[..]));
+        assert_eq!(resp.version(), http::Version::HTTP_11);
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+
+        // HTTP/2 turned off (`tls_http2: false`).
+        let (alpn, resp) = tls_request(false, &[b"h2", b"http/1.1"]).await;
+        assert_eq!(alpn.as_deref(), Some(&b"http/1.1"[..]));
+        assert_eq!(resp.version(), http::Version::HTTP_11);
+
+        // No ALPN at all.
+        let (alpn, resp) = tls_request(true, &[]).await;
+        assert_eq!(alpn, None);
+        assert_eq!(resp.version(), http::Version::HTTP_11);
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("s3cret", "s3cret"));
+        assert!(!constant_time_eq("s3cret", "s3creT"));
+        assert!(!constant_time_eq("s3cret", "s3cre"));
+        assert!(!constant_time_eq("", "s3cret"));
+    }
+
+    #[test]
+    fn test_basic_auth_user() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(basic_auth_user(&headers), None);
+        // "work:secret"
+        headers.insert(header::AUTHORIZATION, "Basic d29yazpzZWNyZXQ=".parse().unwrap());
+        assert_eq!(basic_auth_user(&headers).as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn test_accept_header_selects_format() {
+        let accept = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, value.parse().unwrap());
+            accepted_format(&headers)
+        };
+        use crate::vcard::{Format, Version};
+        let (v3, v4) = (Format::VCard(Version::V3), Format::VCard(Version::V4));
+        assert_eq!(accepted_format(&HeaderMap::new()), v3);
+        assert_eq!(accept("*/*"), v3);
+        assert_eq!(accept("text/vcard; version=4.0"), v4);
+        assert_eq!(accept("text/vcard;version=3.0, text/vcard;version=4.0"), v3);
+        assert_eq!(accept("text/vcard;version=3.0;q=0.5, text/vcard;version=\"4.0\""), v4);
+        assert_eq!(accept("text/vcard;version=4.0;q=0"), v3);
+        assert_eq!(accept("application/vcard+json"), Format::JCard);
+        assert_eq!(accept("application/vcard+xml, text/vcard;q=0.1"), Format::XCard);
+    }
+
+    #[test]
+    fn test_address_data_format_attributes() {
+        let body = |attrs: &str| {
+            format!(
+                r#"<C:addressbook-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <D:prop><C:address-data {attrs}/></D:prop>
+</C:addressbook-multiget>"#
+            )
+        };
+        use crate::vcard::{Format, Version};
+        assert_eq!(report(&body("")).props.format(), Format::VCard(Version::V3));
+        assert_eq!(
+            report(&body(r#"content-type="text/vcard" version="4.0""#)).props.format(),
+            Format::VCard(Version::V4)
+        );
+        assert_eq!(
+            report(&body(r#"content-type="application/vcard+json""#)).props.format(),
+            Format::JCard
+        );
+        assert_eq!(
+            report(&body(r#"content-type="application/vcard+xml" version="4.0""#)).props.format(),
+            Format::XCard
+        );
+
+        let err = parse_report(&body(r#"content-type="text/x-vcard""#)).unwrap_err();
+        assert!(err.downcast_ref::<UnsupportedAddressData>().is_some());
+        assert_eq!(body_error(err).status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_address_data_in_jcard_and_xcard() {
+        use crate::vcard::Format;
+        let vcard = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nTEL:+15551234567\r\nEND:VCARD\r\n";
+        let request = |names: &[QName], format| PropRequest::Prop {
+            names: names.to_vec(),
+            address_data: Some(vec![crate::vcard::PropSelector {
+                name: "FN".into(),
+                novalue: false,
+            }]),
+            format,
+        };
+        let render = |format| {
+            let request = request(&[QName::new(CARDDAV, "address-data")], format);
+            let mut xml = String::new();
+            append_response(
+                &mut xml,
+                "/addressbook/x.vcf",
+                &contact_props("e1", vcard, &request),
+                &request,
+            );
+            xml
+        };
+
+        let jcard = render(Format::JCard);
+        assert!(
+            jcard.contains(r#"[&quot;fn&quot;,{},&quot;text&quot;,&quot;Jane&quot;]"#),
+            "{jcard}"
+        );
+        assert!(
+            !jcard.contains("tel"),
+            "address-data subset applies before rendering"
+        );
+
+        let xcard = render(Format::XCard);
+        assert!(
+            xcard.contains("&lt;fn&gt;&lt;text&gt;Jane&lt;/text&gt;&lt;/fn&gt;"),
+            "{xcard}"
+        );
+
+        // ETag, content type and length describe the payload actually
+        // returned, as GET would serve it.
+        let trimmed = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nEND:VCARD\r\n";
+        let props = |format| {
+            let mut xml = String::new();
+            let names = [
+                QName::new(DAV, "getetag"),
+                QName::new(DAV, "getcontenttype"),
+                QName::new(DAV, "getcontentlength"),
+            ];
+            let request = request(&names, format);
+            append_response(&mut xml, "/addressbook/x.vcf", &contact_props("e1", vcard, &request), &request);
+            xml
+        };
+        let jcard = props(Format::JCard);
+        assert!(jcard.contains("<D:getetag>\"e1-json\"</D:getetag>"), "{jcard}");
+        assert!(jcard.contains("<D:getcontenttype>application/vcard+json</D:getcontenttype>"), "{jcard}");
+        let length = crate::jcard::to_jcard(trimmed).len();
+        assert!(jcard.contains(&format!("<D:getcontentlength>{length}</D:getcontentlength>")), "{jcard}");
+        let vcard4 = props(Format::VCard(crate::vcard::Version::V4));
+        assert!(vcard4.contains("<D:getetag>\"e1-v4\"</D:getetag>"), "{vcard4}");
+        assert!(vcard4.contains("version=4.0</D:getcontenttype>"), "{vcard4}");
+        assert!(vcard4.contains(&format!("<D:getcontentlength>{}</D:getcontentlength>", trimmed.len())));
+
+        // A 4.0 request answered from the 3.0 fallback says so.
+        let v3 = "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Jane\r\nEND:VCARD\r\n";
+        let names = [QName::new(DAV, "getetag"), QName::new(DAV, "getcontenttype")];
+        let request = request(&names, Format::VCard(crate::vcard::Version::V4));
+        let mut xml = String::new();
+        append_response(&mut xml, "/addressbook/x.vcf", &contact_props("e1", v3, &request), &request);
+        assert!(xml.contains("<D:getetag>\"e1\"</D:getetag>"), "{xml}");
+        assert!(xml.contains("<D:getcontenttype>text/vcard;charset=utf-8</D:getcontenttype>"), "{xml}");
+    }
+
+    #[test]
+    fn test_vcard4_is_cached_beside_vcard3() {
+        let conn = db::open_in_memory().unwrap();
+        let person = Person {
+            resource_name: Some("people/c1".into()),
+            etag: Some("e1".into()),
+            ..Default::default()
+        };
+        let (rn, _, vcard3) = cache_person_to_conn(&conn, ACCOUNT, &person).unwrap();
+
+        let (served, vcard) = vcard_in(&conn, ACCOUNT, &rn, vcard3.clone(), crate::vcard::Version::V4).unwrap();
+        assert_eq!(served, crate::vcard::Version::V4);
+        assert!(vcard.contains("VERSION:4.0"));
+
+        let (served, vcard) = vcard_in(&conn, ACCOUNT, &rn, vcard3.clone(), crate::vcard::Version::V3).unwrap();
+        assert_eq!(served, crate::vcard::Version::V3);
+        assert_eq!(vcard, vcard3);
+
+        // Rows cached before vCard 4.0 existed fall back to 3.0.
+        db::set_vcard4(&conn, ACCOUNT, &rn, "").unwrap();
+        let (served, _) = vcard_in(&conn, ACCOUNT, &rn, vcard3, crate::vcard::Version::V4).unwrap();
+        assert_eq!(served, crate::vcard::Version::V3);
+    }
 }
@@ -103,10 +103,45 @@ fn apply_theme(ctx: &egui::Context) {
 enum LoginState {
     NotLoggedIn,
     InProgress,
-    LoggedIn(String),
+    LoggedIn,
     Error(String),
 }
 
+/// A Google account as listed in the accounts card.
+#[derive(Clone)]
+struct AccountRow {
+    id: String,
+    /// Google email, or a placeholder when it is not known.
+    email: String,
+    signed_in: bool,
+}
+
+/// Registered accounts with their sign-in status.
+fn load_accounts(vault: &SecureVault, db_key: &str) -> Vec<AccountRow> {
+    let accounts = setu_lib::db::open(Some(db_key))
+        .and_then(|conn| setu_lib::db::accounts(&conn))
+        .unwrap_or_default();
+    accounts
+        .into_iter()
+        .map(|a| AccountRow {
+            signed_in: setu_lib::auth::ensure_authenticated(vault, &a.id),
+            email: if a.google_email.is_empty() {
+                "Authenticated".into()
+            } else {
+                a.google_email
+            },
+            id: a.id,
+        })
+        .collect()
+}
+
+/// App passwords for the list in the app passwords card.
+fn load_app_passwords(db_key: &str) -> Vec<setu_lib::db::AppPassword> {
+    setu_lib::db::open(Some(db_key))
+        .and_then(|conn| setu_lib::db::app_passwords(&conn))
+        .unwrap_or_default()
+}
+
 // ── App state ────────────────────────────────────────────────────────
 
 struct SettingsApp {
@@ -114,13 +149,32 @@ struct SettingsApp {
     client_secret: String,
     sync_interval: String,
     server_port: String,
+    carddav_username: String,
     carddav_password: String,
     use_tls: bool,
+    write_back: bool,
+    /// The loaded config, so saving keeps the values not editable here
+    /// (published groups, bind addresses, lockout thresholds …).
+    config: Config,
     status_msg: String,
     status_is_error: bool,
     login_state: LoginState,
+    /// Account the running (or last failed) sign-in is for.
+    login_account: String,
     /// Receives the result of the background OAuth flow.
     login_rx: Option<std::sync::mpsc::Receiver<Result<String, String>>>,
+    accounts: Vec<AccountRow>,
+    /// Name typed for the next account to add.
+    new_account: String,
+    app_passwords: Vec<setu_lib::db::AppPassword>,
+    /// Form for the next app password; account and book may stay empty.
+    new_app_username: String,
+    new_app_label: String,
+    new_app_account: String,
+    new_app_book: String,
+    new_app_read_only: bool,
+    /// `(username, password)` of the password just created, shown once.
+    created_app_password: Option<(String, String)>,
     vault: SecureVault,
     db_key: String,
     show_client_secret: bool,
@@ -129,12 +183,17 @@ struct SettingsApp {
 
 impl SettingsApp {
     fn new(config: Config, vault: SecureVault, db_key: String) -> Self {
-        let login_state = match setu_lib::auth::get_logged_in_email(&db_key) {
-            Ok(Some(email)) => LoginState::LoggedIn(email),
-            _ if setu_lib::auth::ensure_authenticated(&vault) => {
-                LoginState::LoggedIn("Authenticated".into())
-            }
-            _ => LoginState::NotLoggedIn,
+        let accounts = load_accounts(&vault, &db_key);
+        let login_state = if accounts.iter().any(|a| a.signed_in) {
+            LoginState::LoggedIn
+        } else {
+            LoginState::NotLoggedIn
+        };
+        // The first account keeps the single-account token location.
+        let new_account = if accounts.is_empty() {
+            setu_lib::db::DEFAULT_ACCOUNT.to_string()
+        } else {
+            String::new()
         };
 
         let client_secret = vault
@@ -148,16 +207,29 @@ impl SettingsApp {
             .unwrap_or_default();
 
         Self {
-            client_id: config.google_client_id,
+            client_id: config.google_client_id.clone(),
             client_secret,
             sync_interval: config.sync_interval_secs.to_string(),
             server_port: config.server_port.to_string(),
+            carddav_username: config.carddav_username.clone().unwrap_or_default(),
             carddav_password,
             use_tls: config.use_tls,
+            write_back: config.write_back,
+            config,
             status_msg: String::new(),
             status_is_error: false,
             login_state,
+            login_account: String::new(),
             login_rx: None,
+            accounts,
+            new_account,
+            app_passwords: load_app_passwords(&db_key),
+            new_app_username: String::new(),
+            new_app_label: String::new(),
+            new_app_account: String::new(),
+            new_app_book: String::new(),
+            new_app_read_only: false,
+            created_app_password: None,
             vault,
             db_key,
             show_client_secret: false,
@@ -183,6 +255,13 @@ impl SettingsApp {
             }
         };
 
+        let username = self.carddav_username.trim().to_string();
+        if username.contains(':') {
+            self.status_msg = "CardDAV username must not contain ':'".into();
+            self.status_is_error = true;
+            return false;
+        }
+
         let secret = self.client_secret.trim().to_string();
         if !secret.is_empty() {
             if let Err(e) = self.vault.store_google_client_secret(&secret) {
@@ -201,9 +280,21 @@ impl SettingsApp {
             }
         }
 
-        // If TLS was just enabled, generate certs and install the CA.
-        if self.use_tls {
-            if let Err(e) = setu_lib::tls::ensure_certs() {
+        let config = Config {
+            google_client_id: self.client_id.trim().to_string(),
+            google_client_secret: String::new(),
+            sync_interval_secs: interval,
+            server_port: port,
+            use_tls: self.use_tls,
+            write_back: self.write_back,
+            carddav_username: Some(username).filter(|u| !u.is_empty()),
+            ..self.config.clone()
+        };
+
+        // If TLS was just enabled, generate certs and install the CA (not
+        // needed with the user's own certificate).
+        if self.use_tls && config.tls_cert_path.is_none() {
+            if let Err(e) = setu_lib::tls::ensure_certs(&self.vault, &config.tls_names()) {
                 self.status_msg = format!("Error generating TLS certs: {e}");
                 self.status_is_error = true;
                 return false;
@@ -215,16 +306,9 @@ impl SettingsApp {
             }
         }
 
-        let config = Config {
-            google_client_id: self.client_id.trim().to_string(),
-            google_client_secret: String::new(),
-            sync_interval_secs: interval,
-            server_port: port,
-            use_tls: self.use_tls,
-        };
-
         match config.save() {
             Ok(()) => {
+                self.config = config;
                 let scheme = if self.use_tls { "https" } else { "http" };
                 self.status_msg = format!(
                     "Settings saved. Server URL: {scheme}://localhost:{port}"
@@ -243,16 +327,24 @@ impl SettingsApp {
         }
     }
 
-    fn start_login(&mut self, ctx: &egui::Context) {
+    fn start_login(&mut self, ctx: &egui::Context, account: String) {
+        if !setu_lib::db::valid_account_id(&account) {
+            self.status_msg =
+                "Account names use 1–32 lowercase letters, digits, - or _ (not \"contacts\")".into();
+            self.status_is_error = true;
+            return;
+        }
         if !self.save() {
             return;
         }
 
         self.login_state = LoginState::InProgress;
+        self.login_account = account.clone();
         self.status_msg.clear();
 
         let client_id = self.client_id.trim().to_string();
         let client_secret = self.client_secret.trim().to_string();
+        let write_back = self.write_back;
         let vault = self.vault;
         let db_key = self.db_key.clone();
         let (tx, rx) = std::sync::mpsc::channel();
@@ -261,8 +353,14 @@ impl SettingsApp {
 
         std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
-            let result =
-                rt.block_on(setu_lib::auth::login(&client_id, &client_secret, &vault, &db_key));
+            let result = rt.block_on(setu_lib::auth::login(
+                &client_id,
+                &client_secret,
+                write_back,
+                &vault,
+                &db_key,
+                &account,
+            ));
             let _ = match result {
                 Ok(r) => tx.send(Ok(r.email)),
                 Err(e) => tx.send(Err(format!("{e:#}"))),
@@ -271,6 +369,75 @@ impl SettingsApp {
         });
     }
 
+    /// Sign an account out and drop its cached contacts.
+    fn remove_account(&mut self, account: &str) {
+        match setu_lib::auth::remove_account(&self.vault, &self.db_key, account) {
+            Ok(()) => {
+                self.status_msg = format!("Removed account \"{account}\". Restart Setu to apply.");
+                self.status_is_error = false;
+            }
+            Err(e) => {
+                self.status_msg = format!("Error removing account: {e:#}");
+                self.status_is_error = true;
+            }
+        }
+        self.accounts = load_accounts(&self.vault, &self.db_key);
+        if !self.accounts.iter().any(|a| a.signed_in) {
+            self.login_state = LoginState::NotLoggedIn;
+        }
+    }
+
+    /// Create an app password from the form and keep it for display.
+    fn create_app_password(&mut self) {
+        let optional = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+        let scope = setu_lib::app_password::Scope {
+            account: optional(&self.new_app_account),
+            book: optional(&self.new_app_book),
+            read_only: self.new_app_read_only,
+        };
+        let username = self.new_app_username.trim().to_string();
+        let created = setu_lib::db::open(Some(&self.db_key)).and_then(|conn| {
+            setu_lib::app_password::create(&conn, &username, &self.new_app_label, &scope)
+        });
+        match created {
+            Ok(password) => {
+                self.status_msg = format!("App password for \"{username}\" created.");
+                self.status_is_error = false;
+                self.created_app_password = Some((username, password));
+                self.new_app_username.clear();
+                self.new_app_label.clear();
+                self.new_app_account.clear();
+                self.new_app_book.clear();
+                self.new_app_read_only = false;
+            }
+            Err(e) => {
+                self.status_msg = format!("Error creating app password: {e:#}");
+                self.status_is_error = true;
+            }
+        }
+        self.app_passwords = load_app_passwords(&self.db_key);
+    }
+
+    /// Revoke an app password; the device using it is locked out at once.
+    fn revoke_app_password(&mut self, username: &str) {
+        let revoked = setu_lib::db::open(Some(&self.db_key))
+            .and_then(|conn| setu_lib::db::revoke_app_password(&conn, username));
+        match revoked {
+            Ok(_) => {
+                self.status_msg = format!("App password for \"{username}\" revoked.");
+                self.status_is_error = false;
+            }
+            Err(e) => {
+                self.status_msg = format!("Error revoking app password: {e:#}");
+                self.status_is_error = true;
+            }
+        }
+        if self.created_app_password.as_ref().is_some_and(|(u, _)| u == username) {
+            self.created_app_password = None;
+        }
+        self.app_passwords = load_app_passwords(&self.db_key);
+    }
+
     fn has_credentials(&self) -> bool {
         !self.client_id.trim().is_empty() && !self.client_secret.trim().is_empty()
     }
@@ -480,10 +647,15 @@ impl eframe::App for SettingsApp {
             if let Ok(result) = rx.try_recv() {
                 match result {
                     Ok(email) => {
-                        self.login_state = LoginState::LoggedIn(email);
-                        self.status_msg =
-                            "Login successful! Close this window to start Setu.".into();
+                        self.accounts = load_accounts(&self.vault, &self.db_key);
+                        self.status_msg = if self.accounts.len() > 1 {
+                            format!("Signed in as {email}. Restart Setu to sync this account.")
+                        } else {
+                            "Login successful! Close this window to start Setu.".into()
+                        };
+                        self.login_state = LoginState::LoggedIn;
                         self.status_is_error = false;
+                        self.new_account.clear();
                     }
                     Err(msg) => {
                         self.login_state = LoginState::Error(msg.clone());
@@ -495,7 +667,7 @@ impl eframe::App for SettingsApp {
             }
         }
 
-        let is_logged_in = matches!(self.login_state, LoginState::LoggedIn(_));
+        let is_logged_in = self.accounts.iter().any(|a| a.signed_in);
 
         egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(SURFACE).inner_margin(egui::Margin::same(24.0)))
@@ -598,7 +770,7 @@ impl eframe::App for SettingsApp {
 
                     // ── Credentials & Login ─────────────────────────
                     let cred_header = if is_logged_in {
-                        "Google Account"
+                        "Google Accounts"
                     } else {
                         "Step 2 — Credentials & Sign In"
                     };
@@ -611,33 +783,71 @@ impl eframe::App for SettingsApp {
                         password_field(ui, "Client Secret", &mut self.client_secret, &mut self.show_client_secret);
                         ui.add_space(12.0);
 
-                        ui.horizontal(|ui| {
-                            let can_login = self.has_credentials()
-                                && !matches!(self.login_state, LoginState::InProgress);
-                            if primary_button(ui, "Login with Google", can_login).clicked() && can_login {
-                                self.start_login(ctx);
-                            }
+                        let can_login = self.has_credentials()
+                            && !matches!(self.login_state, LoginState::InProgress);
 
-                            ui.add_space(8.0);
-
-                            match &self.login_state {
-                                LoginState::NotLoggedIn => {
+                        // One row per account; actions run after the loop.
+                        let mut sign_in: Option<String> = None;
+                        let mut remove: Option<String> = None;
+                        for account in &self.accounts {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(&account.id).strong().size(13.0));
+                                ui.add_space(8.0);
+                                if account.signed_in {
+                                    ui.label(
+                                        egui::RichText::new(&account.email)
+                                            .color(GREEN_SUCCESS).size(13.0),
+                                    );
+                                } else {
                                     ui.label(
                                         egui::RichText::new("Not signed in")
                                             .color(TEXT_SECONDARY).size(13.0),
                                     );
                                 }
+                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    if ui.button("Remove").clicked() {
+                                        remove = Some(account.id.clone());
+                                    }
+                                    if ui.add_enabled(can_login, egui::Button::new("Sign in again")).clicked() {
+                                        sign_in = Some(account.id.clone());
+                                    }
+                                });
+                            });
+                            ui.add_space(4.0);
+                        }
+                        if let Some(account) = remove {
+                            self.remove_account(&account);
+                        }
+                        if let Some(account) = sign_in {
+                            self.start_login(ctx, account);
+                        }
+
+                        ui.add_space(8.0);
+                        small_field(ui, "Account name", &mut self.new_account, 140.0);
+                        ui.add_space(4.0);
+                        ui.horizontal(|ui| {
+                            let label = if self.accounts.is_empty() {
+                                "Login with Google"
+                            } else {
+                                "Add Google account"
+                            };
+                            let can_add = can_login && !self.new_account.trim().is_empty();
+                            if primary_button(ui, label, can_add).clicked() && can_add {
+                                let account = self.new_account.trim().to_lowercase();
+                                self.start_login(ctx, account);
+                            }
+
+                            ui.add_space(8.0);
+
+                            match &self.login_state {
                                 LoginState::InProgress => {
                                     ui.spinner();
                                     ui.label(
-                                        egui::RichText::new("Complete sign-in in your browser...")
-                                            .color(TEXT_SECONDARY).size(13.0),
-                                    );
-                                }
-                                LoginState::LoggedIn(email) => {
-                                    ui.label(
-                                        egui::RichText::new(format!("Signed in as {email}"))
-                                            .color(GREEN_SUCCESS).size(13.0),
+                                        egui::RichText::new(format!(
+                                            "Complete sign-in for \"{}\" in your browser...",
+                                            self.login_account
+                                        ))
+                                        .color(TEXT_SECONDARY).size(13.0),
                                     );
                                 }
                                 LoginState::Error(msg) => {
@@ -646,8 +856,18 @@ impl eframe::App for SettingsApp {
                                             .color(RED_ERROR).size(13.0),
                                     );
                                 }
+                                LoginState::NotLoggedIn | LoginState::LoggedIn => {}
                             }
                         });
+                        ui.add_space(2.0);
+                        ui.label(
+                            egui::RichText::new(
+                                "Each account is served at /principals/<account name>/ with its own address books.",
+                            )
+                            .size(12.0)
+                            .color(TEXT_SECONDARY)
+                            .italics(),
+                        );
                     });
 
                     ui.add_space(16.0);
@@ -661,10 +881,12 @@ impl eframe::App for SettingsApp {
                         ui.add_space(4.0);
                         small_field(ui, "CardDAV server port", &mut self.server_port, 80.0);
                         ui.add_space(8.0);
+                        small_field(ui, "CardDAV username", &mut self.carddav_username, 140.0);
+                        ui.add_space(4.0);
                         password_field(ui, "CardDAV password", &mut self.carddav_password, &mut self.show_carddav_password);
                         ui.add_space(2.0);
                         ui.label(
-                            egui::RichText::new("Use any username with this password to connect your CardDAV client.")
+                            egui::RichText::new("Connect your CardDAV client with this username and password (an empty username accepts any). For per-device access, or to open another account, create app passwords below.")
                                 .size(12.0)
                                 .color(TEXT_SECONDARY)
                                 .italics(),
@@ -684,6 +906,16 @@ impl eframe::App for SettingsApp {
                                 .italics(),
                             );
                         }
+                        ui.add_space(8.0);
+                        ui.checkbox(&mut self.write_back, "Allow CardDAV clients to edit Google Contacts");
+                        if self.write_back {
+                            ui.label(
+                                egui::RichText::new("Requires signing in again to grant write access.")
+                                .size(12.0)
+                                .color(TEXT_SECONDARY)
+                                .italics(),
+                            );
+                        }
                         ui.add_space(4.0);
                         let scheme = if self.use_tls { "https" } else { "http" };
                         let port_str = self.server_port.trim();
@@ -705,6 +937,86 @@ impl eframe::App for SettingsApp {
 
                     ui.add_space(16.0);
 
+                    // ── App Passwords ───────────────────────────────
+                    section_heading(ui, "App Passwords");
+                    ui.add_space(6.0);
+
+                    card_frame(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new("Give each device its own username and password, so a lost phone can be revoked on its own.")
+                                .size(12.0)
+                                .color(TEXT_SECONDARY)
+                                .italics(),
+                        );
+                        ui.add_space(8.0);
+
+                        let mut revoke: Option<String> = None;
+                        for entry in &self.app_passwords {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(&entry.username).strong().size(13.0));
+                                if !entry.label.is_empty() {
+                                    ui.label(egui::RichText::new(&entry.label).size(13.0));
+                                }
+                                ui.label(
+                                    egui::RichText::new(format!(
+                                        "{} · last used {}",
+                                        setu_lib::app_password::Scope::of(entry).describe(),
+                                        entry.last_used_at.as_deref().unwrap_or("never"),
+                                    ))
+                                    .size(12.0)
+                                    .color(TEXT_SECONDARY),
+                                );
+                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    if ui.button("Revoke").clicked() {
+                                        revoke = Some(entry.username.clone());
+                                    }
+                                });
+                            });
+                            ui.add_space(4.0);
+                        }
+                        if let Some(username) = revoke {
+                            self.revoke_app_password(&username);
+                        }
+
+                        ui.add_space(8.0);
+                        small_field(ui, "Username", &mut self.new_app_username, 140.0);
+                        ui.add_space(4.0);
+                        small_field(ui, "Label", &mut self.new_app_label, 200.0);
+                        ui.add_space(4.0);
+                        small_field(ui, "Only account (optional)", &mut self.new_app_account, 120.0);
+                        ui.add_space(4.0);
+                        small_field(ui, "Only address book (optional)", &mut self.new_app_book, 120.0);
+                        ui.add_space(4.0);
+                        ui.checkbox(&mut self.new_app_read_only, "Read-only (no edits or deletes)");
+                        ui.add_space(6.0);
+                        let can_create = !self.new_app_username.trim().is_empty();
+                        if primary_button(ui, "Create app password", can_create).clicked() && can_create {
+                            self.create_app_password();
+                        }
+
+                        if let Some((username, password)) = &self.created_app_password {
+                            ui.add_space(8.0);
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    egui::RichText::new(format!("{username} / {password}"))
+                                        .monospace()
+                                        .color(BLUE_PRIMARY),
+                                );
+                                if ui.button("Copy").clicked() {
+                                    write_clipboard(password);
+                                }
+                            });
+                            ui.label(
+                                egui::RichText::new("Copy this password now — it will not be shown again.")
+                                    .size(12.0)
+                                    .color(TEXT_SECONDARY)
+                                    .italics(),
+                            );
+                        }
+                    });
+
+                    ui.add_space(16.0);
+
                     // ── Save button + status ────────────────────────
                     ui.horizontal(|ui| {
                         if primary_button(ui, "Save Settings", true).clicked() {
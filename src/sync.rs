@@ -3,230 +3,379 @@
 //! Flow:
 //!   1. First run  → full sync (fetch all contacts, store syncToken).
 //!   2. Later runs → incremental sync (fetch only deltas via syncToken).
-//!   3. If the token expires (410 Gone) → fall back to a full sync.
+//!   3. If the token expires ([`GoogleError::SyncTokenExpired`]) → fall
+//!      back to a full sync.
+//!      A full sync reconciles: contacts Google no longer lists are purged
+//!      (see [`db::reconcile_contacts`]).
+//!   4. Contact groups are re-listed on every run (there are only a few).
+//!
+//! An account whose Google sign-in was revoked is not synced again until
+//! "Sync Now" (after signing in again) or a restart; one over its API
+//! quota waits as long as Google asks.  Network trouble is retried at the
+//! next interval.
+//!
+//! Each signed-in Google account is synced in turn, into its own
+//! partition of the database with its own sync token.
+//!
+//! A sync run is written page by page as the pages arrive, each in one
+//! SQLite transaction with a checkpoint (see [`apply`]): after an error or
+//! crash halfway, the next run resumes at the page that was missing.  The
+//! last page lands together with the new sync token and, for a full sync,
+//! the reconciliation — the old token stays until the whole run is stored.
+
+use std::collections::HashMap;
+use std::time::Instant;
 
 use anyhow::{Context, Result};
 use google_people1::api::Person;
-use google_people1::common::FieldMask;
 use tokio::sync::mpsc;
 
-use setu_lib::{auth, db};
-use setu_lib::google_api::{GoogleApi, Hub, PERSON_FIELDS};
-use setu_lib::vault::SecureVault;
-use crate::vcard;
+use crate::{auth, db, vcard};
+use crate::google_api::{self, GoogleApi, GoogleError};
+use crate::vault::SecureVault;
 
 // ── Public entry point ───────────────────────────────────────────────────
 
 /// Run the sync loop forever.
 ///
-/// * `google_api` – shared Google API client (already authenticated).
+/// * `google_apis` – one Google API client per signed-in account.
 /// * `interval_secs` – seconds between automatic syncs.
 /// * `trigger_rx` – receives `()` when the user clicks "Sync Now".
 /// * `vault` – OS keyring handle for auth checks.
 /// * `db_key` – hex-encoded SQLCipher encryption key.
 pub async fn run_sync_loop(
-    google_api: GoogleApi,
+    google_apis: Vec<GoogleApi>,
     interval_secs: u64,
     mut trigger_rx: mpsc::Receiver<()>,
     vault: SecureVault,
     db_key: String,
 ) -> Result<()> {
-    let hub = google_api.hub();
     let interval = tokio::time::Duration::from_secs(interval_secs);
-    tracing::info!(interval_secs, "sync loop started");
+    tracing::info!(interval_secs, accounts = google_apis.len(), "sync loop started");
+    let mut holds: HashMap<String, Hold> = HashMap::new();
 
     loop {
-        if let Err(e) = run_one_sync(hub, &vault, &db_key).await {
-            tracing::error!("sync failed: {e:#}");
+        // One account failing (revoked token, quota) must not hold up the rest.
+        for api in &google_apis {
+            let account = api.account();
+            match holds.get(account) {
+                Some(Hold::SignIn) => continue,
+                Some(Hold::Until(until)) if Instant::now() < *until => continue,
+                _ => {}
+            }
+            match run_one_sync(api, &vault, &db_key).await {
+                Ok(()) => {
+                    holds.remove(account);
+                }
+                Err(e) => {
+                    log_failure(account, &e);
+                    match hold_after(&e) {
+                        Some(hold) => holds.insert(account.to_string(), hold),
+                        None => holds.remove(account),
+                    };
+                }
+            }
         }
 
         tokio::select! {
             _ = tokio::time::sleep(interval) => {},
             _ = trigger_rx.recv() => {
                 tracing::info!("immediate sync triggered");
+                // The user may have signed in again in the meantime.
+                holds.retain(|_, hold| *hold != Hold::SignIn);
             },
         }
     }
 }
 
+/// Why automatic syncs of an account are paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hold {
+    /// Until the user signs in again and clicks "Sync Now".
+    SignIn,
+    /// Until the quota is expected back.
+    Until(Instant),
+}
+
+/// Whether a failed sync pauses the account's automatic syncs.  Other
+/// errors are simply retried at the next interval.
+fn hold_after(err: &anyhow::Error) -> Option<Hold> {
+    match err.downcast_ref::<GoogleError>()? {
+        GoogleError::AuthRevoked(_) => Some(Hold::SignIn),
+        GoogleError::QuotaExceeded { retry_after: Some(wait), .. } => Some(Hold::Until(Instant::now() + *wait)),
+        _ => None,
+    }
+}
+
+fn log_failure(account: &str, err: &anyhow::Error) {
+    match err.downcast_ref::<GoogleError>() {
+        Some(GoogleError::AuthRevoked(_)) => tracing::error!(
+            account,
+            "Google sign-in no longer valid — sign in again in the settings, then Sync Now: {err:#}"
+        ),
+        Some(GoogleError::QuotaExceeded { .. }) => tracing::warn!(account, "sync deferred: {err:#}"),
+        Some(GoogleError::Transient(_)) => tracing::warn!(account, "sync failed, retrying next time: {err:#}"),
+        _ => tracing::error!(account, "sync failed: {err:#}"),
+    }
+}
+
 // ── Single sync cycle ────────────────────────────────────────────────────
 
-async fn run_one_sync(hub: &Hub, vault: &SecureVault, db_key: &str) -> Result<()> {
+async fn run_one_sync(api: &GoogleApi, vault: &SecureVault, db_key: &str) -> Result<()> {
     // Verify OAuth token is present before attempting API calls.
     let v = *vault;
-    if !auth::ensure_authenticated(&v) {
+    if !auth::ensure_authenticated(&v, api.account()) {
         anyhow::bail!("not authenticated — skipping sync");
     }
 
-    // Read the sync token on a blocking thread (rusqlite::Connection is !Send).
+    // Read the sync state on a blocking thread (rusqlite::Connection is !Send).
     let db_key_owned = db_key.to_string();
-    let sync_token = tokio::task::spawn_blocking(move || {
+    let account = api.account().to_string();
+    let (sync_token, checkpoint) = tokio::task::spawn_blocking(move || -> Result<_> {
         let conn = db::open(Some(&db_key_owned))?;
-        db::get_sync_token(&conn)
+        Ok((db::get_sync_token(&conn, &account)?, db::get_checkpoint(&conn, &account)?))
     })
     .await??;
 
-    match sync_token {
-        Some(token) => match incremental_sync(hub, &token, db_key).await {
-            Ok(()) => {}
-            Err(e) => {
-                let msg = format!("{e:#}");
-                if msg.contains("410") || msg.contains("Sync token") || msg.contains("expired") {
-                    tracing::warn!("sync token expired, falling back to full sync");
-                    full_sync(hub, db_key).await?;
-                } else {
-                    return Err(e);
-                }
+    // An incremental run is only resumable from the token it started with.
+    let (mut run, mut page_token) = match checkpoint {
+        Some(cp) if cp.since.is_none() || cp.since == sync_token => {
+            tracing::info!(account = api.account(), full = cp.since.is_none(), "resuming interrupted sync");
+            (Run { since: cp.since, started: cp.started }, Some(cp.page_token))
+        }
+        _ => {
+            if sync_token.is_none() {
+                tracing::info!("no sync token found — performing full sync");
             }
-        },
-        None => {
-            tracing::info!("no sync token found — performing full sync");
-            full_sync(hub, db_key).await?;
+            (Run::new(sync_token), None)
         }
-    }
-
-    Ok(())
-}
-
-// ── Full sync ────────────────────────────────────────────────────────────
-
-async fn full_sync(hub: &Hub, db_key: &str) -> Result<()> {
-    let fields = FieldMask::new::<&str>(PERSON_FIELDS);
-    let mut page_token: Option<String> = None;
-    let mut all_persons: Vec<Person> = Vec::new();
-    let mut new_sync_token: Option<String> = None;
+    };
 
     loop {
-        let mut req = hub
-            .people()
-            .connections_list("people/me")
-            .person_fields(fields.clone())
-            .page_size(1000)
-            .request_sync_token(true);
-
-        if let Some(ref pt) = page_token {
-            req = req.page_token(pt);
-        }
-
-        let (_resp, body) = req.doit().await.context("People API connections_list")?;
-
-        if let Some(connections) = body.connections {
-            all_persons.extend(connections);
-        }
-
-        if body.next_sync_token.is_some() {
-            new_sync_token = body.next_sync_token;
-        }
-
-        match body.next_page_token {
-            Some(pt) => page_token = Some(pt),
-            None => break,
+        let resuming = page_token.is_some();
+        match sync_pages(api, &run, page_token.take(), db_key).await {
+            Err(e) if run.since.is_some() && matches!(e.downcast_ref(), Some(GoogleError::SyncTokenExpired)) => {
+                tracing::warn!(account = api.account(), "sync token expired, falling back to full sync");
+                run = Run::new(None);
+            }
+            // Page tokens expire too; start the same kind of run afresh.
+            Err(e) if resuming && matches!(e.downcast_ref(), Some(GoogleError::Permanent(_))) => {
+                tracing::warn!(account = api.account(), "cannot resume sync, starting over: {e:#}");
+                run = Run::new(run.since);
+            }
+            result => break result?,
         }
     }
 
-    let total = all_persons.len();
+    sync_contact_groups(api, db_key).await
+}
 
-    // Write all contacts to DB on a blocking thread.
-    let token = new_sync_token.clone();
+// ── Contact groups ───────────────────────────────────────────────────────
+
+/// Refresh the cached contact groups.  Memberships arrive with each
+/// contact; this only keeps group names and the set of groups current.
+async fn sync_contact_groups(api: &GoogleApi, db_key: &str) -> Result<()> {
+    let groups: Vec<db::StoredGroup> = api
+        .list_contact_groups()
+        .await
+        .context("People API contactGroups.list")?
+        .into_iter()
+        .filter(|g| !g.metadata.as_ref().and_then(|m| m.deleted).unwrap_or(false))
+        .filter_map(|g| {
+            let resource_name = g.resource_name?;
+            Some(db::StoredGroup {
+                name: g.formatted_name.or(g.name).unwrap_or_else(|| resource_name.clone()),
+                group_type: g.group_type.unwrap_or_default(),
+                resource_name,
+            })
+        })
+        .collect();
+
+    let count = groups.len();
     let db_key_owned = db_key.to_string();
+    let account = api.account().to_string();
     tokio::task::spawn_blocking(move || -> Result<()> {
         let conn = db::open(Some(&db_key_owned))?;
-        for person in &all_persons {
-            store_person(&conn, person)?;
-        }
-        if let Some(t) = token {
-            db::set_sync_token(&conn, &t)?;
-        }
-        Ok(())
+        db::replace_contact_groups(&conn, &account, &groups)
     })
     .await??;
 
-    tracing::info!(contacts = total, "full sync complete");
+    tracing::debug!(account = api.account(), groups = count, "contact groups synced");
     Ok(())
 }
 
-// ── Incremental sync ─────────────────────────────────────────────────────
+// ── Sync runs ────────────────────────────────────────────────────────────
 
-async fn incremental_sync(hub: &Hub, sync_token: &str, db_key: &str) -> Result<()> {
-    let fields = FieldMask::new::<&str>(PERSON_FIELDS);
-    let mut page_token: Option<String> = None;
-    let mut upserts: Vec<Person> = Vec::new();
-    let mut deletions: Vec<String> = Vec::new();
-    let mut new_sync_token: Option<String> = None;
+/// One listing of an account's contacts: all of them (a full sync) or the
+/// changes since a sync token (an incremental one).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Run {
+    /// Sync token the run lists changes since; `None` for a full sync.
+    since: Option<String>,
+    /// When the first page was requested (see [`db::reconcile_contacts`]).
+    started: chrono::DateTime<chrono::Utc>,
+}
 
-    loop {
-        let mut req = hub
-            .people()
-            .connections_list("people/me")
-            .person_fields(fields.clone())
-            .sync_token(sync_token)
-            .request_sync_token(true)
-            .page_size(1000);
-
-        if let Some(ref pt) = page_token {
-            req = req.page_token(pt);
-        }
+impl Run {
+    fn new(since: Option<String>) -> Self {
+        Self { since, started: chrono::Utc::now() }
+    }
+}
 
-        let (_resp, body) = req
-            .doit()
+/// Fetch `run` from `page_token` on (`None` starts it), writing each page
+/// as it arrives.
+async fn sync_pages(api: &GoogleApi, run: &Run, mut page_token: Option<String>, db_key: &str) -> Result<()> {
+    let fresh = page_token.is_none();
+    let (mut pages, mut upserted, mut deleted) = (0usize, 0usize, 0usize);
+
+    loop {
+        let body = api
+            .connections_page(run.since.as_deref(), page_token.as_deref())
             .await
-            .context("People API incremental connections_list")?;
-
-        if let Some(connections) = body.connections {
-            for person in connections {
-                let is_deleted = person
-                    .metadata
-                    .as_ref()
-                    .and_then(|m| m.deleted)
-                    .unwrap_or(false);
-
-                if is_deleted {
-                    if let Some(rn) = person.resource_name.clone() {
-                        deletions.push(rn);
-                    }
-                } else {
-                    upserts.push(person);
-                }
+            .context(match run.since {
+                Some(_) => "People API incremental connections_list",
+                None => "People API connections_list",
+            })?;
+
+        let mut batch = Batch {
+            run: run.clone(),
+            fresh: fresh && pages == 0,
+            upserts: Vec::new(),
+            deletions: Vec::new(),
+            next_page: body.next_page_token,
+            sync_token: body.next_sync_token,
+        };
+        for person in body.connections.unwrap_or_default() {
+            let is_deleted = person
+                .metadata
+                .as_ref()
+                .and_then(|m| m.deleted)
+                .unwrap_or(false);
+
+            match (is_deleted, person.resource_name.clone()) {
+                (true, Some(rn)) => batch.deletions.push(rn),
+                (true, None) => {}
+                (false, _) => batch.upserts.push(person),
             }
         }
-
-        if body.next_sync_token.is_some() {
-            new_sync_token = body.next_sync_token;
+        pages += 1;
+        upserted += batch.upserts.len();
+        deleted += batch.deletions.len();
+        page_token = batch.next_page.clone();
+
+        // Write the page on a blocking thread.
+        let db_key_owned = db_key.to_string();
+        let account = api.account().to_string();
+        let reconciled = tokio::task::spawn_blocking(move || {
+            let mut conn = db::open(Some(&db_key_owned))?;
+            apply(&mut conn, &account, &batch)
+        })
+        .await??;
+
+        if page_token.is_none() {
+            log_run(api.account(), run, pages, upserted, deleted, reconciled);
+            return Ok(());
         }
+        tracing::debug!(account = api.account(), pages, upserted, "sync page written");
+    }
+}
 
-        match body.next_page_token {
-            Some(pt) => page_token = Some(pt),
-            None => break,
+fn log_run(
+    account: &str,
+    run: &Run,
+    pages: usize,
+    upserted: usize,
+    deleted: usize,
+    reconciled: Option<db::Reconciliation>,
+) {
+    match (run.since.as_ref(), reconciled) {
+        (None, reconciled) => {
+            let reconciled = reconciled.unwrap_or_default();
+            tracing::info!(
+                account,
+                pages,
+                contacts = upserted,
+                purged = reconciled.purged,
+                purged_on_demand = reconciled.purged_on_demand,
+                kept_on_demand = reconciled.kept_on_demand,
+                adopted_on_demand = reconciled.adopted,
+                "full sync complete"
+            );
+        }
+        (Some(_), _) if upserted > 0 || deleted > 0 => {
+            tracing::info!(account, upserted, deleted, "incremental sync complete")
         }
+        (Some(_), _) => tracing::debug!(account, "incremental sync: no changes"),
     }
+}
 
-    let upserted = upserts.len();
-    let deleted = deletions.len();
+// ── Applying a page ──────────────────────────────────────────────────────
+
+/// One page of a sync run, as written to the database.
+struct Batch {
+    run: Run,
+    /// First page of a run started afresh: whatever an abandoned run left
+    /// behind is dropped.
+    fresh: bool,
+    upserts: Vec<Person>,
+    deletions: Vec<String>,
+    /// `nextPageToken`: where the run resumes; `None` on its last page.
+    next_page: Option<String>,
+    /// `nextSyncToken`, sent with the last page.
+    sync_token: Option<String>,
+}
 
-    // Write changes to DB on a blocking thread.
-    let token = new_sync_token.clone();
-    let db_key_owned = db_key.to_string();
-    tokio::task::spawn_blocking(move || -> Result<()> {
-        let conn = db::open(Some(&db_key_owned))?;
-        for person in &upserts {
-            store_person(&conn, person)?;
+/// Write one page of a run for `account` in a single transaction, with the
+/// checkpoint to resume after it.
+///
+/// The last page instead lands together with the reconciliation of a full
+/// sync and the new sync token, and clears the checkpoint: the switch to
+/// the new token happens all at once or not at all.  Until then the old
+/// token stays, and pages already written are merely fetched again should
+/// the run have to start over.
+fn apply(conn: &mut rusqlite::Connection, account: &str, batch: &Batch) -> Result<Option<db::Reconciliation>> {
+    let run = &batch.run;
+    let tx = conn.transaction()?;
+    if batch.fresh {
+        db::clear_checkpoint(&tx, account)?;
+    }
+    for person in &batch.upserts {
+        store_person(&tx, account, person)?;
+        if let (None, Some(rn)) = (&run.since, &person.resource_name) {
+            db::add_listed(&tx, account, rn)?;
         }
-        for rn in &deletions {
-            db::delete_contact(&conn, rn)?;
+    }
+    for rn in &batch.deletions {
+        db::delete_contact(&tx, account, rn)?;
+    }
+
+    let reconciled = match &batch.next_page {
+        Some(page_token) => {
+            let checkpoint = db::SyncCheckpoint {
+                since: run.since.clone(),
+                started: run.started,
+                page_token: page_token.clone(),
+            };
+            db::set_checkpoint(&tx, account, &checkpoint)?;
+            None
         }
-        if let Some(t) = token {
-            db::set_sync_token(&conn, &t)?;
+        None => {
+            let reconciled = match run.since {
+                Some(_) => None,
+                None => {
+                    let listed = db::listed_contacts(&tx, account)?;
+                    Some(db::reconcile_contacts(&tx, account, &listed, run.started)?)
+                }
+            };
+            if let Some(token) = &batch.sync_token {
+                db::set_sync_token(&tx, account, token)?;
+            }
+            db::clear_checkpoint(&tx, account)?;
+            reconciled
         }
-        Ok(())
-    })
-    .await??;
-
-    if upserted > 0 || deleted > 0 {
-        tracing::info!(upserted, deleted, "incremental sync complete");
-    } else {
-        tracing::debug!("incremental sync: no changes");
-    }
-    Ok(())
+    };
+    tx.commit().context("committing sync")?;
+    Ok(reconciled)
 }
 
 // ── Helpers ──────────────────────────────────────────────────────────────
@@ -248,7 +397,7 @@ fn normalize_phones(person: &Person) -> String {
         .join(" ")
 }
 
-fn store_person(conn: &rusqlite::Connection, person: &Person) -> Result<()> {
+fn store_person(conn: &rusqlite::Connection, account: &str, person: &Person) -> Result<()> {
     let resource_name = match person.resource_name.as_deref() {
         Some(rn) => rn,
         None => {
@@ -257,11 +406,224 @@ fn store_person(conn: &rusqlite::Connection, person: &Person) -> Result<()> {
         }
     };
 
-    let etag = person.etag.as_deref().unwrap_or("");
     let display = vcard::display_name(person);
     let vcard = vcard::person_to_vcard(person);
+    let etag = person
+        .etag
+        .clone()
+        .unwrap_or_else(|| vcard::content_etag(&vcard));
     let searchable_phone = normalize_phones(person);
 
-    db::upsert_contact(conn, resource_name, etag, &display, &vcard, &searchable_phone)?;
+    db::upsert_contact(conn, account, resource_name, &etag, &display, &vcard, &searchable_phone)?;
+    db::set_vcard4(conn, account, resource_name, &vcard::person_to_vcard4(person))?;
+    if let Some(groups) = google_api::group_memberships(person) {
+        db::set_memberships(conn, account, resource_name, &groups)?;
+    }
     Ok(())
 }
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use google_people1::api::Name;
+
+    const ACCOUNT: &str = db::DEFAULT_ACCOUNT;
+
+    fn person(rn: &str, name: &str) -> Person {
+        Person {
+            resource_name: Some(rn.into()),
+            etag: Some(format!("etag-{name}")),
+            names: Some(vec![Name {
+                display_name: Some(name.into()),
+                given_name: Some(name.into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    /// The only page of an incremental run.
+    fn batch(upserts: Vec<Person>, deletions: &[&str], token: &str) -> Batch {
+        Batch {
+            run: Run::new(Some("t0".into())),
+            fresh: false,
+            upserts,
+            deletions: deletions.iter().map(|rn| rn.to_string()).collect(),
+            next_page: None,
+            sync_token: Some(token.into()),
+        }
+    }
+
+    /// A page of the full sync `run`, followed by `next` if any.
+    fn page(run: &Run, upserts: Vec<Person>, next: Option<&str>, token: Option<&str>) -> Batch {
+        Batch {
+            run: run.clone(),
+            fresh: false,
+            upserts,
+            deletions: Vec::new(),
+            next_page: next.map(String::from),
+            sync_token: token.map(String::from),
+        }
+    }
+
+    fn names(conn: &rusqlite::Connection) -> Vec<String> {
+        db::all_contacts(conn, ACCOUNT).unwrap().into_iter().map(|c| c.0).collect()
+    }
+
+    /// Make writing the contact `people/boom` or the sync token `boom`
+    /// fail, as a full disk or an I/O error would halfway through a run.
+    fn inject_failures(conn: &rusqlite::Connection) {
+        conn.execute_batch(
+            "CREATE TRIGGER fail_contact BEFORE INSERT ON contacts
+                 WHEN NEW.resource_name = 'people/boom'
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END;
+             CREATE TRIGGER fail_token_insert BEFORE INSERT ON sync_metadata
+                 WHEN NEW.sync_token = 'boom'
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END;
+             CREATE TRIGGER fail_token_update BEFORE UPDATE ON sync_metadata
+                 WHEN NEW.sync_token = 'boom'
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+        )
+        .unwrap();
+    }
+
+    /// Contacts, sync token, CTag, change log position and checkpoint.
+    type Snapshot = (Vec<(String, String, String)>, Option<String>, i64, i64, Option<db::SyncCheckpoint>);
+
+    fn snapshot(conn: &rusqlite::Connection) -> Snapshot {
+        (
+            db::all_contacts(conn, ACCOUNT).unwrap(),
+            db::get_sync_token(conn, ACCOUNT).unwrap(),
+            db::change_counter(conn, ACCOUNT).unwrap(),
+            db::current_change_seq(conn).unwrap(),
+            db::get_checkpoint(conn, ACCOUNT).unwrap(),
+        )
+    }
+
+    fn assert_injected(result: Result<Option<db::Reconciliation>>) {
+        let err = format!("{:#}", result.unwrap_err());
+        assert!(err.contains("injected failure"), "{err}");
+    }
+
+    #[test]
+    fn google_errors_decide_the_hold() {
+        let err = |e: GoogleError| anyhow::Error::new(e).context("People API connections_list");
+        assert_eq!(hold_after(&err(GoogleError::AuthRevoked("invalid_grant".into()))), Some(Hold::SignIn));
+        let quota = GoogleError::QuotaExceeded { retry_after: Some(std::time::Duration::from_secs(60)), message: String::new() };
+        assert!(matches!(hold_after(&err(quota)), Some(Hold::Until(until)) if until > Instant::now()));
+
+        // Retried at the next interval anyway.
+        let quota = GoogleError::QuotaExceeded { retry_after: None, message: String::new() };
+        assert_eq!(hold_after(&err(quota)), None);
+        assert_eq!(hold_after(&err(GoogleError::Transient("reset".into()))), None);
+        assert_eq!(hold_after(&anyhow::anyhow!("database is locked")), None);
+
+        // The expired token is recognised through the context, not the text.
+        let expired = err(GoogleError::SyncTokenExpired);
+        assert!(matches!(expired.downcast_ref(), Some(GoogleError::SyncTokenExpired)));
+        let lookalike = anyhow::anyhow!("HTTP 410: Sync token expired");
+        assert!(lookalike.downcast_ref::<GoogleError>().is_none());
+    }
+
+    #[test]
+    fn incremental_sync_is_all_or_nothing() {
+        let mut conn = db::open_in_memory().unwrap();
+        let first = batch(vec![person("people/c1", "Alice"), person("people/c2", "Bob")], &[], "t1");
+        apply(&mut conn, ACCOUNT, &first).unwrap();
+        inject_failures(&conn);
+        let before = snapshot(&conn);
+
+        // Fails on the third upsert, after an update and an insert.
+        let delta = |token| {
+            batch(vec![person("people/c1", "Alice B"), person("people/c3", "Carol")], &["people/c2"], token)
+        };
+        let mut failing = delta("t2");
+        failing.upserts.push(person("people/boom", "Boom"));
+        assert_injected(apply(&mut conn, ACCOUNT, &failing));
+        assert_eq!(snapshot(&conn), before);
+
+        // Fails on the very last write, the sync token.
+        assert_injected(apply(&mut conn, ACCOUNT, &delta("boom")));
+        assert_eq!(snapshot(&conn), before);
+
+        // The retry with the old token applies the whole delta.
+        apply(&mut conn, ACCOUNT, &delta("t2")).unwrap();
+        let (contacts, token, ..) = snapshot(&conn);
+        let names: Vec<&str> = contacts.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(names, ["people/c1", "people/c3"]);
+        assert!(contacts[0].2.contains("Alice B"));
+        assert_eq!(token.as_deref(), Some("t2"));
+    }
+
+    #[test]
+    fn full_sync_resumes_from_its_checkpoint() {
+        let mut conn = db::open_in_memory().unwrap();
+        let first = batch(vec![person("people/c1", "Alice"), person("people/c2", "Bob")], &[], "t1");
+        apply(&mut conn, ACCOUNT, &first).unwrap();
+        inject_failures(&conn);
+
+        // The first page is stored right away, with where to go on.
+        let run = Run::new(None);
+        let first_page = Batch {
+            fresh: true,
+            ..page(&run, vec![person("people/c1", "Alice B")], Some("p2"), None)
+        };
+        assert_eq!(apply(&mut conn, ACCOUNT, &first_page).unwrap(), None);
+        let (contacts, token, .., checkpoint) = snapshot(&conn);
+        assert!(contacts[0].2.contains("Alice B"));
+        assert_eq!(contacts.len(), 2, "nothing is purged before the last page");
+        assert_eq!(token.as_deref(), Some("t1"));
+        let expected = db::SyncCheckpoint { since: None, started: run.started, page_token: "p2".into() };
+        assert_eq!(checkpoint, Some(expected));
+
+        // A failed last page leaves the first one and the checkpoint.
+        let before = snapshot(&conn);
+        let failing = page(&run, vec![person("people/boom", "Boom")], None, Some("t2"));
+        assert_injected(apply(&mut conn, ACCOUNT, &failing));
+        assert_eq!(snapshot(&conn), before);
+        let last = |token| page(&run, vec![person("people/c3", "Carol")], None, Some(token));
+        assert_injected(apply(&mut conn, ACCOUNT, &last("boom")));
+        assert_eq!(snapshot(&conn), before);
+
+        // Resumed, the last page reconciles against every page listed.
+        let reconciled = apply(&mut conn, ACCOUNT, &last("t2")).unwrap();
+        assert_eq!(reconciled.unwrap().purged, 1);
+        assert_eq!(names(&conn), ["people/c1", "people/c3"]);
+        let (_, token, .., checkpoint) = snapshot(&conn);
+        assert_eq!(token.as_deref(), Some("t2"));
+        assert_eq!(checkpoint, None);
+        assert!(db::listed_contacts(&conn, ACCOUNT).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fresh_run_drops_an_abandoned_one() {
+        let mut conn = db::open_in_memory().unwrap();
+        let first = batch(vec![person("people/c1", "Alice"), person("people/c2", "Bob")], &[], "t1");
+        apply(&mut conn, ACCOUNT, &first).unwrap();
+
+        let abandoned = Run::new(None);
+        let abandoned_page = page(&abandoned, vec![person("people/c1", "Alice")], Some("p2"), None);
+        apply(&mut conn, ACCOUNT, &abandoned_page).unwrap();
+
+        // What the abandoned run listed does not save c1 from this one.
+        let run = Run::new(None);
+        let only_page = Batch {
+            fresh: true,
+            ..page(&run, vec![person("people/c2", "Bob")], None, Some("t2"))
+        };
+        let reconciled = apply(&mut conn, ACCOUNT, &only_page).unwrap();
+        assert_eq!(reconciled.unwrap().purged, 1);
+        assert_eq!(names(&conn), ["people/c2"]);
+        assert_eq!(db::get_checkpoint(&conn, ACCOUNT).unwrap(), None);
+
+        // Incremental runs checkpoint the token they list changes since.
+        let delta = Run::new(Some("t2".into()));
+        let delta_page = Batch { run: delta, ..page(&run, vec![person("people/c4", "Dan")], Some("p2"), None) };
+        assert_eq!(apply(&mut conn, ACCOUNT, &delta_page).unwrap(), None);
+        let checkpoint = db::get_checkpoint(&conn, ACCOUNT).unwrap().unwrap();
+        assert_eq!(checkpoint.since.as_deref(), Some("t2"));
+        assert!(db::listed_contacts(&conn, ACCOUNT).unwrap().is_empty());
+    }
+}
@@ -2,14 +2,46 @@
 //! and server TLS configuration loading.
 //!
 //! When the user enables HTTPS in settings, Setu generates a local Certificate
-//! Authority and a server certificate signed by that CA.  The CA is installed
+//! Authority and a server certificate signed by that CA.  The CA may only
+//! issue certificates for the names Setu is reached under (X.509 name
+//! constraints), and its key lives in the vault.  The CA is installed
 //! into the OS trust store so that apps trust the server certificate automatically:
 //!   - **Windows**: `certutil.exe -user -addstore Root` (CurrentUser, one-time dialog)
 //!   - **Linux**: `pkexec` to copy into the system trust store (password prompt)
+//!
+//! Alternatively the server uses a certificate the user already has (e.g.
+//! from an internal CA), configured as [`CertificateFiles`]; no local CA is
+//! generated then.  Either certificate is validated when it is loaded.
+//!
+//! The running server picks up new certificate files without a restart
+//! (see [`ServerCert`]).  The generated server certificate is re-issued by
+//! the local CA well before it expires; upcoming expiry of the CA, or of
+//! the user's own certificate, is logged as a warning.
 
 use anyhow::{Context, Result};
-use std::path::PathBuf;
-use std::sync::Arc;
+use chrono::Datelike;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::vault::SecureVault;
+
+const DAY: i64 = 24 * 3600;
+
+/// Validity of a generated server certificate.  Apple platforms reject
+/// server certificates valid for more than 825 days, even from a private CA.
+const SERVER_CERT_DAYS: i64 = 397;
+/// Validity of a newly generated local CA.
+const CA_DAYS: i64 = 10 * 365;
+/// Re-issue the generated server certificate this long before it expires.
+const RENEW_BEFORE_DAYS: i64 = 30;
+/// Warn this long before the local CA expires.  It cannot be replaced
+/// silently: every device has to trust the new one.
+const CA_WARN_DAYS: i64 = 90;
+/// How often the running server looks for changed certificate files.
+const RELOAD_POLL: Duration = Duration::from_secs(30);
+/// How often the running server checks for upcoming expiry.
+const EXPIRY_CHECK: Duration = Duration::from_secs(12 * 3600);
 
 /// Directory where TLS certificates are stored: `%APPDATA%/setu/`.
 fn cert_dir() -> Result<PathBuf> {
@@ -17,38 +49,193 @@ fn cert_dir() -> Result<PathBuf> {
     Ok(base.join("setu"))
 }
 
-/// Ensure the local CA and server certificate exist on disk.
+/// Ensure the local CA and a server certificate covering `names` exist.
 ///
-/// Generates them if missing; skips if all four PEM files already exist.
+/// `names` are the host names and IP addresses clients connect to (see
+/// [`crate::config::Config::tls_names`]).  The CA is name-constrained to
+/// exactly these names, so even a stolen CA key cannot mint certificates
+/// this machine trusts for other sites; its private key is kept in the
+/// [`SecureVault`], never on disk.  The CA is (re-)created when it is
+/// missing, not constrained to cover `names`, its key is not in the vault,
+/// or it expires within 30 days — each new CA has to be trusted again.
+/// The server certificate is re-issued whenever it is missing, does not
+/// cover every name or expires within 30 days.
 /// Files created:
-///   - `ca.crt` / `ca.key`   — local Certificate Authority
+///   - `ca.crt`   — local Certificate Authority (key in the vault)
 ///   - `server.crt` / `server.key` — server cert signed by the CA
-pub fn ensure_certs() -> Result<()> {
+pub fn ensure_certs(vault: &SecureVault, names: &[String]) -> Result<()> {
     let dir = cert_dir()?;
     std::fs::create_dir_all(&dir)?;
+    ensure_certs_in(&dir, names, vault, chrono::Utc::now().timestamp())
+}
+
+/// Where the local CA's private key is kept: the [`SecureVault`].  A trait
+/// so the tests need no OS keyring.
+trait CaKeyStore {
+    fn ca_key(&self) -> Result<Option<String>>;
+    fn store_ca_key(&self, pem: &str) -> Result<()>;
+}
+
+impl CaKeyStore for SecureVault {
+    fn ca_key(&self) -> Result<Option<String>> {
+        self.get_tls_ca_key()
+    }
+
+    fn store_ca_key(&self, pem: &str) -> Result<()> {
+        self.store_tls_ca_key(pem)
+    }
+}
 
+/// [`ensure_certs`] in `dir`; `now` is a Unix timestamp.
+fn ensure_certs_in(dir: &Path, names: &[String], store: &dyn CaKeyStore, now: i64) -> Result<()> {
     let ca_crt_path = dir.join("ca.crt");
-    let ca_key_path = dir.join("ca.key");
     let srv_crt_path = dir.join("server.crt");
     let srv_key_path = dir.join("server.key");
+    // Older versions kept an unconstrained CA with its key in plaintext here.
+    let legacy_key_path = dir.join("ca.key");
 
-    // Idempotent — skip if all files already exist.
-    if ca_crt_path.exists()
-        && ca_key_path.exists()
-        && srv_crt_path.exists()
-        && srv_key_path.exists()
-    {
-        tracing::info!("TLS certificates already exist — skipping generation");
-        return Ok(());
+    let ca_key_pem = store.ca_key()?;
+    let (ca_key_pair, new_ca) = match ca_problem(&ca_crt_path, ca_key_pem.as_deref(), names, now)? {
+        None => {
+            let pem = ca_key_pem.as_deref().unwrap_or_default();
+            (rcgen::KeyPair::from_pem(pem).context("parsing the local CA key")?, false)
+        }
+        Some(reason) => {
+            if ca_crt_path.exists() {
+                tracing::warn!(
+                    reason,
+                    ca_crt = %ca_crt_path.display(),
+                    "replacing the local CA — trust the new ca.crt on every device \
+                     (saving the settings installs it on this machine)"
+                );
+            }
+            (generate_ca(&ca_crt_path, names, store, now)?, true)
+        }
+    };
+    if legacy_key_path.exists() {
+        std::fs::remove_file(&legacy_key_path).context("removing the plaintext ca.key")?;
+        tracing::info!(path = %legacy_key_path.display(), "removed the plaintext local CA key");
     }
 
-    tracing::info!("generating local CA and server certificate");
+    // Idempotent — keep a server certificate from this CA that already
+    // covers `names` and is not about to expire.
+    if !new_ca && srv_crt_path.exists() && srv_key_path.exists() {
+        let pem = std::fs::read(&srv_crt_path).context("reading server.crt")?;
+        let server = inspect_certificate(&pem).context("parsing server.crt")?;
+        let missing: Vec<&String> = names.iter().filter(|n| !server.names.contains(n)).collect();
+        let expires = format_date(server.not_after);
+        if !missing.is_empty() {
+            tracing::info!(?missing, "server certificate does not cover every bound name — re-issuing");
+        } else if server.not_after - now < RENEW_BEFORE_DAYS * DAY {
+            tracing::info!(%expires, "server certificate expires soon — re-issuing");
+        } else {
+            tracing::debug!(%expires, "TLS certificates already exist — skipping generation");
+            return Ok(());
+        }
+    }
+
+    // ── Generate server cert signed by CA ────────────────────────
+    let ca_issuer = rcgen::Issuer::from_ca_cert_pem(
+        &std::fs::read_to_string(&ca_crt_path).context("reading ca.crt")?,
+        ca_key_pair,
+    )
+    .context("parsing ca.crt")?;
+
+    let server_key_pair = rcgen::KeyPair::generate()?;
+
+    let mut server_params = rcgen::CertificateParams::new(names.to_vec())
+        .context("invalid host name for the server certificate")?;
+    server_params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "Setu CardDAV Server");
+    set_validity(&mut server_params, now, SERVER_CERT_DAYS);
+
+    let server_cert = server_params.signed_by(&server_key_pair, &ca_issuer)?;
+
+    // Key first: a reload between the two writes fails the key check and
+    // is retried once the certificate lands.
+    std::fs::write(&srv_key_path, server_key_pair.serialize_pem())?;
+    std::fs::write(&srv_crt_path, server_cert.pem())?;
+
+    tracing::info!(
+        ca_crt = %ca_crt_path.display(),
+        srv_crt = %srv_crt_path.display(),
+        ?names,
+        "TLS certificates generated"
+    );
+
+    Ok(())
+}
+
+/// Why the local CA in `ca_crt_path` cannot issue a certificate for
+/// `names`, if it can't; warns if it expires within 90 days.
+fn ca_problem(
+    ca_crt_path: &Path,
+    ca_key_pem: Option<&str>,
+    names: &[String],
+    now: i64,
+) -> Result<Option<&'static str>> {
+    if !ca_crt_path.exists() {
+        return Ok(Some("no local CA yet"));
+    }
+    let Some(key_pem) = ca_key_pem else {
+        return Ok(Some("its private key is not in the vault"));
+    };
+    let pem = std::fs::read(ca_crt_path).context("reading ca.crt")?;
+    let ca = inspect_certificate(&pem).context("parsing ca.crt")?;
+
+    let key = rcgen::KeyPair::from_pem(key_pem).context("parsing the local CA key")?;
+    if rcgen::PublicKeyData::subject_public_key_info(&key) != ca.public_key {
+        return Ok(Some("its private key in the vault belongs to another CA"));
+    }
+    let Some(permitted) = ca.permitted else {
+        return Ok(Some("it is not name-constrained"));
+    };
+    if names.iter().any(|n| !permitted.contains(n)) {
+        return Ok(Some("its name constraints do not cover every bound name"));
+    }
+    if ca.not_after - now < RENEW_BEFORE_DAYS * DAY {
+        return Ok(Some("it expires soon"));
+    }
+    if ca.not_after - now < CA_WARN_DAYS * DAY {
+        tracing::warn!(
+            expires = %format_date(ca.not_after),
+            ca_crt = %ca_crt_path.display(),
+            "local CA expires soon — a new one is created 30 days before, \
+             and every device has to trust it again"
+        );
+    }
+    Ok(None)
+}
+
+/// Generate a local CA that may only issue certificates for `names`; the
+/// key goes into `store`.
+fn generate_ca(
+    ca_crt_path: &Path,
+    names: &[String],
+    store: &dyn CaKeyStore,
+    now: i64,
+) -> Result<rcgen::KeyPair> {
+    tracing::info!(?names, "generating local CA");
 
-    // ── Generate CA ──────────────────────────────────────────────
     let ca_key_pair = rcgen::KeyPair::generate()?;
 
     let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new())?;
-    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    // Issues only end-entity certificates, and only for `names`.
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
+    ca_params.name_constraints = Some(rcgen::NameConstraints {
+        permitted_subtrees: names
+            .iter()
+            .map(|name| match name.parse::<std::net::IpAddr>() {
+                Ok(ip) => {
+                    let prefix = if ip.is_ipv4() { 32 } else { 128 };
+                    rcgen::GeneralSubtree::IpAddress(rcgen::CidrSubnet::from_addr_prefix(ip, prefix))
+                }
+                Err(_) => rcgen::GeneralSubtree::DnsName(name.clone()),
+            })
+            .collect(),
+        excluded_subtrees: Vec::new(),
+    });
     ca_params.key_usages = vec![
         rcgen::KeyUsagePurpose::KeyCertSign,
         rcgen::KeyUsagePurpose::CrlSign,
@@ -59,38 +246,80 @@ pub fn ensure_certs() -> Result<()> {
     ca_params
         .distinguished_name
         .push(rcgen::DnType::OrganizationName, "Setu");
+    set_validity(&mut ca_params, now, CA_DAYS);
 
     let ca_cert = ca_params.self_signed(&ca_key_pair)?;
 
-    std::fs::write(&ca_crt_path, ca_cert.pem())?;
-    std::fs::write(&ca_key_path, ca_key_pair.serialize_pem())?;
+    // Key first: a ca.crt without its key in the vault is replaced next time.
+    store
+        .store_ca_key(&ca_key_pair.serialize_pem())
+        .context("storing the local CA key in the vault")?;
+    std::fs::write(ca_crt_path, ca_cert.pem())?;
+    Ok(ca_key_pair)
+}
 
-    // ── Generate server cert signed by CA ────────────────────────
-    let server_key_pair = rcgen::KeyPair::generate()?;
+/// Make `params` valid for `days` days from the start of the day of `now`.
+fn set_validity(params: &mut rcgen::CertificateParams, now: i64, days: i64) {
+    let today = chrono::DateTime::from_timestamp(now, 0).unwrap_or_default().date_naive();
+    params.not_before = rcgen::date_time_ymd(today.year(), today.month() as u8, today.day() as u8);
+    params.not_after = params.not_before + Duration::from_secs((days * DAY) as u64);
+}
 
-    let mut server_params = rcgen::CertificateParams::new(vec!["localhost".to_string()])?;
-    server_params
-        .subject_alt_names
-        .push(rcgen::SanType::IpAddress(std::net::IpAddr::V4(
-            std::net::Ipv4Addr::LOCALHOST,
-        )));
-    server_params
-        .distinguished_name
-        .push(rcgen::DnType::CommonName, "Setu CardDAV Server");
+/// The parts of a certificate the lifecycle code looks at.
+#[derive(Debug)]
+struct CertificateInfo {
+    /// DNS names and IP addresses in the subjectAltName.
+    names: Vec<String>,
+    /// Names a CA may issue for (its permitted name constraints); `None`
+    /// if it is not constrained.
+    permitted: Option<Vec<String>>,
+    /// End of validity, as a Unix timestamp.
+    not_after: i64,
+    /// DER subjectPublicKeyInfo.
+    public_key: Vec<u8>,
+}
 
-    let ca_issuer = rcgen::Issuer::from_params(&ca_params, &ca_key_pair);
-    let server_cert = server_params.signed_by(&server_key_pair, &ca_issuer)?;
+/// Inspect the first certificate of a PEM file.
+fn inspect_certificate(pem: &[u8]) -> Result<CertificateInfo> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem)?;
+    let cert = pem.parse_x509()?;
+    let names = match cert.subject_alternative_name()? {
+        Some(san) => san.value.general_names.iter().filter_map(general_name).collect(),
+        None => Vec::new(),
+    };
+    let permitted = cert
+        .name_constraints()?
+        .and_then(|nc| nc.value.permitted_subtrees.as_ref())
+        .map(|subtrees| subtrees.iter().filter_map(|s| general_name(&s.base)).collect());
+    Ok(CertificateInfo {
+        names,
+        permitted,
+        not_after: cert.validity().not_after.timestamp(),
+        public_key: cert.public_key().raw.to_vec(),
+    })
+}
 
-    std::fs::write(&srv_crt_path, server_cert.pem())?;
-    std::fs::write(&srv_key_path, server_key_pair.serialize_pem())?;
+/// A DNS name or IP address as text.  Name constraints carry an address
+/// followed by its mask; only the address is kept.
+fn general_name(name: &x509_parser::extensions::GeneralName) -> Option<String> {
+    use x509_parser::extensions::GeneralName;
 
-    tracing::info!(
-        ca_crt = %ca_crt_path.display(),
-        srv_crt = %srv_crt_path.display(),
-        "TLS certificates generated"
-    );
+    match name {
+        GeneralName::DNSName(dns) => Some(dns.to_string()),
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 | 8 => Some(std::net::IpAddr::from(<[u8; 4]>::try_from(&bytes[..4]).ok()?).to_string()),
+            16 | 32 => Some(std::net::IpAddr::from(<[u8; 16]>::try_from(&bytes[..16]).ok()?).to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
 
-    Ok(())
+/// A Unix timestamp as `2026-01-31 12:00 UTC`.
+fn format_date(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
 }
 
 /// Install the local CA certificate into the Windows CurrentUser trust store.
@@ -106,6 +335,12 @@ pub fn install_ca_to_trust_store() -> Result<()> {
         anyhow::bail!("CA certificate not found at {}", ca_crt.display());
     }
 
+    // Drop earlier Setu CAs first — older ones were not name-constrained.
+    // Fails harmlessly if there are none.
+    let _ = std::process::Command::new("certutil.exe")
+        .args(["-user", "-delstore", "Root", "Setu Local CA"])
+        .status();
+
     tracing::info!("installing CA into Windows CurrentUser trust store");
 
     let status = std::process::Command::new("certutil.exe")
@@ -182,32 +417,660 @@ pub fn install_ca_to_trust_store() -> Result<()> {
     Ok(())
 }
 
-/// Load the server TLS configuration from disk.
+/// A certificate, private key and optional intermediate chain supplied by
+/// the user (`tls_cert_path`, `tls_key_path`, `tls_chain_path` in the
+/// config).  All PEM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateFiles {
+    /// Server certificate, optionally followed by its chain.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Intermediate certificates, if not already in `cert`.
+    pub chain: Option<PathBuf>,
+}
+
+impl CertificateFiles {
+    /// The generated `server.crt` and `server.key` in `dir`.
+    fn generated(dir: &Path) -> Self {
+        Self {
+            cert: dir.join("server.crt"),
+            key: dir.join("server.key"),
+            chain: None,
+        }
+    }
+
+    /// Modification times of the files, to notice when they change.
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        std::iter::once(&self.cert)
+            .chain(std::iter::once(&self.key))
+            .chain(self.chain.iter())
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+/// The server certificate, reloaded from disk while the server runs.
 ///
-/// Reads `server.crt` and `server.key` and builds a `rustls::ServerConfig`
-/// suitable for HTTPS on localhost.
-pub fn load_server_tls_config() -> Result<Arc<rustls::ServerConfig>> {
-    let dir = cert_dir()?;
-    let cert_pem = std::fs::read(&dir.join("server.crt"))
-        .context("reading server.crt")?;
-    let key_pem = std::fs::read(&dir.join("server.key"))
-        .context("reading server.key")?;
+/// Every TLS handshake gets the certificate loaded last.  [`ServerCert::watch`]
+/// polls the files and swaps in a new certificate once it validates;
+/// until then the previous one keeps being served.
+#[derive(Debug)]
+pub struct ServerCert {
+    files: CertificateFiles,
+    /// `None` for the user's own certificate, which Setu cannot renew.
+    renewal: Option<Renewal>,
+    current: RwLock<Arc<rustls::sign::CertifiedKey>>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+/// How to re-issue the generated certificate.
+#[derive(Debug)]
+struct Renewal {
+    dir: PathBuf,
+    names: Vec<String>,
+    vault: SecureVault,
+}
+
+impl ServerCert {
+    /// Load `own` if given, otherwise the generated `server.crt` and
+    /// `server.key`, which are renewed for `names` by the CA whose key is
+    /// in `vault`.  Fails with a message
+    /// naming the file if the certificate is expired or not yet valid, or
+    /// if the key does not belong to it.
+    pub fn load(own: Option<&CertificateFiles>, names: &[String], vault: SecureVault) -> Result<Arc<Self>> {
+        let (files, renewal) = match own {
+            Some(files) => (files.clone(), None),
+            None => {
+                let dir = cert_dir()?;
+                let files = CertificateFiles::generated(&dir);
+                (files, Some(Renewal { dir, names: names.to_vec(), vault }))
+            }
+        };
+        Self::open(files, renewal).map(Arc::new)
+    }
+
+    fn open(files: CertificateFiles, renewal: Option<Renewal>) -> Result<Self> {
+        let modified = files.modified();
+        let key = load_certified_key(&files, chrono::Utc::now().timestamp())?;
+        Ok(Self {
+            files,
+            renewal,
+            current: RwLock::new(Arc::new(key)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// A `rustls::ServerConfig` for HTTPS that always serves the current
+    /// certificate.  With `client_auth`, every client must present a
+    /// device certificate issued by the local CA (see
+    /// [`issue_client_certificate`]); with `http2`, clients may negotiate
+    /// HTTP/2 (see [`alpn_protocols`]).
+    pub fn server_config(self: &Arc<Self>, client_auth: bool, http2: bool) -> Result<Arc<rustls::ServerConfig>> {
+        let builder = rustls::ServerConfig::builder();
+        let builder = if client_auth {
+            builder.with_client_cert_verifier(client_verifier(&cert_dir()?.join("ca.crt"))?)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let mut config = builder.with_cert_resolver(self.clone());
+
+        config.alpn_protocols = alpn_protocols(http2);
+
+        Ok(Arc::new(config))
+    }
+
+    /// The certificate served right now.
+    pub fn current(&self) -> Arc<rustls::sign::CertifiedKey> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
 
-    let certs: Vec<rustls::pki_types::CertificateDer<'static>> =
-        rustls_pemfile::certs(&mut &cert_pem[..])
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .context("parsing server.crt PEM")?;
+    /// Poll the certificate files forever: reload them when they change,
+    /// and renew or warn before the certificate expires.
+    pub async fn watch(self: Arc<Self>) {
+        let mut last_expiry_check = Instant::now();
+        loop {
+            tokio::time::sleep(RELOAD_POLL).await;
+            let check_expiry = last_expiry_check.elapsed() >= EXPIRY_CHECK;
+            if check_expiry {
+                last_expiry_check = Instant::now();
+            }
 
+            let cert = self.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let now = chrono::Utc::now().timestamp();
+                if check_expiry {
+                    if let Err(e) = cert.check_expiry(now) {
+                        tracing::warn!("TLS certificate renewal failed: {e:#}");
+                    }
+                }
+                cert.reload_if_changed(now)
+            })
+            .await;
+            match result {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::error!("TLS certificate reload failed, keeping the current one: {e:#}"),
+                Err(e) => tracing::error!("TLS certificate reload task failed: {e}"),
+            }
+        }
+    }
+
+    /// Re-issue the generated certificate if it expires soon (the new files
+    /// are picked up by the next reload), or warn about the user's own.
+    fn check_expiry(&self, now: i64) -> Result<()> {
+        match &self.renewal {
+            Some(r) => ensure_certs_in(&r.dir, &r.names, &r.vault, now),
+            None => check_validity(&self.current().cert[0], now)
+                .with_context(|| format!("certificate {}", self.files.cert.display())),
+        }
+    }
+
+    /// Load the files again if any of them changed since the last look.
+    /// Returns whether a new certificate is being served.
+    fn reload_if_changed(&self, now: i64) -> Result<bool> {
+        let modified = self.files.modified();
+        {
+            let mut last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+            if *last == modified {
+                return Ok(false);
+            }
+            // Also on failure: a half-written pair is retried when the
+            // second file lands, not on every poll.
+            *last = modified;
+        }
+
+        let key = load_certified_key(&self.files, now)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        tracing::info!(cert = %self.files.cert.display(), "TLS certificate reloaded");
+        Ok(true)
+    }
+}
+
+impl rustls::server::ResolvesServerCert for ServerCert {
+    fn resolve(&self, _: rustls::server::ClientHello<'_>) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// ALPN protocols offered on HTTPS, most preferred first: `h2` (when
+/// `http2`) and `http/1.1`.  A client that offers neither still gets
+/// HTTP/1.1.
+pub fn alpn_protocols(http2: bool) -> Vec<Vec<u8>> {
+    let mut protocols = Vec::new();
+    if http2 {
+        protocols.push(b"h2".to_vec());
+    }
+    protocols.push(b"http/1.1".to_vec());
+    protocols
+}
+
+// ── Client certificates ──────────────────────────────────────────────────
+
+/// Validity of a device's client certificate.
+const CLIENT_CERT_DAYS: i64 = 2 * 365;
+
+/// A device certificate issued by the local CA.
+pub struct ClientCertificate {
+    /// The certificate, its key and the CA as PKCS#12, for import on the
+    /// device.
+    pub pkcs12: Vec<u8>,
+    /// Identifies the device to the server (see [`fingerprint`]).
+    pub sha256: String,
+}
+
+/// Issue a client certificate for the app password `username` from the
+/// local CA, packaged as PKCS#12 protected by `password`.
+///
+/// The bundle uses the legacy 3DES / SHA-1 encryption, the only one older
+/// Android and iOS versions can import; `password` mainly guards it in
+/// transit.
+pub fn issue_client_certificate(vault: &SecureVault, username: &str, password: &str) -> Result<ClientCertificate> {
+    issue_client_certificate_in(&cert_dir()?, vault, username, password, chrono::Utc::now().timestamp())
+}
+
+fn issue_client_certificate_in(
+    dir: &Path,
+    store: &dyn CaKeyStore,
+    username: &str,
+    password: &str,
+    now: i64,
+) -> Result<ClientCertificate> {
+    let ca_crt_path = dir.join("ca.crt");
+    let ca_key_pem = store.ca_key()?;
+    if let Some(reason) = ca_problem(&ca_crt_path, ca_key_pem.as_deref(), &[], now)? {
+        anyhow::bail!("the local CA cannot issue certificates ({reason}) — enable HTTPS and save the settings first");
+    }
+    let ca_key_pair = rcgen::KeyPair::from_pem(ca_key_pem.as_deref().unwrap_or_default())
+        .context("parsing the local CA key")?;
+    let ca_issuer = rcgen::Issuer::from_ca_cert_pem(
+        &std::fs::read_to_string(&ca_crt_path).context("reading ca.crt")?,
+        ca_key_pair,
+    )
+    .context("parsing ca.crt")?;
+
+    let key_pair = rcgen::KeyPair::generate()?;
+    let mut params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+    params.distinguished_name.push(rcgen::DnType::CommonName, username);
+    params.distinguished_name.push(rcgen::DnType::OrganizationName, "Setu");
+    params.key_usages = vec![rcgen::KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+    set_validity(&mut params, now, CLIENT_CERT_DAYS);
+    let cert = params.signed_by(&key_pair, &ca_issuer)?;
+
+    let ca_der = read_certificates(&ca_crt_path)?
+        .into_iter()
+        .next()
+        .context("no PEM certificate found in ca.crt")?;
+    let sha256 = fingerprint(cert.der());
+    let chain = p12_keystore::PrivateKeyChain::new(
+        key_pair.serialize_der(),
+        // Pairs the key with its certificate inside the bundle.
+        <sha2::Sha256 as sha2::Digest>::digest(cert.der()),
+        [
+            p12_keystore::Certificate::from_der(cert.der())?,
+            p12_keystore::Certificate::from_der(&ca_der)?,
+        ],
+    );
+    let mut keystore = p12_keystore::KeyStore::new();
+    keystore.add_entry(username, p12_keystore::KeyStoreEntry::PrivateKeyChain(chain));
+    let pkcs12 = keystore
+        .writer(password)
+        .encryption_algorithm(p12_keystore::EncryptionAlgorithm::PbeWithShaAnd3KeyTripleDesCbc)
+        .mac_algorithm(p12_keystore::MacAlgorithm::HmacSha1)
+        .write()
+        .context("writing the PKCS#12 bundle")?;
+
+    tracing::info!(username, "client certificate issued");
+    Ok(ClientCertificate { pkcs12, sha256 })
+}
+
+/// Accepts client certificates issued by the CA in `ca_crt`.
+fn client_verifier(ca_crt: &Path) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in read_certificates(ca_crt)? {
+        roots.add(cert).with_context(|| format!("adding {} as client CA", ca_crt.display()))?;
+    }
+    rustls::server::WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .context("building the client certificate verifier")
+}
+
+/// Hex SHA-256 of a DER certificate, the identity of a device's client
+/// certificate.
+pub fn fingerprint(der: &[u8]) -> String {
+    use sha2::Digest;
+    sha2::Sha256::digest(der).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Read and validate a certificate chain and its key; `now` is a Unix
+/// timestamp.
+fn load_certified_key(files: &CertificateFiles, now: i64) -> Result<rustls::sign::CertifiedKey> {
+    let mut certs = read_certificates(&files.cert)?;
+    anyhow::ensure!(!certs.is_empty(), "no PEM certificate found in {}", files.cert.display());
+    if let Some(chain) = &files.chain {
+        certs.extend(read_certificates(chain)?);
+    }
+
+    let key_pem = std::fs::read(&files.key)
+        .with_context(|| format!("reading {}", files.key.display()))?;
     let key = rustls_pemfile::private_key(&mut &key_pem[..])
-        .context("parsing server.key PEM")?
-        .context("no private key found in server.key")?;
+        .with_context(|| format!("parsing {}", files.key.display()))?
+        .with_context(|| format!("no private key found in {}", files.key.display()))?;
+
+    check_validity(&certs[0], now).with_context(|| format!("certificate {}", files.cert.display()))?;
+
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .with_context(|| format!("unsupported private key type in {}", files.key.display()))?;
+    let certified = rustls::sign::CertifiedKey::new(certs, signing_key);
+    certified.keys_match().map_err(|e| {
+        anyhow::anyhow!(
+            "private key {} does not match certificate {}: {e}",
+            files.key.display(),
+            files.cert.display()
+        )
+    })?;
+
+    Ok(certified)
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    rustls_pemfile::certs(&mut &pem[..])
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Fail if a DER certificate is not valid at `now` (Unix timestamp); warn
+/// if it expires within 30 days.
+fn check_validity(der: &[u8], now: i64) -> Result<()> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).context("parsing X.509")?;
+    let validity = cert.validity();
+    let (not_before, not_after) = (validity.not_before.timestamp(), validity.not_after.timestamp());
+    anyhow::ensure!(not_before <= now, "not valid before {}", format_date(not_before));
+    anyhow::ensure!(now <= not_after, "expired on {}", format_date(not_after));
+    if not_after - now < RENEW_BEFORE_DAYS * DAY {
+        tracing::warn!(expires = %format_date(not_after), "TLS certificate expires soon");
+    }
+    Ok(())
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory stand-in for the vault.
+    #[derive(Default)]
+    struct MemoryStore(std::cell::RefCell<Option<String>>);
 
-    let mut config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .context("building ServerConfig")?;
+    impl CaKeyStore for MemoryStore {
+        fn ca_key(&self) -> Result<Option<String>> {
+            Ok(self.0.borrow().clone())
+        }
 
-    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        fn store_ca_key(&self, pem: &str) -> Result<()> {
+            *self.0.borrow_mut() = Some(pem.to_string());
+            Ok(())
+        }
+    }
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|n| n.to_string()).collect()
+    }
 
-    Ok(Arc::new(config))
+    /// Verify `cert_pem` for `name` against `ca_pem` the way a client would.
+    fn verify(ca_pem: &[u8], cert_pem: &[u8], name: &str) -> Result<(), rustls::Error> {
+        use rustls::client::danger::ServerCertVerifier;
+        use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let der = |pem: &[u8]| -> CertificateDer<'static> {
+            rustls_pemfile::certs(&mut &pem[..]).next().unwrap().unwrap()
+        };
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(der(ca_pem)).unwrap();
+        let verifier = rustls::client::WebPkiServerVerifier::builder(Arc::new(roots)).build().unwrap();
+        verifier
+            .verify_server_cert(
+                &der(cert_pem),
+                &[],
+                &ServerName::try_from(name.to_string()).unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+            .map(|_| ())
+    }
+
+    #[test]
+    fn server_certificate_follows_bound_names() {
+        let dir = std::env::temp_dir().join(format!("setu-tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = MemoryStore::default();
+        let now = chrono::Utc::now().timestamp();
+
+        ensure_certs_in(&dir, &names(&["localhost", "127.0.0.1", "::1"]), &store, now).unwrap();
+        let ca = std::fs::read(dir.join("ca.crt")).unwrap();
+        let server = std::fs::read(dir.join("server.crt")).unwrap();
+        assert_eq!(
+            inspect_certificate(&server).unwrap().names,
+            ["localhost", "127.0.0.1", "::1"]
+        );
+        assert_eq!(
+            inspect_certificate(&ca).unwrap().permitted.unwrap(),
+            ["localhost", "127.0.0.1", "::1"]
+        );
+        // The key lives in the vault, not next to the certificate.
+        assert!(store.0.borrow().is_some());
+        assert!(!dir.join("ca.key").exists());
+        verify(&ca, &server, "localhost").unwrap();
+        verify(&ca, &server, "127.0.0.1").unwrap();
+
+        // A subset is already covered: nothing changes.
+        ensure_certs_in(&dir, &names(&["localhost"]), &store, now).unwrap();
+        assert_eq!(std::fs::read(dir.join("server.crt")).unwrap(), server);
+        assert_eq!(std::fs::read(dir.join("ca.crt")).unwrap(), ca);
+
+        // A new LAN address needs a new CA constrained to it.
+        let lan = names(&["localhost", "192.168.1.10", "homeserver.local"]);
+        ensure_certs_in(&dir, &lan, &store, now).unwrap();
+        let ca = std::fs::read(dir.join("ca.crt")).unwrap();
+        let reissued = std::fs::read(dir.join("server.crt")).unwrap();
+        assert_eq!(inspect_certificate(&reissued).unwrap().names, lan);
+        assert_eq!(inspect_certificate(&ca).unwrap().permitted.unwrap(), lan);
+        verify(&ca, &reissued, "homeserver.local").unwrap();
+        verify(&ca, &reissued, "192.168.1.10").unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ca_cannot_issue_for_other_names() {
+        let dir = std::env::temp_dir().join(format!("setu-tls-nc-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = MemoryStore::default();
+        ensure_certs_in(&dir, &names(&["localhost"]), &store, chrono::Utc::now().timestamp()).unwrap();
+        let ca = std::fs::read_to_string(dir.join("ca.crt")).unwrap();
+
+        // Someone holding the CA key mints a certificate for another site.
+        let ca_key = rcgen::KeyPair::from_pem(store.0.borrow().as_deref().unwrap()).unwrap();
+        let issuer = rcgen::Issuer::from_ca_cert_pem(&ca, ca_key).unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let forged = rcgen::CertificateParams::new(names(&["bank.example"]))
+            .unwrap()
+            .signed_by(&key, &issuer)
+            .unwrap();
+        let err = verify(ca.as_bytes(), forged.pem().as_bytes(), "bank.example").unwrap_err();
+        assert!(format!("{err:?}").contains("NameConstraint"), "{err:?}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn issues_client_certificates() {
+        use rustls::pki_types::{CertificateDer, UnixTime};
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = std::env::temp_dir().join(format!("setu-tls-client-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = MemoryStore::default();
+        let now = chrono::Utc::now().timestamp();
+
+        let err = issue_client_certificate_in(&dir, &store, "phone", "pw", now).err().unwrap();
+        assert!(err.to_string().contains("no local CA yet"), "{err}");
+
+        ensure_certs_in(&dir, &names(&["localhost", "homeserver.local"]), &store, now).unwrap();
+        let issued = issue_client_certificate_in(&dir, &store, "phone", "s3cret", now).unwrap();
+
+        // The bundle opens with its password and holds certificate, key and CA.
+        assert!(p12_keystore::KeyStore::from_pkcs12(&issued.pkcs12, "wrong").is_err());
+        let keystore = p12_keystore::KeyStore::from_pkcs12(&issued.pkcs12, "s3cret").unwrap();
+        let (alias, chain) = keystore.private_key_chain().unwrap();
+        assert_eq!(alias, "phone");
+        assert_eq!(chain.chain().len(), 2);
+        let cert = CertificateDer::from(chain.chain()[0].as_der().to_vec());
+        assert_eq!(fingerprint(&cert), issued.sha256);
+        let key = rcgen::KeyPair::try_from(chain.key()).unwrap();
+        let (_, parsed) = x509_parser::parse_x509_certificate(&cert).unwrap();
+        assert_eq!(parsed.public_key().raw, rcgen::PublicKeyData::subject_public_key_info(&key));
+        assert_eq!(
+            parsed.subject().iter_common_name().next().unwrap().as_str().unwrap(),
+            "phone"
+        );
+
+        // The server accepts it despite the CA's name constraints, and
+        // nothing from another CA.
+        let verifier = client_verifier(&dir.join("ca.crt")).unwrap();
+        verifier.verify_client_cert(&cert, &[], UnixTime::now()).unwrap();
+        write_cert(&dir, "stranger", 2020, 2100);
+        let stranger = read_certificates(&dir.join("stranger.crt")).unwrap().remove(0);
+        assert!(verifier.verify_client_cert(&stranger, &[], UnixTime::now()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_legacy_ca() {
+        let dir = std::env::temp_dir().join(format!("setu-tls-legacy-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = MemoryStore::default();
+
+        // Earlier versions: unconstrained CA, key in plaintext next to it.
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let legacy = params.self_signed(&key).unwrap();
+        std::fs::write(dir.join("ca.crt"), legacy.pem()).unwrap();
+        std::fs::write(dir.join("ca.key"), key.serialize_pem()).unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let localhost = names(&["localhost"]);
+        assert_eq!(
+            ca_problem(&dir.join("ca.crt"), None, &localhost, now).unwrap(),
+            Some("its private key is not in the vault")
+        );
+        assert_eq!(
+            ca_problem(&dir.join("ca.crt"), Some(&key.serialize_pem()), &localhost, now).unwrap(),
+            Some("it is not name-constrained")
+        );
+
+        ensure_certs_in(&dir, &localhost, &store, now).unwrap();
+        assert!(!dir.join("ca.key").exists());
+        let ca = std::fs::read(dir.join("ca.crt")).unwrap();
+        assert_ne!(ca, legacy.pem().into_bytes());
+        assert!(inspect_certificate(&ca).unwrap().permitted.is_some());
+
+        // A vault key that doesn't belong to ca.crt is not used either.
+        let stranger = rcgen::KeyPair::generate().unwrap().serialize_pem();
+        assert_eq!(
+            ca_problem(&dir.join("ca.crt"), Some(&stranger), &localhost, now).unwrap(),
+            Some("its private key in the vault belongs to another CA")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renews_server_certificate_before_expiry() {
+        let dir = std::env::temp_dir().join(format!("setu-tls-renew-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = MemoryStore::default();
+        let names = names(&["localhost"]);
+        let now = chrono::Utc::now().timestamp();
+
+        ensure_certs_in(&dir, &names, &store, now).unwrap();
+        let ca = std::fs::read(dir.join("ca.crt")).unwrap();
+        let server = std::fs::read(dir.join("server.crt")).unwrap();
+        let not_after = inspect_certificate(&server).unwrap().not_after;
+        assert!((not_after - now - SERVER_CERT_DAYS * DAY).abs() <= DAY);
+        assert!(inspect_certificate(&ca).unwrap().not_after - now > (CA_DAYS - 1) * DAY);
+
+        // Well before expiry nothing changes; 30 days before it is re-issued.
+        ensure_certs_in(&dir, &names, &store, not_after - 31 * DAY).unwrap();
+        assert_eq!(std::fs::read(dir.join("server.crt")).unwrap(), server);
+        ensure_certs_in(&dir, &names, &store, not_after - 29 * DAY).unwrap();
+        let renewed = std::fs::read(dir.join("server.crt")).unwrap();
+        assert!(inspect_certificate(&renewed).unwrap().not_after > not_after);
+        assert_eq!(std::fs::read(dir.join("ca.crt")).unwrap(), ca);
+
+        // So is the CA, which devices then have to trust again.
+        let ca_not_after = inspect_certificate(&ca).unwrap().not_after;
+        ensure_certs_in(&dir, &names, &store, ca_not_after - 29 * DAY).unwrap();
+        let new_ca = std::fs::read(dir.join("ca.crt")).unwrap();
+        assert!(inspect_certificate(&new_ca).unwrap().not_after > ca_not_after);
+        assert_ne!(std::fs::read(dir.join("server.crt")).unwrap(), renewed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Write `<name>.crt`, self-signed and valid from the start of year
+    /// `from` to the start of `until`, and its key `<name>.key`.
+    fn write_cert(dir: &Path, name: &str, from: i32, until: i32) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["homeserver.local".to_string()]).unwrap();
+        params.not_before = rcgen::date_time_ymd(from, 1, 1);
+        params.not_after = rcgen::date_time_ymd(until, 1, 1);
+        let cert = params.self_signed(&key).unwrap();
+        std::fs::write(dir.join(format!("{name}.crt")), cert.pem()).unwrap();
+        std::fs::write(dir.join(format!("{name}.key")), key.serialize_pem()).unwrap();
+    }
+
+    #[test]
+    fn validates_own_certificate() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = std::env::temp_dir().join(format!("setu-own-cert-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = rcgen::date_time_ymd(2026, 6, 1).unix_timestamp();
+        let files = |cert: &str, key: &str, chain: Option<&str>| CertificateFiles {
+            cert: dir.join(format!("{cert}.crt")),
+            key: dir.join(format!("{key}.key")),
+            chain: chain.map(|c| dir.join(format!("{c}.crt"))),
+        };
+
+        write_cert(&dir, "good", 2025, 2027);
+        write_cert(&dir, "expired", 2020, 2021);
+        write_cert(&dir, "future", 2030, 2031);
+        write_cert(&dir, "other", 2025, 2027);
+
+        let key = load_certified_key(&files("good", "good", None), now).unwrap();
+        assert_eq!(key.cert.len(), 1);
+        let key = load_certified_key(&files("good", "good", Some("other")), now).unwrap();
+        assert_eq!(key.cert.len(), 2);
+
+        let err = format!("{:#}", load_certified_key(&files("good", "other", None), now).unwrap_err());
+        assert!(err.contains("does not match"), "{err}");
+        let err = format!("{:#}", load_certified_key(&files("expired", "expired", None), now).unwrap_err());
+        assert!(err.contains("expired on 2021-01-01"), "{err}");
+        let err = format!("{:#}", load_certified_key(&files("future", "future", None), now).unwrap_err());
+        assert!(err.contains("not valid before 2030-01-01"), "{err}");
+        let err = format!("{:#}", load_certified_key(&files("missing", "good", None), now).unwrap_err());
+        assert!(err.contains("missing.crt"), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Copy `<from>.crt` and `.key` over `<to>.crt` and `.key`, with a
+    /// modification time that differs even on coarse file systems.
+    fn replace_cert(dir: &Path, from: &str, to: &str, key_only: bool) {
+        let exts: &[&str] = if key_only { &["key"] } else { &["crt", "key"] };
+        for ext in exts {
+            let target = dir.join(format!("{to}.{ext}"));
+            let later = std::fs::metadata(&target).unwrap().modified().unwrap() + Duration::from_secs(60);
+            std::fs::copy(dir.join(format!("{from}.{ext}")), &target).unwrap();
+            std::fs::File::options().write(true).open(&target).unwrap().set_modified(later).unwrap();
+        }
+    }
+
+    #[test]
+    fn reloads_changed_certificate() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = std::env::temp_dir().join(format!("setu-reload-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let year = chrono::Utc::now().year();
+        for name in ["served", "next", "other"] {
+            write_cert(&dir, name, year - 1, year + 2);
+        }
+        let files = CertificateFiles {
+            cert: dir.join("served.crt"),
+            key: dir.join("served.key"),
+            chain: None,
+        };
+        let server = ServerCert::open(files.clone(), None).unwrap();
+        let first = server.current().cert[0].clone();
+        assert!(!server.reload_if_changed(now).unwrap());
+
+        replace_cert(&dir, "next", "served", false);
+        assert!(server.reload_if_changed(now).unwrap());
+        let next = server.current().cert[0].clone();
+        assert_ne!(next, first);
+        assert_eq!(next, read_certificates(&dir.join("next.crt")).unwrap()[0]);
+        assert!(!server.reload_if_changed(now).unwrap());
+
+        // A key that doesn't match is refused; the last good pair stays.
+        replace_cert(&dir, "other", "served", true);
+        let err = format!("{:#}", server.reload_if_changed(now).unwrap_err());
+        assert!(err.contains("does not match"), "{err}");
+        assert_eq!(server.current().cert[0], next);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
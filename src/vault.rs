@@ -17,6 +17,17 @@ const KEY_DB: &str = "db_key";
 const KEY_OAUTH_TOKEN: &str = "oauth_token";
 const KEY_CARDDAV_PASSWORD: &str = "carddav_password";
 const KEY_GOOGLE_CLIENT_SECRET: &str = "google_client_secret";
+const KEY_TLS_CA_KEY: &str = "tls_ca_key";
+
+/// Vault key of an account's OAuth token.  The default account keeps the
+/// single-account key, so existing sign-ins survive the upgrade.
+fn oauth_token_key(account: &str) -> String {
+    if account == crate::db::DEFAULT_ACCOUNT {
+        KEY_OAUTH_TOKEN.to_string()
+    } else {
+        format!("{KEY_OAUTH_TOKEN}:{account}")
+    }
+}
 
 // ── Backend detection ────────────────────────────────────────────────
 
@@ -103,24 +114,24 @@ impl SecureVault {
         Ok(key)
     }
 
-    /// Store the full OAuth token JSON blob.
-    pub fn store_oauth_token(&self, token_json: &str) -> Result<()> {
-        vault_set(KEY_OAUTH_TOKEN, token_json)
+    /// Store an account's full OAuth token JSON blob.
+    pub fn store_oauth_token(&self, account: &str, token_json: &str) -> Result<()> {
+        vault_set(&oauth_token_key(account), token_json)
     }
 
-    /// Retrieve the OAuth token JSON (None if absent).
-    pub fn get_oauth_token(&self) -> Result<Option<String>> {
-        vault_get(KEY_OAUTH_TOKEN)
+    /// Retrieve an account's OAuth token JSON (None if absent).
+    pub fn get_oauth_token(&self, account: &str) -> Result<Option<String>> {
+        vault_get(&oauth_token_key(account))
     }
 
-    /// Remove the stored OAuth token.
-    pub fn clear_oauth_token(&self) -> Result<()> {
-        vault_delete(KEY_OAUTH_TOKEN)
+    /// Remove an account's stored OAuth token.
+    pub fn clear_oauth_token(&self, account: &str) -> Result<()> {
+        vault_delete(&oauth_token_key(account))
     }
 
-    /// Returns `true` if an OAuth token exists.
-    pub fn has_oauth_token(&self) -> bool {
-        vault_get(KEY_OAUTH_TOKEN)
+    /// Returns `true` if an OAuth token exists for the account.
+    pub fn has_oauth_token(&self, account: &str) -> bool {
+        vault_get(&oauth_token_key(account))
             .ok()
             .flatten()
             .is_some()
@@ -150,6 +161,16 @@ impl SecureVault {
     pub fn get_google_client_secret(&self) -> Result<Option<String>> {
         vault_get(KEY_GOOGLE_CLIENT_SECRET)
     }
+
+    /// Store the local TLS CA's private key (PEM).
+    pub fn store_tls_ca_key(&self, pem: &str) -> Result<()> {
+        vault_set(KEY_TLS_CA_KEY, pem)
+    }
+
+    /// Retrieve the local TLS CA's private key (None if absent).
+    pub fn get_tls_ca_key(&self) -> Result<Option<String>> {
+        vault_get(KEY_TLS_CA_KEY)
+    }
 }
 
 // ── Keyring backend ──────────────────────────────────────────────────
@@ -279,7 +300,7 @@ const HEX_CHARS: [char; 16] = [
 ];
 
 /// Generate a random alphanumeric string of length `n`.
-fn generate_alphanumeric(n: usize) -> String {
+pub(crate) fn generate_alphanumeric(n: usize) -> String {
     use rand::Rng;
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
     let mut rng = rand::thread_rng();
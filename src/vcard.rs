@@ -1,6 +1,117 @@
-//! Convert a Google People API `Person` into a vCard 3.0 (RFC 2426) string.
+//! Convert a Google People API `Person` into a vCard 3.0 (RFC 2426) or
+//! 4.0 (RFC 6350) string, and parse vCards sent by CardDAV clients back
+//! into a `Person`.
 
-use google_people1::api::Person;
+use anyhow::Result;
+use google_people1::api::{
+    Address, Birthday, Date, EmailAddress, Name, Organization, Person, PhoneNumber,
+};
+
+// ── Versions ─────────────────────────────────────────────────────────────
+
+/// vCard versions we render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Version {
+    /// RFC 2426 — what every client understands; the default.
+    #[default]
+    V3,
+    /// RFC 6350.
+    V4,
+}
+
+impl Version {
+    /// The `version` parameter / `VERSION` property value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V3 => "3.0",
+            Self::V4 => "4.0",
+        }
+    }
+}
+
+/// Representations of a contact we serve: vCard text, or its JSON / XML
+/// forms (see [`crate::jcard`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    VCard(Version),
+    /// jCard, RFC 7095.
+    JCard,
+    /// xCard, RFC 6351.
+    XCard,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self::VCard(Version::default())
+    }
+}
+
+impl Format {
+    pub const ALL: [Self; 4] = [Self::VCard(Version::V3), Self::VCard(Version::V4), Self::JCard, Self::XCard];
+
+    /// Strong entity tag of a contact served in this format (by GET, or
+    /// as `address-data` in PROPFIND and REPORT), from the etag of its
+    /// vCard 3.0.  Each representation needs its own, or a cache could
+    /// answer a request for one format with the bytes of another.
+    pub fn etag(self, etag: &str) -> String {
+        match self {
+            Self::VCard(Version::V3) => etag.to_string(),
+            Self::VCard(Version::V4) => format!("{etag}-v4"),
+            Self::JCard => format!("{etag}-json"),
+            Self::XCard => format!("{etag}-xml"),
+        }
+    }
+
+    /// Media type, for `Content-Type`.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::VCard(Version::V3) => "text/vcard;charset=utf-8",
+            Self::VCard(Version::V4) => "text/vcard;charset=utf-8;version=4.0",
+            Self::JCard => "application/vcard+json",
+            Self::XCard => "application/vcard+xml;charset=utf-8",
+        }
+    }
+
+    /// Resolve a `content-type` / `version` pair (as in a CardDAV
+    /// `address-data` element or an `Accept` entry).  A missing version
+    /// means 3.0 for `text/vcard`; jCard and xCard only exist as 4.0.
+    /// `None` for anything we can't produce.
+    pub fn from_media_type(content_type: &str, version: Option<&str>) -> Option<Self> {
+        let media = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let version = version.map(str::trim);
+        match (media.as_str(), version) {
+            ("text/vcard", None | Some("3.0")) => Some(Self::VCard(Version::V3)),
+            ("text/vcard", Some("4.0")) => Some(Self::VCard(Version::V4)),
+            ("application/vcard+json", None | Some("4.0")) => Some(Self::JCard),
+            ("application/vcard+xml", None | Some("4.0")) => Some(Self::XCard),
+            _ => None,
+        }
+    }
+
+    /// The vCard version to start from: jCard and xCard are mappings of
+    /// vCard 4.0.
+    pub fn source_version(self) -> Version {
+        match self {
+            Self::VCard(version) => version,
+            Self::JCard | Self::XCard => Version::V4,
+        }
+    }
+
+    /// Render vCard text (in [`Self::source_version`], or 3.0 as a
+    /// fallback) in this format.
+    pub fn render(self, vcard: &str) -> String {
+        match self {
+            Self::VCard(_) => vcard.to_string(),
+            Self::JCard => crate::jcard::to_jcard(vcard),
+            Self::XCard => crate::jcard::to_xcard(vcard),
+        }
+    }
+}
 
 /// Escape special characters for vCard text values.
 fn escape(s: &str) -> String {
@@ -14,10 +125,25 @@ fn escape(s: &str) -> String {
 ///
 /// `resource_name` is used as the UID (e.g. `people/c1234567890`).
 pub fn person_to_vcard(person: &Person) -> String {
+    render_vcard(person, Version::V3)
+}
+
+/// Build a vCard 4.0 string from a Google `Person`.  On top of the 3.0
+/// properties this adds KIND, GENDER and ANNIVERSARY, uses RFC 6350 date
+/// and timestamp formats and tags PHOTO with a MEDIATYPE.
+pub fn person_to_vcard4(person: &Person) -> String {
+    render_vcard(person, Version::V4)
+}
+
+fn render_vcard(person: &Person, version: Version) -> String {
+    let v4 = version == Version::V4;
     let mut lines: Vec<String> = Vec::with_capacity(20);
 
     lines.push("BEGIN:VCARD".into());
-    lines.push("VERSION:3.0".into());
+    lines.push(format!("VERSION:{}", version.as_str()));
+    if v4 {
+        lines.push("KIND:individual".into());
+    }
 
     // ── UID ──────────────────────────────────────────────────────
     let uid = person
@@ -43,13 +169,7 @@ pub fn person_to_vcard(person: &Person) -> String {
                 escape(prefix),
                 escape(suffix)
             ));
-            let display = n
-                .display_name
-                .as_deref()
-                .unwrap_or_else(|| {
-                    // Won't be used as deref, build a owned string below instead
-                    ""
-                });
+            let display = n.display_name.as_deref().unwrap_or("");
             if !display.is_empty() {
                 lines.push(format!("FN:{}", escape(display)));
             } else {
@@ -69,12 +189,17 @@ pub fn person_to_vcard(person: &Person) -> String {
             if addr.is_empty() {
                 continue;
             }
-            let type_param = match email.type_.as_deref() {
-                Some("home") => "HOME",
-                Some("work") => "WORK",
-                _ => "INTERNET",
+            // TYPE=INTERNET no longer exists in 4.0.
+            let type_param = match (email.type_.as_deref(), v4) {
+                (Some("home"), _) => Some("HOME"),
+                (Some("work"), _) => Some("WORK"),
+                (_, false) => Some("INTERNET"),
+                (_, true) => None,
             };
-            lines.push(format!("EMAIL;TYPE={type_param}:{addr}"));
+            match type_param {
+                Some(t) => lines.push(format!("EMAIL;TYPE={}:{addr}", type_case(t, v4))),
+                None => lines.push(format!("EMAIL:{addr}")),
+            }
         }
     }
 
@@ -92,7 +217,7 @@ pub fn person_to_vcard(person: &Person) -> String {
                 Some("homeFax") | Some("workFax") => "FAX",
                 _ => "VOICE",
             };
-            lines.push(format!("TEL;TYPE={type_param}:{number}"));
+            lines.push(format!("TEL;TYPE={}:{number}", type_case(type_param, v4)));
         }
     }
 
@@ -111,7 +236,8 @@ pub fn person_to_vcard(person: &Person) -> String {
             };
             // ADR: PO Box ; Extended ; Street ; City ; Region ; Postal ; Country
             lines.push(format!(
-                "ADR;TYPE={type_param}:;;{};{};{};{};{}",
+                "ADR;TYPE={}:;;{};{};{};{};{}",
+                type_case(type_param, v4),
                 escape(street),
                 escape(city),
                 escape(region),
@@ -135,21 +261,35 @@ pub fn person_to_vcard(person: &Person) -> String {
 
     // ── BDAY ─────────────────────────────────────────────────────
     if let Some(bdays) = person.birthdays.as_ref() {
-        if let Some(bday) = bdays.first() {
-            if let Some(date) = bday.date.as_ref() {
-                let y = date.year.unwrap_or(0);
-                let m = date.month.unwrap_or(0);
-                let d = date.day.unwrap_or(0);
-                if m > 0 && d > 0 {
-                    if y > 0 {
-                        lines.push(format!("BDAY:{y:04}-{m:02}-{d:02}"));
-                    } else {
-                        // Year unknown — use vCard 3.0 convention
-                        lines.push(format!("BDAY:--{m:02}-{d:02}"));
-                    }
-                }
+        if let Some(date) = bdays.first().and_then(|b| b.date.as_ref()) {
+            if let Some(value) = format_date(date, version) {
+                lines.push(format!("BDAY:{value}"));
+            }
+        }
+    }
+
+    // ── GENDER / ANNIVERSARY (4.0 only) ──────────────────────────
+    if v4 {
+        if let Some(gender) = person.genders.as_ref().and_then(|g| g.first()) {
+            let value = gender.value.as_deref().unwrap_or("");
+            match value {
+                "" => {}
+                "male" => lines.push("GENDER:M".into()),
+                "female" => lines.push("GENDER:F".into()),
+                "unknown" => lines.push("GENDER:U".into()),
+                other => lines.push(format!("GENDER:O;{}", escape(other))),
             }
         }
+
+        let anniversary = person
+            .events
+            .as_ref()
+            .and_then(|events| events.iter().find(|e| e.type_.as_deref() == Some("anniversary")))
+            .and_then(|e| e.date.as_ref())
+            .and_then(|date| format_date(date, version));
+        if let Some(value) = anniversary {
+            lines.push(format!("ANNIVERSARY:{value}"));
+        }
     }
 
     // ── PHOTO ────────────────────────────────────────────────────
@@ -158,6 +298,9 @@ pub fn person_to_vcard(person: &Person) -> String {
             if let Some(url) = photo.url.as_deref() {
                 if photo.default.unwrap_or(false) {
                     // Skip Google's default silhouette
+                } else if v4 {
+                    // Google serves contact photos as JPEG.
+                    lines.push(format!("PHOTO;MEDIATYPE=image/jpeg:{url}"));
                 } else {
                     lines.push(format!("PHOTO;VALUE=URI:{url}"));
                 }
@@ -166,10 +309,17 @@ pub fn person_to_vcard(person: &Person) -> String {
     }
 
     // ── REV (last modified) ──────────────────────────────────────
-    // Google doesn't consistently expose modification time on Person,
-    // so we use the current time.
-    let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
-    lines.push(format!("REV:{now}"));
+    // Taken from the newest source update time.  Omitted when Google
+    // doesn't expose one, so the vCard stays stable between renders.
+    let updated = person
+        .metadata
+        .as_ref()
+        .and_then(|m| m.sources.as_ref())
+        .and_then(|sources| sources.iter().filter_map(|s| s.update_time).max());
+    if let Some(updated) = updated {
+        let format = if v4 { "%Y%m%dT%H%M%SZ" } else { "%Y-%m-%dT%H:%M:%SZ" };
+        lines.push(format!("REV:{}", updated.format(format)));
+    }
 
     lines.push("END:VCARD".into());
 
@@ -177,6 +327,52 @@ pub fn person_to_vcard(person: &Person) -> String {
     lines.join("\r\n") + "\r\n"
 }
 
+/// TYPE parameter values are upper-case in our 3.0 output and lower-case
+/// (as in RFC 6350's examples) in 4.0.
+fn type_case(value: &str, v4: bool) -> String {
+    if v4 {
+        value.to_ascii_lowercase()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render a Google `Date`: `1985-04-12` / `--04-12` in 3.0, the RFC 6350
+/// basic forms `19850412` / `--0412` in 4.0.  `None` without month and day.
+fn format_date(date: &Date, version: Version) -> Option<String> {
+    let y = date.year.unwrap_or(0);
+    let m = date.month.unwrap_or(0);
+    let d = date.day.unwrap_or(0);
+    if m <= 0 || d <= 0 {
+        return None;
+    }
+    Some(match (version, y > 0) {
+        (Version::V3, true) => format!("{y:04}-{m:02}-{d:02}"),
+        // Year unknown — use vCard 3.0 convention
+        (Version::V3, false) => format!("--{m:02}-{d:02}"),
+        (Version::V4, true) => format!("{y:04}{m:02}{d:02}"),
+        (Version::V4, false) => format!("--{m:02}{d:02}"),
+    })
+}
+
+/// Version of a rendered vCard: 4.0 if it says so, else 3.0.
+pub fn version_of(vcard: &str) -> Version {
+    if vcard.lines().any(|line| line.trim_end().eq_ignore_ascii_case("VERSION:4.0")) {
+        Version::V4
+    } else {
+        Version::V3
+    }
+}
+
+/// Deterministic ETag derived from the vCard text, for contacts that come
+/// back from Google without an etag (64-bit FNV-1a, hex encoded).
+pub fn content_etag(vcard: &str) -> String {
+    let hash = vcard.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
 /// Extract the display name from a Person (for the DB `display_name` column).
 pub fn display_name(person: &Person) -> String {
     person
@@ -187,14 +383,516 @@ pub fn display_name(person: &Person) -> String {
         .unwrap_or_default()
 }
 
+// ── Parsing ──────────────────────────────────────────────────────────────
+
+/// A single content line of a vCard, e.g. `TEL;TYPE=CELL:+1-555-0100`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// Optional group prefix (`item1` in `item1.EMAIL:...`).
+    pub group: Option<String>,
+    /// Upper-cased property name, e.g. `TEL`.
+    pub name: String,
+    /// Parameters as `(NAME, value)` pairs; names are upper-cased and
+    /// comma-separated `TYPE` values are split into separate pairs.
+    pub params: Vec<(String, String)>,
+    /// Raw (still escaped) property value.
+    pub value: String,
+}
+
+impl Property {
+    /// Returns `true` if any `TYPE` parameter equals `ty` (case-insensitive).
+    pub fn has_type(&self, ty: &str) -> bool {
+        self.params
+            .iter()
+            .any(|(k, v)| k == "TYPE" && v.eq_ignore_ascii_case(ty))
+    }
+
+    /// The unescaped text value.
+    pub fn text(&self) -> String {
+        unescape(&self.value)
+    }
+
+    /// The value split on unescaped `;` into unescaped components
+    /// (for structured properties such as `N` and `ADR`).
+    pub fn components(&self) -> Vec<String> {
+        split_unescaped(&self.value, ';')
+            .iter()
+            .map(|c| unescape(c))
+            .collect()
+    }
+}
+
+/// Unfold and split a vCard into its content lines.
+///
+/// Handles CRLF and bare LF line endings, RFC 6350 §3.2 line folding,
+/// group prefixes and quoted parameter values.  Malformed lines (no `:`)
+/// are skipped.
+pub fn parse_properties(text: &str) -> Vec<Property> {
+    let mut unfolded: Vec<String> = Vec::new();
+    for raw in text.split('\n') {
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        if let Some(cont) = line.strip_prefix([' ', '\t']) {
+            if let Some(last) = unfolded.last_mut() {
+                last.push_str(cont);
+                continue;
+            }
+        }
+        if !line.is_empty() {
+            unfolded.push(line.to_string());
+        }
+    }
+
+    unfolded.iter().filter_map(|l| parse_line(l)).collect()
+}
+
+/// Byte offset of the first `:` outside a quoted parameter value.
+fn value_colon(line: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_line(line: &str) -> Option<Property> {
+    let colon = value_colon(line)?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+    let mut parts = split_quoted(head, ';').into_iter();
+    let full_name = parts.next()?;
+    let (group, name) = match full_name.rsplit_once('.') {
+        Some((g, n)) => (Some(g.to_string()), n),
+        None => (None, full_name.as_str()),
+    };
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    for param in parts {
+        let (key, val) = match param.split_once('=') {
+            Some((k, v)) => (k.to_ascii_uppercase(), v.to_string()),
+            // vCard 2.1 style bare type, e.g. `TEL;CELL:...`
+            None => ("TYPE".to_string(), param.clone()),
+        };
+        let val = val.trim_matches('"');
+        if key == "TYPE" {
+            // Clients send both `TYPE=a,b` and `TYPE="a,b"` for multiple types.
+            for v in val.split(',') {
+                params.push((key.clone(), v.to_string()));
+            }
+        } else {
+            params.push((key, val.to_string()));
+        }
+    }
+
+    Some(Property {
+        group,
+        name: name.to_ascii_uppercase(),
+        params,
+        value: value.to_string(),
+    })
+}
+
+// ── Partial retrieval ────────────────────────────────────────────────────
+
+/// One `<C:prop name="…" novalue="…"/>` of an RFC 6352 §10.4.2 partial
+/// `address-data` request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropSelector {
+    /// Upper-cased property name, e.g. `TEL`.
+    pub name: String,
+    /// Return the property with its parameters but an empty value.
+    pub novalue: bool,
+}
+
+/// Properties always kept by [`trim_vcard`] so the result is still a
+/// valid vCard that clients can identify.
+const ALWAYS_KEPT: &[&str] = &["BEGIN", "VERSION", "UID", "END"];
+
+/// Reduce a vCard to the selected properties (plus BEGIN, VERSION, UID and
+/// END).  Kept lines are copied verbatim, folding included.
+pub fn trim_vcard(text: &str, keep: &[PropSelector]) -> String {
+    // Group physical lines into content lines (continuations start with
+    // a space or tab).
+    let mut content_lines: Vec<Vec<&str>> = Vec::new();
+    for raw in text.split('\n') {
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        match content_lines.last_mut() {
+            Some(last) if line.starts_with([' ', '\t']) => last.push(line),
+            _ if line.is_empty() => {}
+            _ => content_lines.push(vec![line]),
+        }
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for physical in content_lines {
+        let head_end = physical[0].find([';', ':']).unwrap_or(physical[0].len());
+        let full_name = &physical[0][..head_end];
+        let name = full_name
+            .rsplit_once('.')
+            .map_or(full_name, |(_, n)| n)
+            .to_ascii_uppercase();
+
+        if ALWAYS_KEPT.contains(&name.as_str()) {
+            for line in physical {
+                out.push_str(line);
+                out.push_str("\r\n");
+            }
+            continue;
+        }
+        let Some(selector) = keep.iter().find(|s| s.name == name) else {
+            continue;
+        };
+        if selector.novalue {
+            let unfolded: String = physical
+                .iter()
+                .enumerate()
+                .map(|(i, l)| if i == 0 { *l } else { &l[1..] })
+                .collect();
+            if let Some(colon) = value_colon(&unfolded) {
+                out.push_str(&unfolded[..=colon]);
+                out.push_str("\r\n");
+            }
+        } else {
+            for line in physical {
+                out.push_str(line);
+                out.push_str("\r\n");
+            }
+        }
+    }
+    out
+}
+
+/// Split on `sep`, ignoring separators inside double quotes.
+fn split_quoted(s: &str, sep: char) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut in_quotes = false;
+    for ch in s.chars() {
+        if ch == '"' {
+            in_quotes = !in_quotes;
+        }
+        if ch == sep && !in_quotes {
+            out.push(std::mem::take(&mut cur));
+        } else {
+            cur.push(ch);
+        }
+    }
+    out.push(cur);
+    out
+}
+
+/// Split on `sep`, ignoring backslash-escaped separators.  Escapes are kept.
+fn split_unescaped(s: &str, sep: char) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            cur.push(ch);
+            if let Some(next) = chars.next() {
+                cur.push(next);
+            }
+        } else if ch == sep {
+            out.push(std::mem::take(&mut cur));
+        } else {
+            cur.push(ch);
+        }
+    }
+    out.push(cur);
+    out
+}
+
+/// Reverse of [`escape`].
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Build a Google `Person` from a vCard sent by a CardDAV client.
+///
+/// Only the fields Setu renders in [`person_to_vcard`] are mapped back
+/// (see [`crate::google_api::WRITABLE_PERSON_FIELDS`]); everything else in
+/// the card is ignored.  `resource_name` and `etag` are left unset — the
+/// caller fills them in for updates.
+pub fn vcard_to_person(text: &str) -> Result<Person> {
+    let props = parse_properties(text);
+    let is_vcard = props
+        .first()
+        .is_some_and(|p| p.name == "BEGIN" && p.value.eq_ignore_ascii_case("VCARD"));
+    if !is_vcard {
+        anyhow::bail!("body is not a vCard (missing BEGIN:VCARD)");
+    }
+
+    let mut person = Person::default();
+    let mut name: Option<Name> = None;
+    let mut full_name: Option<String> = None;
+    let mut emails = Vec::new();
+    let mut phones = Vec::new();
+    let mut addresses = Vec::new();
+    let mut org: Option<Organization> = None;
+    let mut birthday: Option<Birthday> = None;
+
+    for prop in &props {
+        match prop.name.as_str() {
+            "N" => {
+                let c = prop.components();
+                let field = |i: usize| c.get(i).filter(|s| !s.is_empty()).cloned();
+                name = Some(Name {
+                    family_name: field(0),
+                    given_name: field(1),
+                    middle_name: field(2),
+                    honorific_prefix: field(3),
+                    honorific_suffix: field(4),
+                    ..Default::default()
+                });
+            }
+            "FN" => full_name = Some(prop.text()).filter(|s| !s.is_empty()),
+            "EMAIL" => {
+                let value = prop.text();
+                if value.is_empty() {
+                    continue;
+                }
+                let type_ = if prop.has_type("HOME") {
+                    "home"
+                } else if prop.has_type("WORK") {
+                    "work"
+                } else {
+                    "other"
+                };
+                emails.push(EmailAddress {
+                    value: Some(value),
+                    type_: Some(type_.into()),
+                    ..Default::default()
+                });
+            }
+            "TEL" => {
+                let value = prop.text();
+                let value = value.strip_prefix("tel:").unwrap_or(&value).to_string();
+                if value.is_empty() {
+                    continue;
+                }
+                let type_ = if prop.has_type("FAX") {
+                    if prop.has_type("HOME") {
+                        "homeFax"
+                    } else {
+                        "workFax"
+                    }
+                } else if prop.has_type("CELL") {
+                    "mobile"
+                } else if prop.has_type("HOME") {
+                    "home"
+                } else if prop.has_type("WORK") {
+                    "work"
+                } else {
+                    "other"
+                };
+                phones.push(PhoneNumber {
+                    value: Some(value),
+                    type_: Some(type_.into()),
+                    ..Default::default()
+                });
+            }
+            "ADR" => {
+                // ADR: PO Box ; Extended ; Street ; City ; Region ; Postal ; Country
+                let c = prop.components();
+                let field = |i: usize| c.get(i).filter(|s| !s.is_empty()).cloned();
+                let type_ = if prop.has_type("WORK") { "work" } else { "home" };
+                addresses.push(Address {
+                    po_box: field(0),
+                    extended_address: field(1),
+                    street_address: field(2),
+                    city: field(3),
+                    region: field(4),
+                    postal_code: field(5),
+                    country: field(6),
+                    type_: Some(type_.into()),
+                    ..Default::default()
+                });
+            }
+            "ORG" => {
+                let company = prop.components().into_iter().next().unwrap_or_default();
+                if !company.is_empty() {
+                    org.get_or_insert_with(Organization::default).name = Some(company);
+                }
+            }
+            "TITLE" => {
+                let title = prop.text();
+                if !title.is_empty() {
+                    org.get_or_insert_with(Organization::default).title = Some(title);
+                }
+            }
+            "BDAY" => {
+                birthday = parse_date(&prop.value).map(|date| Birthday {
+                    date: Some(date),
+                    ..Default::default()
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // Google derives the display name from the structured fields; fall
+    // back to the unstructured FN when the client sent no usable N.
+    let has_structured = name.as_ref().is_some_and(|n| {
+        n.family_name.is_some() || n.given_name.is_some() || n.middle_name.is_some()
+    });
+    match (name, full_name) {
+        (Some(n), _) if has_structured => person.names = Some(vec![n]),
+        (_, Some(fname)) => {
+            person.names = Some(vec![Name {
+                unstructured_name: Some(fname),
+                ..Default::default()
+            }])
+        }
+        _ => {}
+    }
+
+    person.email_addresses = Some(emails).filter(|v| !v.is_empty());
+    person.phone_numbers = Some(phones).filter(|v| !v.is_empty());
+    person.addresses = Some(addresses).filter(|v| !v.is_empty());
+    person.organizations = org.map(|o| vec![o]);
+    person.birthdays = birthday.map(|b| vec![b]);
+
+    Ok(person)
+}
+
+// ── Write-back ───────────────────────────────────────────────────────────
+
+/// What an update from a client's vCard may change in Google.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Fields to pass as `updatePersonFields`.
+    pub update: Vec<&'static str>,
+    /// Fields the client changed but that hold data its vCard cannot
+    /// carry; they are left as they are in Google.
+    pub skipped: Vec<&'static str>,
+}
+
+/// Subfields Google computes itself, ignored when comparing a field with
+/// its round trip through a vCard.
+const OUTPUT_ONLY: &[&str] = &[
+    "metadata",
+    "formattedType",
+    "canonicalForm",
+    "formattedValue",
+    "displayName",
+    "displayNameLastFirst",
+    "unstructuredName",
+];
+
+/// Compare `edited` (parsed from a client's vCard) with `current` (the
+/// contact in Google) field by field.
+///
+/// A [`WRITABLE_PERSON_FIELDS`](crate::google_api::WRITABLE_PERSON_FIELDS)
+/// entry is only updated if the client changed it and rendering `current`
+/// to a vCard and back reproduces it exactly.  Otherwise sending the field
+/// would overwrite what the render drops: a second organization, a
+/// department, a `pager` phone, an `other` address.
+pub fn changes(current: &Person, edited: &Person) -> Changes {
+    let rendered = vcard_to_person(&person_to_vcard(current)).unwrap_or_default();
+    let (current, rendered, edited) = (as_fields(current), as_fields(&rendered), as_fields(edited));
+
+    let mut changes = Changes::default();
+    for &name in crate::google_api::WRITABLE_PERSON_FIELDS {
+        let field = |fields: &serde_json::Value| fields.get(name).cloned().unwrap_or_default();
+        if field(&edited) == field(&rendered) {
+            continue;
+        }
+        if writable_part(field(&current), name) == writable_part(field(&rendered), name) {
+            changes.update.push(name);
+        } else {
+            changes.skipped.push(name);
+        }
+    }
+    changes
+}
+
+/// A `Person` as its JSON fields, nulls and empty lists removed.
+fn as_fields(person: &Person) -> serde_json::Value {
+    let mut value = serde_json::to_value(person).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.retain(|_, v| !v.is_null() && v.as_array().is_none_or(|a| !a.is_empty()));
+    }
+    value
+}
+
+/// The entries of a field as a write would send them: output-only and
+/// null subfields dropped, and an untyped email, phone or address read as
+/// `other` (the type the vCard parser gives it).
+fn writable_part(field: serde_json::Value, name: &str) -> serde_json::Value {
+    let serde_json::Value::Array(entries) = field else {
+        return field;
+    };
+    let typed = matches!(name, "emailAddresses" | "phoneNumbers" | "addresses");
+    entries
+        .into_iter()
+        .map(|mut entry| {
+            if let Some(sub) = entry.as_object_mut() {
+                sub.retain(|k, v| !v.is_null() && !OUTPUT_ONLY.contains(&k.as_str()));
+                if typed {
+                    sub.entry("type").or_insert_with(|| "other".into());
+                }
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Parse a vCard date (`1990-03-15`, `19900315`, `--03-15`, `--0315`),
+/// ignoring any time component.
+fn parse_date(value: &str) -> Option<Date> {
+    let date = value.split('T').next().unwrap_or("");
+    let (year, rest) = match date.strip_prefix("--") {
+        Some(rest) => (None, rest.replace('-', "")),
+        None => {
+            let digits = date.replace('-', "");
+            if digits.len() != 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            (Some(digits[..4].parse().ok()?), digits[4..].to_string())
+        }
+    };
+    if rest.len() != 4 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let month: i32 = rest[..2].parse().ok()?;
+    let day: i32 = rest[2..].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(Date {
+        year,
+        month: Some(month),
+        day: Some(day),
+    })
+}
+
 // ── Tests ────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use google_people1::api::{
-        Address, Birthday, Date, EmailAddress, Name, Organization, Person, PersonMetadata,
-        PhoneNumber, Photo,
+        Address, Birthday, Date, EmailAddress, Event, Gender, Name, Organization, Person,
+        PersonMetadata, PhoneNumber, Photo, Source,
     };
 
     /// Build a fully-populated mock Person.
@@ -406,4 +1104,294 @@ mod tests {
         let empty = Person::default();
         assert_eq!(display_name(&empty), "");
     }
+
+    #[test]
+    fn rendering_is_stable_and_rev_follows_source_update_time() {
+        let mut person = mock_person();
+        assert_eq!(person_to_vcard(&person), person_to_vcard(&person));
+        assert!(!person_to_vcard(&person).contains("REV:"));
+
+        person.metadata.as_mut().unwrap().sources = Some(vec![
+            Source {
+                update_time: "2024-03-01T10:00:00Z".parse().ok(),
+                ..Default::default()
+            },
+            Source {
+                update_time: "2024-05-02T08:30:00Z".parse().ok(),
+                ..Default::default()
+            },
+        ]);
+        assert!(person_to_vcard(&person).contains("REV:2024-05-02T08:30:00Z\r\n"));
+    }
+
+    #[test]
+    fn vcard4_uses_rfc6350_properties_and_formats() {
+        let mut person = mock_person();
+        person.genders = Some(vec![Gender {
+            value: Some("female".into()),
+            ..Default::default()
+        }]);
+        person.events = Some(vec![Event {
+            type_: Some("anniversary".into()),
+            date: Some(Date {
+                year: None,
+                month: Some(6),
+                day: Some(1),
+            }),
+            ..Default::default()
+        }]);
+        person.metadata.as_mut().unwrap().sources = Some(vec![Source {
+            update_time: "2024-05-02T08:30:00Z".parse().ok(),
+            ..Default::default()
+        }]);
+        let vcard = person_to_vcard4(&person);
+
+        assert!(vcard.starts_with("BEGIN:VCARD\r\nVERSION:4.0\r\nKIND:individual\r\n"));
+        assert!(vcard.contains("EMAIL;TYPE=home:jane@example.com\r\n"));
+        assert!(vcard.contains("TEL;TYPE=cell:+1-555-0100\r\n"));
+        assert!(vcard.contains("ADR;TYPE=home:;;123 Main St;"));
+        assert!(vcard.contains("BDAY:19900315\r\n"));
+        assert!(vcard.contains("GENDER:F\r\n"));
+        assert!(vcard.contains("ANNIVERSARY:--0601\r\n"));
+        assert!(vcard.contains("PHOTO;MEDIATYPE=image/jpeg:https://lh3.google.com/photo.jpg\r\n"));
+        assert!(vcard.contains("REV:20240502T083000Z\r\n"));
+
+        // 3.0 output is unaffected.
+        let vcard3 = person_to_vcard(&person);
+        assert!(vcard3.contains("VERSION:3.0\r\n"));
+        assert!(!vcard3.contains("GENDER") && !vcard3.contains("KIND"));
+
+        // And 4.0 parses back like 3.0.
+        let parsed = vcard_to_person(&vcard).unwrap();
+        assert_eq!(parsed.birthdays.unwrap()[0].date.as_ref().unwrap().year, Some(1990));
+    }
+
+    #[test]
+    fn format_from_media_type() {
+        let v3 = Some(Format::VCard(Version::V3));
+        assert_eq!(Format::from_media_type("text/vcard", None), v3);
+        assert_eq!(Format::from_media_type("text/vcard; charset=utf-8", Some("4.0")), Some(Format::VCard(Version::V4)));
+        assert_eq!(Format::from_media_type("TEXT/VCARD", Some("3.0")), v3);
+        assert_eq!(Format::from_media_type("text/vcard", Some("2.1")), None);
+        assert_eq!(Format::from_media_type("application/vcard+json", None), Some(Format::JCard));
+        assert_eq!(Format::from_media_type("application/vcard+xml", Some("4.0")), Some(Format::XCard));
+        assert_eq!(Format::from_media_type("application/vcard+json", Some("3.0")), None);
+    }
+
+    #[test]
+    fn each_format_has_its_own_etag() {
+        let etags: std::collections::HashSet<String> = Format::ALL.iter().map(|f| f.etag("e1")).collect();
+        assert_eq!(etags.len(), Format::ALL.len());
+        assert_eq!(Format::default().etag("e1"), "e1", "vCard 3.0 keeps the etag PROPFIND reports");
+    }
+
+    #[test]
+    fn content_etag_is_deterministic() {
+        let vcard = person_to_vcard(&mock_person());
+        assert_eq!(content_etag(&vcard), content_etag(&vcard));
+        assert_eq!(content_etag(&vcard).len(), 16);
+        assert_ne!(content_etag(&vcard), content_etag("BEGIN:VCARD\r\nEND:VCARD\r\n"));
+    }
+
+    #[test]
+    fn parse_properties_unfolds_and_splits_params() {
+        let text = "BEGIN:VCARD\r\nitem1.EMAIL;TYPE=\"work,pref\":a@b.c\r\nNOTE:long\r\n  line\r\nTEL;CELL:123\nEND:VCARD\r\n";
+        let props = parse_properties(text);
+        assert_eq!(props.len(), 5);
+
+        assert_eq!(props[1].group.as_deref(), Some("item1"));
+        assert_eq!(props[1].name, "EMAIL");
+        assert!(props[1].has_type("WORK"));
+        assert!(props[1].has_type("pref"));
+        assert_eq!(props[1].value, "a@b.c");
+
+        assert_eq!(props[2].value, "long line");
+        assert!(props[3].has_type("CELL"));
+    }
+
+    #[test]
+    fn trim_vcard_keeps_selected_properties() {
+        let full = person_to_vcard(&mock_person());
+        let keep = [
+            PropSelector {
+                name: "FN".into(),
+                novalue: false,
+            },
+            PropSelector {
+                name: "TEL".into(),
+                novalue: false,
+            },
+            PropSelector {
+                name: "EMAIL".into(),
+                novalue: true,
+            },
+        ];
+        let trimmed = trim_vcard(&full, &keep);
+
+        assert!(trimmed.starts_with("BEGIN:VCARD\r\nVERSION:3.0\r\n"));
+        assert!(trimmed.contains("UID:people-c1234567890\r\n"));
+        assert!(trimmed.contains("FN:"));
+        assert!(trimmed.contains("TEL;"));
+        assert!(trimmed.contains("EMAIL;TYPE=HOME:\r\n"));
+        assert!(!trimmed.contains("jane@example.com"));
+        assert!(!trimmed.contains("\r\nN:"));
+        assert!(!trimmed.contains("ADR"));
+        assert!(!trimmed.contains("ORG"));
+        assert!(trimmed.ends_with("END:VCARD\r\n"));
+    }
+
+    #[test]
+    fn trim_vcard_preserves_folding_and_groups() {
+        let text = "BEGIN:VCARD\r\nVERSION:3.0\r\nitem1.NOTE:very\r\n  long\r\nitem2.TEL:123\r\nEND:VCARD\r\n";
+        let keep = [PropSelector {
+            name: "NOTE".into(),
+            novalue: false,
+        }];
+        assert_eq!(
+            trim_vcard(text, &keep),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nitem1.NOTE:very\r\n  long\r\nEND:VCARD\r\n"
+        );
+    }
+
+    #[test]
+    fn unescape_reverses_escape() {
+        let raw = "a;b,c\\d\ne";
+        assert_eq!(unescape(&escape(raw)), raw);
+    }
+
+    #[test]
+    fn vcard_to_person_roundtrip() {
+        let original = mock_person();
+        let parsed = vcard_to_person(&person_to_vcard(&original)).unwrap();
+
+        let name = &parsed.names.as_ref().unwrap()[0];
+        assert_eq!(name.family_name.as_deref(), Some("Doe"));
+        assert_eq!(name.given_name.as_deref(), Some("Jane"));
+        assert_eq!(name.middle_name.as_deref(), Some("M"));
+        assert_eq!(name.honorific_prefix.as_deref(), Some("Dr."));
+        assert_eq!(name.honorific_suffix.as_deref(), Some("PhD"));
+
+        let emails = parsed.email_addresses.as_ref().unwrap();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[1].value.as_deref(), Some("jane@work.com"));
+        assert_eq!(emails[1].type_.as_deref(), Some("work"));
+
+        let phone = &parsed.phone_numbers.as_ref().unwrap()[0];
+        assert_eq!(phone.value.as_deref(), Some("+1-555-0100"));
+        assert_eq!(phone.type_.as_deref(), Some("mobile"));
+
+        let addr = &parsed.addresses.as_ref().unwrap()[0];
+        assert_eq!(addr.street_address.as_deref(), Some("123 Main St"));
+        assert_eq!(addr.postal_code.as_deref(), Some("62701"));
+
+        let org = &parsed.organizations.as_ref().unwrap()[0];
+        assert_eq!(org.name.as_deref(), Some("Acme Corp"));
+        assert_eq!(org.title.as_deref(), Some("Engineer"));
+
+        let date = parsed.birthdays.as_ref().unwrap()[0].date.as_ref().unwrap();
+        assert_eq!((date.year, date.month, date.day), (Some(1990), Some(3), Some(15)));
+    }
+
+    #[test]
+    fn vcard_to_person_fn_only() {
+        let text = "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:O'Brien\\, Jr.\r\nBDAY:--12-25\r\nEND:VCARD\r\n";
+        let parsed = vcard_to_person(text).unwrap();
+        let name = &parsed.names.as_ref().unwrap()[0];
+        assert_eq!(name.unstructured_name.as_deref(), Some("O'Brien, Jr."));
+
+        let date = parsed.birthdays.as_ref().unwrap()[0].date.as_ref().unwrap();
+        assert_eq!((date.year, date.month, date.day), (None, Some(12), Some(25)));
+    }
+
+    #[test]
+    fn vcard_to_person_ignores_malformed_birthdays() {
+        for bday in ["123é456", "--1é2", "+990-03-15", "1990-03-1x", "--13-01"] {
+            let text = format!("BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Jane\r\nBDAY:{bday}\r\nEND:VCARD\r\n");
+            let parsed = vcard_to_person(&text).unwrap();
+            assert!(parsed.birthdays.is_none(), "{bday}");
+        }
+    }
+
+    #[test]
+    fn update_leaves_fields_the_vcard_drops_alone() {
+        let mut current = mock_person();
+        current.organizations = Some(vec![
+            Organization {
+                name: Some("Acme Corp".into()),
+                department: Some("R&D".into()),
+                title: Some("Engineer".into()),
+                ..Default::default()
+            },
+            Organization {
+                name: Some("City Orchestra".into()),
+                title: Some("Cellist".into()),
+                ..Default::default()
+            },
+        ]);
+        current.phone_numbers = Some(vec![
+            PhoneNumber {
+                value: Some("+1-555-0100".into()),
+                type_: Some("mobile".into()),
+                ..Default::default()
+            },
+            PhoneNumber {
+                value: Some("+1-555-0199".into()),
+                type_: Some("Boat".into()),
+                formatted_type: Some("Boat".into()),
+                ..Default::default()
+            },
+        ]);
+
+        // GET, then PUT back unchanged: nothing to update.
+        let vcard = person_to_vcard(&current);
+        let unchanged = vcard_to_person(&vcard).unwrap();
+        assert_eq!(changes(&current, &unchanged), Changes::default());
+
+        // A new email address only updates the email addresses.
+        let edited = vcard.replace("jane@work.com", "jane@acme.example");
+        let edited = vcard_to_person(&edited).unwrap();
+        assert_eq!(
+            changes(&current, &edited),
+            Changes {
+                update: vec!["emailAddresses"],
+                skipped: vec![],
+            }
+        );
+
+        // Edits to the organizations or phones would drop the second
+        // organization, the department and the custom phone type.
+        let edited = vcard
+            .replace("TITLE:Engineer", "TITLE:Lead Engineer")
+            .replace("+1-555-0100", "+1-555-0111");
+        let edited = vcard_to_person(&edited).unwrap();
+        assert_eq!(
+            changes(&current, &edited),
+            Changes {
+                update: vec![],
+                skipped: vec!["phoneNumbers", "organizations"],
+            }
+        );
+    }
+
+    #[test]
+    fn update_of_a_losslessly_rendered_contact_sends_the_changed_fields() {
+        let current = mock_person();
+        let edited = person_to_vcard(&current)
+            .replace("TITLE:Engineer", "TITLE:Lead Engineer")
+            .replace("BDAY:1990-03-15", "BDAY:1990-03-16");
+        let edited = vcard_to_person(&edited).unwrap();
+        assert_eq!(
+            changes(&current, &edited),
+            Changes {
+                update: vec!["organizations", "birthdays"],
+                skipped: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn vcard_to_person_rejects_non_vcard() {
+        assert!(vcard_to_person("hello world").is_err());
+        assert!(vcard_to_person("").is_err());
+    }
 }
@@ -0,0 +1,330 @@
+//! Minimal namespace-aware XML tree for WebDAV / CardDAV request bodies.
+//!
+//! Clients are free to pick any namespace prefix (`D:`, `d:`, `A:`, a
+//! default `xmlns="DAV:"` …), reorder attributes, wrap values in CDATA or
+//! escape them as entities.  [`parse`] resolves all of that with
+//! `quick-xml` and hands back a small owned [`Element`] tree whose names
+//! are `(namespace, local name)` pairs.
+//!
+//! The `*_tag` helpers go the other way: they render a qualified name with
+//! the fixed prefixes used in our responses (`D:`, `C:`, `CS:`), declaring
+//! any other namespace inline.
+
+use anyhow::{bail, Context, Result};
+use quick_xml::events::Event;
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+
+// ── Namespaces ──────────────────────────────────────────────────────────
+
+/// WebDAV (RFC 4918).
+pub const DAV: &str = "DAV:";
+/// CardDAV (RFC 6352).
+pub const CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+/// Apple CalendarServer extensions (`getctag`).
+pub const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+/// Prefixes declared on every multistatus root we emit.
+const KNOWN_PREFIXES: &[(&str, &str)] = &[(DAV, "D"), (CARDDAV, "C"), (CALENDARSERVER, "CS")];
+
+/// Namespace declarations matching [`KNOWN_PREFIXES`], for the root element.
+pub const NAMESPACE_DECLS: &str = r#"xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav" xmlns:CS="http://calendarserver.org/ns/""#;
+
+// ── Tree ────────────────────────────────────────────────────────────────
+
+/// A namespace-qualified element or property name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QName {
+    /// Namespace URI (empty when the name is not in any namespace).
+    pub ns: String,
+    pub local: String,
+}
+
+impl QName {
+    pub fn new(ns: &str, local: &str) -> Self {
+        Self {
+            ns: ns.to_string(),
+            local: local.to_string(),
+        }
+    }
+
+    /// Name comparison.  A name in no namespace matches `local` in any
+    /// namespace: some clients send bare `<href>` / `<prop-filter>` without
+    /// declaring `DAV:` or the CardDAV namespace at all.
+    pub fn is(&self, ns: &str, local: &str) -> bool {
+        self.local == local && (self.ns == ns || self.ns.is_empty())
+    }
+}
+
+/// A parsed element: name, attributes (by local name), children and the
+/// concatenated text content (entities and CDATA already resolved).
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub name: QName,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    fn new(name: QName) -> Self {
+        Self {
+            name,
+            attrs: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    pub fn is(&self, ns: &str, local: &str) -> bool {
+        self.name.is(ns, local)
+    }
+
+    /// First direct child with the given name.
+    pub fn child(&self, ns: &str, local: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.is(ns, local))
+    }
+
+    /// All direct children with the given name.
+    pub fn children_named<'a>(
+        &'a self,
+        ns: &'a str,
+        local: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.is(ns, local))
+    }
+
+    /// Attribute value by local name (attributes on WebDAV elements are
+    /// unqualified, e.g. `name="TEL"`).
+    pub fn attr(&self, local: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == local)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Text content with surrounding whitespace removed.
+    pub fn text(&self) -> &str {
+        self.text.trim()
+    }
+}
+
+// ── Parsing ─────────────────────────────────────────────────────────────
+
+/// Parse a request body into its root element.
+pub fn parse(xml: &str) -> Result<Element> {
+    let mut reader = NsReader::from_str(xml);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+
+    loop {
+        let (resolved, event) = reader.read_resolved_event().context("malformed XML")?;
+        match event {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                if root.is_some() {
+                    bail!("content after the root element");
+                }
+                let name = QName {
+                    ns: namespace_uri(resolved)?,
+                    local: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+                };
+                let mut element = Element::new(name);
+                for attr in start.attributes() {
+                    let attr = attr.context("malformed XML attribute")?;
+                    // Namespace declarations are not attributes of the element.
+                    if attr.key.as_namespace_binding().is_some() {
+                        continue;
+                    }
+                    let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+                    let value = attr.unescape_value().context("malformed XML attribute")?;
+                    element.attrs.push((key, value.into_owned()));
+                }
+
+                if matches!(event, Event::Start(_)) {
+                    stack.push(element);
+                } else {
+                    close(&mut stack, &mut root, element);
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().context("unbalanced end tag")?;
+                close(&mut stack, &mut root, element);
+            }
+            Event::Text(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&text.decode().context("invalid text encoding")?);
+                }
+            }
+            Event::CData(cdata) => {
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&cdata.decode().context("invalid CDATA encoding")?);
+                }
+            }
+            Event::GeneralRef(reference) => {
+                let Some(current) = stack.last_mut() else {
+                    continue;
+                };
+                if let Some(ch) = reference.resolve_char_ref().context("bad character reference")? {
+                    current.text.push(ch);
+                } else {
+                    let name = reference.decode().context("invalid entity encoding")?;
+                    let value = quick_xml::escape::resolve_predefined_entity(&name)
+                        .with_context(|| format!("unknown entity &{name};"))?;
+                    current.text.push_str(value);
+                }
+            }
+            Event::Eof => break,
+            Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => {}
+        }
+    }
+
+    if !stack.is_empty() {
+        bail!("unexpected end of XML document");
+    }
+    root.context("empty XML document")
+}
+
+/// Attach a finished element to its parent, or make it the root.
+fn close(stack: &mut [Element], root: &mut Option<Element>, element: Element) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None => *root = Some(element),
+    }
+}
+
+fn namespace_uri(resolved: ResolveResult<'_>) -> Result<String> {
+    match resolved {
+        ResolveResult::Bound(ns) => Ok(String::from_utf8_lossy(ns.as_ref()).into_owned()),
+        ResolveResult::Unbound => Ok(String::new()),
+        ResolveResult::Unknown(prefix) => {
+            bail!("undeclared namespace prefix {:?}", String::from_utf8_lossy(&prefix))
+        }
+    }
+}
+
+// ── Rendering ───────────────────────────────────────────────────────────
+
+/// Opening tag for `name`, e.g. `<D:getetag>` or
+/// `<x:calendar-color xmlns:x="http://apple.com/ns/ical/">`.
+pub fn start_tag(name: &QName) -> String {
+    match known_prefix(&name.ns) {
+        Some(prefix) => format!("<{prefix}:{}>", name.local),
+        None if name.ns.is_empty() => format!("<{} xmlns=\"\">", name.local),
+        None => format!("<x:{} xmlns:x=\"{}\">", name.local, escape(&name.ns)),
+    }
+}
+
+/// Closing tag matching [`start_tag`].
+pub fn end_tag(name: &QName) -> String {
+    match known_prefix(&name.ns) {
+        Some(prefix) => format!("</{prefix}:{}>", name.local),
+        None if name.ns.is_empty() => format!("</{}>", name.local),
+        None => format!("</x:{}>", name.local),
+    }
+}
+
+/// Self-closing tag for `name`, e.g. `<D:resourcetype/>`.
+pub fn empty_tag(name: &QName) -> String {
+    match known_prefix(&name.ns) {
+        Some(prefix) => format!("<{prefix}:{}/>", name.local),
+        None if name.ns.is_empty() => format!("<{} xmlns=\"\"/>", name.local),
+        None => format!("<x:{} xmlns:x=\"{}\"/>", name.local, escape(&name.ns)),
+    }
+}
+
+fn known_prefix(ns: &str) -> Option<&'static str> {
+    KNOWN_PREFIXES
+        .iter()
+        .find(|(uri, _)| *uri == ns)
+        .map(|(_, prefix)| *prefix)
+}
+
+/// Minimal XML escaping for attribute/text values.
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ── Tests ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_any_prefix_and_default_namespace() {
+        let a = parse(r#"<A:propfind xmlns:A="DAV:"><A:prop><A:getetag/></A:prop></A:propfind>"#)
+            .unwrap();
+        let b = parse(r#"<propfind xmlns="DAV:"><prop><getetag/></prop></propfind>"#).unwrap();
+
+        for root in [a, b] {
+            assert!(root.is(DAV, "propfind"));
+            let prop = root.child(DAV, "prop").unwrap();
+            assert!(prop.children[0].is(DAV, "getetag"));
+        }
+    }
+
+    #[test]
+    fn unprefixed_without_default_namespace_matches_any() {
+        let root = parse("<href>/a</href>").unwrap();
+        assert_eq!(root.name, QName::new("", "href"));
+        assert_eq!(root.text(), "/a");
+
+        // …but still matches the expected namespace.
+        assert!(root.is(DAV, "href"));
+        assert!(!QName::new(CARDDAV, "href").is(DAV, "href"));
+    }
+
+    #[test]
+    fn resolves_entities_and_cdata() {
+        let root = parse(
+            r#"<D:x xmlns:D="DAV:"><D:a>a&amp;b&#x41;&#66;</D:a><D:b><![CDATA[<raw>&]]></D:b></D:x>"#,
+        )
+        .unwrap();
+        assert_eq!(root.child(DAV, "a").unwrap().text(), "a&bAB");
+        assert_eq!(root.child(DAV, "b").unwrap().text(), "<raw>&");
+    }
+
+    #[test]
+    fn attributes_are_unescaped_and_order_independent() {
+        let root = parse(
+            r#"<C:prop-filter xmlns:C="urn:ietf:params:xml:ns:carddav" test="anyof" name="T&#69;L"/>"#,
+        )
+        .unwrap();
+        assert!(root.is(CARDDAV, "prop-filter"));
+        assert_eq!(root.attr("name"), Some("TEL"));
+        assert_eq!(root.attr("test"), Some("anyof"));
+        assert_eq!(root.attrs.len(), 2);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(parse("").is_err());
+        assert!(parse("<D:a xmlns:D=\"DAV:\">").is_err());
+        assert!(parse("<X:a/>").is_err());
+        assert!(parse("<a></b>").is_err());
+    }
+
+    #[test]
+    fn renders_known_and_foreign_namespaces() {
+        assert_eq!(empty_tag(&QName::new(DAV, "getetag")), "<D:getetag/>");
+        assert_eq!(start_tag(&QName::new(CARDDAV, "address-data")), "<C:address-data>");
+        assert_eq!(
+            empty_tag(&QName::new("http://apple.com/ns/ical/", "calendar-color")),
+            r#"<x:calendar-color xmlns:x="http://apple.com/ns/ical/"/>"#
+        );
+        assert_eq!(
+            end_tag(&QName::new("http://apple.com/ns/ical/", "calendar-color")),
+            "</x:calendar-color>"
+        );
+        assert_eq!(empty_tag(&QName::new("", "foo")), r#"<foo xmlns=""/>"#);
+    }
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!(escape("a<b>c&d\"e"), "a&lt;b&gt;c&amp;d&quot;e");
+        assert_eq!(escape("plain text"), "plain text");
+    }
+}